@@ -0,0 +1,82 @@
+// 大きな文字列を細かく切り出したときに確保されるメモリの量を測る。
+//
+//   cargo bench --bench substring_memory
+//
+// 50MB の入力から substring/bytes で 100 万個の部分文字列を切り出し、その間に確保したバイト数を数える。
+// 切り出す長さを変えて測り、1 個あたりの確保量が長さによらず一定なら中身をコピーしていない。
+// 一定の分は評価器が引数や Env のために確保する分。
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use mr_lisp::prelude::{Interpreter, Object};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const INPUT_SIZE: usize = 50 * 1024 * 1024;
+const PIECES: usize = 1_000_000;
+
+// 長さ len の部分文字列を PIECES 個切り出す間に確保したバイト数と、かかった時間を返す。
+fn measure(interpreter: &mut Interpreter, len: usize) -> (usize, f64) {
+    let program = format!(
+        "(do ((i 0 (+ i 1))) ((> i {})) (let ((start (* i {}))) (substring/bytes input start (+ start {}))))",
+        PIECES - 1,
+        (INPUT_SIZE - len) / PIECES,
+        len
+    );
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let started = Instant::now();
+    interpreter.eval(&program).unwrap();
+    let elapsed = started.elapsed().as_secs_f64();
+    (ALLOCATED.load(Ordering::Relaxed) - before, elapsed)
+}
+
+fn main() {
+    // ASCII だけの入力なので、どの位置で切っても文字の境界になる
+    let line = "2024-01-01T00:00:00Z GET /index.html 200 0.012\n";
+    let input = line.repeat(INPUT_SIZE / line.len() + 1)[..INPUT_SIZE].to_string();
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .env()
+        .borrow_mut()
+        .set("input", Object::String(input.into()));
+
+    println!(
+        "substring/bytes: {} pieces from a {} MB input",
+        PIECES,
+        INPUT_SIZE / 1024 / 1024
+    );
+    for len in [16, 1024, 16 * 1024] {
+        let (allocated, elapsed) = measure(&mut interpreter, len);
+        println!(
+            "  {:>6} bytes/piece: allocated {:>10} bytes ({:.1} bytes/piece, copying would add {}) in {:.2}s",
+            len,
+            allocated,
+            allocated as f64 / PIECES as f64,
+            len,
+            elapsed
+        );
+    }
+}
@@ -0,0 +1,395 @@
+// 評価せずにたくさんのファイルを読むツール (lint、フォーマッター、LSP など) 向けのパーサー。
+//
+//   let arena = Arena::with_capacity(4096);
+//   for form in parse_in(&arena, source)? {
+//       if let Node::List(children) = arena.node(form) { ... }
+//   }
+//
+// parser::parse は式ごとに Rc で Object を作るが、parse_in はノードを Arena の Vec に並べて添字 (NodeId) で指す。
+// 文字列やシンボルの中身も Arena の 1 つの String に詰めるので、ノードごとの確保は無い (字句解析のトークンは別)。
+// Arena::clear で中身を捨てれば、確保済みの領域を次のファイルで使い回せる。
+// 評価したくなったら Arena::to_object で parser::parse と同じ Object に変換する。
+
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+use crate::keyword::{Op, SpecialForm};
+use crate::lexer::{Token, tokenize_spanned};
+use crate::parser::{Object, Pair, ParseError, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+// Arena の中の文字列。Arena::text で中身を読む。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Text {
+    start: u32,
+    end: u32,
+}
+
+// リストの要素の並び。Arena::children で読む。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Children {
+    start: u32,
+    end: u32,
+}
+
+impl Children {
+    pub fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+// 'x は parser::parse と同じく (quote x) のリストとして表す。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node {
+    Integer(i64),
+    Float(f64),
+    Char(char),
+    Bool(bool),
+    String(Text),
+    Symbol(Text),
+    Keyword(SpecialForm),
+    BinaryOp(Op),
+    KeywordArg(Text), // #:name
+    List(Children),
+    Vector(Children),     // #(a b)
+    Pair(NodeId, NodeId), // (a . b)。(a b . c) は (a . (b . c)) になる
+}
+
+#[derive(Debug, Default)]
+pub struct Arena {
+    nodes: RefCell<Vec<(Node, Span)>>,
+    children: RefCell<Vec<NodeId>>,
+    text: RefCell<String>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Arena::default()
+    }
+
+    // nodes 個のノードを確保し直さずに入れられるようにする。文字列などの領域はノードの数から見積もる。
+    pub fn with_capacity(nodes: usize) -> Self {
+        Arena {
+            nodes: RefCell::new(Vec::with_capacity(nodes)),
+            children: RefCell::new(Vec::with_capacity(nodes)),
+            text: RefCell::new(String::with_capacity(nodes * 8)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.borrow().is_empty()
+    }
+
+    // すべてのノードを捨てる。それまでの NodeId は使えなくなる。
+    pub fn clear(&mut self) {
+        self.nodes.get_mut().clear();
+        self.children.get_mut().clear();
+        self.text.get_mut().clear();
+    }
+
+    pub fn node(&self, id: NodeId) -> Node {
+        self.nodes.borrow()[id.0 as usize].0
+    }
+
+    // ノードのソース上のバイト範囲。'x を展開した quote は ' の範囲になる。
+    pub fn span(&self, id: NodeId) -> Span {
+        self.nodes.borrow()[id.0 as usize].1.clone()
+    }
+
+    pub fn text(&self, text: Text) -> Ref<'_, str> {
+        Ref::map(self.text.borrow(), |s| {
+            &s[text.start as usize..text.end as usize]
+        })
+    }
+
+    pub fn children(&self, children: Children) -> Ref<'_, [NodeId]> {
+        Ref::map(self.children.borrow(), |c| {
+            &c[children.start as usize..children.end as usize]
+        })
+    }
+
+    pub fn to_object(&self, id: NodeId) -> Object {
+        match self.node(id) {
+            Node::Integer(i) => Object::Integer(i),
+            Node::Float(f) => Object::Float(f),
+            Node::Char(c) => Object::Char(c),
+            Node::Bool(b) => Object::truth(b),
+            Node::String(text) => Object::String((*self.text(text)).into()),
+            Node::Symbol(text) => Object::Symbol((*self.text(text)).into()),
+            Node::Keyword(kw) => Object::Keyword(kw),
+            Node::BinaryOp(op) => Object::BinaryOp(op),
+            Node::KeywordArg(text) => Object::KeywordArg((*self.text(text)).into()),
+            Node::Pair(car, cdr) => Object::Pair(Rc::new(Pair {
+                car: self.to_object(car),
+                cdr: self.to_object(cdr),
+            })),
+            Node::List(children) => {
+                let children = self.children(children).to_vec();
+                let list: Vec<Object> = children.into_iter().map(|id| self.to_object(id)).collect();
+                Object::List(list.into())
+            }
+            Node::Vector(children) => {
+                let children = self.children(children).to_vec();
+                let items = children.into_iter().map(|id| self.to_object(id)).collect();
+                Object::Vector(Rc::new(RefCell::new(items)))
+            }
+        }
+    }
+
+    fn push(&self, node: Node, span: Span) -> NodeId {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push((node, span));
+        NodeId(index(nodes.len() - 1))
+    }
+
+    fn push_text(&self, s: &str) -> Text {
+        let mut text = self.text.borrow_mut();
+        let start = index(text.len());
+        text.push_str(s);
+        Text {
+            start,
+            end: index(text.len()),
+        }
+    }
+}
+
+fn index(n: usize) -> u32 {
+    u32::try_from(n).expect("arena: more than 4 GiB of nodes or text")
+}
+
+// プログラム中のトップレベルの式をすべて読み、Arena に入れたノードを返す。
+// 読めなかった場合は、この呼び出しで Arena に入れたノードを取り除いてからエラーを返す。
+pub fn parse_in(arena: &Arena, program: &str) -> Result<Vec<NodeId>, ParseError> {
+    let mut parser = Parser {
+        arena,
+        tokens: tokenize_spanned(program)?.into_iter().rev().collect(),
+        stack: Vec::new(),
+    };
+    let marks = (
+        arena.nodes.borrow().len(),
+        arena.children.borrow().len(),
+        arena.text.borrow().len(),
+    );
+    let mut forms = Vec::new();
+    while !parser.tokens.is_empty() {
+        match parser.parse_expr() {
+            Ok(id) => forms.push(id),
+            Err(e) => {
+                arena.nodes.borrow_mut().truncate(marks.0);
+                arena.children.borrow_mut().truncate(marks.1);
+                arena.text.borrow_mut().truncate(marks.2);
+                return Err(e);
+            }
+        }
+    }
+    Ok(forms)
+}
+
+struct Parser<'a> {
+    arena: &'a Arena,
+    tokens: Vec<(Token, Span)>, // 逆順にしてスタックのように扱う
+    stack: Vec<NodeId>,         // 読んでいる途中のリストの要素。入れ子のリストで共有する
+}
+
+impl Parser<'_> {
+    fn parse_expr(&mut self) -> Result<NodeId, ParseError> {
+        let Some((token, span)) = self.tokens.pop() else {
+            return Err(ParseError::new("Unexpected end of input"));
+        };
+        let arena = self.arena;
+        let node = match token {
+            Token::Integer(i) => Node::Integer(i),
+            Token::Float(f) => Node::Float(f),
+            Token::Char(c) => Node::Char(c),
+            Token::Bool(b) => Node::Bool(b),
+            Token::String(s) => Node::String(arena.push_text(&s)),
+            Token::Symbol(s) => Node::Symbol(arena.push_text(&s)),
+            Token::BinaryOp(op) => Node::BinaryOp(op),
+            Token::Keyword(kw) => Node::Keyword(kw),
+            Token::KeywordArg(kw) => Node::KeywordArg(arena.push_text(&kw)),
+            Token::LParen => return self.parse_list(span),
+            Token::VectorOpen => return self.parse_vector(span),
+            Token::RParen => return Err(ParseError::new("Unexpected ')'")),
+            // アリーナのノードには任意の Object を置けない
+            Token::Datum(_) => {
+                return Err(ParseError::new(
+                    "Reader macros are not supported by parse_in",
+                ));
+            }
+            Token::Quote => return self.prefixed(SpecialForm::Quote, span),
+            Token::Quasiquote => return self.prefixed(SpecialForm::Quasiquote, span),
+            Token::Unquote => return self.prefixed(SpecialForm::Unquote, span),
+            Token::UnquoteSplicing => return self.prefixed(SpecialForm::UnquoteSplicing, span),
+        };
+        Ok(arena.push(node, span))
+    }
+
+    // 'x や `x、,x、,@x を (quote x) などの形にする。
+    fn prefixed(&mut self, keyword: SpecialForm, span: Span) -> Result<NodeId, ParseError> {
+        let keyword = self.arena.push(Node::Keyword(keyword), span.clone());
+        let expr = self.parse_expr()?;
+        let end = self.arena.span(expr).end;
+        let mut children = self.arena.children.borrow_mut();
+        let start = index(children.len());
+        children.extend([keyword, expr]);
+        let list = Children {
+            start,
+            end: index(children.len()),
+        };
+        drop(children);
+        Ok(self.arena.push(Node::List(list), span.start..end))
+    }
+
+    // stack の base から先の要素でリストを作る。tail があれば parser::dotted と同じく
+    // tail がリストならその要素を後ろにつなげ、そうでなければ (a b . tail) のペアの並びにする。
+    fn finish_list(&mut self, base: usize, tail: Option<NodeId>, span: Span) -> NodeId {
+        let tail_children = tail.map(|tail| match self.arena.node(tail) {
+            Node::List(children) => Ok(self.arena.children(children).to_vec()),
+            _ => Err(tail),
+        });
+        if let Some(Err(tail)) = tail_children {
+            let items: Vec<NodeId> = self.stack.drain(base..).collect();
+            let end = span.end;
+            let mut cdr = tail;
+            for (i, car) in items.into_iter().enumerate().rev() {
+                let start = if i == 0 {
+                    span.start
+                } else {
+                    self.arena.span(car).start
+                };
+                cdr = self.arena.push(Node::Pair(car, cdr), start..end);
+            }
+            return cdr;
+        }
+        let mut children = self.arena.children.borrow_mut();
+        let start = index(children.len());
+        children.extend(self.stack.drain(base..));
+        if let Some(Ok(rest)) = tail_children {
+            children.extend(rest);
+        }
+        let list = Children {
+            start,
+            end: index(children.len()),
+        };
+        drop(children);
+        self.arena.push(Node::List(list), span)
+    }
+
+    // ( は読んだ後。要素は stack に積み、) を読んだら children にまとめて移す。
+    fn parse_list(&mut self, open: Span) -> Result<NodeId, ParseError> {
+        let base = self.stack.len();
+        loop {
+            match self.tokens.last() {
+                Some((Token::RParen, close)) => {
+                    let end = close.end;
+                    self.tokens.pop();
+                    return Ok(self.finish_list(base, None, open.start..end));
+                }
+                Some((Token::Symbol(s), _)) if s == "." => {
+                    self.tokens.pop();
+                    let tail = self.parse_expr()?;
+                    match self.tokens.pop() {
+                        Some((Token::RParen, close)) if self.stack.len() > base => {
+                            return Ok(self.finish_list(base, Some(tail), open.start..close.end));
+                        }
+                        _ => return Err(ParseError::new("Expected one expression after '.'")),
+                    }
+                }
+                Some(_) => {
+                    let id = self.parse_expr()?;
+                    self.stack.push(id);
+                }
+                None => return Err(ParseError::new("Expected ')' at the end of list")),
+            }
+        }
+    }
+
+    // #( は読んだ後。リストと違って . は特別扱いしない。
+    fn parse_vector(&mut self, open: Span) -> Result<NodeId, ParseError> {
+        let base = self.stack.len();
+        loop {
+            match self.tokens.last() {
+                Some((Token::RParen, close)) => {
+                    let end = close.end;
+                    self.tokens.pop();
+                    let mut children = self.arena.children.borrow_mut();
+                    let start = index(children.len());
+                    children.extend(self.stack.drain(base..));
+                    let items = Children {
+                        start,
+                        end: index(children.len()),
+                    };
+                    drop(children);
+                    return Ok(self.arena.push(Node::Vector(items), open.start..end));
+                }
+                Some(_) => {
+                    let id = self.parse_expr()?;
+                    self.stack.push(id);
+                }
+                None => return Err(ParseError::new("Expected ')' at the end of vector")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_spanned;
+
+    #[test]
+    fn test_parse_in() {
+        let source = "(define (f x) \"doc\" (* x 1.5)) '(a #:k `(b ,c ,@d)) (g => 1) (1 2 . 3) (a . (b)) #(1 (a) \"s\") #\\space (#t #f true)";
+        let arena = Arena::with_capacity(64);
+        let forms = parse_in(&arena, source).unwrap();
+        let expected = parse_spanned(source).unwrap();
+        assert_eq!(forms.len(), expected.len());
+        for (id, (obj, span)) in forms.iter().zip(expected) {
+            assert_eq!(arena.to_object(*id), obj);
+            assert_eq!(arena.span(*id), span);
+        }
+
+        let Node::List(children) = arena.node(forms[0]) else {
+            panic!("expected a list");
+        };
+        assert_eq!(children.len(), 4);
+        let name = arena.children(children)[0];
+        assert_eq!(arena.node(name), Node::Keyword(SpecialForm::Define));
+        assert_eq!(&source[arena.span(name)], "define");
+        assert_eq!(&source[arena.span(forms[1])], "'(a #:k `(b ,c ,@d))");
+    }
+
+    #[test]
+    fn test_parse_in_error_and_reuse() {
+        let mut arena = Arena::new();
+        let first = parse_in(&arena, "(a b)").unwrap();
+        let len = arena.len();
+        assert_eq!(
+            parse_in(&arena, "(c (d)").unwrap_err().to_string(),
+            "ParseError: Expected ')' at the end of list"
+        );
+        assert!(parse_in(&arena, "(1 . 2 3)").is_err());
+        assert_eq!(
+            parse_in(&arena, "e)").unwrap_err().to_string(),
+            "ParseError: Unexpected ')'"
+        );
+        // 失敗した分は取り除かれ、先に読んだノードはそのまま使える
+        assert_eq!(arena.len(), len);
+        assert_eq!(arena.to_object(first[0]).to_string(), "(a b)");
+
+        arena.clear();
+        assert!(arena.is_empty());
+        let forms = parse_in(&arena, "x 1").unwrap();
+        assert_eq!(forms, [NodeId(0), NodeId(1)]);
+    }
+}
@@ -0,0 +1,387 @@
+// i64 に収まらない整数。整数どうしの + - * / が桁あふれしたときに、評価器がこの型に切り替える。
+//
+//   (define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+//   (* 4611686018427387904 4)   ; 18446744073709551616
+//
+// 計算の結果が i64 に収まれば Object::Integer に戻すので、Object::BigInt は常に i64 の範囲の外の値になる。
+// そのため同じ値が Integer と BigInt の両方で表されることはなく、比較は中身をそのまま比べればよい。
+// 2^32 進数の筆算で計算する。割り算はビットごとの筆算なので、非常に大きな数どうしの割り算は遅い。
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::parser::Object;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>, // 絶対値。下の桁から並べ、上の桁の 0 は持たない。0 は空で negative は false
+}
+
+impl BigInt {
+    pub fn to_i64(&self) -> Option<i64> {
+        let magnitude = match self.digits[..] {
+            [] => 0,
+            [low] => low as u64,
+            [low, high] => (high as u64) << 32 | low as u64,
+            _ => return None,
+        };
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &d| acc * 4294967296.0 + d as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    // radix 進数で書く。10 より上の桁は小文字の英字。radix は 2 から 36 まで。
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        let mut text = Vec::new();
+        let mut rest = self.digits.clone();
+        while !rest.is_empty() {
+            let (q, r) = div_rem_small(&rest, radix);
+            text.push(char::from_digit(r, radix).unwrap());
+            rest = q;
+        }
+        if text.is_empty() {
+            text.push('0');
+        }
+        if self.negative {
+            text.push('-');
+        }
+        text.iter().rev().collect()
+    }
+
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    // 符号の付いた radix 進数。i64::from_str_radix と同じく、10 より上の桁は大文字でも小文字でもよい。
+    pub fn from_str_radix(s: &str, radix: u32) -> Option<BigInt> {
+        let (negative, text) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if text.is_empty() {
+            return None;
+        }
+        let mut digits = Vec::new();
+        for c in text.chars() {
+            digits = mul_add_small(&digits, radix, c.to_digit(radix)?);
+        }
+        Some(BigInt::new(negative, digits))
+    }
+
+    // 0 で割った場合は None。商は 0 の方向に切り捨て、余りの符号は割られる数と同じにする (i64 と同じ)。
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = div_rem_digits(&self.digits, &other.digits);
+        Some((
+            BigInt::new(self.negative != other.negative, q),
+            BigInt::new(self.negative, r),
+        ))
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> BigInt {
+        let magnitude = n.unsigned_abs();
+        BigInt::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+// i64 に収まれば Integer にする。
+impl From<BigInt> for Object {
+    fn from(n: BigInt) -> Object {
+        match n.to_i64() {
+            Some(n) => Object::Integer(n),
+            None => Object::BigInt(Rc::new(n)),
+        }
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_digits(&self.digits, &other.digits));
+        }
+        // 符号が違えば、絶対値の大きい方から小さい方を引き、大きい方の符号にする
+        match cmp_digits(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, sub_digits(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_digits(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != other.negative,
+            mul_digits(&self.digits, &other.digits),
+        )
+    }
+}
+
+// 0 で割ると panic する。0 かもしれない場合は div_rem を使うこと。
+impl Div for &BigInt {
+    type Output = BigInt;
+
+    fn div(self, other: &BigInt) -> BigInt {
+        self.div_rem(other).expect("BigInt: division by zero").0
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_digits(&self.digits, &other.digits),
+            (true, true) => cmp_digits(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 10^9 で割っていき、9 桁ずつ下から取り出す
+        let mut chunks = Vec::new();
+        let mut rest = self.digits.clone();
+        while !rest.is_empty() {
+            let (q, r) = div_rem_small(&rest, 1_000_000_000);
+            chunks.push(r);
+            rest = q;
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                for chunk in rest.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// 符号の付いた 10 進数。字句解析器が i64 に収まらない整数を読むのに使う。
+impl FromStr for BigInt {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<BigInt, ()> {
+        BigInt::from_str_radix(s, 10).ok_or(())
+    }
+}
+
+fn cmp_digits(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let s = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(s as u32);
+        carry = s >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// a >= b であること。
+fn sub_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &d) in a.iter().enumerate() {
+        let mut s = d as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (s < 0) as i64;
+        if s < 0 {
+            s += 1 << 32;
+        }
+        diff.push(s as u32);
+    }
+    diff
+}
+
+fn mul_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + product[i + j] as u64 + carry;
+            product[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    product
+}
+
+// a * m + c
+fn mul_add_small(a: &[u32], m: u32, c: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = c as u64;
+    for &d in a {
+        let t = d as u64 * m as u64 + carry;
+        result.push(t as u32);
+        carry = t >> 32;
+    }
+    result.push(carry as u32);
+    result
+}
+
+fn div_rem_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut r = 0u64;
+    for i in (0..a.len()).rev() {
+        let t = r << 32 | a[i] as u64;
+        q[i] = (t / d as u64) as u32;
+        r = t % d as u64;
+    }
+    while q.last() == Some(&0) {
+        q.pop();
+    }
+    (q, r as u32)
+}
+
+// b は 0 でないこと。
+fn div_rem_digits(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if let [d] = b {
+        let (q, r) = div_rem_small(a, *d);
+        return (q, vec![r]);
+    }
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+    for bit in (0..a.len() * 32).rev() {
+        // r = r * 2 + (a の bit 番目)
+        let mut carry = a[bit / 32] >> (bit % 32) & 1;
+        for d in r.iter_mut() {
+            let next = *d >> 31;
+            *d = *d << 1 | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            r.push(carry);
+        }
+        if cmp_digits(&r, b) != Ordering::Less {
+            r = sub_digits(&r, b);
+            while r.last() == Some(&0) {
+                r.pop();
+            }
+            q[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (q, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(n: i128) -> BigInt {
+        let magnitude = n.unsigned_abs();
+        BigInt::new(
+            n < 0,
+            (0..4).map(|i| (magnitude >> (32 * i)) as u32).collect(),
+        )
+    }
+
+    #[test]
+    fn test_same_as_i128() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..2000 {
+            // 桁数の違う数が混ざるように、ランダムな幅だけ右にずらす
+            let mut operand = || (next() as i64 as i128) << (next() % 60) >> (next() % 90);
+            let (a, b) = (operand(), operand());
+            assert_eq!((&big(a) + &big(b)), big(a + b), "{} + {}", a, b);
+            assert_eq!((&big(a) - &big(b)), big(a - b), "{} - {}", a, b);
+            assert_eq!(big(a).cmp(&big(b)), a.cmp(&b), "{} < {}", a, b);
+            assert_eq!(big(a).to_string(), a.to_string());
+            assert_eq!(a.to_string().parse(), Ok(big(a)));
+            if a.abs() < 1 << 62 && b.abs() < 1 << 62 {
+                assert_eq!((&big(a) * &big(b)), big(a * b), "{} * {}", a, b);
+            }
+            if b != 0 {
+                assert_eq!(
+                    big(a).div_rem(&big(b)),
+                    Some((big(a / b), big(a % b))),
+                    "{} / {}",
+                    a,
+                    b
+                );
+            }
+            assert_eq!(big(a).to_i64(), i64::try_from(a).ok());
+        }
+        assert_eq!(big(7).div_rem(&big(0)), None);
+        assert_eq!(BigInt::from(i64::MIN), big(i64::MIN as i128));
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!("+007".parse(), Ok(big(7)));
+        assert_eq!("-".parse::<BigInt>(), Err(()));
+        assert_eq!("1.5".parse::<BigInt>(), Err(()));
+        assert_eq!(BigInt::from_str_radix("-fF", 16), Some(big(-255)));
+        assert_eq!(BigInt::from_str_radix("12", 2), None);
+    }
+}
@@ -0,0 +1,11 @@
+// Jupyter から起動されるカーネル。引数は Jupyter が用意する接続ファイルのパス。
+fn main() {
+    let Some(connection_file) = std::env::args().nth(1) else {
+        eprintln!("usage: mr-lisp-kernel <connection-file>");
+        std::process::exit(2);
+    };
+    if let Err(e) = mr_lisp::jupyter::run(&connection_file) {
+        eprintln!("mr-lisp-kernel: {}", e);
+        std::process::exit(1);
+    }
+}
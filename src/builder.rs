@@ -0,0 +1,307 @@
+// Rust のコードからプログラム (Object の木) を組み立てるための関数とマクロ。
+// 文字列を組み立ててパースし直さずに、埋め込み先やテストで式を作れる。
+//
+//   let program = list![sym("define"), list![sym("sq"), sym("x")], app("*", [sym("x"), sym("x")])];
+//   let call = ObjectBuilder::call("sq").int(3).build();
+//
+// 作られるのはパーサーと同じ形の式なので、Interpreter::eval_object でそのまま評価できる。
+
+use std::rc::Rc;
+
+use crate::lexer::{Token, tokenize};
+use crate::parser::Object;
+
+// パーサーが読んだ場合と同じように、define や if などは Keyword に、+ などは BinaryOp にする。
+pub fn sym(name: &str) -> Object {
+    match tokenize(name).as_deref() {
+        Ok([Token::Keyword(kw)]) if kw.name() == name => Object::Keyword(*kw),
+        Ok([Token::BinaryOp(op)]) if op.name() == name => Object::BinaryOp(*op),
+        _ => Object::Symbol(name.into()),
+    }
+}
+
+pub fn int(n: i64) -> Object {
+    Object::Integer(n)
+}
+
+pub fn float(f: f64) -> Object {
+    Object::Float(f)
+}
+
+pub fn string(s: &str) -> Object {
+    Object::String(s.into())
+}
+
+// (quote obj)
+pub fn quote(obj: Object) -> Object {
+    Object::List(Rc::new(vec![sym("quote"), obj]))
+}
+
+// (f args...)
+pub fn app(f: &str, args: impl IntoIterator<Item = Object>) -> Object {
+    let mut list = vec![sym(f)];
+    list.extend(args);
+    Object::List(Rc::new(list))
+}
+
+impl From<i64> for Object {
+    fn from(n: i64) -> Self {
+        Object::Integer(n)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(f: f64) -> Self {
+        Object::Float(f)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(b: bool) -> Self {
+        Object::truth(b)
+    }
+}
+
+// &str は文字列になる。シンボルは sym で作る。
+impl From<&str> for Object {
+    fn from(s: &str) -> Self {
+        Object::String(s.into())
+    }
+}
+
+// list![a, b, ...] は (a b ...) という式になる。要素は Object に変換できるものなら何でもよい。
+#[macro_export]
+macro_rules! list {
+    ($($item:expr),* $(,)?) => {
+        $crate::parser::Object::List(::std::rc::Rc::new(vec![
+            $($crate::parser::Object::from($item)),*
+        ]))
+    };
+}
+
+pub use crate::list;
+
+// lisp!((define (sq x) (* x x))) のように Lisp の式をそのまま書いて Object を作る。
+// ,x で Rust の式の値を埋め込み、,@xs で Object の並びを展開して埋め込む。,(a + b) のように括弧で囲めば任意の式を書ける。
+// 複数の式を書いた場合は (begin ...) になる。
+//
+// Rust のトークンとして読むので、次の制限がある。
+//   - string->number や null? のような名前は読めるが、a - b のように識別子の間に - を置くと a-b という 1 つのシンボルになる
+//   - 負の数や ' での quote は書けない。,(-1) や (quote x) と書く
+#[macro_export]
+macro_rules! lisp {
+    // [...] にはリストの要素を並べる Iterator を積んでいき、最後に chain してリストにする。
+    (@list [$($parts:expr),*]) => {
+        $crate::parser::Object::List(::std::rc::Rc::new(
+            ::std::iter::empty()$(.chain($parts))*.collect(),
+        ))
+    };
+    (@list [$($parts:expr),*] , @ $e:tt $($rest:tt)*) => {
+        $crate::lisp!(@list [$($parts,)* $e] $($rest)*)
+    };
+    (@list [$($parts:expr),*] , $e:tt $($rest:tt)*) => {
+        $crate::lisp!(@one [$($parts),*] [$crate::parser::Object::from($e)] $($rest)*)
+    };
+    (@list [$($parts:expr),*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::lisp!(@one [$($parts),*] [$crate::lisp!(@list [] $($inner)*)] $($rest)*)
+    };
+    (@list [$($parts:expr),*] # : $name:ident $($rest:tt)*) => {
+        $crate::lisp!(@one [$($parts),*] [
+            $crate::parser::Object::KeywordArg(stringify!($name).into())
+        ] $($rest)*)
+    };
+    // 演算子は literal より先に試す。そうしないと (- 1 2) の - 1 が負の数として読まれる
+    (@list [$($parts:expr),*] + $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["+"] $($rest)*) };
+    (@list [$($parts:expr),*] - $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["-"] $($rest)*) };
+    (@list [$($parts:expr),*] * $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["*"] $($rest)*) };
+    (@list [$($parts:expr),*] / $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["/"] $($rest)*) };
+    (@list [$($parts:expr),*] % $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["%"] $($rest)*) };
+    (@list [$($parts:expr),*] < $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["<"] $($rest)*) };
+    (@list [$($parts:expr),*] > $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] [">"] $($rest)*) };
+    (@list [$($parts:expr),*] = $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["="] $($rest)*) };
+    (@list [$($parts:expr),*] <= $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["<="] $($rest)*) };
+    (@list [$($parts:expr),*] >= $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] [">="] $($rest)*) };
+    (@list [$($parts:expr),*] == $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["=="] $($rest)*) };
+    (@list [$($parts:expr),*] != $($rest:tt)*) => { $crate::lisp!(@sym [$($parts),*] ["!="] $($rest)*) };
+    (@list [$($parts:expr),*] $lit:literal $($rest:tt)*) => {
+        $crate::lisp!(@one [$($parts),*] [$crate::parser::Object::from($lit)] $($rest)*)
+    };
+    (@list [$($parts:expr),*] $name:ident $($rest:tt)*) => {
+        $crate::lisp!(@ident [$($parts),*] [stringify!($name)] $($rest)*)
+    };
+
+    // a-b、a->b、a?、a! を 1 つの名前にまとめる
+    (@ident [$($parts:expr),*] [$($name:expr),*] - $next:ident $($rest:tt)*) => {
+        $crate::lisp!(@ident [$($parts),*] [$($name,)* "-", stringify!($next)] $($rest)*)
+    };
+    (@ident [$($parts:expr),*] [$($name:expr),*] -> $next:ident $($rest:tt)*) => {
+        $crate::lisp!(@ident [$($parts),*] [$($name,)* "->", stringify!($next)] $($rest)*)
+    };
+    (@ident [$($parts:expr),*] [$($name:expr),*] ? $($rest:tt)*) => {
+        $crate::lisp!(@ident [$($parts),*] [$($name,)* "?"] $($rest)*)
+    };
+    (@ident [$($parts:expr),*] [$($name:expr),*] ! $($rest:tt)*) => {
+        $crate::lisp!(@ident [$($parts),*] [$($name,)* "!"] $($rest)*)
+    };
+    (@ident [$($parts:expr),*] [$($name:expr),*] $($rest:tt)*) => {
+        $crate::lisp!(@sym [$($parts),*] [concat!($($name),*)] $($rest)*)
+    };
+
+    (@sym [$($parts:expr),*] [$name:expr] $($rest:tt)*) => {
+        $crate::lisp!(@one [$($parts),*] [$crate::builder::sym($name)] $($rest)*)
+    };
+    (@one [$($parts:expr),*] [$item:expr] $($rest:tt)*) => {
+        $crate::lisp!(@list [$($parts,)* ::std::iter::once($item)] $($rest)*)
+    };
+
+    ($($form:tt)+) => {
+        $crate::builder::forms($crate::lisp!(@list [] $($form)+))
+    };
+}
+
+// lisp! に書いた式の並びを 1 つの式にする。
+#[doc(hidden)]
+pub fn forms(list: Object) -> Object {
+    match list {
+        Object::List(forms) if forms.len() == 1 => forms[0].clone(),
+        Object::List(forms) => app("begin", forms.iter().cloned()),
+        _ => unreachable!(),
+    }
+}
+
+pub use crate::lisp;
+
+// 要素を 1 つずつ足してリストを作る。build は式のリストを、build_data は quote したのと同じデータのリストを返す。
+#[derive(Debug, Clone, Default)]
+pub struct ObjectBuilder {
+    items: Vec<Object>,
+}
+
+impl ObjectBuilder {
+    pub fn new() -> Self {
+        ObjectBuilder { items: Vec::new() }
+    }
+
+    // 関数呼び出し (f ...) を作り始める。
+    pub fn call(f: &str) -> Self {
+        ObjectBuilder::new().sym(f)
+    }
+
+    pub fn push(mut self, item: impl Into<Object>) -> Self {
+        self.items.push(item.into());
+        self
+    }
+
+    pub fn sym(self, name: &str) -> Self {
+        self.push(sym(name))
+    }
+
+    pub fn int(self, n: i64) -> Self {
+        self.push(n)
+    }
+
+    pub fn float(self, f: f64) -> Self {
+        self.push(f)
+    }
+
+    pub fn string(self, s: &str) -> Self {
+        self.push(s)
+    }
+
+    pub fn extend(mut self, items: impl IntoIterator<Item = Object>) -> Self {
+        self.items.extend(items);
+        self
+    }
+
+    pub fn build(self) -> Object {
+        Object::List(Rc::new(self.items))
+    }
+
+    pub fn build_data(self) -> Object {
+        Object::ListData(Rc::new(self.items))
+    }
+}
+
+impl From<ObjectBuilder> for Object {
+    fn from(builder: ObjectBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_build_program() {
+        let define = list![
+            sym("define"),
+            list![sym("greet"), sym("name")],
+            app("format", [string("~a, ~a!"), "hello".into(), sym("name")]),
+        ];
+        assert_eq!(
+            define,
+            parse("(define (greet name) (format \"~a, ~a!\" \"hello\" name))").unwrap()
+        );
+        let call = ObjectBuilder::call("greet").string("lisp").build();
+        assert_eq!(call, parse("(greet \"lisp\")").unwrap());
+        assert_eq!(
+            ObjectBuilder::new()
+                .sym("if")
+                .push(app("<", [int(1), float(2.5)]))
+                .push(quote(list![sym("a"), sym("b")]))
+                .push(ObjectBuilder::call("-").int(1))
+                .build(),
+            parse("(if (< 1 2.5) '(a b) (- 1))").unwrap()
+        );
+
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_object(&define).unwrap();
+        assert_eq!(
+            interpreter.eval_object(&call),
+            Ok(Object::String("hello, lisp!".into()))
+        );
+        assert_eq!(
+            ObjectBuilder::new()
+                .int(1)
+                .sym("x")
+                .build_data()
+                .to_string(),
+            "(1 x)"
+        );
+        let data = ObjectBuilder::new().int(1).sym("x").build_data();
+        assert_eq!(interpreter.eval_object(&data), Ok(data.clone()));
+        let wrapped = ObjectBuilder::call("car").push(data).build();
+        assert_eq!(interpreter.eval_object(&wrapped), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_lisp_macro() {
+        let n = 3;
+        let names = vec![sym("a"), sym("b")];
+        assert_eq!(
+            lisp!((define (sq x) (* x x))),
+            parse("(define (sq x) (* x x))").unwrap()
+        );
+        assert_eq!(
+            lisp!((f ,n ,(n as f64 / 2.0) ,@names "s" true (- 1 2) #:precision 2)).to_string(),
+            "(f 3 1.5 a b s true (- 1 2) #:precision 2)"
+        );
+        assert_eq!(
+            lisp!((number->string (string-length "ab")) (null? x) (ws-send! s)),
+            parse("(begin (number->string (string-length \"ab\")) (null? x) (ws-send! s))")
+                .unwrap()
+        );
+
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_object(&lisp!((define (sq x) (* x x))))
+            .unwrap();
+        assert_eq!(
+            interpreter.eval_object(&lisp!((sq, n))),
+            Ok(Object::Integer(9))
+        );
+    }
+}
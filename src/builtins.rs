@@ -0,0 +1,2268 @@
+use std::{cell::RefCell, fmt, fmt::Write, rc::Rc};
+
+use crate::bigint::BigInt;
+use crate::eval::{Env, eval_toplevel, to_code};
+use crate::pair::list_items;
+use crate::parser::Object;
+use crate::string::Str;
+
+// Rust で実装された組み込み関数。引数は評価済みの Object で受け取る。
+// Lisp の関数を呼び出す組み込み関数のために、呼び出し元の Env も渡す。
+pub type BuiltinFn = fn(&[Object], &mut Rc<RefCell<Env>>) -> Result<Object, String>;
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFn,
+    pub doc: &'static str,                // apropos で表示する 1 行の説明
+    pub params: Option<&'static [Param]>, // None なら引数の数や型は func 自身が確かめる
+}
+
+// 組み込み関数を 1 行で宣言する。引数の並びを書くと、func を呼ぶ前に数と型を確かめる。
+//
+//   builtin!("car", car, "Returns the first element of a pair"),            ; func が自分で確かめる
+//   builtin!("string-pad-left", string_pad_left, (String, Any, Any?), "..."),  ; 2 個か 3 個
+//   builtin!("max", max, (Number, Number...), "..."),                         ; 1 個以上
+//
+// 型は ArgType の名前で、? を付けると省略でき、... を付けると残りの引数すべてになる。
+// 数や型が合わなければ、"name: expected 2 or 3 arguments, got 1" や "name: expected a string, got 1" のエラーにする。
+macro_rules! builtin {
+    (@params [$($out:tt)*]) => { &[$($out)*] };
+    (@params [$($out:tt)*] $ty:ident ... $(, $($rest:tt)*)?) => {
+        builtin!(@params [$($out)* $crate::builtins::Param::Rest($crate::builtins::ArgType::$ty),] $($($rest)*)?)
+    };
+    (@params [$($out:tt)*] $ty:ident ? $(, $($rest:tt)*)?) => {
+        builtin!(@params [$($out)* $crate::builtins::Param::Optional($crate::builtins::ArgType::$ty),] $($($rest)*)?)
+    };
+    (@params [$($out:tt)*] $ty:ident $(, $($rest:tt)*)?) => {
+        builtin!(@params [$($out)* $crate::builtins::Param::Required($crate::builtins::ArgType::$ty),] $($($rest)*)?)
+    };
+    ($name:literal, $func:path, $doc:literal) => {
+        $crate::builtins::Builtin { name: $name, func: $func, doc: $doc, params: None }
+    };
+    ($name:literal, $func:path, ($($params:tt)*), $doc:literal) => {
+        $crate::builtins::Builtin { name: $name, func: $func, doc: $doc, params: Some(builtin!(@params [] $($params)*)) }
+    };
+}
+#[allow(unused_imports)] // ほかのモジュールで Builtin を作るときに使う
+pub(crate) use builtin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgType {
+    Any,
+    Integer,
+    Number,
+    String,
+    Symbol,
+    Boolean,
+    Char,
+    Procedure,
+}
+
+impl ArgType {
+    const ALL: [ArgType; 8] = [
+        ArgType::Any,
+        ArgType::Integer,
+        ArgType::Number,
+        ArgType::String,
+        ArgType::Symbol,
+        ArgType::Boolean,
+        ArgType::Char,
+        ArgType::Procedure,
+    ];
+
+    pub(crate) fn from_name(name: &str) -> Option<ArgType> {
+        ArgType::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ArgType::Any => "any",
+            ArgType::Integer => "integer",
+            ArgType::Number => "number",
+            ArgType::String => "string",
+            ArgType::Symbol => "symbol",
+            ArgType::Boolean => "boolean",
+            ArgType::Char => "char",
+            ArgType::Procedure => "procedure",
+        }
+    }
+
+    // "an integer" や "a string"
+    pub(crate) fn with_article(self) -> String {
+        match self {
+            ArgType::Any | ArgType::Integer => format!("an {}", self.name()),
+            _ => format!("a {}", self.name()),
+        }
+    }
+
+    pub(crate) fn accepts(self, obj: &Object) -> bool {
+        match self {
+            ArgType::Any => true,
+            ArgType::Integer => matches!(obj, Object::Integer(_) | Object::BigInt(_)),
+            ArgType::Number => {
+                matches!(
+                    obj,
+                    Object::Integer(_) | Object::BigInt(_) | Object::Float(_)
+                )
+            }
+            ArgType::String => matches!(obj, Object::String(_)),
+            ArgType::Symbol => matches!(obj, Object::Symbol(_)),
+            ArgType::Boolean => matches!(obj, Object::Bool(_)),
+            ArgType::Char => matches!(obj, Object::Char(_)),
+            ArgType::Procedure => crate::eval::is_procedure(obj),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Required(ArgType),
+    Optional(ArgType),
+    Rest(ArgType),
+}
+
+impl Builtin {
+    // params を確かめてから func を呼ぶ。eval から組み込み関数を呼ぶときは必ずここを通る。
+    pub(crate) fn call(
+        &self,
+        args: &[Object],
+        env: &mut Rc<RefCell<Env>>,
+    ) -> Result<Object, String> {
+        if let Some(params) = self.params {
+            check_args(self.name, params, args)?;
+        }
+        (self.func)(args, env)
+    }
+
+    // (string-pad-left string any [any]) のような呼び出し方。params が無ければ None。
+    pub fn usage(&self) -> Option<String> {
+        let mut usage = format!("({}", self.name);
+        for param in self.params? {
+            match param {
+                Param::Required(ty) => write!(usage, " {}", ty.name()),
+                Param::Optional(ty) => write!(usage, " [{}]", ty.name()),
+                Param::Rest(ty) => write!(usage, " {}...", ty.name()),
+            }
+            .unwrap();
+        }
+        usage.push(')');
+        Some(usage)
+    }
+
+    // (doc name) の説明。呼び出し方が分かれば 1 行目に付ける。
+    pub(crate) fn full_doc(&self) -> String {
+        match self.usage() {
+            Some(usage) => format!("{}\n{}", usage, self.doc),
+            None => self.doc.to_string(),
+        }
+    }
+}
+
+fn check_args(name: &str, params: &[Param], args: &[Object]) -> Result<(), String> {
+    check_arity(name, params, args.len())?;
+    for (ty, arg) in param_types(params).zip(args) {
+        if !ty.accepts(arg) {
+            return Err(format!(
+                "{}: expected {}, got {}",
+                name,
+                ty.with_article(),
+                arg
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 引数が count 個の呼び出しが params に合うか。mr-lisp typecheck も同じメッセージで報告する。
+pub(crate) fn check_arity(name: &str, params: &[Param], count: usize) -> Result<(), String> {
+    let min = params
+        .iter()
+        .filter(|param| matches!(param, Param::Required(_)))
+        .count();
+    let rest = params.iter().any(|param| matches!(param, Param::Rest(_)));
+    let max = params.len() - usize::from(rest);
+    let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+    let expected = match rest {
+        true if count < min => Some(format!("at least {} {}", min, plural(min))),
+        false if min == max && count != min => Some(format!("{} {}", min, plural(min))),
+        false if count < min || count > max => {
+            let range = if max == min + 1 { "or" } else { "to" };
+            Some(format!("{} {} {} {}", min, range, max, plural(max)))
+        }
+        _ => None,
+    };
+    match expected {
+        Some(expected) => Err(format!("{}: expected {}, got {}", name, expected, count)),
+        None => Ok(()),
+    }
+}
+
+// 先頭から順に、引数ごとの型。... の型はそれ以降のすべての引数に使う。
+pub(crate) fn param_types(params: &[Param]) -> impl Iterator<Item = ArgType> + '_ {
+    let rest = params.iter().find_map(|param| match param {
+        Param::Rest(ty) => Some(*ty),
+        _ => None,
+    });
+    params
+        .iter()
+        .map(|param| match param {
+            Param::Required(ty) | Param::Optional(ty) | Param::Rest(ty) => *ty,
+        })
+        .chain(std::iter::repeat(rest.unwrap_or(ArgType::Any)))
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+pub(crate) static BUILTINS: &[Builtin] = &[
+    builtin!(
+        "number->string",
+        number_to_string,
+        (Number, Any...),
+        "Formats a number as a string, optionally in a radix or with #:precision digits"
+    ),
+    builtin!(
+        "format-number",
+        format_number,
+        (Number, Any...),
+        "Formats a number with digit grouping, as in 1,234,567.89"
+    ),
+    builtin!(
+        "string->number",
+        string_to_number,
+        (String),
+        "Parses a number from a string, or returns false"
+    ),
+    builtin!(
+        "string-pad-left",
+        string_pad_left,
+        (String, Integer, Any?),
+        "Pads a string on the left to a width, truncating longer strings"
+    ),
+    builtin!(
+        "format",
+        format,
+        (String, Any...),
+        "Formats values into a string using ~a, ~d, ~f and ~% directives"
+    ),
+    builtin!(
+        "string-foldcase",
+        string_foldcase,
+        (String),
+        "Folds a string to lower case for case-insensitive comparison"
+    ),
+    builtin!(
+        "char-alphabetic?",
+        char_alphabetic,
+        (Char),
+        "Tests whether a character is a letter"
+    ),
+    builtin!(
+        "char-numeric?",
+        char_numeric,
+        (Char),
+        "Tests whether a character is a digit"
+    ),
+    builtin!(
+        "char-whitespace?",
+        char_whitespace,
+        (Char),
+        "Tests whether a character is whitespace"
+    ),
+    builtin!(
+        "char->integer",
+        char_to_integer,
+        (Char),
+        "Returns the Unicode code point of a character"
+    ),
+    builtin!(
+        "integer->char",
+        integer_to_char,
+        (Integer),
+        "Returns the character with a Unicode code point"
+    ),
+    builtin!(
+        "char-upcase",
+        char_upcase,
+        (Char),
+        "Converts a character to upper case"
+    ),
+    builtin!("char=?", char_eq, (Char, Char, Char...), "Tests whether all characters are equal"),
+    builtin!(
+        "string-length",
+        string_length,
+        (String),
+        "Returns the number of characters in a string"
+    ),
+    builtin!(
+        "string-ref",
+        string_ref,
+        (String, Integer),
+        "Returns the character at an index of a string"
+    ),
+    builtin!(
+        "string->list",
+        string_to_list,
+        (String),
+        "Returns the characters of a string as a list"
+    ),
+    builtin!(
+        "list->string",
+        list_to_string,
+        (Any),
+        "Makes a string from a list of characters"
+    ),
+    builtin!(
+        "substring",
+        substring,
+        (String, Integer, Integer?),
+        "Returns the characters of a string between two indices"
+    ),
+    builtin!(
+        "string-byte-length",
+        string_byte_length,
+        (String),
+        "Returns the number of UTF-8 bytes in a string"
+    ),
+    builtin!(
+        "substring/bytes",
+        substring_bytes,
+        (String, Integer, Integer?),
+        "Returns the part of a string between two byte offsets"
+    ),
+    builtin!(
+        "string-split",
+        string_split,
+        (String, Any, Any...),
+        "Splits a string on a string or regex separator, with an optional #:limit"
+    ),
+    builtin!(
+        "string-join",
+        string_join,
+        (Any, String),
+        "Joins a list of strings with a separator"
+    ),
+    builtin!(
+        "regex",
+        crate::regex::regex,
+        (String),
+        "Compiles a regular expression for use as a string-split separator"
+    ),
+    builtin!(
+        "template",
+        template,
+        (String, Any...),
+        "Fills {name} placeholders in a string from keyword arguments"
+    ),
+    builtin!(
+        "html->string",
+        html_to_string,
+        (Any),
+        "Renders a nested list like (div ((class \"x\")) \"hi\") as HTML"
+    ),
+    builtin!(
+        "cons",
+        crate::pair::cons,
+        (Any, Any),
+        "Makes a pair from two values"
+    ),
+    builtin!(
+        "car",
+        crate::pair::car,
+        (Any),
+        "Returns the first element of a pair or list"
+    ),
+    builtin!(
+        "cdr",
+        crate::pair::cdr,
+        (Any),
+        "Returns the rest of a pair or list"
+    ),
+    builtin!(
+        "pair?",
+        crate::pair::is_pair,
+        (Any),
+        "Tests whether a value is a non-empty pair or list"
+    ),
+    builtin!(
+        "null?",
+        crate::pair::is_null,
+        (Any),
+        "Tests whether a value is the empty list"
+    ),
+    builtin!(
+        "length",
+        crate::pair::length,
+        (Any),
+        "Returns the number of elements in a list"
+    ),
+    builtin!(
+        "make-vector",
+        crate::vector::make_vector,
+        (Integer, Any?),
+        "Makes a vector of a length, filled with a value (default 0)"
+    ),
+    builtin!(
+        "vector-ref",
+        crate::vector::vector_ref,
+        (Any, Integer),
+        "Returns the element at an index of a vector"
+    ),
+    builtin!(
+        "vector-set!",
+        crate::vector::vector_set,
+        (Any, Integer, Any),
+        "Replaces the element at an index of a vector"
+    ),
+    builtin!(
+        "vector-length",
+        crate::vector::vector_length,
+        (Any),
+        "Returns the number of elements in a vector"
+    ),
+    builtin!(
+        "vector->list",
+        crate::vector::vector_to_list,
+        (Any),
+        "Returns the elements of a vector as a list"
+    ),
+    builtin!(
+        "not",
+        not,
+        (Any),
+        "Returns true for false and false for everything else"
+    ),
+    builtin!(
+        "eval",
+        eval,
+        (Any, Any...),
+        "Evaluates a datum as code, optionally in an environment"
+    ),
+    builtin!(
+        "eval-in-sandbox",
+        crate::sandbox::eval_in_sandbox,
+        (String, Any...),
+        "Evaluates a code string in a fresh environment with limited fuel and builtins"
+    ),
+    builtin!(
+        "apropos",
+        crate::help::apropos_builtin,
+        (String),
+        "Lists builtins and definitions whose name or description contains a string"
+    ),
+    builtin!(
+        "for-each-line",
+        crate::stdin::for_each_line,
+        (Procedure),
+        "Calls a function with each line read from standard input"
+    ),
+    builtin!(
+        "read-all-stdin",
+        crate::stdin::read_all_stdin,
+        (),
+        "Reads the rest of standard input as a string"
+    ),
+    builtin!(
+        "write-to-string",
+        crate::printer::write_to_string,
+        (Any),
+        "Returns the written representation of a value, which reads back"
+    ),
+    builtin!(
+        "current-environment",
+        crate::environment::current_environment,
+        (),
+        "Returns the environment where it is called"
+    ),
+    builtin!(
+        "make-environment",
+        crate::environment::make_environment,
+        (Any?),
+        "Makes a fresh environment, or a child of an environment"
+    ),
+    builtin!(
+        "environment-define!",
+        crate::environment::environment_define,
+        (Any, Symbol, Any),
+        "Binds a symbol to a value in an environment"
+    ),
+    builtin!(
+        "env-shared?",
+        crate::environment::env_shared,
+        (Any, Any, Symbol?),
+        "Tests whether two environments, or a symbol's bindings in them, are the same"
+    ),
+    builtin!(
+        "values",
+        crate::values::values,
+        (Any...),
+        "Returns multiple values to call-with-values"
+    ),
+    builtin!(
+        "call-with-values",
+        crate::values::call_with_values,
+        (Procedure, Procedure),
+        "Calls a consumer with the values returned by a producer"
+    ),
+    builtin!(
+        "interpreter-version",
+        interpreter_version,
+        (),
+        "Returns the interpreter version string"
+    ),
+    builtin!(
+        "feature?",
+        is_feature,
+        (Any),
+        "Tests whether the interpreter was built with a feature"
+    ),
+    builtin!(
+        "available-builtins",
+        available_builtins,
+        (),
+        "Lists the names of all builtin functions"
+    ),
+    builtin!(
+        "make-parameter",
+        crate::parameter::make_parameter,
+        (Any, Procedure?),
+        "Makes a parameter whose value parameterize can rebind"
+    ),
+    builtin!(
+        "make-generator",
+        crate::generator::make_generator,
+        (Procedure),
+        "Makes a generator from a function that receives a yield procedure"
+    ),
+    builtin!(
+        "generator-done?",
+        crate::generator::is_generator_done,
+        (Any),
+        "Tests whether a generator has no more values"
+    ),
+    builtin!(
+        "module-reload",
+        crate::module::module_reload,
+        (Symbol),
+        "Reevaluates a module's file and rebinds the module to the new definitions"
+    ),
+    builtin!("load", crate::config::load, (String), "Evaluates a file"),
+    builtin!(
+        "force",
+        force,
+        (Any),
+        "Returns the value of a promise, evaluating it the first time"
+    ),
+    builtin!(
+        "promise?",
+        is_promise,
+        (Any),
+        "Tests whether a value is a promise"
+    ),
+    builtin!(
+        "call/cc",
+        crate::continuation::call_cc,
+        (Procedure),
+        "Calls a function with the current continuation"
+    ),
+    builtin!(
+        "call-with-current-continuation",
+        crate::continuation::call_with_current_continuation,
+        (Procedure),
+        "Calls a function with the current continuation"
+    ),
+    builtin!(
+        "test",
+        crate::test_runner::test,
+        (String, Procedure),
+        "Registers a named test thunk for mr-lisp test, or runs it at once outside the runner"
+    ),
+    builtin!(
+        "assert",
+        crate::test_runner::assert,
+        (Boolean, String?),
+        "Raises an error with an optional message unless the condition is true"
+    ),
+    builtin!(
+        "assert-equal",
+        crate::test_runner::assert_equal,
+        (Any, Any),
+        "Raises an error unless the expected and actual values are equal"
+    ),
+    builtin!(
+        "assert-type",
+        crate::test_runner::assert_type,
+        (Any, Symbol),
+        "Returns a value if it has the named type, like a lambda parameter annotation"
+    ),
+    builtin!(
+        "raise",
+        crate::exception::raise,
+        (Any),
+        "Raises a value as an exception"
+    ),
+    builtin!(
+        "error",
+        crate::exception::error,
+        (String, Any...),
+        "Raises an error object with a message and irritants"
+    ),
+    builtin!(
+        "error-message",
+        crate::exception::error_message,
+        (Any),
+        "Returns the message of an error object"
+    ),
+    builtin!(
+        "error-object?",
+        crate::exception::is_error_object,
+        (Any),
+        "Tests whether a value is an error object"
+    ),
+    builtin!(
+        "parallel-map/process",
+        crate::parallel::parallel_map,
+        (Procedure, Any, Any...),
+        "Maps a function over a list in worker processes"
+    ),
+    builtin!(
+        "after",
+        crate::timer::after,
+        (Integer, Procedure),
+        "Calls a function once after a delay in milliseconds"
+    ),
+    builtin!(
+        "every",
+        crate::timer::every,
+        (Integer, Procedure),
+        "Calls a function repeatedly at an interval in milliseconds"
+    ),
+    builtin!(
+        "cancel-timer",
+        crate::timer::cancel_timer,
+        (Integer),
+        "Cancels a timer made by after or every"
+    ),
+    builtin!(
+        "run-event-loop",
+        crate::timer::run_event_loop,
+        (Any...),
+        "Runs pending timers until none remain or #:timeout passes"
+    ),
+    #[cfg(feature = "http")]
+    builtin!(
+        "serve",
+        crate::http::serve,
+        (Integer, Procedure, Any...),
+        "Serves HTTP requests on a port with a handler function"
+    ),
+    #[cfg(feature = "websocket")]
+    builtin!(
+        "ws-connect",
+        crate::websocket::ws_connect,
+        (String, Any...),
+        "Opens a WebSocket connection to a URL"
+    ),
+    #[cfg(feature = "websocket")]
+    builtin!(
+        "ws-send!",
+        crate::websocket::ws_send,
+        (Any, String),
+        "Sends a text message on a WebSocket"
+    ),
+    #[cfg(feature = "websocket")]
+    builtin!(
+        "ws-recv!",
+        crate::websocket::ws_recv,
+        (Any, Any...),
+        "Receives the next message from a WebSocket"
+    ),
+    #[cfg(feature = "websocket")]
+    builtin!(
+        "ws-close!",
+        crate::websocket::ws_close,
+        (Any),
+        "Closes a WebSocket"
+    ),
+    #[cfg(all(unix, feature = "signals"))]
+    builtin!(
+        "on-signal",
+        crate::signal::on_signal,
+        (Any, Procedure),
+        "Calls a function when a Unix signal arrives"
+    ),
+    #[cfg(feature = "osc")]
+    builtin!(
+        "osc-send",
+        crate::osc::osc_send,
+        (String, String, Any...),
+        "Sends an OSC message over UDP"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "canvas",
+        crate::graphics::canvas,
+        (Integer, Integer, Any...),
+        "Makes the drawing canvas for turtle graphics"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "line",
+        crate::graphics::line,
+        (Number, Number, Number, Number, Any...),
+        "Draws a line between two points on the canvas"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "forward",
+        crate::graphics::forward,
+        (Number),
+        "Moves the turtle forward, drawing if the pen is down"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "right",
+        crate::graphics::right,
+        (Number),
+        "Turns the turtle clockwise by degrees"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "left",
+        crate::graphics::left,
+        (Number),
+        "Turns the turtle counterclockwise by degrees"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "pen-up",
+        crate::graphics::pen_up,
+        (),
+        "Lifts the turtle's pen so moving does not draw"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "pen-down",
+        crate::graphics::pen_down,
+        (),
+        "Lowers the turtle's pen so moving draws"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "pen-color",
+        crate::graphics::pen_color,
+        (Any),
+        "Sets the turtle's pen color"
+    ),
+    #[cfg(feature = "graphics")]
+    builtin!(
+        "save-png",
+        crate::graphics::save_png,
+        (String),
+        "Saves the canvas as a PNG file"
+    ),
+];
+
+type KeywordArgs<'a> = Vec<(&'a str, &'a Object)>;
+
+// 位置引数と `#:name value` 形式のキーワード引数を分ける。
+pub(crate) fn split_keyword_args<'a>(
+    name: &str,
+    args: &'a [Object],
+) -> Result<(Vec<&'a Object>, KeywordArgs<'a>), String> {
+    let mut positional = Vec::new();
+    let mut keywords = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg {
+            Object::KeywordArg(kw) => match iter.next() {
+                Some(value) => keywords.push((kw.as_ref(), value)),
+                None => return Err(format!("{}: missing value for #:{}", name, kw)),
+            },
+            _ => positional.push(arg),
+        }
+    }
+    Ok((positional, keywords))
+}
+
+pub(crate) fn expect_usize(name: &str, obj: &Object) -> Result<usize, String> {
+    match obj {
+        Object::Integer(n) if *n >= 0 => Ok(*n as usize),
+        _ => Err(format!(
+            "{}: expected a non-negative integer, got {}",
+            name, obj
+        )),
+    }
+}
+
+fn format_fixed(name: &str, obj: &Object, precision: usize) -> Result<String, String> {
+    match obj {
+        Object::Integer(n) => Ok(format!("{:.*}", precision, *n as f64)),
+        // f64 を通すと桁が落ちるので、整数の後に 0 を並べる
+        Object::BigInt(n) if precision == 0 => Ok(n.to_string()),
+        Object::BigInt(n) => Ok(format!("{}.{}", n, "0".repeat(precision))),
+        Object::Float(f) => Ok(format!("{:.*}", precision, f)),
+        _ => Err(format!("{}: expected a number, got {}", name, obj)),
+    }
+}
+
+// (number->string n #:precision 2)。(number->string 255 16) => "ff" のように整数は基数も指定できる
+fn number_to_string(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("number->string", args)?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(format!(
+            "number->string: expected 1 or 2 arguments, got {}",
+            positional.len()
+        ));
+    }
+    let mut precision = None;
+    for (kw, value) in keywords {
+        match kw {
+            "precision" => precision = Some(expect_usize("number->string", value)?),
+            _ => return Err(format!("number->string: unknown keyword #:{}", kw)),
+        }
+    }
+
+    let n = positional[0];
+    if let Some(radix) = positional.get(1) {
+        let radix = match radix {
+            Object::Integer(r @ 2..=36) => *r as u32,
+            _ => {
+                return Err(format!(
+                    "number->string: expected a radix from 2 to 36, got {}",
+                    radix
+                ));
+            }
+        };
+        let s = match (n, precision) {
+            (Object::Integer(i), None) => BigInt::from(*i).to_string_radix(radix),
+            (Object::BigInt(b), None) => b.to_string_radix(radix),
+            (Object::Float(_), None) if radix == 10 => n.to_string(),
+            (Object::Integer(_) | Object::BigInt(_) | Object::Float(_), _) => {
+                return Err(format!(
+                    "number->string: radix {} is only for integers without #:precision",
+                    radix
+                ));
+            }
+            _ => return Err(format!("number->string: expected a number, got {}", n)),
+        };
+        return Ok(Object::String(s.into()));
+    }
+    let s = match (n, precision) {
+        (Object::Integer(_) | Object::BigInt(_) | Object::Float(_), None) => n.to_string(),
+        (_, Some(p)) => format_fixed("number->string", n, p)?,
+        _ => return Err(format!("number->string: expected a number, got {}", n)),
+    };
+    Ok(Object::String(s.into()))
+}
+
+// (format-number 1234567.891 #:group "," #:decimal "." #:precision 2) => "1,234,567.89"
+// 整数部を 3 桁ずつ group で区切り、小数点を decimal にする。ロケールは見ない。
+// precision を省くと、整数はそのまま、小数は number->string と同じ桁数で書く。
+fn format_number(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("format-number", args)?;
+    if positional.len() != 1 {
+        return Err(format!(
+            "format-number: expected 1 argument, got {}",
+            positional.len()
+        ));
+    }
+    let (mut group, mut decimal, mut precision) = (",", ".", None);
+    for (kw, value) in keywords {
+        match kw {
+            "group" => group = expect_string("format-number", value)?,
+            "decimal" => decimal = expect_string("format-number", value)?,
+            "precision" => precision = Some(expect_usize("format-number", value)?),
+            _ => return Err(format!("format-number: unknown keyword #:{}", kw)),
+        }
+    }
+
+    let digits = match (positional[0], precision) {
+        (Object::Float(f), _) if !f.is_finite() => {
+            return Err(format!(
+                "format-number: expected a finite number, got {}",
+                f
+            ));
+        }
+        (Object::Float(f), None) => f.to_string(),
+        (Object::Float(f), Some(p)) => format!("{:.*}", p, f),
+        // 整数は f64 を通さずに書くので、桁が落ちない
+        (Object::Integer(_) | Object::BigInt(_), Some(p)) if p > 0 => {
+            format!("{}.{}", positional[0], "0".repeat(p))
+        }
+        (n, _) => n.to_string(),
+    };
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", digits.as_str()),
+    };
+    let (int, frac) = match digits.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (digits, None),
+    };
+    let mut formatted = sign.to_string();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            formatted.push_str(group);
+        }
+        formatted.push(c);
+    }
+    if let Some(frac) = frac {
+        formatted.push_str(decimal);
+        formatted.push_str(frac);
+    }
+    Ok(Object::String(formatted.into()))
+}
+
+// (string->number "1.5") => 1.5。数として読めなければ #f を返す。
+// Rust の f64 のパースは正しく丸められ、ロケールにも依存しないのでそのまま使う。
+fn string_to_number(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let number = match expect_string("string->number", &args[0])? {
+        "+inf.0" => Object::Float(f64::INFINITY),
+        "-inf.0" => Object::Float(f64::NEG_INFINITY),
+        "+nan.0" | "-nan.0" => Object::Float(f64::NAN),
+        // "inf" や "NaN" などの Rust 独自の綴りは受け付けない
+        s if !s.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)) => {
+            return Ok(Object::truth(false));
+        }
+        // i64 に収まらない整数は BigInt にする
+        s => match (s.parse::<i64>(), s.parse::<BigInt>(), s.parse::<f64>()) {
+            (Ok(n), _, _) => Object::Integer(n),
+            (_, Ok(n), _) => Object::BigInt(Rc::new(n)),
+            (_, _, Ok(f)) => Object::Float(f),
+            _ => Object::truth(false),
+        },
+    };
+    Ok(number)
+}
+
+// char 型が無かった頃のプログラムのために、1 文字の文字列も文字として受け付ける。
+fn expect_char(name: &str, obj: &Object) -> Result<char, String> {
+    match obj {
+        Object::Char(c) => Ok(*c),
+        Object::String(s) if s.chars().count() == 1 => Ok(s.chars().next().unwrap()),
+        _ => Err(format!(
+            "{}: expected a single character, got {}",
+            name, obj
+        )),
+    }
+}
+
+// (string-pad-left "7" 3 "0") => "007"
+// SRFI-13 と同じく、width より長い場合は左側を切り詰める。
+fn string_pad_left(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_string("string-pad-left", &args[0])?;
+    let width = expect_usize("string-pad-left", &args[1])?;
+    let pad = match args.get(2) {
+        Some(obj) => expect_char("string-pad-left", obj)?,
+        None => ' ',
+    };
+
+    let len = s.chars().count();
+    let padded: String = if len >= width {
+        s.chars().skip(len - width).collect()
+    } else {
+        std::iter::repeat_n(pad, width - len)
+            .chain(s.chars())
+            .collect()
+    };
+    Ok(Object::String(padded.into()))
+}
+
+fn pad(s: String, width: usize, left: bool) -> String {
+    if left {
+        format!("{:>width$}", s)
+    } else {
+        format!("{:<width$}", s)
+    }
+}
+
+// Common Lisp 風の書式指定。
+//   ~a     値を表示する。~10a で右側を空白で埋めて幅 10 に、~10@a で左側を埋める
+//   ~d     整数。~5d で幅 5 に右寄せ
+//   ~f     小数。~8,2f で幅 8、小数点以下 2 桁に右寄せ
+//   ~%     改行
+//   ~~     ~ そのもの
+fn format(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let template = expect_string("format", &args[0])?;
+    let mut values = args[1..].iter();
+    let mut next_value = |directive: char| {
+        values
+            .next()
+            .ok_or_else(|| format!("format: missing argument for ~{}", directive))
+    };
+
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            out.push(c);
+            continue;
+        }
+
+        let mut params = vec![String::new()];
+        let mut at_sign = false;
+        let directive = loop {
+            match chars.next() {
+                Some(d) if d.is_ascii_digit() => params.last_mut().unwrap().push(d),
+                Some(',') => params.push(String::new()),
+                Some('@') => at_sign = true,
+                Some(d) => break d,
+                None => return Err("format: unterminated directive".to_string()),
+            }
+        };
+        let param = |i: usize| params.get(i).and_then(|p| p.parse::<usize>().ok());
+        let width = param(0).unwrap_or(0);
+
+        match directive.to_ascii_lowercase() {
+            'a' => out.push_str(&pad(next_value('a')?.to_string(), width, at_sign)),
+            'd' => match next_value('d')? {
+                Object::Integer(n) => out.push_str(&pad(n.to_string(), width, true)),
+                obj => return Err(format!("format: ~d expects an integer, got {}", obj)),
+            },
+            'f' => {
+                let value = next_value('f')?;
+                let s = match param(1) {
+                    Some(precision) => format_fixed("format", value, precision)?,
+                    None => match value {
+                        Object::Integer(_) | Object::Float(_) => value.to_string(),
+                        obj => return Err(format!("format: ~f expects a number, got {}", obj)),
+                    },
+                };
+                out.push_str(&pad(s, width, true));
+            }
+            '%' => out.push('\n'),
+            '~' => out.push('~'),
+            d => return Err(format!("format: unknown directive ~{}", d)),
+        }
+    }
+    Ok(Object::String(out.into()))
+}
+
+// unicode feature を外すと ASCII の範囲だけで判定し、Unicode のテーブルをリンクしない。
+#[cfg(feature = "unicode")]
+mod text {
+    pub fn is_alphabetic(c: char) -> bool {
+        c.is_alphabetic()
+    }
+
+    pub fn is_numeric(c: char) -> bool {
+        c.is_numeric()
+    }
+
+    pub fn is_whitespace(c: char) -> bool {
+        c.is_whitespace()
+    }
+
+    // 大文字にすると複数の文字になる文字 (ß など) はそのまま返す。
+    pub fn upcase(c: char) -> char {
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(u), None) => u,
+            _ => c,
+        }
+    }
+
+    // std には case folding が無いので小文字化をベースにし、
+    // 小文字化と folding で結果が異なる代表的な文字だけ補正する。
+    pub fn foldcase(s: &str) -> String {
+        if s.is_ascii() {
+            return s.to_ascii_lowercase();
+        }
+        let mut folded = String::with_capacity(s.len());
+        for c in s.chars() {
+            match c {
+                'ß' | 'ẞ' => folded.push_str("ss"),
+                'ς' | 'Σ' => folded.push('σ'),
+                c => folded.extend(c.to_lowercase()),
+            }
+        }
+        folded
+    }
+}
+
+#[cfg(not(feature = "unicode"))]
+mod text {
+    pub fn is_alphabetic(c: char) -> bool {
+        c.is_ascii_alphabetic()
+    }
+
+    pub fn is_numeric(c: char) -> bool {
+        c.is_ascii_digit()
+    }
+
+    pub fn is_whitespace(c: char) -> bool {
+        c.is_ascii_whitespace()
+    }
+
+    pub fn upcase(c: char) -> char {
+        c.to_ascii_uppercase()
+    }
+
+    pub fn foldcase(s: &str) -> String {
+        s.to_ascii_lowercase()
+    }
+}
+
+fn string_foldcase(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_string("string-foldcase", &args[0])?;
+    Ok(Object::String(text::foldcase(s).into()))
+}
+
+// 文字であることは引数の型で確かめてある。
+fn as_char(obj: &Object) -> char {
+    match obj {
+        Object::Char(c) => *c,
+        _ => unreachable!("checked by the signature"),
+    }
+}
+
+fn char_predicate(args: &[Object], pred: fn(char) -> bool) -> Result<Object, String> {
+    Ok(Object::truth(pred(as_char(&args[0]))))
+}
+
+fn char_alphabetic(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    char_predicate(args, text::is_alphabetic)
+}
+
+fn char_numeric(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    char_predicate(args, text::is_numeric)
+}
+
+fn char_whitespace(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    char_predicate(args, text::is_whitespace)
+}
+
+fn char_to_integer(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::Integer(as_char(&args[0]) as i64))
+}
+
+// サロゲートの範囲など、Unicode scalar value でない値はエラーにする。
+fn integer_to_char(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let code = match &args[0] {
+        Object::Integer(n) => u32::try_from(*n).ok(),
+        _ => None,
+    };
+    code.and_then(char::from_u32)
+        .map(Object::Char)
+        .ok_or_else(|| format!("integer->char: {} is not a valid character code", args[0]))
+}
+
+fn char_upcase(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::Char(text::upcase(as_char(&args[0]))))
+}
+
+// (char=? a b c ...) はすべての文字が等しいか。
+fn char_eq(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::truth(
+        args.windows(2).all(|w| as_char(&w[0]) == as_char(&w[1])),
+    ))
+}
+
+// 文字列の添字は Unicode scalar value (Rust の char) 単位で数える。
+// 書記素クラスタ単位ではないので、"👍🏽" の長さは 2 になる。
+// scalar 単位の操作は先頭から数える O(n) だが、ASCII だけの文字列ならバイト位置と一致するので数えずに済ませる。
+// 大きな文字列を何度も切り出す場合は O(1) のバイト単位の操作 (string-byte-length, substring/bytes) を使う。
+// substring と substring/bytes は中身をコピーせず、元の文字列のバッファを共有する (string.rs)。
+
+pub(crate) fn expect_string<'a>(name: &str, obj: &'a Object) -> Result<&'a str, String> {
+    expect_str(name, obj).map(Str::as_str)
+}
+
+// 切り出した結果を元の文字列とバッファを共有する Str にしたい場合はこちらを使う。
+pub(crate) fn expect_str<'a>(name: &str, obj: &'a Object) -> Result<&'a Str, String> {
+    match obj {
+        Object::String(s) => Ok(s),
+        _ => Err(format!("{}: expected a string, got {}", name, obj)),
+    }
+}
+
+// scalar 単位の添字をバイト位置に変換する。末尾 (長さと同じ添字) も受け付ける。
+fn byte_offset(name: &str, s: &str, index: usize) -> Result<usize, String> {
+    if s.is_ascii() {
+        if index <= s.len() {
+            return Ok(index);
+        }
+    } else if let Some(offset) = s
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+        .nth(index)
+    {
+        return Ok(offset);
+    }
+    Err(format!(
+        "{}: index {} out of range for {:?}",
+        name, index, s
+    ))
+}
+
+fn string_length(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_string("string-length", &args[0])?;
+    Ok(Object::Integer(s.chars().count() as i64))
+}
+
+// (string-ref "日本語" 1) => #\本
+fn string_ref(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_string("string-ref", &args[0])?;
+    let index = expect_usize("string-ref", &args[1])?;
+    match s.chars().nth(index) {
+        Some(c) => Ok(Object::Char(c)),
+        None => Err(format!(
+            "string-ref: index {} out of range for {:?}",
+            index, s
+        )),
+    }
+}
+
+// (string->list "abc") => (#\a #\b #\c)
+fn string_to_list(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_string("string->list", &args[0])?;
+    Ok(crate::pair::pair_list(
+        s.chars().map(Object::Char).collect(),
+        Object::nil(),
+    ))
+}
+
+// (list->string (list #\a #\b)) => "ab"。string->list の逆。
+fn list_to_string(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let list = &args[0];
+    let items =
+        list_items(list).ok_or_else(|| format!("list->string: expected a list, got {}", list))?;
+    let s = items
+        .iter()
+        .map(|obj| expect_char("list->string", obj))
+        .collect::<Result<String, String>>()?;
+    Ok(Object::String(s.into()))
+}
+
+// (substring "日本語です" 1 3) => "本語"。end を省略すると末尾まで。
+fn substring(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_str("substring", &args[0])?;
+    let start = byte_offset("substring", s, expect_usize("substring", &args[1])?)?;
+    let end = match args.get(2) {
+        Some(end) => byte_offset("substring", s, expect_usize("substring", end)?)?,
+        None => s.len(),
+    };
+    if start > end {
+        return Err(format!("substring: start is after end in {:?}", s));
+    }
+    Ok(Object::String(s.slice(start..end).unwrap()))
+}
+
+fn string_byte_length(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_string("string-byte-length", &args[0])?;
+    Ok(Object::Integer(s.len() as i64))
+}
+
+// (substring/bytes "日本語" 3 6) => "本"。UTF-8 の文字境界でない位置はエラー。
+fn substring_bytes(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let s = expect_str("substring/bytes", &args[0])?;
+    let start = expect_usize("substring/bytes", &args[1])?;
+    let end = match args.get(2) {
+        Some(end) => expect_usize("substring/bytes", end)?,
+        None => s.len(),
+    };
+    match s.slice(start..end) {
+        Some(sub) => Ok(Object::String(sub)),
+        None => Err(format!(
+            "substring/bytes: {}..{} is not a valid byte range for {:?}",
+            start, end, s
+        )),
+    }
+}
+
+// (string-split "a,b,,c" ",") => ("a" "b" "" "c")
+// 区切りには文字列か (regex "...") を渡す。#:limit n で最初の n 個の区切りだけで分け、残りは最後の要素にする。
+// 正規表現の空の一致では分けない。分けた要素は元の文字列とバッファを共有する。
+fn string_split(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("string-split", args)?;
+    let [s, separator] = positional[..] else {
+        return Err(format!(
+            "string-split: expected 2 arguments, got {}",
+            positional.len()
+        ));
+    };
+    let mut limit = usize::MAX;
+    for (kw, value) in keywords {
+        match kw {
+            "limit" => limit = expect_usize("string-split", value)?,
+            _ => return Err(format!("string-split: unknown keyword #:{}", kw)),
+        }
+    }
+    let s = expect_str("string-split", s)?;
+
+    let mut pieces = Vec::new();
+    if let Some(regex) = crate::regex::as_regex(separator) {
+        let (mut start, mut from) = (0, 0);
+        while pieces.len() < limit {
+            let Some(found) = regex.find_at(s, from) else {
+                break;
+            };
+            if found.is_empty() {
+                match s[found.end..].chars().next() {
+                    Some(c) => from = found.end + c.len_utf8(),
+                    None => break,
+                }
+                continue;
+            }
+            pieces.push(Object::String(s.slice(start..found.start).unwrap()));
+            (start, from) = (found.end, found.end);
+        }
+        pieces.push(Object::String(s.slice(start..s.len()).unwrap()));
+    } else {
+        let separator = match separator {
+            Object::String(separator) if !separator.is_empty() => separator,
+            Object::String(_) => return Err("string-split: separator is empty".to_string()),
+            _ => {
+                return Err(format!(
+                    "string-split: expected a string or regex separator, got {}",
+                    separator
+                ));
+            }
+        };
+        pieces.extend(
+            s.splitn(limit.saturating_add(1), separator.as_str())
+                .map(|piece| Object::String(s.slice_ref(piece))),
+        );
+    }
+    Ok(Object::ListData(Rc::new(pieces)))
+}
+
+// (string-join '("a" "b" "c") ", ") => "a, b, c"
+fn string_join(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let list = &args[0];
+    let items =
+        list_items(list).ok_or_else(|| format!("string-join: expected a list, got {}", list))?;
+    let separator = expect_string("string-join", &args[1])?;
+    let mut joined = String::new();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            joined.push_str(separator);
+        }
+        joined.push_str(expect_string("string-join", item)?);
+    }
+    Ok(Object::String(joined.into()))
+}
+
+// 束縛は `#:name value` のキーワード引数か、`(name value)` の組のリストで渡す。
+fn bindings(name: &str, args: &[Object]) -> Result<Vec<(String, Object)>, String> {
+    if let [list] = args
+        && let Some(pairs) = list_items(list)
+    {
+        let mut bindings = Vec::new();
+        for pair in pairs {
+            match list_items(&pair).as_deref() {
+                Some([key, value]) => match key {
+                    Object::Symbol(k) => bindings.push((k.to_string(), value.clone())),
+                    Object::String(k) => bindings.push((k.to_string(), value.clone())),
+                    key => return Err(format!("{}: invalid key {}", name, key)),
+                },
+                _ => return Err(format!("{}: invalid binding {}", name, pair)),
+            }
+        }
+        return Ok(bindings);
+    }
+
+    let (positional, keywords) = split_keyword_args(name, args)?;
+    if let Some(arg) = positional.first() {
+        return Err(format!("{}: unexpected argument {}", name, arg));
+    }
+    Ok(keywords
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect())
+}
+
+// (template "Hello, {name}! You have {count} items" #:name "Bob" #:count 3)
+// {{ と }} はそれぞれ { と } そのものになる。
+fn template(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let source = expect_string("template", &args[0])?;
+    let bindings = bindings("template", &args[1..])?;
+
+    let mut out = String::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(format!("template: unclosed {{{}", name)),
+                    }
+                }
+                let name = name.trim();
+                match bindings.iter().find(|(k, _)| k == name) {
+                    Some((_, value)) => out.push_str(&value.to_string()),
+                    None => return Err(format!("template: no binding for {{{}}}", name)),
+                }
+            }
+            '}' => return Err("template: unmatched } (write }} for a literal brace)".to_string()),
+            c => out.push(c),
+        }
+    }
+    Ok(Object::String(out.into()))
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 閉じタグを持たない要素
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+// ((href "/") (class "x")) のような、シンボルと値の組のリストなら、組を返す。
+fn attribute_list(obj: &Object) -> Option<Vec<(Object, Object)>> {
+    list_items(obj)?
+        .iter()
+        .map(|attr| match list_items(attr).as_deref() {
+            Some([key @ Object::Symbol(_), value]) => Some((key.clone(), value.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn write_html(node: &Object, out: &mut String) -> Result<(), String> {
+    let elements = match node {
+        Object::String(s) => {
+            out.push_str(&escape_html(s));
+            return Ok(());
+        }
+        obj => match list_items(obj) {
+            Some(elements) => elements,
+            None => {
+                out.push_str(&escape_html(&obj.to_string()));
+                return Ok(());
+            }
+        },
+    };
+    let tag = match elements.first() {
+        Some(Object::Symbol(tag)) => tag,
+        _ => return Err(format!("html->string: expected a tag name in {}", node)),
+    };
+
+    out.push('<');
+    out.push_str(tag);
+    let mut children = &elements[1..];
+    if let Some(attrs) = children.first().and_then(attribute_list) {
+        for (key, value) in attrs {
+            match value {
+                Object::Bool(false) => {}
+                Object::Bool(true) => out.push_str(&format!(" {}", key)),
+                value => out.push_str(&format!(" {}=\"{}\"", key, escape_html(&value.to_string()))),
+            }
+        }
+        children = &children[1..];
+    }
+    out.push('>');
+
+    if VOID_ELEMENTS.contains(&tag.as_ref()) {
+        if !children.is_empty() {
+            return Err(format!("html->string: <{}> cannot have children", tag));
+        }
+        return Ok(());
+    }
+    for child in children {
+        write_html(child, out)?;
+    }
+    out.push_str(&format!("</{}>", tag));
+    Ok(())
+}
+
+// hiccup 風のリストを HTML に変換する。
+// (html->string '(div ((class "x")) (p "hi"))) => "<div class=\"x\"><p>hi</p></div>"
+// タグ名の次の要素が (name value) の組のリストなら属性として扱う。
+// 文字列や属性値はエスケープされる。#t の属性は名前だけ出力し、#f の属性は出力しない。
+fn html_to_string(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut out = String::new();
+    write_html(&args[0], &mut out)?;
+    Ok(Object::String(out.into()))
+}
+
+// (not x) は x が #f のときだけ true。and や or と同じく、#f 以外の値はすべて真とみなす。
+fn not(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::truth(matches!(args[0], Object::Bool(false))))
+}
+
+// (force promise) は delay した式の値。Promise でない値はそのまま返す。
+fn force(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    match &args[0] {
+        Object::Promise(promise) => crate::eval::force(promise),
+        value => Ok(value.clone()),
+    }
+}
+
+fn is_promise(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::truth(matches!(args[0], Object::Promise(_))))
+}
+
+// (eval '(+ 1 2)) は quote したデータを式として、呼び出した場所の環境で評価する。
+// (eval expr '((x 1))) や (eval expr #:x 1) のように束縛を渡すと、それを加えた環境で評価する。
+// (eval expr env) のように make-environment などで作った環境を渡すと、その環境で評価する。
+fn eval(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let code = to_code(&args[0]);
+    let result = if args.len() == 1 {
+        eval_toplevel(&code, env)
+    } else if let [_, target] = args
+        && let Some(target) = crate::environment::as_environment(target)
+    {
+        eval_toplevel(&code, &mut Rc::clone(target))
+    } else {
+        let mut scope = Env::extend(env.clone());
+        for (name, value) in bindings("eval", &args[1..])? {
+            scope.set(&name, value);
+        }
+        eval_toplevel(&code, &mut Rc::new(RefCell::new(scope)))
+    };
+    result.map_err(|e| format!("eval: {}", e))
+}
+
+// 組み込み関数や機能はビルド時の feature で変わるので、スクリプトが実行中に確かめられるようにする。
+fn interpreter_version(_args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::String(env!("CARGO_PKG_VERSION").into()))
+}
+
+// (feature? 'net) は manifest::FEATURES に名前があれば true を返す。
+fn is_feature(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let name = match &args[0] {
+        Object::Symbol(name) => name.as_ref(),
+        Object::String(name) => name.as_ref(),
+        other => {
+            return Err(format!(
+                "feature?: expected a symbol or string, got {}",
+                other
+            ));
+        }
+    };
+    Ok(Object::truth(crate::manifest::FEATURES.contains(&name)))
+}
+
+// (available-builtins) は組み込み関数の名前を、名前順のシンボルのリストで返す。
+fn available_builtins(_args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut names: Vec<&str> = BUILTINS.iter().map(|builtin| builtin.name).collect();
+    names.sort_unstable();
+    Ok(Object::ListData(Rc::new(
+        names
+            .into_iter()
+            .map(|name| Object::Symbol(name.into()))
+            .collect(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{Env, eval};
+    use crate::parser::Object;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn eval_str(program: &str) -> Result<Object, String> {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        eval(program, &mut env)
+    }
+
+    #[test]
+    fn test_builtin_signatures() {
+        fn max(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+            Ok(args
+                .iter()
+                .max_by_key(|arg| arg.to_string())
+                .unwrap()
+                .clone())
+        }
+        static MAX: super::Builtin =
+            builtin!("max", max, (Number, Number...), "Returns the largest number");
+        assert_eq!(MAX.usage().as_deref(), Some("(max number number...)"));
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert_eq!(
+            MAX.call(&[Object::Integer(1), Object::Integer(3)], &mut env),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            MAX.call(&[], &mut env),
+            Err("max: expected at least 1 argument, got 0".to_string())
+        );
+        assert_eq!(
+            MAX.call(&[Object::Integer(1), Object::truth(true)], &mut env),
+            Err("max: expected a number, got true".to_string())
+        );
+
+        assert_eq!(
+            eval_str("(string-pad-left \"ab\")"),
+            Err("string-pad-left: expected 2 or 3 arguments, got 1".to_string())
+        );
+        assert_eq!(
+            eval_str("(string-foldcase 1)"),
+            Err("string-foldcase: expected a string, got 1".to_string())
+        );
+        assert_eq!(
+            eval_str("(doc string-pad-left)").unwrap().to_string(),
+            "(string-pad-left string integer [any])\nPads a string on the left to a width, truncating longer strings"
+        );
+        assert_eq!(
+            eval_str("(string-ref \"abc\" \"1\")"),
+            Err("string-ref: expected an integer, got 1".to_string())
+        );
+        assert_eq!(
+            eval_str("(substring \"abc\" 0 1.5)"),
+            Err("substring: expected an integer, got 1.5".to_string())
+        );
+        assert_eq!(
+            eval_str("(car)"),
+            Err("car: expected 1 argument, got 0".to_string())
+        );
+        assert_eq!(
+            eval_str("(make-environment 1 2)"),
+            Err("make-environment: expected 0 or 1 argument, got 2".to_string())
+        );
+        assert_eq!(
+            eval_str("(doc char=?)").unwrap().to_string(),
+            "(char=? char char char...)\nTests whether all characters are equal"
+        );
+        assert_eq!(
+            eval_str("(doc car)").unwrap().to_string(),
+            "(car any)\nReturns the first element of a pair or list"
+        );
+    }
+
+    fn string(s: &str) -> Object {
+        Object::String(s.into())
+    }
+
+    #[test]
+    fn test_number_to_string() {
+        assert_eq!(eval_str("(number->string 42)").unwrap(), string("42"));
+        assert_eq!(
+            eval_str("(number->string 3.14159 #:precision 2)").unwrap(),
+            string("3.14")
+        );
+        assert_eq!(
+            eval_str("(number->string 7 #:precision 1)").unwrap(),
+            string("7.0")
+        );
+        assert_eq!(
+            eval_str("(number->string 255 2)").unwrap(),
+            string("11111111")
+        );
+        assert_eq!(
+            eval_str("(number->string 1.5 16)"),
+            Err("number->string: radix 16 is only for integers without #:precision".to_string())
+        );
+        assert!(eval_str("(number->string 1 37)").is_err());
+        assert!(eval_str("(number->string \"x\")").is_err());
+        assert!(eval_str("(number->string 1 #:width 2)").is_err());
+        assert_eq!(
+            eval_str("(number->string (* 10000000000 10000000000))"),
+            Ok(string("100000000000000000000"))
+        );
+        assert_eq!(
+            eval_str("(number->string (- 0 (* 10000000000 10000000000)) #:precision 2)"),
+            Ok(string("-100000000000000000000.00"))
+        );
+        assert_eq!(
+            eval_str("(number->string (* 10000000000 10000000000) #:precision 0)"),
+            Ok(string("100000000000000000000"))
+        );
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(
+            eval_str("(format-number 1234567.891 #:group \",\" #:decimal \".\" #:precision 2)"),
+            Ok(string("1,234,567.89"))
+        );
+        assert_eq!(
+            eval_str("(format-number -1234567.5 #:group \".\" #:decimal \",\")"),
+            Ok(string("-1.234.567,5"))
+        );
+        assert_eq!(eval_str("(format-number 999)"), Ok(string("999")));
+        assert_eq!(
+            eval_str("(format-number 1000 #:precision 2 #:group \"\")"),
+            Ok(string("1000.00"))
+        );
+        assert_eq!(
+            eval_str("(format-number (* 1000000000000 1000000000000))"),
+            Ok(string("1,000,000,000,000,000,000,000,000"))
+        );
+        assert_eq!(
+            eval_str("(format-number (string->number \"+inf.0\"))"),
+            Err("format-number: expected a finite number, got inf".to_string())
+        );
+        assert_eq!(
+            eval_str("(format-number \"1\")"),
+            Err("format-number: expected a number, got 1".to_string())
+        );
+        assert!(eval_str("(format-number 1 #:width 2)").is_err());
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        assert_eq!(
+            eval_str("(string->number \"42\")").unwrap(),
+            Object::Integer(42)
+        );
+        assert_eq!(
+            eval_str("(string->number \"-1.5\")").unwrap(),
+            Object::Float(-1.5)
+        );
+        assert_eq!(
+            eval_str("(string->number \"1e3\")").unwrap(),
+            Object::Float(1000.0)
+        );
+        assert_eq!(
+            eval_str("(string->number \"-inf.0\")").unwrap(),
+            Object::Float(f64::NEG_INFINITY)
+        );
+        assert_eq!(
+            eval_str("(string->number \"abc\")").unwrap(),
+            Object::truth(false)
+        );
+        assert_eq!(
+            eval_str("(string->number \"inf\")").unwrap(),
+            Object::truth(false)
+        );
+        assert_eq!(
+            eval_str("(string->number \"1,5\")").unwrap(),
+            Object::truth(false)
+        );
+        assert_eq!(
+            eval_str("(string->number \"99999999999999999999\")"),
+            eval_str("(+ (* 9999999999 10000000000) 9999999999)")
+        );
+        assert!(matches!(
+            eval_str("(string->number \"-99999999999999999999\")"),
+            Ok(Object::BigInt(_))
+        ));
+    }
+
+    #[test]
+    fn test_float_print_read_roundtrip() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        // xorshift で作ったランダムなビット列の f64 が、表示して読み戻すと元に戻ることを確かめる
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut floats = vec![
+            0.0,
+            -0.0,
+            0.1,
+            1.0 / 3.0,
+            1e16,
+            1e-7,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+        ];
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            floats.push(f64::from_bits(state));
+        }
+
+        for f in floats {
+            let printed = Object::Float(f).to_string();
+            let read = super::string_to_number(&[Object::String(printed.clone().into())], &mut env)
+                .unwrap();
+            match read {
+                Object::Float(g) if f.is_nan() => assert!(g.is_nan(), "{}", printed),
+                Object::Float(g) => {
+                    assert_eq!(g.to_bits(), f.to_bits(), "{}", printed);
+                }
+                obj => panic!("{} was read back as {:?}", printed, obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_pad_left() {
+        assert_eq!(
+            eval_str("(string-pad-left \"7\" 3 \"0\")").unwrap(),
+            string("007")
+        );
+        assert_eq!(
+            eval_str("(string-pad-left \"abc\" 5)").unwrap(),
+            string("  abc")
+        );
+        assert_eq!(
+            eval_str("(string-pad-left \"12345\" 3)").unwrap(),
+            string("345")
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            eval_str("(format \"~a: ~8,2f~%\" \"total\" 3.14159)").unwrap(),
+            string("total:     3.14\n")
+        );
+        assert_eq!(
+            eval_str("(format \"[~5a][~5@a][~3d]\" \"ab\" \"cd\" 7)").unwrap(),
+            string("[ab   ][   cd][  7]")
+        );
+        assert_eq!(eval_str("(format \"100~~\")").unwrap(), string("100~"));
+        assert!(eval_str("(format \"~a ~a\" 1)").is_err());
+        assert!(eval_str("(format \"~q\" 1)").is_err());
+    }
+
+    #[test]
+    fn test_char_predicates() {
+        assert_eq!(
+            eval_str("(char-alphabetic? #\\a)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char-alphabetic? #\\1)").unwrap(),
+            Object::truth(false)
+        );
+        assert_eq!(
+            eval_str("(char-numeric? #\\7)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char-whitespace? #\\space)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char-numeric? \"7\")"),
+            Err("char-numeric?: expected a char, got 7".to_string())
+        );
+    }
+
+    #[test]
+    fn test_chars() {
+        assert_eq!(eval_str("#\\a").unwrap(), Object::Char('a'));
+        assert_eq!(
+            eval_str("(list #\\space #\\newline #\\( (quote #\\x))").unwrap(),
+            Object::ListData(Rc::new(vec![
+                Object::Char(' '),
+                Object::Char('\n'),
+                Object::Char('('),
+                Object::Char('x'),
+            ]))
+        );
+        assert_eq!(
+            eval_str("(char->integer #\\A)").unwrap(),
+            Object::Integer(65)
+        );
+        assert_eq!(eval_str("(integer->char 955)").unwrap(), Object::Char('λ'));
+        assert_eq!(eval_str("(char-upcase #\\q)").unwrap(), Object::Char('Q'));
+        assert_eq!(eval_str("(char-upcase #\\1)").unwrap(), Object::Char('1'));
+        assert_eq!(
+            eval_str("(char=? #\\a (integer->char 97) #\\a)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char=? #\\a #\\A)").unwrap(),
+            Object::truth(false)
+        );
+        assert_eq!(
+            eval_str("(integer->char 55296)"),
+            Err("integer->char: 55296 is not a valid character code".to_string())
+        );
+        assert_eq!(
+            eval_str("(char->integer 1)"),
+            Err("char->integer: expected a char, got 1".to_string())
+        );
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_unicode_text() {
+        assert_eq!(
+            eval_str("(char-alphabetic? #\\é)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char-alphabetic? #\\面)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char-numeric? #\\٣)").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(char-whitespace? (integer->char 12288))").unwrap(),
+            Object::truth(true)
+        );
+        assert_eq!(
+            eval_str("(string-foldcase \"Straße ΣΟΦΟΣ\")").unwrap(),
+            string("strasse σοφοσ")
+        );
+    }
+
+    #[test]
+    fn test_string_foldcase_ascii() {
+        assert_eq!(
+            eval_str("(string-foldcase \"Hello World\")").unwrap(),
+            string("hello world")
+        );
+    }
+
+    #[test]
+    fn test_scalar_indexing() {
+        assert_eq!(
+            eval_str("(string-length \"hello\")").unwrap(),
+            Object::Integer(5)
+        );
+        assert_eq!(
+            eval_str("(string-length \"日本語\")").unwrap(),
+            Object::Integer(3)
+        );
+        // 肌の色の修飾子付きの絵文字は 2 つの scalar value からなる
+        assert_eq!(
+            eval_str("(string-length \"👍🏽\")").unwrap(),
+            Object::Integer(2)
+        );
+        assert_eq!(
+            eval_str("(string-ref \"日本語\" 1)").unwrap(),
+            Object::Char('本')
+        );
+        assert_eq!(
+            eval_str("(string-ref \"a😀b\" 2)").unwrap(),
+            Object::Char('b')
+        );
+        assert_eq!(
+            eval_str("(string->list \"a😀\")").unwrap(),
+            Object::ListData(Rc::new(vec![Object::Char('a'), Object::Char('😀')]))
+        );
+        assert_eq!(
+            eval_str(
+                "(list->string (cons (char-upcase (string-ref \"ab\" 0)) (cdr (string->list \"ab\"))))"
+            ),
+            Ok(string("Ab"))
+        );
+        assert_eq!(eval_str("(list->string '())"), Ok(string("")));
+        assert!(eval_str("(list->string '(1))").is_err());
+        assert_eq!(
+            eval_str("(substring \"日本語です\" 1 3)").unwrap(),
+            string("本語")
+        );
+        assert_eq!(
+            eval_str("(substring \"😀😃😄\" 1)").unwrap(),
+            string("😃😄")
+        );
+        assert_eq!(eval_str("(substring \"hello\" 1 3)").unwrap(), string("el"));
+        assert_eq!(eval_str("(substring \"日本\" 2 2)").unwrap(), string(""));
+        assert!(eval_str("(string-ref \"日本\" 2)").is_err());
+        assert!(eval_str("(substring \"日本\" 1 3)").is_err());
+        assert!(eval_str("(substring \"hello\" 3 1)").is_err());
+    }
+
+    #[test]
+    fn test_byte_indexing() {
+        assert_eq!(
+            eval_str("(string-byte-length \"日本語\")").unwrap(),
+            Object::Integer(9)
+        );
+        assert_eq!(
+            eval_str("(substring/bytes \"日本語\" 3 6)").unwrap(),
+            string("本")
+        );
+        assert!(eval_str("(substring/bytes \"日本語\" 1 3)").is_err());
+        assert!(eval_str("(substring/bytes \"abc\" 2 9)").is_err());
+    }
+
+    #[test]
+    fn test_substring_shares_buffer() {
+        // 切り出した文字列は元の文字列の中を指し、中身をコピーしない
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        eval("(define log \"GET /index.html 200\")", &mut env).unwrap();
+        let Some(Object::String(log)) = env.borrow().get("log") else {
+            panic!("expected a string");
+        };
+        for program in ["(substring log 4 15)", "(substring/bytes log 4 15)"] {
+            let Ok(Object::String(path)) = eval(program, &mut env) else {
+                panic!("expected a string");
+            };
+            assert_eq!(path.as_str(), "/index.html");
+            assert!(std::ptr::eq(path.as_str(), &log[4..15]));
+        }
+    }
+
+    #[test]
+    fn test_string_split() {
+        let cases = [
+            ("(string-split \"a,b,,c\" \",\")", "(a b  c)"),
+            ("(string-split \"a::b::c\" \"::\" #:limit 1)", "(a b::c)"),
+            ("(string-split \"abc\" \",\")", "(abc)"),
+            (
+                "(string-split \"a, b ,c\" (regex \"\\s*,\\s*\"))",
+                "(a b c)",
+            ),
+            (
+                "(string-split \"k=v=w\" (regex \"=\") #:limit 1)",
+                "(k v=w)",
+            ),
+            ("(string-split \"ab\" (regex \"x*\"))", "(ab)"),
+            (
+                "(string-split \"1a22b333\" (regex \"[a-z]\") #:limit 0)",
+                "(1a22b333)",
+            ),
+            ("(string-join '(\"a\" \"b\" \"c\") \", \")", "a, b, c"),
+            ("(string-join (string-split \"x y\" \" \") \"\")", "xy"),
+            ("(string-join '() \"-\")", ""),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(
+                eval_str(program).unwrap().to_string(),
+                expected,
+                "{}",
+                program
+            );
+        }
+
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        eval("(define line \"id,name,age\")", &mut env).unwrap();
+        let Some(Object::String(line)) = env.borrow().get("line") else {
+            panic!("expected a string");
+        };
+        let Ok(Object::ListData(fields)) = eval("(string-split line (regex \",\"))", &mut env)
+        else {
+            panic!("expected a list");
+        };
+        let Object::String(name) = &fields[1] else {
+            panic!("expected a string");
+        };
+        assert!(std::ptr::eq(name.as_str(), &line[3..7]));
+
+        assert_eq!(
+            eval_str("(string-split \"abc\" \"\")"),
+            Err("string-split: separator is empty".to_string())
+        );
+        assert_eq!(
+            eval_str("(string-join '(\"a\" 1) \",\")"),
+            Err("string-join: expected a string, got 1".to_string())
+        );
+        assert_eq!(
+            eval_str("(regex \"(a\")"),
+            Err("regex: missing )".to_string())
+        );
+    }
+
+    #[test]
+    fn test_template() {
+        assert_eq!(
+            eval_str(
+                "(template \"Hello, {name}! You have {count} items\" #:name \"Bob\" #:count 3)"
+            )
+            .unwrap(),
+            string("Hello, Bob! You have 3 items")
+        );
+        assert_eq!(
+            eval_str("(template \"{{literal}} {x}}}\" #:x 1)").unwrap(),
+            string("{literal} 1}")
+        );
+        assert_eq!(
+            eval_str("(template \"{a}-{b}\" '((a 1) (\"b\" x)))").unwrap(),
+            string("1-x")
+        );
+        assert!(eval_str("(template \"{missing}\")").is_err());
+        assert!(eval_str("(template \"{open\" #:open 1)").is_err());
+        assert!(eval_str("(template \"a } b\")").is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval_str("(eval '(+ 1 2))"), Ok(Object::Integer(3)));
+        assert_eq!(
+            eval_str("(begin (define x 10) (define code (list '* 'x 2)) (eval code))"),
+            Ok(Object::Integer(20))
+        );
+        assert_eq!(
+            eval_str("(begin (define x 10) (eval '(+ x y) '((y 5))))"),
+            Ok(Object::Integer(15))
+        );
+        assert_eq!(
+            eval_str("(eval '(+ x y) #:x 1 #:y 2)"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            eval_str("(begin (eval '(define z 3)) (eval 'z))"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            eval_str("(eval (cons '+ (cons 1 (cons 2 '()))))"),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(eval_str("(eval 1)"), Ok(Object::Integer(1)));
+        assert_eq!(
+            eval_str("(eval 'y)"),
+            Err("eval: Undefined symbol: y".to_string())
+        );
+    }
+
+    #[test]
+    fn test_introspection() {
+        assert_eq!(
+            eval_str("(interpreter-version)"),
+            Ok(string(env!("CARGO_PKG_VERSION")))
+        );
+        assert_eq!(
+            eval_str("(feature? 'net)"),
+            Ok(Object::truth(cfg!(all(
+                feature = "http",
+                feature = "websocket",
+                feature = "remote"
+            ))))
+        );
+        assert_eq!(
+            eval_str("(feature? \"unicode\")"),
+            Ok(Object::truth(cfg!(feature = "unicode")))
+        );
+        assert_eq!(eval_str("(feature? 'teleport)"), Ok(Object::truth(false)));
+        let Ok(Object::ListData(names)) = eval_str("(available-builtins)") else {
+            panic!("expected a list");
+        };
+        assert!(names.contains(&Object::Symbol("eval".into())));
+        assert_eq!(
+            names.contains(&Object::Symbol("serve".into())),
+            cfg!(feature = "http")
+        );
+        assert!(names.is_sorted_by_key(|name| name.to_string()));
+    }
+
+    #[test]
+    fn test_html_to_string() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        fn list(items: Vec<Object>) -> Object {
+            Object::ListData(Rc::new(items))
+        }
+        fn sym(s: &str) -> Object {
+            Object::Symbol(s.into())
+        }
+
+        // (div ((class "x") (hidden #f)) (p "hi & <bye>") (br) (input ((disabled #t))))
+        let markup = list(vec![
+            sym("div"),
+            list(vec![
+                list(vec![sym("class"), string("x\"y")]),
+                list(vec![sym("hidden"), Object::truth(false)]),
+            ]),
+            list(vec![sym("p"), string("hi & <bye>")]),
+            list(vec![sym("br")]),
+            list(vec![
+                sym("input"),
+                list(vec![list(vec![sym("disabled"), Object::truth(true)])]),
+            ]),
+        ]);
+        assert_eq!(
+            super::html_to_string(&[markup], &mut env).unwrap(),
+            string("<div class=\"x&quot;y\"><p>hi &amp; &lt;bye&gt;</p><br><input disabled></div>")
+        );
+
+        let bad = list(vec![sym("br"), string("child")]);
+        assert!(super::html_to_string(&[bad], &mut env).is_err());
+        assert!(super::html_to_string(&[list(vec![string("div")])], &mut env).is_err());
+
+        let mut eval = |program| crate::eval::eval(program, &mut env).unwrap();
+        assert_eq!(
+            eval("(html->string (cons 'p (cons \"a\" (list (list 'b \"c\")))))"),
+            string("<p>a<b>c</b></p>")
+        );
+        assert_eq!(
+            eval("(html->string (list 'a (list (list 'href \"/\")) \"home\"))"),
+            string("<a href=\"/\">home</a>")
+        );
+    }
+
+    #[test]
+    fn test_snapshots() {
+        crate::assert_eval_snapshot!("(number->string 2.5 #:precision 3)");
+        crate::assert_eval_snapshot!("(string-pad-left \"7\" 3 \"0\")");
+        crate::assert_eval_snapshot!(
+            "
+            (define (greet name) (format \"hello, ~a\" name))
+            (print (greet \"lisp\"))
+            (greet 1 2)
+            "
+        );
+    }
+
+    // すべての組み込み関数を、足りない・多すぎる・型の違う引数で呼び出しても panic せず、
+    // "名前: " で始まるエラーを返すことを確かめる。
+    #[test]
+    fn test_error_matrix() {
+        let samples = [
+            Object::Void,
+            Object::Integer(-1),
+            Object::Integer(0),
+            Object::Float(f64::NAN),
+            Object::truth(false),
+            Object::String("".into()),
+            Object::Symbol("x".into()),
+            Object::ListData(Rc::new(vec![])),
+            Object::KeywordArg("x".into()),
+            Object::Builtin(&super::BUILTINS[0]),
+        ];
+        let mut calls: Vec<Vec<Object>> = vec![vec![Object::Integer(1); 8]];
+        for sample in &samples {
+            for n in 0..=4 {
+                calls.push(vec![sample.clone(); n]);
+            }
+            for other in &samples {
+                calls.push(vec![sample.clone(), other.clone()]);
+                calls.push(vec![
+                    Object::String("ab".into()),
+                    sample.clone(),
+                    other.clone(),
+                ]);
+            }
+        }
+
+        for builtin in super::BUILTINS {
+            for args in &calls {
+                // 正しいハンドラを渡すとサーバーが起動して戻らない
+                if builtin.name == "serve" && args.iter().any(|a| matches!(a, Object::Builtin(_))) {
+                    continue;
+                }
+                let mut env = Rc::new(RefCell::new(Env::new()));
+                // read-all-stdin などが標準入力を待たないように、空の入力に差し替えておく
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    crate::stdin::with_input("", || builtin.call(args, &mut env))
+                }));
+                match result {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => assert!(
+                        e.starts_with(&format!("{}: ", builtin.name)),
+                        "{} {:?}: unexpected error {:?}",
+                        builtin.name,
+                        args,
+                        e
+                    ),
+                    Err(_) => panic!("{} panicked with {:?}", builtin.name, args),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// Interpreter の設定。Interpreter::new は次の環境変数から読む。
+//
+//   MR_LISP_PATH   load で相対パスのファイルを探すディレクトリ。PATH と同じ区切り文字で並べる
+//   MR_LISP_INIT   Interpreter を作ったときに最初に評価するファイル
+//   MR_LISP_FUEL   1 回の eval で評価できる式の数。使い切ると "Out of fuel" のエラーになる
+//   MR_LISP_COLOR  REPL のエラーを色付けするか。auto (端末のときだけ)、always、never
+//
+// 同じ設定を複数の場所で指定した場合は、API (Interpreter::with_config や set_fuel など)、
+// コマンドラインの引数 (--fuel や --color)、環境変数、既定値の順に優先する。
+
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::builtins::expect_string;
+use crate::eval::{Env, eval_toplevel};
+use crate::parser::{Object, parse_spanned};
+
+// 項目は今後も増えるので、crate の外では Config::default() から作ってフィールドを書き換える。
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Config {
+    pub path: Vec<PathBuf>,
+    pub init: Option<PathBuf>,
+    pub fuel: Option<u64>, // None なら制限しない
+    pub color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Result<ColorChoice, String> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "expected auto, always or never for color, got {}",
+                value
+            )),
+        }
+    }
+
+    // Auto の場合は標準エラー出力が端末かどうかで決める。
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => std::io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, String> {
+        Config::from_vars(|name| std::env::var_os(name).map(|v| v.to_string_lossy().into_owned()))
+    }
+
+    // var は環境変数の名前から値を返す関数。空の値は設定されていないものとして扱う。
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Config, String> {
+        let var = |name: &str| var(name).filter(|v| !v.is_empty());
+        let mut config = Config::default();
+        if let Some(path) = var("MR_LISP_PATH") {
+            config.path = std::env::split_paths(&path).collect();
+        }
+        config.init = var("MR_LISP_INIT").map(PathBuf::from);
+        if let Some(fuel) = var("MR_LISP_FUEL") {
+            let fuel = fuel.parse().map_err(|_| {
+                format!(
+                    "MR_LISP_FUEL: expected a non-negative integer, got {}",
+                    fuel
+                )
+            })?;
+            config.fuel = Some(fuel);
+        }
+        if let Some(color) = var("MR_LISP_COLOR") {
+            config.color =
+                ColorChoice::parse(&color).map_err(|e| format!("MR_LISP_COLOR: {}", e))?;
+        }
+        Ok(config)
+    }
+}
+
+thread_local! {
+    // 評価中の Interpreter の MR_LISP_PATH。load が使う
+    static LOAD_PATH: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) fn with_load_path<T>(path: &[PathBuf], f: impl FnOnce() -> T) -> T {
+    let saved = LOAD_PATH.replace(path.to_vec());
+    let result = f();
+    LOAD_PATH.set(saved);
+    result
+}
+
+// ファイルの式を順に env で評価して、最後の値を返す。
+pub(crate) fn load_file(path: &Path, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let forms = parse_spanned(&source).map_err(|(e, _)| format!("{}: {}", path.display(), e))?;
+    let mut result = Object::Void;
+    for (form, _) in forms {
+        result = eval_toplevel(&form, env)?;
+    }
+    Ok(result)
+}
+
+// (load "file") はファイルを評価する。
+pub(crate) fn load(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let path = find_file(Path::new(expect_string("load", &args[0])?));
+    load_file(&path, env).map_err(|e| format!("load: {}", e))
+}
+
+// 相対パスのファイルが今のディレクトリになければ MR_LISP_PATH から探す。見つからなければ file のまま返す。
+pub(crate) fn find_file(file: &Path) -> PathBuf {
+    if file.is_absolute() || file.exists() {
+        return file.to_path_buf();
+    }
+    LOAD_PATH
+        .with_borrow(|dirs| dirs.iter().map(|dir| dir.join(file)).find(|p| p.exists()))
+        .unwrap_or_else(|| file.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vars() {
+        let vars = |name: &str| match name {
+            "MR_LISP_PATH" => Some(
+                std::env::join_paths(["/a", "/b"])
+                    .unwrap()
+                    .into_string()
+                    .unwrap(),
+            ),
+            "MR_LISP_INIT" => Some("init.lisp".to_string()),
+            "MR_LISP_FUEL" => Some("1000".to_string()),
+            "MR_LISP_COLOR" => Some("never".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            Config::from_vars(vars),
+            Ok(Config {
+                path: vec![PathBuf::from("/a"), PathBuf::from("/b")],
+                init: Some(PathBuf::from("init.lisp")),
+                fuel: Some(1000),
+                color: ColorChoice::Never,
+            })
+        );
+        assert_eq!(
+            Config::from_vars(|_| Some(String::new())),
+            Ok(Config::default())
+        );
+        assert_eq!(
+            Config::from_vars(|name| (name == "MR_LISP_FUEL").then(|| "lots".to_string())),
+            Err("MR_LISP_FUEL: expected a non-negative integer, got lots".to_string())
+        );
+        assert!(
+            Config::from_vars(|name| (name == "MR_LISP_COLOR").then(|| "yes".to_string())).is_err()
+        );
+    }
+}
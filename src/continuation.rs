@@ -0,0 +1,159 @@
+// call/cc (call-with-current-continuation) の脱出専用の実装。
+//
+//   (call/cc (lambda (return)
+//     (do ((i 0 (+ i 1))) ((> i 9) 'none)
+//       (when (< 4 i) (return i)))))   ; => 5
+//
+// 継続は call/cc の呼び出しから戻るまでの間だけ使え、呼ぶとその call/cc からすぐに引数の値で戻る。
+// 戻った後の継続を呼ぶとエラーになる。
+// 脱出はエラーと同じ経路で call/cc まで伝わるので、途中の try では捕まえない。
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::eval::{Env, apply};
+use crate::parser::{Foreign, Object};
+
+#[derive(Debug)]
+struct Continuation {
+    id: usize,
+    active: Cell<bool>, // call/cc から戻るまで true
+}
+
+impl Foreign for Continuation {
+    fn type_name(&self) -> &str {
+        "continuation"
+    }
+}
+
+thread_local! {
+    static NEXT_ID: Cell<usize> = const { Cell::new(0) };
+    // 脱出している途中の継続の id と値
+    static ESCAPE: RefCell<Option<(usize, Object)>> = const { RefCell::new(None) };
+}
+
+fn as_continuation(obj: &Object) -> Option<&Continuation> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<Continuation>(),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_continuation(obj: &Object) -> bool {
+    as_continuation(obj).is_some()
+}
+
+// 継続へ脱出している途中なら true。try はこの間のエラーを捕まえない。
+pub(crate) fn escaping() -> bool {
+    ESCAPE.with_borrow(Option::is_some)
+}
+
+// 継続を呼ぶ。func が継続でなければ None。
+pub(crate) fn invoke(func: &Object, args: &[Object]) -> Option<Result<Object, String>> {
+    let k = as_continuation(func)?;
+    if !k.active.get() {
+        return Some(Err(
+            "continuation called after its call/cc returned".to_string()
+        ));
+    }
+    let value = match args {
+        [] => Object::Void,
+        [value] => value.clone(),
+        _ => {
+            return Some(Err(format!(
+                "continuation: expected 0 or 1 arguments, got {}",
+                args.len()
+            )));
+        }
+    };
+    ESCAPE.set(Some((k.id, value)));
+    Some(Err("continuation called outside its call/cc".to_string()))
+}
+
+// f に脱出先の継続を渡して呼ぶ。継続が呼ばれたらその値と true を、f が普通に戻ったらその値と false を返す。
+pub(crate) fn with_escape(
+    name: &str,
+    f: impl FnOnce(Object) -> Result<Object, String>,
+) -> Result<(Object, bool), String> {
+    let id = NEXT_ID.replace(NEXT_ID.get() + 1);
+    let k = Rc::new(Continuation {
+        id,
+        active: Cell::new(true),
+    });
+    let result = f(Object::Foreign(k.clone()));
+    k.active.set(false);
+    match result {
+        Ok(value) => Ok((value, false)),
+        Err(_) if ESCAPE.with_borrow(|escape| matches!(escape, Some((i, _)) if *i == id)) => {
+            crate::eval::forget_failed();
+            Ok((ESCAPE.take().unwrap().1, true))
+        }
+        Err(e) if escaping() => Err(e),
+        Err(e) => Err(format!("{}: {}", name, e)),
+    }
+}
+
+fn call_cc_as(name: &str, args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    with_escape(name, |k| apply(&args[0], &[k], env)).map(|(value, _)| value)
+}
+
+// (call/cc f) は、呼ぶと call/cc から戻る継続を f に渡して呼ぶ。
+pub(crate) fn call_cc(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    call_cc_as("call/cc", args, env)
+}
+
+pub(crate) fn call_with_current_continuation(
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    call_cc_as("call-with-current-continuation", args, env)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_call_cc() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define (find-first-over limit)
+                (call/cc (lambda (return)
+                    (do ((i 0 (+ i 1))) ((> i 9) 'none)
+                        (when (< limit i) (return i))))))
+            (list (find-first-over 4) (find-first-over 20) (call/cc (lambda (k) 1)))
+        ";
+        assert_eq!(interpreter.eval(program).unwrap().to_string(), "(5 none 1)");
+
+        // 内側の call/cc や try を飛び越えて外側の継続へ戻る
+        let program = "
+            (call-with-current-continuation (lambda (outer)
+                (+ 1 (call/cc (lambda (inner)
+                    (try (outer 'escaped) (catch (e) 'caught)))))))
+        ";
+        assert_eq!(
+            interpreter.eval(program),
+            Ok(Object::Symbol("escaped".into()))
+        );
+        assert_eq!(
+            interpreter.eval("(+ 1 (call/cc (lambda (k) (* 10 (k 2)))))"),
+            Ok(Object::Integer(3))
+        );
+
+        interpreter
+            .eval("(define saved (call/cc (lambda (k) k)))")
+            .unwrap();
+        assert_eq!(
+            interpreter.eval("(saved 1)"),
+            Err("continuation called after its call/cc returned".to_string())
+        );
+        assert!(
+            interpreter
+                .eval("(call/cc (lambda () 1))")
+                .unwrap_err()
+                .starts_with("call/cc: Expected 0 arguments, got 1")
+        );
+    }
+}
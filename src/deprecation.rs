@@ -0,0 +1,74 @@
+// 組み込み関数や特殊形式を非推奨にする仕組み。名前を変えるときに、古い名前を使うスクリプトを壊さずに移行を促す。
+//
+//   deprecation::deprecate("print", "display");
+//
+// 非推奨にした名前を使うと、名前ごとに 1 度だけ警告を出す。警告は eval::write_warning に送るので、
+// ふだんは標準エラー出力に出て、Interpreter::eval_rich では EvalResult::warnings に入る。
+// 組み込み関数は別名で呼んでも元の名前で判定する。
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use crate::eval::write_warning;
+
+thread_local! {
+    // 非推奨の名前と、代わりに使う名前
+    static DEPRECATED: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    // 既に警告を出した名前
+    static WARNED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+pub fn deprecate(name: &str, replacement: &str) {
+    DEPRECATED
+        .with_borrow_mut(|deprecated| deprecated.insert(name.to_string(), replacement.to_string()));
+}
+
+pub fn is_deprecated(name: &str) -> bool {
+    DEPRECATED.with_borrow(|deprecated| deprecated.contains_key(name))
+}
+
+// 組み込み関数を呼ぶときと特殊形式を評価するときに、その名前で呼ぶ。
+pub(crate) fn check(name: &str) {
+    let message = DEPRECATED.with_borrow(|deprecated| {
+        let replacement = deprecated.get(name)?;
+        WARNED
+            .with_borrow_mut(|warned| warned.insert(name.to_string()))
+            .then(|| format!("{} is deprecated; use {} instead", name, replacement))
+    });
+    if let Some(message) = message {
+        write_warning(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_deprecate() {
+        deprecate("string-length", "string-size");
+        deprecate("while", "do");
+        assert!(is_deprecated("while"));
+        assert!(!is_deprecated("print"));
+
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define len string-length)
+            (define n 0)
+            (while (< n 2) (set! n (+ n 1)) (len \"a\"))
+            (string-length \"abc\")
+        ";
+        let result = interpreter.eval_rich(program);
+        assert_eq!(result.value, Ok(Object::Integer(3)));
+        assert_eq!(
+            result.warnings,
+            [
+                "while is deprecated; use do instead",
+                "string-length is deprecated; use string-size instead"
+            ]
+        );
+        assert!(interpreter.eval_rich(program).warnings.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+// --dump-tokens と --dump-ast で使う、トークン列と構文木のデバッグ表示。
+// どちらも 1 行に 1 要素で、先頭にソース上のバイト範囲を付ける。
+
+use crate::lexer::{Token, tokenize_spanned};
+use crate::parser::{Object, Span, parse_spanned};
+use std::fmt::Write;
+
+pub fn dump_tokens(program: &str) -> Result<String, String> {
+    let tokens = tokenize_spanned(program).map_err(|e| format!("{} at {}", e, e.pos))?;
+    let mut out = String::new();
+    for (token, span) in tokens {
+        writeln!(out, "{:?}\t{:?}", span, token).unwrap();
+    }
+    Ok(out)
+}
+
+// 構文木を字下げして表示する。リストの中の式にもそれぞれの範囲を付ける。
+pub fn dump_ast(program: &str) -> Result<String, String> {
+    let forms = parse_spanned(program).map_err(|(e, span)| format!("{} at {:?}", e, span))?;
+    // parse_spanned で読めたので、トークンにも分けられる
+    let tokens = tokenize_spanned(program).unwrap_or_default();
+    let mut pos = 0;
+    let mut out = String::new();
+    for (obj, _) in &forms {
+        write_node(&mut out, obj, &tokens, &mut pos, 0);
+    }
+    Ok(out)
+}
+
+// パーサーと同じ順番でトークンを読み進め、各ノードに対応するトークンから範囲を求める。
+fn write_node(
+    out: &mut String,
+    obj: &Object,
+    tokens: &[(Token, Span)],
+    pos: &mut usize,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    let (token, span) = &tokens[*pos];
+    *pos += 1;
+    match (token, obj) {
+        // 'expr は (quote expr) として読まれている。` , ,@ も同じ
+        (
+            Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing,
+            Object::List(list),
+        ) => {
+            let mut inner = String::new();
+            write_node(&mut inner, &list[1], tokens, pos, depth + 1);
+            let end = tokens[*pos - 1].1.end;
+            writeln!(out, "{}{:?}\tList", indent, span.start..end).unwrap();
+            writeln!(out, "{}  {:?}\t{:?}", indent, span, list[0]).unwrap();
+            out.push_str(&inner);
+        }
+        // (a b . c) はペアの並びとして、(a . (b c)) は (a b c) として読まれている
+        (Token::LParen, Object::List(_) | Object::Pair(_)) => {
+            let (items, tail) = split_dotted(obj);
+            let is_dot = |pos: usize| matches!(&tokens[pos].0, Token::Symbol(s) if s == ".");
+            let mut inner = String::new();
+            let mut closers = 1;
+            for item in &items {
+                if is_dot(*pos) {
+                    *pos += 2; // . と、要素をつなげたリストの (
+                    closers += 1;
+                }
+                write_node(&mut inner, item, tokens, pos, depth + 1);
+            }
+            while is_dot(*pos) && matches!(tokens[*pos + 1].0, Token::LParen) {
+                *pos += 2;
+                closers += 1;
+            }
+            if let Some(tail) = &tail {
+                *pos += 1; // .
+                write_node(&mut inner, tail, tokens, pos, depth + 1);
+            }
+            *pos += closers;
+            let end = tokens[*pos - 1].1.end; // 最後の閉じ括弧
+            let kind = if tail.is_some() { "Pair" } else { "List" };
+            writeln!(out, "{}{:?}\t{}", indent, span.start..end, kind).unwrap();
+            out.push_str(&inner);
+        }
+        (Token::VectorOpen, Object::Vector(vector)) => {
+            let mut inner = String::new();
+            for item in vector.borrow().iter() {
+                write_node(&mut inner, item, tokens, pos, depth + 1);
+            }
+            *pos += 1; // )
+            let end = tokens[*pos - 1].1.end;
+            writeln!(out, "{}{:?}\tVector", indent, span.start..end).unwrap();
+            out.push_str(&inner);
+        }
+        _ => writeln!(out, "{}{:?}\t{:?}", indent, span, obj).unwrap(),
+    }
+}
+
+// リストの要素と、(a b . c) の最後の c。
+fn split_dotted(obj: &Object) -> (Vec<Object>, Option<Object>) {
+    let mut items = Vec::new();
+    let mut rest = obj;
+    loop {
+        match rest {
+            Object::Pair(pair) => {
+                items.push(pair.car.clone());
+                rest = &pair.cdr;
+            }
+            Object::List(list) => {
+                items.extend(list.iter().cloned());
+                return (items, None);
+            }
+            tail => return (items, Some(tail.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(
+            dump_tokens("(+ 1 \"a\")").unwrap(),
+            "0..1\tLParen\n1..2\tBinaryOp(Add)\n3..4\tInteger(1)\n5..8\tString(\"a\")\n8..9\tRParen\n"
+        );
+        assert_eq!(
+            dump_ast("(f 'x\n  (g))\n2").unwrap(),
+            "0..12\tList\n  1..2\tSymbol(\"f\")\n  3..5\tList\n    3..4\tKeyword(Quote)\n    4..5\tSymbol(\"x\")\n  8..11\tList\n    9..10\tSymbol(\"g\")\n13..14\tInteger(2)\n"
+        );
+        assert_eq!(
+            dump_ast("(1 . (2 . 3)) (a . (b)) x").unwrap(),
+            "0..13\tPair\n  1..2\tInteger(1)\n  6..7\tInteger(2)\n  10..11\tInteger(3)\n14..23\tList\n  15..16\tSymbol(\"a\")\n  20..21\tSymbol(\"b\")\n24..25\tSymbol(\"x\")\n"
+        );
+        assert_eq!(
+            dump_ast("#(1 (a))").unwrap(),
+            "0..8\tVector\n  2..3\tInteger(1)\n  4..7\tList\n    5..6\tSymbol(\"a\")\n"
+        );
+        assert_eq!(
+            dump_ast("1 (f").unwrap_err(),
+            "ParseError: Expected ')' at the end of list at 2..4"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+// Env を Lisp の値として扱う。サンドボックスや REPL の中の REPL、モジュールの読み込みなどを Lisp で書くのに使う。
+//
+//   (define sandbox (make-environment))
+//   (environment-define! sandbox 'x 10)
+//   (eval '(+ x 1) sandbox)                 ; 11
+//   (define (f y) (current-environment))
+//   (eval 'y (f 5))                         ; 5
+//
+// (make-environment) は組み込み関数だけが束縛された新しい大域の Env を作り、呼び出し元の束縛は見えない。
+// (make-environment parent) は parent を親にした Env を作る。どちらも大域の Env のポリシーは引き継がない。
+// (env-shared? a b) は a と b が同じ Env か、(env-shared? a b 'x) は a と b から見える x が同じ束縛の箱か。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::parser::{Foreign, Object};
+
+struct Environment(Rc<RefCell<Env>>);
+
+// Env は循環参照になりうるので中身は出さない。
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Environment")
+    }
+}
+
+impl Foreign for Environment {
+    fn type_name(&self) -> &str {
+        "environment"
+    }
+}
+
+pub(crate) fn as_environment(obj: &Object) -> Option<&Rc<RefCell<Env>>> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any)
+            .downcast_ref::<Environment>()
+            .map(|environment| &environment.0),
+        _ => None,
+    }
+}
+
+fn expect_environment<'a>(name: &str, obj: &'a Object) -> Result<&'a Rc<RefCell<Env>>, String> {
+    as_environment(obj).ok_or_else(|| format!("{}: expected an environment, got {}", name, obj))
+}
+
+// 呼び出した場所の Env。lambda の中で呼べば、その引数や局所的な define も見える。
+pub(crate) fn current_environment(
+    _args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    Ok(Object::Foreign(Rc::new(Environment(Rc::clone(env)))))
+}
+
+pub(crate) fn make_environment(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let env = match args.first() {
+        Some(parent) => Env::extend(Rc::clone(expect_environment("make-environment", parent)?)),
+        None => Env::new(),
+    };
+    Ok(Object::Foreign(Rc::new(Environment(Rc::new(
+        RefCell::new(env),
+    )))))
+}
+
+// (environment-define! env 'name value) は define と同じく env 自身に束縛する。
+pub(crate) fn environment_define(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let env = expect_environment("environment-define!", &args[0])?;
+    let Object::Symbol(name) = &args[1] else {
+        unreachable!("checked by the signature")
+    };
+    env.borrow_mut().define(name, args[2].clone())?;
+    Ok(Object::Void)
+}
+
+// Env を共有する場合と写す場合の決まりは eval.rs の Env を参照。
+pub(crate) fn env_shared(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let a = expect_environment("env-shared?", &args[0])?;
+    let b = expect_environment("env-shared?", &args[1])?;
+    let shared = match args.get(2) {
+        Some(Object::Symbol(name)) => match (a.borrow().binding(name), b.borrow().binding(name)) {
+            (Some(a), Some(b)) => Rc::ptr_eq(&a, &b),
+            _ => false,
+        },
+        _ => Rc::ptr_eq(a, b),
+    };
+    Ok(Object::truth(shared))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_environments() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define secret 42)
+            (define sandbox (make-environment))
+            (environment-define! sandbox 'x 10)
+            (eval '(define y (* x 2)) sandbox)
+            (define (f a) (define b (+ a 1)) (current-environment))
+            (define child (make-environment (f 5)))
+            (eval '(define a 100) child)
+            (list (eval '(+ x y) sandbox) (eval '(list a b) (f 5)) (eval 'a child) (eval 'b child) sandbox)
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "(30 (5 6) 100 6 #<environment>)"
+        );
+        assert_eq!(
+            interpreter.eval("(eval 'secret sandbox)"),
+            Err("eval: Undefined symbol: secret".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("y"),
+            Err("Undefined symbol: y".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(eval 'secret (current-environment))"),
+            Ok(Object::Integer(42))
+        );
+        assert_eq!(
+            interpreter.eval("(environment-define! 1 'x 1)"),
+            Err("environment-define!: expected an environment, got 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_env_shared() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define x 1)
+            (define (f) (current-environment))
+            (define here (current-environment))
+            (define sandbox (make-environment))
+            (environment-define! sandbox 'x 2)
+            (list (env-shared? here (current-environment)) (env-shared? (f) (f))
+                  (env-shared? (f) (f) 'x) (env-shared? here sandbox 'x) (env-shared? here sandbox 'y))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "(true false true false false)"
+        );
+        assert_eq!(
+            interpreter.eval("(env-shared? here sandbox 1)"),
+            Err("env-shared?: expected a symbol, got 1".to_string())
+        );
+    }
+}
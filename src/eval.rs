@@ -1,46 +1,269 @@
-use crate::parser::Object;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use crate::bigint::BigInt;
+use crate::builtins::{ArgType, BUILTINS, Builtin};
+use crate::continuation::is_continuation;
+use crate::generator::is_generator_procedure;
+use crate::keyword::{Op, SpecialForm};
+use crate::pair::{list_items, pair_items, pair_list};
+use crate::parameter::is_parameter;
+use crate::parser::{Foreign, Lambda, Object, Pair, Promise, PromiseState};
+use crate::printer::{debug, debug_form};
+use crate::record::is_record_procedure;
+use crate::syntax_rules::{SyntaxRules, original_name};
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+// 式を順に評価して最後の値を返す。式が 1 つもなければ Void を返す。
 pub fn eval(program: &str, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    let ast = crate::parser::parse(program).map_err(|e| e.to_string())?;
-    eval_obj(&ast, env)
+    let forms = crate::parser::parse_all(program).map_err(|e| e.to_string())?;
+    let mut result = Object::Void;
+    for form in &forms {
+        result = eval_toplevel(form, env)?;
+    }
+    Ok(result)
 }
 
-fn eval_obj(obj: &Object, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    match obj {
-        Object::Void => Ok(Object::Void),
-        Object::Bool(b) => Ok(Object::Bool(*b)),
-        Object::Integer(n) => Ok(Object::Integer(*n)),
-        Object::Float(f) => Ok(Object::Float(*f)),
-        Object::ListData(list) => eval_list_data(list, env),
-        Object::String(s) => Ok(Object::String(s.clone())),
-        Object::Symbol(s) => eval_symbol(s, env),
-        Object::Lambda(_, _) => Ok(Object::Void), // 仮
-        Object::List(list) => eval_list(list, env),
-        _ => Err(format!("Invalid object: {:?}", obj)),
+// トップレベルの式を、マクロを展開してから評価する。
+// トップレベルの begin の中の式は 1 つずつ展開して評価するので、前の式で定義したマクロを後の式で使える。
+pub(crate) fn eval_toplevel(obj: &Object, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    if let Object::List(list) = obj
+        && matches!(list.first(), Some(Object::Keyword(SpecialForm::Begin)))
+    {
+        let mut result = Object::Void;
+        for expr in &list[1..] {
+            result = eval_toplevel(expr, env)?;
+        }
+        return Ok(result);
+    }
+    let expanded = expand(obj, env)?;
+    eval_obj(&expanded, env)
+}
+
+// 式の中のマクロ呼び出しを、展開結果がマクロ呼び出しでなくなるまで再帰的に展開する。quote の中は展開しない。
+// マクロには引数の式を quote したのと同じデータを渡し、返ってきたデータを式に戻して元の位置に置く。
+fn expand(obj: &Object, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let Object::List(list) = obj else {
+        return Ok(obj.clone());
+    };
+    match list.first() {
+        Some(Object::Keyword(SpecialForm::Quote)) => return Ok(obj.clone()),
+        Some(Object::Symbol(name)) => match lookup(env, name) {
+            Some(Object::Macro(lambda)) => {
+                let args: Vec<Object> = list[1..].iter().map(to_data).collect();
+                let expansion = apply(&Object::Lambda(lambda), &args, env)
+                    .map_err(|e| format!("{}: {}", name, e))?;
+                return expand(&to_code(&expansion), env);
+            }
+            Some(Object::SyntaxRules(rules)) => {
+                let expansion = rules.expand(list).map_err(|e| format!("{}: {}", name, e))?;
+                return expand(&expansion, env);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    let items = list
+        .iter()
+        .map(|item| expand(item, env))
+        .collect::<Result<Vec<_>, _>>()?;
+    // 何も展開しなかったリストは元の Rc のまま返す。エラーの位置をソースから引けるようにするため
+    let unchanged = items.iter().zip(list.iter()).all(|pair| match pair {
+        (Object::List(new), Object::List(old)) => Rc::ptr_eq(new, old),
+        (new, old) => !matches!(new, Object::List(_)) && !matches!(old, Object::List(_)),
+    });
+    if unchanged {
+        return Ok(obj.clone());
+    }
+    Ok(Object::List(Rc::new(items)))
+}
+
+// 特殊形式や関数呼び出しを 1 段評価した結果。
+// Tail は末尾位置に残った式で、値を得るにはその Env でさらに評価する必要がある。
+enum Step {
+    Done(Object),
+    Tail(Object, Rc<RefCell<Env>>),
+}
+
+// 末尾位置の式は Step::Tail で受け取ってこのループで評価を続けるので、Rust のスタックは伸びない。
+// 末尾再帰で書いたループは、繰り返しの回数によらず一定のスタックで動く。
+pub(crate) fn eval_obj(obj: &Object, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut step = eval_step(obj, env)?;
+    loop {
+        match step {
+            Step::Done(value) => return Ok(value),
+            Step::Tail(obj, mut env) => step = eval_step(&obj, &mut env)?,
+        }
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// 評価中の式を中断する。別のスレッドやシグナルハンドラーから呼んでよい。
+// 次に式を評価するところで "Interrupted" のエラーになる。
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+thread_local! {
+    // 残りの評価できる式の数。None なら制限しない
+    static FUEL: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+// f の中では式を fuel 個までしか評価できない。使い切ると "Out of fuel" のエラーになる。
+pub(crate) fn with_fuel<T>(fuel: Option<u64>, f: impl FnOnce() -> T) -> T {
+    let saved = FUEL.replace(fuel);
+    let result = f();
+    FUEL.set(saved);
+    result
+}
+
+// f の中では式を limit 個までしか評価できない。外側に fuel があれば、その残りも超えず、使った分だけ減らす。
+pub(crate) fn with_sub_fuel<T>(limit: u64, f: impl FnOnce() -> T) -> T {
+    let outer = FUEL.get();
+    let fuel = outer.map_or(limit, |outer| outer.min(limit));
+    FUEL.set(Some(fuel));
+    let result = f();
+    let used = fuel - FUEL.get().unwrap_or(0);
+    FUEL.set(outer.map(|outer| outer - used));
+    result
+}
+
+pub(crate) fn remaining_fuel() -> Option<u64> {
+    FUEL.get()
+}
+
+fn check_interrupt(env: &mut Rc<RefCell<Env>>) -> Result<(), String> {
+    match FUEL.get() {
+        Some(0) => return Err("Out of fuel".to_string()),
+        Some(n) => FUEL.set(Some(n - 1)),
+        None => {}
+    }
+    #[cfg(all(unix, feature = "signals"))]
+    crate::signal::dispatch(env)?;
+    #[cfg(not(all(unix, feature = "signals")))]
+    let _ = env;
+    if INTERRUPTED.swap(false, Ordering::SeqCst) {
+        return Err("Interrupted".to_string());
     }
+    Ok(())
+}
+
+thread_local! {
+    // エラーになったリストのアドレス。内側の式から順に並ぶ
+    static FAILED: RefCell<Vec<*const Vec<Object>>> = const { RefCell::new(Vec::new()) };
+}
+
+// 直前のエラーが通ったリストのアドレスを、内側から順に取り出す。
+// Interpreter は SourceMap で引いて、エラーの位置を行と列で示す。
+pub(crate) fn take_failed() -> Vec<*const Vec<Object>> {
+    FAILED.take()
+}
+
+// エラーを捕まえて評価を続けるときに呼ぶ。捕まえたエラーの位置が、後のエラーに混ざらないようにする。
+pub(crate) fn forget_failed() {
+    FAILED.with_borrow_mut(Vec::clear);
 }
 
+fn eval_step(obj: &Object, env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    check_interrupt(env)?;
+    let value = match obj {
+        Object::List(list) if list.is_empty() => Object::nil(),
+        Object::List(list) => {
+            return eval_list(list, env).inspect_err(|_| {
+                FAILED.with_borrow_mut(|failed| failed.push(Rc::as_ptr(list)));
+            });
+        }
+        Object::Void => Object::Void,
+        Object::Bool(b) => Object::truth(*b),
+        Object::Integer(n) => Object::Integer(*n),
+        Object::BigInt(_) => obj.clone(),
+        Object::Float(f) => Object::Float(*f),
+        Object::Char(c) => Object::Char(*c),
+        // ObjectBuilder::build_data などで作ったデータのリストは、quote したものと同じく自分自身になる
+        Object::ListData(_) | Object::Pair(_) => obj.clone(),
+        Object::Vector(_) => to_data(obj),
+        Object::String(s) => Object::String(s.clone()),
+        Object::Symbol(s) => eval_symbol(s, env)?,
+        Object::Lambda(_) | Object::Macro(_) | Object::SyntaxRules(_) => obj.clone(),
+        Object::Promise(_) | Object::Record(_) => obj.clone(),
+        Object::KeywordArg(_) | Object::Builtin(_) | Object::Foreign(_) => obj.clone(),
+        _ => return Err(format!("Invalid object: {}", debug(obj))),
+    };
+    Ok(Step::Done(value))
+}
+
+// Env に束縛する値の表現。tagged-value feature では NaN-boxing した値で持つ。
+#[cfg(not(feature = "tagged-value"))]
+type Slot = Object;
+#[cfg(feature = "tagged-value")]
+type Slot = crate::tagged::Value;
+
+// 束縛 1 つ分の箱。update で別の Env に束縛を渡すときは箱ごと共有するので、
+// どちらの Env から set! しても、同じ変数を捕まえたすべてのクロージャから見える。
+pub(crate) type Binding = Rc<RefCell<Slot>>;
+
+// 大域の束縛を変更する前に呼ばれる関数。名前と束縛する値を受け取り、Err を返すとその変更を拒否する。
+pub type BindingPolicy = dyn Fn(&str, &Object) -> Result<(), String>;
+
+// Env を共有する場合と写す場合:
+// - lambda、let、モジュールの本体、(make-environment parent) で作る Env は、親の Env を Rc で共有する。
+//   クロージャは作られた Env そのものを持つので、外側の変数への set! はいつでも見える。値を写す場面は無い。
+// - 束縛は 1 つずつ箱 (Binding) に入っている。update と import は束縛を写さずに箱を共有する (別名にする)。
+//   別名のどちらから set! や define をしても、もう一方から同じ値が見える。
+// - (module-reload 'm) は新しい箱を持つモジュールを作るので、前に import した別名は古いモジュールの箱を指したまま。
+// (env-shared? a b) と (env-shared? a b 'x) で、Env や束縛を共有しているかを確かめられる (environment.rs)。
 pub struct Env {
     parent: Option<Rc<RefCell<Env>>>,
-    vars: HashMap<String, Object>,
+    vars: HashMap<String, Binding>,
+    docs: HashMap<String, String>, // (define (f x) "説明" ...) で束縛に付けた説明
+    pending: HashSet<String>,      // 本体の中の define で束縛する予定で、まだ値の無い名前
+    policy: Option<Rc<BindingPolicy>>, // 大域の Env にだけ設定する
+    modules: HashMap<PathBuf, Object>, // import で読み込んだモジュールのファイル。大域の Env にだけ置く
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
 }
 
 impl Env {
     pub fn new() -> Self {
-        Env {
+        Env::with_builtins(BUILTINS)
+    }
+
+    // builtins だけを束縛した大域の Env。eval-in-sandbox が使う組み込み関数を絞るのに使う (sandbox.rs)
+    pub(crate) fn with_builtins(builtins: impl IntoIterator<Item = &'static Builtin>) -> Self {
+        let mut env = Env {
             parent: None,
             vars: HashMap::new(),
+            docs: HashMap::new(),
+            pending: HashSet::new(),
+            policy: None,
+            modules: HashMap::new(),
+        };
+        for builtin in builtins {
+            env.set(builtin.name, Object::Builtin(builtin));
         }
+        env
     }
 
+    // data の束縛をこの Env にも置く。値は写さずに束縛の箱を共有する。
     pub fn update(&mut self, data: Rc<RefCell<Self>>) {
         self.vars.extend(
             data.borrow()
                 .vars
                 .iter()
+                .map(|(k, v)| (k.clone(), Rc::clone(v))),
+        );
+        self.docs.extend(
+            data.borrow()
+                .docs
+                .iter()
                 .map(|(k, v)| (k.clone(), v.clone())),
         )
     }
@@ -49,12 +272,19 @@ impl Env {
         Env {
             parent: Some(parent),
             vars: HashMap::new(),
+            docs: HashMap::new(),
+            pending: HashSet::new(),
+            policy: None,
+            modules: HashMap::new(),
         }
     }
 
+    #[allow(clippy::useless_conversion)] // Slot が Object のときは恒等変換になる
     pub fn get(&self, name: &str) -> Option<Object> {
         match self.vars.get(name) {
-            Some(value) => Some(value.clone()),
+            Some(value) => Some(Object::from(value.borrow().clone())),
+            // 外側の同じ名前の束縛は、この Env の define で隠れる予定なので見ない
+            None if self.pending.contains(name) => None,
             None => self
                 .parent
                 .as_ref()
@@ -63,260 +293,1607 @@ impl Env {
     }
 
     pub fn set(&mut self, name: &str, val: Object) {
-        self.vars.insert(name.to_string(), val);
+        if !self.pending.is_empty() {
+            self.pending.remove(name);
+        }
+        // すでに束縛があれば箱の中身を書き換え、update で共有している Env からも見えるようにする
+        match self.vars.get(name) {
+            Some(binding) => *binding.borrow_mut() = Slot::from(val),
+            None => {
+                let binding = Rc::new(RefCell::new(Slot::from(val)));
+                self.vars.insert(name.to_string(), binding);
+            }
+        }
+    }
+
+    // 本体の中の define で後から束縛する名前を、値の無い束縛として先に作る。
+    // 引数などですでに束縛していれば、define はその束縛を書き換えるだけなので何もしない。
+    pub(crate) fn declare(&mut self, name: &str) {
+        if !self.vars.contains_key(name) {
+            self.pending.insert(name.to_string());
+        }
+    }
+
+    // name を束縛している一番内側の Env で、まだ値が無いか。
+    pub(crate) fn is_uninitialized(&self, name: &str) -> bool {
+        if self.vars.contains_key(name) {
+            return false;
+        }
+        self.pending.contains(name)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.borrow().is_uninitialized(name))
+    }
+
+    // name の束縛に付けた説明。内側の Env で束縛し直していれば、外側の説明は見えない。
+    pub fn doc(&self, name: &str) -> Option<String> {
+        if self.vars.contains_key(name) {
+            return self.docs.get(name).cloned();
+        }
+        self.parent.as_ref().and_then(|o| o.borrow().doc(name))
+    }
+
+    pub(crate) fn set_doc(&mut self, name: &str, doc: Option<String>) {
+        match doc {
+            Some(doc) => self.docs.insert(name.to_string(), doc),
+            None => self.docs.remove(name),
+        };
+    }
+
+    // name を束縛している一番内側の箱。
+    pub(crate) fn binding(&self, name: &str) -> Option<Binding> {
+        match self.vars.get(name) {
+            Some(binding) => Some(Rc::clone(binding)),
+            None if self.pending.contains(name) => None,
+            None => self.parent.as_ref()?.borrow().binding(name),
+        }
+    }
+
+    // name を binding の別名としてこの Env に束縛する。すでに束縛があれば、その束縛との共有はやめる。
+    #[allow(clippy::useless_conversion)] // Slot が Object のときは恒等変換になる
+    pub(crate) fn define_alias(&mut self, name: &str, binding: Binding) -> Result<(), String> {
+        if let Some(policy) = &self.policy {
+            let value = Object::from(binding.borrow().clone());
+            policy(name, &value).map_err(|e| format!("Cannot define {}: {}", name, e))?;
+        }
+        self.pending.remove(name);
+        self.vars.insert(name.to_string(), binding);
+        Ok(())
+    }
+
+    // 親の Env は見ずに、この Env 自身の束縛だけを探す。
+    #[allow(clippy::useless_conversion)]
+    pub(crate) fn get_local(&self, name: &str) -> Option<Object> {
+        self.vars
+            .get(name)
+            .map(|value| Object::from(value.borrow().clone()))
+    }
+
+    // path のモジュールのファイルを、この実行ですでに import していればそのモジュール。
+    pub(crate) fn loaded_module(&self, path: &Path) -> Option<Object> {
+        match &self.parent {
+            Some(parent) => parent.borrow().loaded_module(path),
+            None => self.modules.get(path).cloned(),
+        }
+    }
+
+    pub(crate) fn cache_module(&mut self, path: PathBuf, module: Object) {
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().cache_module(path, module),
+            None => {
+                self.modules.insert(path, module);
+            }
+        }
+    }
+
+    // cache_module で覚えたものを previous に戻す。None なら忘れる。
+    pub(crate) fn uncache_module(&mut self, path: &Path, previous: Option<Object>) {
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().uncache_module(path, previous),
+            None => match previous {
+                Some(module) => {
+                    self.modules.insert(path.to_path_buf(), module);
+                }
+                None => {
+                    self.modules.remove(path);
+                }
+            },
+        }
+    }
+
+    // env を含む大域の Env
+    pub(crate) fn global(env: &Rc<RefCell<Env>>) -> Rc<RefCell<Env>> {
+        match &env.borrow().parent {
+            Some(parent) => Env::global(parent),
+            None => Rc::clone(env),
+        }
+    }
+
+    // この Env 自身が束縛している名前を名前順に並べる。
+    pub(crate) fn local_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.vars.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // 大域の Env が最初から束縛している組み込み関数以外の束縛を、外側の Env のものから順に並べる。
+    // (define my-car car) のように組み込み関数を別の名前で束縛したものは含める。
+    // 内側で同じ名前を束縛していれば内側の値だけを返す。
+    #[allow(clippy::useless_conversion)]
+    pub(crate) fn user_bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings = match &self.parent {
+            Some(parent) => parent.borrow().user_bindings(),
+            None => Vec::new(),
+        };
+        bindings.retain(|(name, _)| !self.vars.contains_key(name));
+        let mut vars: Vec<(String, Object)> = self
+            .vars
+            .iter()
+            .map(|(name, value)| (name.clone(), Object::from(value.borrow().clone())))
+            .filter(|(name, value)| match value {
+                Object::Builtin(builtin) => self.parent.is_some() || builtin.name != name,
+                _ => true,
+            })
+            .collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings.extend(vars);
+        bindings
+    }
+
+    pub fn set_binding_policy(&mut self, policy: Rc<BindingPolicy>) {
+        self.policy = Some(policy);
+    }
+
+    // define などで束縛する。大域の Env ではポリシーに確認してから変更する。
+    pub(crate) fn define(&mut self, name: &str, val: Object) -> Result<(), String> {
+        if let Some(policy) = &self.policy {
+            policy(name, &val).map_err(|e| format!("Cannot define {}: {}", name, e))?;
+        }
+        self.set(name, val);
+        Ok(())
+    }
+
+    // set! で既にある束縛を書き換える。name を束縛している一番内側の Env を変更する。
+    pub(crate) fn assign(&mut self, name: &str, val: Object) -> Result<(), String> {
+        if self.pending.contains(name) {
+            return Err(format!("{}: used before initialization", name));
+        }
+        if !self.vars.contains_key(name) {
+            return match &self.parent {
+                Some(parent) => parent.borrow_mut().assign(name, val),
+                None => Err(format!("Undefined symbol: {}", name)),
+            };
+        }
+        if let Some(policy) = &self.policy {
+            policy(name, &val).map_err(|e| format!("Cannot set! {}: {}", name, e))?;
+        }
+        self.set(name, val);
+        Ok(())
     }
 }
 
-fn eval_list_data(_list: &Vec<Object>, _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    unimplemented!();
+// 変数を探す。syntax-rules の展開で付けた別名 (name#1) に束縛が無ければ、元の名前で探す。
+// math/square のような名前に束縛が無ければ、モジュールの export から探す。
+fn lookup(env: &Rc<RefCell<Env>>, name: &str) -> Option<Object> {
+    let mut name = name;
+    loop {
+        let value = env.borrow().get(name);
+        if value.is_some() {
+            return value;
+        }
+        match original_name(name) {
+            Some(original) => name = original,
+            None => return crate::module::lookup_qualified(env, name),
+        }
+    }
 }
 
-fn eval_symbol(symbol: &String, env: &Rc<RefCell<Env>>) -> Result<Object, String> {
-    match env.borrow().get(symbol.as_str()) {
+fn eval_symbol(symbol: &str, env: &Rc<RefCell<Env>>) -> Result<Object, String> {
+    match lookup(env, symbol) {
         Some(value) => Ok(value),
+        None if env.borrow().is_uninitialized(symbol) => {
+            Err(format!("{}: used before initialization", symbol))
+        }
         None => Err(format!("Undefined symbol: {}", symbol)),
     }
 }
 
-fn eval_list(list: &Rc<Vec<Object>>, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+fn eval_list(list: &Rc<Vec<Object>>, env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
     let head = list.first().ok_or("Empty list")?;
     match head {
-        Object::Keyword(_) => eval_keyword(list, env),
-        Object::BinaryOp(_) => eval_binary_op(list, env),
+        Object::Keyword(keyword) => eval_keyword(*keyword, list, env),
+        Object::BinaryOp(op) => eval_binary_op(*op, list, env).map(Step::Done),
         Object::Symbol(s) => eval_function_call(s, list, env),
-        _ => Err(format!("Invalid list op: {:?}", list)),
+        Object::List(_) => {
+            // ((make-adder 1) 2) のように、先頭が関数を返す式の場合
+            let func = eval_obj(head, env)?;
+            let mut args = Vec::with_capacity(list.len() - 1);
+            for arg in &list[1..] {
+                args.push(eval_obj(arg, env)?);
+            }
+            call(&func, &args, env)
+        }
+        _ => Err(format!("Invalid list op: {}", debug_form(list))),
     }
 }
 
-fn eval_keyword(list: &Rc<Vec<Object>>, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    if list.is_empty() {
-        return Err("Empty keyword list".to_string());
+fn eval_keyword(
+    keyword: SpecialForm,
+    list: &Rc<Vec<Object>>,
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Step, String> {
+    crate::deprecation::check(keyword.name());
+    match keyword {
+        SpecialForm::Begin => eval_body(&list[1..], Rc::clone(env)),
+        // モジュールの外では define と同じ。モジュールの本体では export しない (module.rs)
+        SpecialForm::Define | SpecialForm::DefinePrivate => eval_define(list, env).map(Step::Done),
+        SpecialForm::DefineMacro => eval_define_macro(list, env).map(Step::Done),
+        SpecialForm::DefineSyntax => eval_define_syntax(list, env).map(Step::Done),
+        SpecialForm::Parameterize => crate::parameter::eval_parameterize(list, env).map(Step::Done),
+        SpecialForm::Module | SpecialForm::Import if crate::sandbox::active() => {
+            Err(format!("{}: not allowed in a sandbox", keyword))
+        }
+        SpecialForm::Module => crate::module::eval_module(list, env).map(Step::Done),
+        SpecialForm::Import => crate::module::eval_import(list, env).map(Step::Done),
+        SpecialForm::DefineRecordType => {
+            crate::record::eval_define_record_type(list, env).map(Step::Done)
+        }
+        SpecialForm::Doc => eval_doc(list, env).map(Step::Done),
+        SpecialForm::Set => eval_set(list, env).map(Step::Done),
+        SpecialForm::While => eval_while(list, env).map(Step::Done),
+        SpecialForm::When => eval_when(list, env, true),
+        SpecialForm::Unless => eval_when(list, env, false),
+        SpecialForm::Try => eval_try(list, env),
+        SpecialForm::Delay => eval_delay(list, env).map(Step::Done),
+        SpecialForm::Do => eval_do(list, env),
+        SpecialForm::If => eval_if(list, env),
+        SpecialForm::Cond => eval_cond(list, env),
+        SpecialForm::Let => eval_let(list, env),
+        SpecialForm::LetStar => eval_let_star(list, env),
+        SpecialForm::Letrec | SpecialForm::LetrecStar => eval_letrec(list, env),
+        SpecialForm::LetValues => eval_let_values(list, env),
+        SpecialForm::Quote => eval_quote(list).map(Step::Done),
+        SpecialForm::Quasiquote => eval_quasiquote(list, env).map(Step::Done),
+        SpecialForm::List => eval_make_list(list, env).map(Step::Done),
+        SpecialForm::Print => eval_print(list, env).map(Step::Done),
+        SpecialForm::And => eval_and(list, env),
+        SpecialForm::Or => eval_or(list, env),
+        SpecialForm::Lambda => eval_function_definition(list, env).map(Step::Done),
+        SpecialForm::CaseLambda => eval_case_lambda(list, env).map(Step::Done),
+        SpecialForm::Else | SpecialForm::Arrow => {
+            Err(format!("{}: only allowed in a cond clause", keyword))
+        }
+        SpecialForm::Unquote | SpecialForm::UnquoteSplicing => {
+            Err(format!("{}: only allowed inside quasiquote", keyword))
+        }
+        SpecialForm::Range => Err("range: not implemented".to_string()),
     }
-    let keyword = match &list[0] {
-        Object::Keyword(kw) => kw.as_str(),
-        _ => return Err(format!("Expected keyword, found {:?}", list[0])),
+}
+
+// begin や lambda の本体。最後の式以外を順に評価し、最後の式は末尾位置として返す。
+fn eval_body(body: &[Object], mut env: Rc<RefCell<Env>>) -> Result<Step, String> {
+    let Some((last, init)) = body.split_last() else {
+        return Ok(Step::Done(Object::Void));
     };
-    match keyword {
-        "begin" => eval_begin(list, env),
-        "define" => eval_define(list, env),
-        "if" => eval_if(list, env),
-        "lambda" => eval_function_definition(list, env),
-        _ => Err(format!("Unsupported keyword: {}", keyword)),
+    for expr in init {
+        eval_obj(expr, &mut env)?;
     }
+    Ok(Step::Tail(last.clone(), env))
 }
 
-fn eval_begin(list: &Vec<Object>, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    let mut result = Object::Void;
-    for expr in &list[1..] {
-        result = eval_obj(expr, env)?;
+// (define name ...) や (define (name ...) ...) の形の式が束縛する名前。keyword には define-private なども渡せる。
+pub(crate) fn defined_name(form: &Object, keyword: SpecialForm) -> Option<&str> {
+    let Object::List(list) = form else {
+        return None;
+    };
+    match list.as_slice() {
+        [Object::Keyword(head), Object::Symbol(name), ..] if *head == keyword => Some(name),
+        [Object::Keyword(head), Object::List(signature), ..] if *head == keyword => {
+            match signature.first() {
+                Some(Object::Symbol(name)) => Some(name),
+                _ => None,
+            }
+        }
+        _ => None,
     }
-    Ok(result)
 }
 
-fn eval_define(list: &Vec<Object>, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    let sym = match &list[1] {
-        Object::Symbol(s) => s.clone(),
-        _ => return Err(format!("Invalid define syntax: {:?}", list)),
+// lambda や let の本体のように、新しい Env を作って評価する本体。
+// 本体の中の define は letrec* と同じく、本体の Env にはじめから束縛があるものとして扱う。
+// 後の define は先の define の値を使えるが、define より前にその名前の値を使うと
+// 外側の同じ名前の束縛は見ずに "used before initialization" のエラーになる。
+fn eval_scope_body(body: &[Object], env: Rc<RefCell<Env>>) -> Result<Step, String> {
+    let mut names: Vec<&str> = Vec::new();
+    for form in body {
+        let Some(name) = defined_name(form, SpecialForm::Define) else {
+            continue;
+        };
+        if names.contains(&name) {
+            return Err(format!(
+                "define: {} is defined more than once in the same body",
+                name
+            ));
+        }
+        names.push(name);
+    }
+    for name in names {
+        env.borrow_mut().declare(name);
+    }
+    eval_body(body, env)
+}
+
+fn eval_define(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    if list.len() < 3 {
+        return Err(format!("Invalid define syntax: {}", debug_form(list)));
+    }
+    let mut doc = None;
+    let (sym, val) = match &list[1] {
+        Object::Symbol(s) if list.len() == 3 => (s.to_string(), eval_obj(&list[2], env)?),
+        // (define (f x y) body...) は (define f (lambda (x y) body...)) と同じ
+        // 本体の先頭が文字列で後に式が続くなら、それは本体ではなく (doc f) で読める説明になる
+        signature => match split_signature(signature) {
+            Some((s, params)) => {
+                let mut body = &list[2..];
+                if let [Object::String(text), rest @ ..] = body
+                    && !rest.is_empty()
+                {
+                    doc = Some(text.to_string());
+                    body = rest;
+                }
+                let mut lambda = vec![Object::Keyword(SpecialForm::Lambda), params];
+                lambda.extend_from_slice(body);
+                (s.to_string(), eval_function_definition(&lambda, env)?)
+            }
+            None => return Err(format!("Invalid define syntax: {}", debug_form(list))),
+        },
     };
 
-    let val = eval_obj(&list[2], env)?;
-    env.borrow_mut().set(&sym, val);
+    // (define f (lambda ...)) でも、作ったばかりの lambda なら f という名前を付ける
+    let val = match val {
+        Object::Lambda(mut lambda) => {
+            if let Some(lambda) = Rc::get_mut(&mut lambda)
+                && lambda.name.is_none()
+            {
+                lambda.name = Some(sym.as_str().into());
+            }
+            Object::Lambda(lambda)
+        }
+        val => val,
+    };
+    let mut env = env.borrow_mut();
+    env.define(&sym, val)?;
+    env.set_doc(&sym, doc);
     Ok(Object::Void)
 }
 
-fn eval_binary_op(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    if list.len() != 3 {
-        return Err(format!("Invalid binary operation: {:?}", list));
+// (doc f) は f の束縛に付けた説明の文字列を返す。説明が無ければ Void。
+fn eval_doc(list: &[Object], env: &Rc<RefCell<Env>>) -> Result<Object, String> {
+    let [_, Object::Symbol(name)] = list else {
+        return Err(format!("Invalid doc syntax: {}", debug_form(list)));
+    };
+    if lookup(env, name).is_none() {
+        return Err(format!("doc: {} is not defined", name));
     }
+    // 組み込み関数は Builtin の doc を使う。define し直していればその説明
+    let doc = env.borrow().doc(name).or_else(|| match lookup(env, name) {
+        Some(Object::Builtin(builtin)) => Some(builtin.full_doc()),
+        _ => None,
+    });
+    Ok(match doc {
+        Some(doc) => Object::String(doc.into()),
+        None => Object::Void,
+    })
+}
 
-    let op = list[0].clone();
-    let left = eval_obj(&list[1], env)?;
-    let right = eval_obj(&list[2], env)?;
+// (set! name expr) は name の既存の束縛を expr の値に書き換える。
+fn eval_set(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let [_, Object::Symbol(name), expr] = list else {
+        return Err(format!("Invalid set! syntax: {}", debug_form(list)));
+    };
+    let val = eval_obj(expr, env)?;
+    // syntax-rules の展開で付けた別名 (name#1) に束縛が無ければ、元の名前の束縛を書き換える
+    let name = match original_name(name) {
+        Some(base) if env.borrow().get(name).is_none() => base,
+        _ => name.as_ref(),
+    };
+    env.borrow_mut().assign(name, val)?;
+    Ok(Object::Void)
+}
 
-    match op {
-        Object::BinaryOp(s) => match s.as_str() {
-            "+" => match (left, right) {
-                (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l + r)),
-                (Object::Float(l), Object::Float(r)) => Ok(Object::Float(l + r)),
-                (Object::Integer(l), Object::Float(r)) => Ok(Object::Float(l as f64 + r)),
-                (Object::Float(l), Object::Integer(r)) => Ok(Object::Float(l + r as f64)),
-                (left, right) => Err(format!("Invalid operands for +: {:?}, {:?}", &left, right)),
-            },
-            "-" => match (left, right) {
-                (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l - r)),
-                (Object::Float(l), Object::Float(r)) => Ok(Object::Float(l - r)),
-                (Object::Integer(l), Object::Float(r)) => Ok(Object::Float(l as f64 - r)),
-                (Object::Float(l), Object::Integer(r)) => Ok(Object::Float(l - r as f64)),
-                (left, right) => Err(format!("Invalid operands for -: {:?}, {:?}", left, right)),
-            },
-            "*" => match (left, right) {
-                (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l * r)),
-                (Object::Float(l), Object::Float(r)) => Ok(Object::Float(l * r)),
-                (Object::Integer(l), Object::Float(r)) => Ok(Object::Float(l as f64 * r)),
-                (Object::Float(l), Object::Integer(r)) => Ok(Object::Float(l * r as f64)),
-                (left, right) => Err(format!("Invalid operands for *: {:?}, {:?}", left, right)),
-            },
-            "/" => match (left, right) {
-                (Object::Integer(l), Object::Integer(r)) => {
-                    if r == 0 {
-                        Err("Division by zero".to_string())
-                    } else {
-                        Ok(Object::Integer(l / r))
-                    }
-                }
-                (Object::Float(l), Object::Float(r)) => {
-                    if r == 0.0 {
-                        Err("Division by zero".to_string())
-                    } else {
-                        Ok(Object::Float(l / r))
-                    }
-                }
-                (Object::Integer(l), Object::Float(r)) => {
-                    if r == 0.0 {
-                        Err("Division by zero".to_string())
-                    } else {
-                        Ok(Object::Float(l as f64 / r))
-                    }
-                }
-                (Object::Float(l), Object::Integer(r)) => {
-                    if r == 0 {
-                        Err("Division by zero".to_string())
-                    } else {
-                        Ok(Object::Float(l / r as f64))
-                    }
-                }
-                (left, right) => Err(format!("Invalid operands for /: {:?}, {:?}", left, right)),
-            },
-            "<" => match (left, right) {
-                (Object::Integer(l), Object::Integer(r)) => Ok(Object::Bool(l < r)),
-                (Object::Float(l), Object::Float(r)) => Ok(Object::Bool(l < r)),
-                (Object::Integer(l), Object::Float(r)) => Ok(Object::Bool((l as f64) < r)),
-                (Object::Float(l), Object::Integer(r)) => Ok(Object::Bool(l < (r as f64))),
-                (left, right) => Err(format!("Invalid operands for <: {:?}, {:?}", left, right)),
-            },
-            ">" => match (left, right) {
-                (Object::Integer(l), Object::Integer(r)) => Ok(Object::Bool(l > r)),
-                (Object::Float(l), Object::Float(r)) => Ok(Object::Bool(l > r)),
-                (Object::Integer(l), Object::Float(r)) => Ok(Object::Bool((l as f64) > r)),
-                (Object::Float(l), Object::Integer(r)) => Ok(Object::Bool(l > (r as f64))),
-                (left, right) => Err(format!("Invalid operands for >: {:?}, {:?}", left, right)),
-            },
-            _ => Err(format!("Unsupported binary operator: {}", s)),
-        },
-        _ => Err(format!("Invalid binary operation: {:?}", op)),
+// (while cond body...) は cond が true の間 body を繰り返す。値は Void。
+fn eval_while(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    if list.len() < 2 {
+        return Err(format!("Invalid while syntax: {}", debug_form(list)));
+    }
+    while eval_condition(&list[1], env)? {
+        for expr in &list[2..] {
+            eval_obj(expr, env)?;
+        }
     }
+    Ok(Object::Void)
 }
 
-fn eval_if(list: &Vec<Object>, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
-    let cond_obj = eval_obj(&list[1], env)?;
-    let cond = match cond_obj {
-        Object::Bool(b) => b,
-        _ => return Err(format!("Condition must be a boolean: {:?}", cond_obj)),
+// (do ((var init step) ...) (test result...) body...)
+// test が true になるまで body を評価し、そのたびに各 var を step の値に束縛し直す。step は省略できる。
+// test が true になったら result... を評価して最後の値を返す。繰り返しごとに新しい Env で束縛する。
+fn eval_do(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let invalid = || format!("Invalid do syntax: {}", debug_form(list));
+    let (Some(Object::List(specs)), Some(Object::List(exit))) = (list.get(1), list.get(2)) else {
+        return Err(invalid());
     };
-    if cond {
-        eval_obj(&list[2], env)
-    } else {
-        eval_obj(&list[3], env)
+    let Some((test, result)) = exit.split_first() else {
+        return Err(invalid());
+    };
+    let mut vars = Vec::with_capacity(specs.len());
+    for spec in specs.iter() {
+        match spec {
+            Object::List(spec) => match spec.as_slice() {
+                [Object::Symbol(name), init] => vars.push((name, init, None)),
+                [Object::Symbol(name), init, step] => vars.push((name, init, Some(step))),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        }
+    }
+
+    let mut loop_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    for (name, init, _) in &vars {
+        let val = eval_obj(init, env)?;
+        loop_env.borrow_mut().set(name, val);
     }
+    while !eval_condition(test, &mut loop_env)? {
+        for expr in &list[3..] {
+            eval_obj(expr, &mut loop_env)?;
+        }
+        let mut next = Env::extend(Rc::clone(env));
+        for (name, _, step) in &vars {
+            let val = match step {
+                Some(step) => eval_obj(step, &mut loop_env)?,
+                None => loop_env.borrow().get(name).unwrap_or(Object::Void),
+            };
+            next.set(name, val);
+        }
+        loop_env = Rc::new(RefCell::new(next));
+    }
+    eval_body(result, loop_env)
 }
 
-fn eval_function_definition(
-    list: &Vec<Object>,
-    _env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-    let params = match &list[1] {
-        Object::List(list) => {
-            let mut params = Vec::new();
-            for param in list.iter() {
-                match param {
-                    Object::Symbol(s) => params.push(s.clone()),
-                    _ => return Err(format!("Invalid lamdba parameter: {:?}", param)),
-                }
+// (f x y) や (f x . rest) を、名前と lambda の仮引数の (x y) や (x . rest) に分ける。(f . args) の仮引数は args。
+fn split_signature(signature: &Object) -> Option<(&Rc<str>, Object)> {
+    match signature {
+        Object::List(signature) => match signature.split_first() {
+            Some((Object::Symbol(name), params)) => {
+                Some((name, Object::List(Rc::new(params.to_vec()))))
             }
-            params
-        }
-        _ => return Err(format!("Invalid lambda parameters: {:?}", list[1])),
+            _ => None,
+        },
+        Object::Pair(pair) => match &pair.car {
+            Object::Symbol(name) => Some((name, pair.cdr.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// (define-macro (name params...) body...)
+// 本体は展開時に、評価していない引数の式を受け取って評価され、その結果が呼び出しの式に置き換わる。
+fn eval_define_macro(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let Some((name, params)) = list
+        .get(1)
+        .filter(|_| list.len() >= 3)
+        .and_then(split_signature)
+    else {
+        return Err(format!("Invalid define-macro syntax: {}", debug_form(list)));
     };
-    let body = match &list[2] {
-        Object::List(list) => list.as_ref().clone(),
-        _ => return Err(format!("Invalid lambda body: {:?}", list[2])),
+    let mut lambda = vec![Object::Keyword(SpecialForm::Lambda), params];
+    lambda.extend_from_slice(&list[2..]);
+    let Object::Lambda(lambda) = eval_function_definition(&lambda, env)? else {
+        unreachable!();
     };
-    Ok(Object::Lambda(params, body))
+    env.borrow_mut().define(name, Object::Macro(lambda))?;
+    Ok(Object::Void)
 }
 
-fn eval_function_call(
-    func_name: &String,
-    list: &Rc<Vec<Object>>,
-    env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-    let lambda = env.borrow().get(func_name);
-    if lambda.is_none() {
-        return Err(format!("Undefined function: {}", func_name));
-    }
-    match lambda.unwrap() {
-        Object::Lambda(params, body) => {
-            let mut func_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
-            for (i, param) in params.iter().enumerate() {
-                let arg = eval_obj(&list[i + 1], env)?;
-                func_env.borrow_mut().set(param, arg);
-            }
-            eval_obj(&Object::List(Rc::new(body)), &mut func_env)
+// (define-syntax name (syntax-rules (literal ...) (pattern template) ...))
+fn eval_define_syntax(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    match list {
+        [_, Object::Symbol(name), spec] => {
+            let rules = SyntaxRules::parse(spec)?;
+            env.borrow_mut()
+                .define(name, Object::SyntaxRules(Rc::new(rules)))?;
+            Ok(Object::Void)
         }
-        _ => Err(format!("{} is not a function", func_name)),
+        _ => Err(format!(
+            "Invalid define-syntax syntax: {}",
+            debug_form(list)
+        )),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// let の ((name expr) ...) を名前と式の組にする。
+fn let_bindings(bindings: &Object) -> Result<Vec<(&str, &Object)>, String> {
+    let bindings = match bindings {
+        Object::List(bindings) => bindings,
+        _ => return Err(format!("Invalid let bindings: {}", debug(bindings))),
+    };
+    bindings
+        .iter()
+        .map(|binding| match binding {
+            Object::List(pair) if pair.len() == 2 => match &pair[0] {
+                Object::Symbol(s) => Ok((s.as_ref(), &pair[1])),
+                _ => Err(format!("Invalid let binding: {}", debug(binding))),
+            },
+            _ => Err(format!("Invalid let binding: {}", debug(binding))),
+        })
+        .collect()
+}
 
-    #[test]
-    fn test_simple_add() {
-        let mut env = Rc::new(RefCell::new(Env::new()));
-        let result = eval("(+ 1 2)", &mut env).unwrap();
-        assert_eq!(result, Object::Integer(3));
+// (let ((x 1) (y 2)) body...)
+// 束縛の値は外側の Env で評価し、本体は新しい子の Env で評価するので外側には漏れない。
+fn eval_let(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    if list.len() < 3 {
+        return Err(format!("Invalid let syntax: {}", debug_form(list)));
+    }
+    if matches!(list[1], Object::Symbol(_)) {
+        return eval_named_let(list, env);
     }
 
-    #[test]
-    fn test_circle_area() {
-        let mut env = Rc::new(RefCell::new(Env::new()));
-        let program = "
-        (begin
-            (define r 10)
-            (define pi 314)
-            (* pi (* r r))
-        )
-        ";
+    let let_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    for (name, expr) in let_bindings(&list[1])? {
+        let val = eval_obj(expr, env)?;
+        let_env.borrow_mut().set(name, val);
+    }
+    eval_scope_body(&list[2..], let_env)
+}
 
-        let result = eval(program, &mut env).unwrap();
-        assert_eq!(result, Object::Integer(314 * 10 * 10));
+// (let* ((x 1) (y (+ x 1))) body...)
+// 束縛を 1 つずつ新しい子の Env に加えるので、後の束縛の式から前の変数を参照できる。
+fn eval_let_star(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    if list.len() < 3 {
+        return Err(format!("Invalid let* syntax: {}", debug_form(list)));
     }
+    let mut let_env = Rc::clone(env);
+    for (name, expr) in let_bindings(&list[1])? {
+        let val = eval_obj(expr, &mut let_env)?;
+        let_env = Rc::new(RefCell::new(Env::extend(let_env)));
+        let_env.borrow_mut().set(name, val);
+    }
+    eval_scope_body(&list[2..], let_env)
+}
 
-    #[test]
-    fn test_srq_function() {
-        let mut env = Rc::new(RefCell::new(Env::new()));
-        let program = "
-        (begin
-            (define sqr (lambda (x) (* x x)))
-            (sqr 10)
-        )
-        ";
+// (letrec ((even? (lambda (n) ... (odd? ...))) (odd? (lambda (n) ...))) body...)
+// 束縛の式も新しい子の Env で評価するので、局所的な関数がお互いを呼び出せる。
+// 式は先頭から順に評価する (letrec* と同じ)。まだ値を束縛していない変数を使うと、
+// 外側の同じ名前の束縛は見ずに "used before initialization" のエラーになる。
+fn eval_letrec(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    if list.len() < 3 {
+        return Err(format!("Invalid letrec syntax: {}", debug_form(list)));
+    }
+    let mut let_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    let bindings = let_bindings(&list[1])?;
+    for (name, _) in &bindings {
+        let_env.borrow_mut().declare(name);
+    }
+    for (name, expr) in bindings {
+        let val = eval_obj(expr, &mut let_env)?;
+        let_env.borrow_mut().set(name, val);
+    }
+    eval_scope_body(&list[2..], let_env)
+}
 
-        let result = eval(program, &mut env).unwrap();
-        assert_eq!(result, Object::Integer(100));
+// (let-values (((q r) (divmod 7 2)) ((x) expr)) body...)
+// let と同じだが、式が返した多値を変数の並びにそれぞれ束縛する。値の数が変数の数と違えばエラー。
+fn eval_let_values(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let bindings = match list.get(1) {
+        Some(Object::List(bindings)) if list.len() >= 3 => bindings,
+        _ => return Err(format!("Invalid let-values syntax: {}", debug_form(list))),
+    };
+    let let_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    for binding in bindings.iter() {
+        let (formals, expr) = match binding {
+            Object::List(pair) if pair.len() == 2 => match &pair[0] {
+                Object::List(formals) => (formals, &pair[1]),
+                _ => return Err(format!("Invalid let-values binding: {}", debug(binding))),
+            },
+            _ => return Err(format!("Invalid let-values binding: {}", debug(binding))),
+        };
+        let values = crate::values::spread(eval_obj(expr, env)?);
+        if values.len() != formals.len() {
+            return Err(format!(
+                "let-values: expected {} values, got {}",
+                formals.len(),
+                values.len()
+            ));
+        }
+        for (formal, value) in formals.iter().zip(values) {
+            match formal {
+                Object::Symbol(name) => let_env.borrow_mut().set(name, value),
+                _ => return Err(format!("Invalid let-values binding: {}", debug(binding))),
+            }
+        }
     }
+    eval_scope_body(&list[2..], let_env)
+}
 
-    #[test]
-    fn test_fibonacci() {
-        let mut env = Rc::new(RefCell::new(Env::new()));
-        let program = "
-        (begin
-            (define fib
-                (lambda (n)
-                    (if (< n 2)
-                        n
-                        (+ (fib (- n 1)) (fib (- n 2)))
-                    )
+thread_local! {
+    static OUTPUT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// print の出力先。capture_output の中では標準出力の代わりに文字列に溜める。
+pub(crate) fn write_output(s: &str) {
+    OUTPUT.with_borrow_mut(|output| match output {
+        Some(buffer) => buffer.push_str(s),
+        None => print!("{}", s),
+    })
+}
+
+// f を実行する間の print の出力を文字列として受け取る。Jupyter カーネルなどで使う。
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let outer = OUTPUT.replace(Some(String::new()));
+    let result = f();
+    let captured = OUTPUT.replace(outer).unwrap_or_default();
+    (result, captured)
+}
+
+thread_local! {
+    static WARNINGS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+// 評価は続けるが知らせたいこと (非推奨の関数を使ったなど) を伝える。
+// capture_warnings の中では集め、それ以外では標準エラー出力に書く。
+pub(crate) fn write_warning(message: String) {
+    WARNINGS.with_borrow_mut(|warnings| match warnings {
+        Some(warnings) => warnings.push(message),
+        None => eprintln!("warning: {}", message),
+    })
+}
+
+pub fn capture_warnings<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let outer = WARNINGS.replace(Some(Vec::new()));
+    let result = f();
+    let captured = WARNINGS.replace(outer).unwrap_or_default();
+    (result, captured)
+}
+
+// (print a b ...) は値を空白区切りで出力して改行する。
+fn eval_print(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut values = Vec::with_capacity(list.len() - 1);
+    for expr in &list[1..] {
+        values.push(eval_obj(expr, env)?.to_string());
+    }
+    write_output(&format!("{}\n", values.join(" ")));
+    Ok(Object::Void)
+}
+
+// (let loop ((i 0) (acc 0)) body...)
+// 束縛の変数を引数に取る関数を loop という名前で本体から呼べるようにし、初期値で呼び出す。
+fn eval_named_let(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let name = match &list[1] {
+        Object::Symbol(s) => s,
+        _ => return Err(format!("Invalid let syntax: {}", debug_form(list))),
+    };
+    if list.len() < 4 {
+        return Err(format!("Invalid let syntax: {}", debug_form(list)));
+    }
+    let bindings = let_bindings(&list[2])?;
+
+    let loop_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    let func = Object::Lambda(Rc::new(Lambda {
+        params: bindings.iter().map(|(name, _)| name.to_string()).collect(),
+        rest: None,
+        types: Vec::new(),
+        name: Some(name.clone()),
+        body: Rc::new(list[3..].to_vec()),
+        env: Rc::clone(&loop_env),
+    }));
+    loop_env.borrow_mut().set(name, func.clone());
+
+    let mut args = Vec::with_capacity(bindings.len());
+    for (_, expr) in bindings {
+        args.push(eval_obj(expr, env)?);
+    }
+    call(&func, &args, env)
+}
+
+// (quote expr) は expr を評価せずにデータとして返す。プログラムの List は ListData になる。
+fn eval_quote(list: &[Object]) -> Result<Object, String> {
+    if list.len() != 2 {
+        return Err(format!("Invalid quote syntax: {}", debug_form(list)));
+    }
+    Ok(to_data(&list[1]))
+}
+
+// `(a ,b ,@c) は unquote の中の式だけを評価し、unquote-splicing の値のリストは展開して埋め込む。
+// quasiquote が入れ子になっている場合は、一番外側と同じ深さの unquote だけを評価する。
+fn eval_quasiquote(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    if list.len() != 2 {
+        return Err(format!("Invalid quasiquote syntax: {}", debug_form(list)));
+    }
+    quasiquote(&list[1], 1, env)
+}
+
+// (keyword x) の形なら keyword と x を返す。
+fn prefixed_form(obj: &Object) -> Option<(SpecialForm, &Object)> {
+    match obj {
+        Object::List(list) => match list.as_slice() {
+            [Object::Keyword(kw), x] => Some((*kw, x)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn quasiquote(obj: &Object, depth: usize, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let wrap = |keyword: SpecialForm, obj: Object| {
+        pair_list(vec![Object::Keyword(keyword), obj], Object::nil())
+    };
+    match prefixed_form(obj) {
+        Some((SpecialForm::Unquote, x)) if depth == 1 => return eval_obj(x, env),
+        Some((SpecialForm::UnquoteSplicing, _)) if depth == 1 => {
+            return Err("unquote-splicing: not inside a list".to_string());
+        }
+        Some((kw @ (SpecialForm::Unquote | SpecialForm::UnquoteSplicing), x)) => {
+            return Ok(wrap(kw, quasiquote(x, depth - 1, env)?));
+        }
+        Some((SpecialForm::Quasiquote, x)) => {
+            return Ok(wrap(
+                SpecialForm::Quasiquote,
+                quasiquote(x, depth + 1, env)?,
+            ));
+        }
+        _ => {}
+    }
+    let list = match obj {
+        Object::List(list) => list,
+        Object::Pair(pair) => {
+            return Ok(Object::Pair(Rc::new(Pair {
+                car: quasiquote(&pair.car, depth, env)?,
+                cdr: quasiquote(&pair.cdr, depth, env)?,
+            })));
+        }
+        _ => return Ok(obj.clone()),
+    };
+    // `(a . ,b) は読んだ時点で (a unquote b) になっているので、最後から 2 番目の unquote は cdr の unquote として扱う。
+    let (list, tail) = match list.as_slice() {
+        [init @ .., Object::Keyword(SpecialForm::Unquote), x] if !init.is_empty() => {
+            let tail = match depth {
+                1 => eval_obj(x, env)?,
+                _ => wrap(SpecialForm::Unquote, quasiquote(x, depth - 1, env)?),
+            };
+            (init, tail)
+        }
+        list => (list, Object::nil()),
+    };
+    let mut items = Vec::with_capacity(list.len());
+    for item in list.iter() {
+        match prefixed_form(item) {
+            Some((SpecialForm::UnquoteSplicing, x)) if depth == 1 => {
+                let value = eval_obj(x, env)?;
+                let Some(values) = list_items(&value) else {
+                    return Err(format!("unquote-splicing: expected a list, got {}", value));
+                };
+                items.extend(values);
+            }
+            _ => items.push(quasiquote(item, depth, env)?),
+        }
+    }
+    Ok(pair_list(items, tail))
+}
+
+// (list a b ...) は引数を評価してデータのリストにする。マクロで式を組み立てるときに使う。
+fn eval_make_list(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    if list.len() == 1 {
+        return Ok(Object::nil());
+    }
+    let mut items = Vec::with_capacity(list.len() - 1);
+    for expr in &list[1..] {
+        items.push(eval_obj(expr, env)?);
+    }
+    Ok(pair_list(items, Object::nil()))
+}
+
+fn to_data(obj: &Object) -> Object {
+    match obj {
+        Object::List(list) if list.is_empty() => Object::nil(),
+        Object::List(list) => pair_list(list.iter().map(to_data).collect(), Object::nil()),
+        Object::Pair(pair) => Object::Pair(Rc::new(Pair {
+            car: to_data(&pair.car),
+            cdr: to_data(&pair.cdr),
+        })),
+        // ベクタは書き換えられるので、リテラルでも評価するたびに新しく作る
+        Object::Vector(vector) => Object::Vector(Rc::new(RefCell::new(
+            vector.borrow().iter().map(to_data).collect(),
+        ))),
+        _ => obj.clone(),
+    }
+}
+
+// to_data の逆。マクロが返したデータを評価できる式に戻す。真リストのペアの並びは List にする。
+pub(crate) fn to_code(obj: &Object) -> Object {
+    match obj {
+        Object::ListData(list) => Object::List(Rc::new(list.iter().map(to_code).collect())),
+        Object::Pair(_) if let Some(items) = list_items(obj) => {
+            Object::List(Rc::new(items.iter().map(to_code).collect()))
+        }
+        Object::Pair(pair) => Object::Pair(Rc::new(Pair {
+            car: to_code(&pair.car),
+            cdr: to_code(&pair.cdr),
+        })),
+        _ => obj.clone(),
+    }
+}
+
+// (and a b ...) は左から評価し、#f が出たらそこで止めて #f を返す。すべて真なら最後の値を返す。
+fn eval_and(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let Some((last, init)) = list[1..].split_last() else {
+        return Ok(Step::Done(Object::truth(true)));
+    };
+    for expr in init {
+        let value = eval_obj(expr, env)?;
+        if matches!(value, Object::Bool(false)) {
+            return Ok(Step::Done(value));
+        }
+    }
+    Ok(Step::Tail(last.clone(), Rc::clone(env)))
+}
+
+// (or a b ...) は左から評価し、最初の #f でない値を返す。すべて #f なら #f を返す。
+fn eval_or(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let Some((last, init)) = list[1..].split_last() else {
+        return Ok(Step::Done(Object::truth(false)));
+    };
+    for expr in init {
+        let value = eval_obj(expr, env)?;
+        if !matches!(value, Object::Bool(false)) {
+            return Ok(Step::Done(value));
+        }
+    }
+    Ok(Step::Tail(last.clone(), Rc::clone(env)))
+}
+
+// (op a b)。整数どうしなら整数で、どちらかが小数なら小数で計算する。
+// 整数の計算が i64 に収まらなければ BigInt で計算し直す。比較は (== 1 1.0) のように数としての値で比べる。
+fn eval_binary_op(op: Op, list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    if list.len() != 3 {
+        return Err(format!(
+            "{}: expected 2 arguments, got {}",
+            op,
+            list.len() - 1
+        ));
+    }
+    let left = eval_obj(&list[1], env)?;
+    let right = eval_obj(&list[2], env)?;
+    if !matches!(op, Op::Add | Op::Sub | Op::Mul | Op::Div) && compare(op, 0, 0).is_none() {
+        return Err(format!("Unsupported binary operator: {}", op));
+    }
+    if op == Op::Div && (right == Object::Integer(0) || right == Object::Float(0.0)) {
+        return Err("Division by zero".to_string());
+    }
+    if let (Object::Integer(l), Object::Integer(r)) = (&left, &right) {
+        let (l, r) = (*l, *r);
+        if let Some(b) = compare(op, l, r) {
+            return Ok(Object::truth(b));
+        }
+        let result = match op {
+            Op::Add => l.checked_add(r),
+            Op::Sub => l.checked_sub(r),
+            Op::Mul => l.checked_mul(r),
+            _ => l.checked_div(r),
+        };
+        if let Some(n) = result {
+            return Ok(Object::Integer(n));
+        }
+    }
+    if let (Some(l), Some(r)) = (as_bigint(&left), as_bigint(&right)) {
+        if let Some(b) = compare(op, &l, &r) {
+            return Ok(Object::truth(b));
+        }
+        return Ok(match op {
+            Op::Add => Object::from(&*l + &*r),
+            Op::Sub => Object::from(&*l - &*r),
+            Op::Mul => Object::from(&*l * &*r),
+            _ => Object::from(&*l / &*r), // 0 は Integer なので、0 での割り算は上で弾いている
+        });
+    }
+    let as_float = |obj: &Object| match obj {
+        Object::Integer(n) => Some(*n as f64),
+        Object::BigInt(n) => Some(n.to_f64()),
+        Object::Float(f) => Some(*f),
+        _ => None,
+    };
+    let (Some(l), Some(r)) = (as_float(&left), as_float(&right)) else {
+        return Err(format!(
+            "{}: expected numbers, got {} and {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        ));
+    };
+    if let Some(b) = compare(op, l, r) {
+        return Ok(Object::truth(b));
+    }
+    Ok(match op {
+        Op::Add => Object::Float(l + r),
+        Op::Sub => Object::Float(l - r),
+        Op::Mul => Object::Float(l * r),
+        _ => Object::Float(l / r),
+    })
+}
+
+// 比較の演算子ならその結果。それ以外の演算子は None。
+fn compare<T: PartialOrd>(op: Op, l: T, r: T) -> Option<bool> {
+    match op {
+        Op::Lt => Some(l < r),
+        Op::Gt => Some(l > r),
+        Op::Le => Some(l <= r),
+        Op::Ge => Some(l >= r),
+        Op::EqEq => Some(l == r),
+        Op::Ne => Some(l != r),
+        _ => None,
+    }
+}
+
+fn as_bigint(obj: &Object) -> Option<Cow<'_, BigInt>> {
+    match obj {
+        Object::Integer(n) => Some(Cow::Owned(BigInt::from(*n))),
+        Object::BigInt(n) => Some(Cow::Borrowed(n)),
+        _ => None,
+    }
+}
+
+// (cond (test expr...) (test => receiver) (test) (else expr...))
+// 最初に #f 以外の値になった test の節を評価する。and や or と同じく、#f 以外はすべて真として扱う。
+// => の節は test の値を引数にして receiver を呼び、式の無い節は test の値を返す。どの節も選ばれなければ Void。
+fn eval_cond(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    for clause in &list[1..] {
+        let Some((test, body)) = (match clause {
+            Object::List(clause) => clause.split_first(),
+            _ => None,
+        }) else {
+            return Err(format!("Invalid cond clause: {}", debug(clause)));
+        };
+        if matches!(test, Object::Keyword(SpecialForm::Else)) {
+            return eval_body(body, Rc::clone(env));
+        }
+        let value = eval_obj(test, env)?;
+        if matches!(value, Object::Bool(false)) {
+            continue;
+        }
+        return match body {
+            [] => Ok(Step::Done(value)),
+            [Object::Keyword(SpecialForm::Arrow), receiver] => {
+                let receiver = eval_obj(receiver, env)?;
+                call(&receiver, &[value], env)
+            }
+            _ => eval_body(body, Rc::clone(env)),
+        };
+    }
+    Ok(Step::Done(Object::Void))
+}
+
+// if や while の条件を評価する。値は真偽値でなければならない。
+fn eval_condition(expr: &Object, env: &mut Rc<RefCell<Env>>) -> Result<bool, String> {
+    match eval_obj(expr, env)? {
+        Object::Bool(b) => Ok(b),
+        cond_obj => Err(format!("Condition must be a boolean: {}", debug(&cond_obj))),
+    }
+}
+
+// (try body... (catch (e) handler...))
+// body の評価がエラーになったら、raise された値かエラーオブジェクトを e に束縛して handler を評価する。
+fn eval_try(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let invalid = || format!("Invalid try syntax: {}", debug_form(list));
+    let Some((Object::List(clause), body)) = list[1..].split_last() else {
+        return Err(invalid());
+    };
+    let (var, handler) = match clause.as_slice() {
+        [Object::Symbol(catch), Object::List(var), handler @ ..] if catch.as_ref() == "catch" => {
+            match var.as_slice() {
+                [Object::Symbol(var)] => (var, handler),
+                _ => return Err(invalid()),
+            }
+        }
+        _ => return Err(invalid()),
+    };
+
+    let mut result = Ok(Object::Void);
+    for expr in body {
+        result = eval_obj(expr, env);
+        if result.is_err() {
+            break;
+        }
+    }
+    match result {
+        Ok(value) => Ok(Step::Done(value)),
+        // 継続への脱出はエラーではないので捕まえない
+        Err(message) if crate::continuation::escaping() => Err(message),
+        Err(message) => {
+            forget_failed();
+            let handler_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+            handler_env
+                .borrow_mut()
+                .set(var, crate::exception::caught(message));
+            eval_body(handler, handler_env)
+        }
+    }
+}
+
+// (delay expr) は expr を評価せずに Promise にする。expr は force したときにこの Env で評価する。
+fn eval_delay(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let [_, expr] = list else {
+        return Err(format!("Invalid delay syntax: {}", debug_form(list)));
+    };
+    Ok(Object::Promise(Rc::new(Promise {
+        state: RefCell::new(PromiseState::Delayed(expr.clone(), Rc::clone(env))),
+    })))
+}
+
+// Promise の値を求める。評価は最初の 1 回だけで、以降は覚えておいた値を返す。
+// 評価の途中で同じ Promise を force して先に値が決まった場合は、その値を使う。
+pub(crate) fn force(promise: &Promise) -> Result<Object, String> {
+    let (expr, mut env) = match &*promise.state.borrow() {
+        PromiseState::Forced(value) => return Ok(value.clone()),
+        PromiseState::Delayed(expr, env) => (expr.clone(), Rc::clone(env)),
+    };
+    let value = eval_obj(&expr, &mut env)?;
+    let mut state = promise.state.borrow_mut();
+    match &*state {
+        PromiseState::Forced(value) => Ok(value.clone()),
+        PromiseState::Delayed(..) => {
+            *state = PromiseState::Forced(value.clone());
+            Ok(value)
+        }
+    }
+}
+
+// (when cond body...) は cond が true のとき、(unless cond body...) は false のときに body を順に評価する。
+// 評価しなかった場合は Void を返す。
+fn eval_when(list: &[Object], env: &mut Rc<RefCell<Env>>, expected: bool) -> Result<Step, String> {
+    if list.len() < 2 {
+        return Err(format!("Invalid {} syntax: {}", list[0], debug_form(list)));
+    }
+    if eval_condition(&list[1], env)? == expected {
+        eval_body(&list[2..], Rc::clone(env))
+    } else {
+        Ok(Step::Done(Object::Void))
+    }
+}
+
+// (if cond then else)。片方の分岐だけなら when や unless を使う。
+fn eval_if(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    let [_, cond, then, otherwise] = list else {
+        return Err(format!("Invalid if syntax: {}", debug_form(list)));
+    };
+    let branch = if eval_condition(cond, env)? {
+        then
+    } else {
+        otherwise
+    };
+    Ok(Step::Tail(branch.clone(), Rc::clone(env)))
+}
+
+fn eval_function_definition(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let Some(params) = list.get(1) else {
+        return Err(format!("Invalid lambda syntax: {}", debug_form(list)));
+    };
+    // (x . rest) や args のように書くと、残りの引数をリストで受け取る
+    let (fixed, rest) = match params {
+        Object::List(list) => (list.to_vec(), None),
+        Object::Pair(_) => match pair_items(params) {
+            (fixed, Some(Object::Symbol(rest))) => (fixed, Some(rest.to_string())),
+            _ => return Err(format!("Invalid lambda parameters: {}", debug(params))),
+        },
+        Object::Symbol(rest) => (Vec::new(), Some(rest.to_string())),
+        _ => return Err(format!("Invalid lambda parameters: {}", debug(params))),
+    };
+    // (x : integer) は呼び出すときに型を確かめる仮引数。注釈の無い仮引数は any と同じ
+    let mut types = Vec::new();
+    let mut params = Vec::new();
+    for param in fixed.iter() {
+        match param {
+            Object::Symbol(s) => params.push(s.to_string()),
+            Object::List(annotated) => match &annotated[..] {
+                [Object::Symbol(s), Object::Symbol(colon), Object::Symbol(ty)]
+                    if colon.as_ref() == ":" =>
+                {
+                    let ty = ArgType::from_name(ty)
+                        .ok_or_else(|| format!("Unknown parameter type: {}", ty))?;
+                    types.resize(params.len(), ArgType::Any);
+                    types.push(ty);
+                    params.push(s.to_string());
+                }
+                _ => return Err(format!("Invalid lamdba parameter: {}", debug(param))),
+            },
+            _ => return Err(format!("Invalid lamdba parameter: {}", debug(param))),
+        }
+    }
+    if !types.is_empty() {
+        types.resize(params.len(), ArgType::Any);
+    }
+    // 本体は複数の式を持てる。呼び出し時には begin と同じように順に評価して最後の値を返す。
+    if list.len() < 3 {
+        return Err(format!("Lambda body is empty: {}", debug_form(list)));
+    }
+    let body = Rc::new(list[2..].to_vec());
+    Ok(Object::Lambda(Rc::new(Lambda {
+        params,
+        rest,
+        types,
+        name: None,
+        body,
+        env: Rc::clone(env),
+    })))
+}
+
+// case-lambda で作る関数。節はそれぞれ Lambda で、呼び出すと引数の数が合う最初の節を呼ぶ。
+#[derive(Debug)]
+struct CaseLambda(Vec<Object>);
+
+impl Foreign for CaseLambda {
+    fn type_name(&self) -> &str {
+        "procedure"
+    }
+}
+
+fn as_case_lambda(obj: &Object) -> Option<&CaseLambda> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<CaseLambda>(),
+        _ => None,
+    }
+}
+
+// (case-lambda ((x) body...) ((x y) body...) ...)
+fn eval_case_lambda(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut clauses = Vec::with_capacity(list.len() - 1);
+    for clause in &list[1..] {
+        let Object::List(clause) = clause else {
+            return Err(format!("Invalid case-lambda clause: {}", debug(clause)));
+        };
+        let mut lambda = Vec::with_capacity(clause.len() + 1);
+        lambda.push(list[0].clone());
+        lambda.extend(clause.iter().cloned());
+        clauses.push(eval_function_definition(&lambda, env)?);
+    }
+    Ok(Object::Foreign(Rc::new(CaseLambda(clauses))))
+}
+
+// 関数として呼び出せる値かどうか。
+pub(crate) fn is_procedure(obj: &Object) -> bool {
+    matches!(obj, Object::Lambda(_) | Object::Builtin(_))
+        || is_continuation(obj)
+        || is_record_procedure(obj)
+        || as_case_lambda(obj).is_some()
+        || is_parameter(obj)
+        || is_generator_procedure(obj)
+}
+
+fn eval_function_call(
+    func_name: &str,
+    list: &Rc<Vec<Object>>,
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Step, String> {
+    let func = lookup(env, func_name);
+    if func.is_none() {
+        return Err(format!("Undefined function: {}", func_name));
+    }
+    let func = func.unwrap();
+    if let Object::Macro(_) | Object::SyntaxRules(_) = func {
+        // 展開の後で定義されたマクロ。lambda の本体の中で define-macro した場合など
+        return Err(format!(
+            "Macro {} used before its definition was expanded",
+            func_name
+        ));
+    }
+    if !is_procedure(&func) {
+        return Err(format!("{} is not a function", func_name));
+    }
+
+    let mut args = Vec::with_capacity(list.len() - 1);
+    for arg in &list[1..] {
+        args.push(eval_obj(arg, env)?);
+    }
+    call(&func, &args, env)
+}
+
+// (f #:height 50 #:width 100) のように #:name value で渡した引数を、同じ名前の仮引数の位置に並べる。
+// ほかの引数は先頭の仮引数から順に渡すので、#:name はそれより後の仮引数にしか使えない。
+fn keyword_args(func: &str, params: &[String], args: &[Object]) -> Result<Vec<Object>, String> {
+    let mut keywords = Vec::new();
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let Object::KeywordArg(name) = arg else {
+            positional.push(arg.clone());
+            continue;
+        };
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("{}: missing value for keyword argument #:{}", func, name))?;
+        keywords.push((name, value));
+    }
+
+    let mut slots: Vec<Option<Object>> = vec![None; params.len()];
+    for (name, value) in keywords {
+        let index = params
+            .iter()
+            .position(|param| param == name.as_ref())
+            .ok_or_else(|| format!("{}: unknown keyword argument #:{}", func, name))?;
+        if index < positional.len() {
+            return Err(format!(
+                "{}: argument {} given both positionally and by keyword",
+                func, name
+            ));
+        }
+        if slots[index].replace(value.clone()).is_some() {
+            return Err(format!("{}: duplicate keyword argument #:{}", func, name));
+        }
+    }
+    let mut positional = positional.into_iter();
+    let mut arranged = Vec::with_capacity(params.len());
+    for (param, slot) in params.iter().zip(slots) {
+        match slot.or_else(|| positional.next()) {
+            Some(value) => arranged.push(value),
+            None => return Err(format!("{}: missing argument {}", func, param)),
+        }
+    }
+    // 余った位置引数は数の不一致として呼び出し側で報告する
+    arranged.extend(positional);
+    Ok(arranged)
+}
+
+// 型を注釈した仮引数に、その型の値が渡されたかを確かめる。
+fn check_contracts(lambda: &Lambda, args: &[Object]) -> Result<(), String> {
+    for ((param, ty), arg) in lambda.params.iter().zip(&lambda.types).zip(args) {
+        if !ty.accepts(arg) {
+            return Err(format!(
+                "Contract violation: {} expected {} to be {}, got {}",
+                lambda.name.as_deref().unwrap_or("lambda"),
+                param,
+                ty.with_article(),
+                debug(arg)
+            ));
+        }
+    }
+    Ok(())
+}
+
+// n 個の引数で呼び出せるか。(x . rest) なら x の分より多くてもよい。
+fn accepts(lambda: &Lambda, n: usize) -> bool {
+    match lambda.rest {
+        Some(_) => n >= lambda.params.len(),
+        None => n == lambda.params.len(),
+    }
+}
+
+// 評価済みの引数で関数を呼び出す。組み込み関数から Lisp の関数を呼ぶときや、マクロの展開に使う。
+pub(crate) fn apply(
+    func: &Object,
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    match call(func, args, env)? {
+        Step::Done(value) => Ok(value),
+        Step::Tail(obj, mut env) => eval_obj(&obj, &mut env),
+    }
+}
+
+// apply と同じだが、Lambda の本体の最後の式は評価せずに末尾位置として返す。
+fn call(func: &Object, args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Step, String> {
+    match func {
+        Object::Lambda(lambda) => {
+            let args = if args.iter().any(|arg| matches!(arg, Object::KeywordArg(_))) {
+                let name = lambda.name.as_deref().unwrap_or("lambda");
+                Cow::Owned(keyword_args(name, &lambda.params, args)?)
+            } else {
+                Cow::Borrowed(args)
+            };
+            if !accepts(lambda, args.len()) {
+                let at_least = if lambda.rest.is_some() {
+                    "at least "
+                } else {
+                    ""
+                };
+                return Err(format!(
+                    "Expected {}{} arguments, got {}: {}",
+                    at_least,
+                    lambda.params.len(),
+                    args.len(),
+                    func
+                ));
+            }
+            check_contracts(lambda, &args)?;
+            let func_env = Rc::new(RefCell::new(Env::extend(Rc::clone(&lambda.env))));
+            for (param, arg) in lambda.params.iter().zip(args.iter()) {
+                func_env.borrow_mut().set(param, arg.clone());
+            }
+            if let Some(rest) = &lambda.rest {
+                let rest_args = args[lambda.params.len()..].to_vec();
+                func_env
+                    .borrow_mut()
+                    .set(rest, pair_list(rest_args, Object::nil()));
+            }
+            eval_scope_body(&lambda.body, func_env)
+        }
+        Object::Builtin(builtin) => {
+            crate::deprecation::check(builtin.name);
+            builtin.call(args, env).map(Step::Done)
+        }
+        _ if let Some(case_lambda) = as_case_lambda(func) => {
+            let clause = case_lambda
+                .0
+                .iter()
+                .find(|clause| matches!(clause, Object::Lambda(l) if accepts(l, args.len())))
+                .ok_or_else(|| {
+                    format!("case-lambda: no clause accepts {} arguments", args.len())
+                })?;
+            call(clause, args, env)
+        }
+        _ => match crate::continuation::invoke(func, args)
+            .or_else(|| crate::record::invoke(func, args))
+            .or_else(|| crate::parameter::invoke(func, args))
+            .or_else(|| crate::generator::invoke(func, args, env))
+        {
+            Some(result) => result.map(Step::Done),
+            None => Err(format!("{} is not a function", func)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_add() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let result = eval("(+ 1 2)", &mut env).unwrap();
+        assert_eq!(result, Object::Integer(3));
+    }
+
+    #[test]
+    fn test_multiple_forms() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert_eq!(
+            eval("(define x 1) (define (f y) (+ x y)) (f 2)", &mut env),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(eval("", &mut env), Ok(Object::Void));
+        // 前の式の define は、エラーになった式より前に評価される
+        assert!(eval("(define z 5) undefined (define w 1)", &mut env).is_err());
+        assert_eq!(eval("z", &mut env), Ok(Object::Integer(5)));
+        assert!(eval("w", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+            (list (<= 1 1) (<= 2 1) (>= 1 1.5) (>= 2.5 2)
+                  (== 1 1.0) (== 1 2) (!= 1 2) (!= 0.5 0.5)
+                  (<= (* 4611686018427387904 8) (* 4611686018427387904 4)))
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(true false false true true false true false false)"
+        );
+        assert_eq!(
+            eval("(<= 1 \"a\")", &mut env),
+            Err("<=: expected numbers, got integer and string".to_string())
+        );
+        assert_eq!(
+            eval("(!= 1)", &mut env),
+            Err("!=: expected 2 arguments, got 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_circle_area() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define r 10)
+            (define pi 314)
+            (* pi (* r r))
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(314 * 10 * 10));
+    }
+
+    #[test]
+    fn test_srq_function() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define sqr (lambda (x) (* x x)))
+            (sqr 10)
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(100));
+    }
+
+    #[test]
+    fn test_let() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define x 10)
+            (let ((x 1) (y (+ x 1)))
+                (define z 100)
+                (+ x (+ y z))
+            )
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(112));
+        assert_eq!(eval("(begin x)", &mut env).unwrap(), Object::Integer(10));
+        assert!(eval("(begin y)", &mut env).is_err());
+        assert!(eval("(begin z)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert_eq!(eval("'x", &mut env).unwrap(), Object::Symbol("x".into()));
+        assert_eq!(
+            eval("(quote (1 (+ 2 3)))", &mut env).unwrap(),
+            Object::ListData(Rc::new(vec![
+                Object::Integer(1),
+                Object::ListData(Rc::new(vec![
+                    Object::BinaryOp(Op::Add),
+                    Object::Integer(2),
+                    Object::Integer(3),
+                ])),
+            ]))
+        );
+        assert_eq!(
+            eval("'()", &mut env).unwrap(),
+            Object::ListData(Rc::new(vec![]))
+        );
+        assert_eq!(
+            eval("(html->string '(p ((class \"x\")) \"hi\"))", &mut env).unwrap(),
+            Object::String("<p class=\"x\">hi</p>".into())
+        );
+        assert!(eval("(quote)", &mut env).is_err());
+        // quote と list はペアの並びを作るが、Vec のリストと同じ値として比べられる
+        assert!(matches!(eval("'(1 2)", &mut env).unwrap(), Object::Pair(_)));
+        assert_eq!(
+            eval("(list 1 '(2))", &mut env).unwrap(),
+            Object::ListData(Rc::new(vec![
+                Object::Integer(1),
+                Object::ListData(Rc::new(vec![Object::Integer(2)])),
+            ]))
+        );
+        assert_ne!(
+            eval("'(1 2)", &mut env).unwrap(),
+            Object::ListData(Rc::new(vec![Object::Integer(1)]))
+        );
+    }
+
+    #[test]
+    fn test_closure() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define make-adder (lambda (n) (lambda (x) (+ x n))))
+            (define add5 (make-adder 5))
+            (define add10 (make-adder 10))
+            (+ (add5 1) ((make-adder 100) (add10 1)))
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(117));
+    }
+
+    #[test]
+    fn test_bank_account() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (make-account balance)
+                (define (withdraw amount)
+                    (if (> amount balance)
+                        (error \"Insufficient funds\")
+                        (begin (set! balance (- balance amount)) balance)))
+                (define (deposit amount) (set! balance (+ balance amount)) balance)
+                (list withdraw deposit (lambda () balance)))
+            (define acc (make-account 100))
+            (define other (make-account 10))
+            (define withdraw (car acc))
+            (define deposit (car (cdr acc)))
+            (define balance (car (cdr (cdr acc))))
+            (list (withdraw 30) (deposit 50) (withdraw 60) (balance) ((car (cdr (cdr other)))))
+        )
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(70 120 60 60 10)"
+        );
+        assert_eq!(
+            eval("(withdraw 61)", &mut env),
+            Err("error: Insufficient funds".to_string())
+        );
+        assert_eq!(eval("(balance)", &mut env), Ok(Object::Integer(60)));
+
+        // update で渡した束縛は箱を共有するので、どちらの Env からの set! も、もう一方のクロージャから見える
+        let mut shared = Rc::new(RefCell::new(Env::extend(Rc::clone(&env))));
+        shared.borrow_mut().update(Rc::clone(&env));
+        eval("(withdraw 10)", &mut shared).unwrap();
+        eval("(define acc 'replaced)", &mut shared).unwrap();
+        assert_eq!(eval("(balance)", &mut env), Ok(Object::Integer(50)));
+        assert_eq!(eval("acc", &mut env), Ok(Object::Symbol("replaced".into())));
+    }
+
+    #[test]
+    fn test_parameter_contracts() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+            (define (repeat (s : string) (n : integer) sep) (string-pad-left s n sep))
+            (define twice (lambda ((f : procedure) x) (f (f x))))
+            (list (repeat \"ab\" 4 \"-\") (twice (lambda ((x : number)) (* x 2)) 1.5))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap().to_string(), "(--ab 6.0)");
+        assert_eq!(
+            eval("(repeat \"ab\" \"3\" 1)", &mut env),
+            Err("Contract violation: repeat expected n to be an integer, got \"3\"".to_string())
+        );
+        assert_eq!(
+            eval("(twice 1 2)", &mut env),
+            Err("Contract violation: twice expected f to be a procedure, got 1".to_string())
+        );
+        assert_eq!(
+            eval("((lambda ((b : boolean)) b) 'x)", &mut env),
+            Err("Contract violation: lambda expected b to be a boolean, got x".to_string())
+        );
+        assert_eq!(
+            eval("(repeat #:n 2 #:sep 0 #:s 'x)", &mut env),
+            Err("Contract violation: repeat expected s to be a string, got x".to_string())
+        );
+        assert_eq!(
+            eval("(lambda ((x : float)) x)", &mut env),
+            Err("Unknown parameter type: float".to_string())
+        );
+        assert_eq!(
+            eval(
+                "(list (assert-type 1 'integer) (assert-type \"a\" 'symbol))",
+                &mut env
+            ),
+            Err("assert-type: expected a symbol, got \"a\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lexical_scope() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define x 1)
+            (define get-x (lambda () (+ x 0)))
+            (let ((x 2)) (get-x))
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(1));
+    }
+
+    #[test]
+    fn test_fibonacci() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define fib
+                (lambda (n)
+                    (if (< n 2)
+                        n
+                        (+ (fib (- n 1)) (fib (- n 2)))
+                    )
                 )
             )
             (fib 10)
@@ -326,4 +1903,597 @@ mod tests {
         let result = eval(program, &mut env).unwrap();
         assert_eq!(result, Object::Integer(55));
     }
+
+    #[test]
+    fn test_multi_expression_lambda_body() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define f (lambda (x) (define y (* x x)) (+ y 1)))
+            (define id (lambda (x) x))
+            (+ (f 3) (id 100))
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(110));
+        assert!(eval("(begin y)", &mut env).is_err());
+        assert!(eval("(lambda (x))", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_define_function_shorthand() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (square x) (* x x))
+            (define (sum-of-squares x y)
+                (define sx (square x))
+                (+ sx (square y)))
+            (define (answer) 42)
+            (+ (sum-of-squares 3 4) (answer))
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(67));
+        assert!(eval("(define (1 x) x)", &mut env).is_err());
+        assert!(eval("(define (f x))", &mut env).is_err());
+        assert!(eval("(define x 1 2)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_print() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let (result, output) =
+            capture_output(|| eval("(begin (print \"x =\" (+ 1 2)) (print) 5)", &mut env));
+        assert_eq!(result.unwrap(), Object::Integer(5));
+        assert_eq!(output, "x = 3\n\n");
+    }
+
+    #[test]
+    fn test_and_or() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let cases = [
+            ("(and)", Object::truth(true)),
+            ("(or)", Object::truth(false)),
+            ("(and (< 1 2) 10)", Object::Integer(10)),
+            ("(and (< 2 1) undefined-symbol)", Object::truth(false)),
+            ("(or (< 2 1) 7 undefined-symbol)", Object::Integer(7)),
+            ("(or (< 2 1) (> 1 2))", Object::truth(false)),
+            ("(not (< 2 1))", Object::truth(true)),
+            ("(not 0)", Object::truth(false)),
+        ];
+        for (program, expected) in cases {
+            assert_eq!(eval(program, &mut env).unwrap(), expected, "{}", program);
+        }
+        assert!(eval("(and 1 undefined-symbol)", &mut env).is_err());
+        assert!(eval("(not)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_named_let() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (let loop ((i 0) (acc 0))
+            (if (< i 10)
+                (loop (+ i 1) (+ acc i))
+                acc))
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Integer(45));
+        assert!(eval("(begin loop)", &mut env).is_err());
+        assert!(eval("(let loop ((i 0)))", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_let_star_letrec() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define x 10)
+            (let* ((x 1) (y (+ x 1)) (x (* y 10)))
+                (list x y)))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap().to_string(), "(20 2)");
+
+        let program = "
+        (letrec ((even? (lambda (n) (if (< n 1) 'even (odd? (- n 1)))))
+                 (odd? (lambda (n) (if (< n 1) 'odd (even? (- n 1))))))
+            (list (even? 10) (odd? 8)))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap().to_string(), "(even odd)");
+        assert!(eval("(begin even?)", &mut env).is_err());
+        assert_eq!(
+            eval("(letrec* ((a 1) (b (+ a 1))) b)", &mut env),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(
+            eval("(letrec ((a b) (b 1)) a)", &mut env),
+            Err("b: used before initialization".to_string())
+        );
+    }
+
+    #[test]
+    fn test_special_form_errors() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let cases = [
+            ("(+ 1 \"a\")", "+: expected numbers, got integer and string"),
+            ("(< 1)", "<: expected 2 arguments, got 1"),
+            ("(/ 1.5 0)", "Division by zero"),
+            ("(% 7 2)", "Unsupported binary operator: %"),
+            ("(else 1)", "else: only allowed in a cond clause"),
+            ("(unquote x)", "unquote: only allowed inside quasiquote"),
+            ("(if #f 1)", "Invalid if syntax: (if false 1)"),
+            ("(if #t)", "Invalid if syntax: (if true)"),
+            ("(if)", "Invalid if syntax: (if)"),
+            ("(lambda)", "Invalid lambda syntax: (lambda)"),
+        ];
+        for (program, message) in cases {
+            assert_eq!(eval(program, &mut env), Err(message.to_string()));
+        }
+        assert_eq!(eval("(- 7 2.5)", &mut env), Ok(Object::Float(4.5)));
+        assert_eq!(eval("(/ 7 2)", &mut env), Ok(Object::Integer(3)));
+    }
+
+    #[test]
+    fn test_docstring() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (square x) \"Squares x.\" (* x x))
+            (define (greeting) \"hello\")
+            (list (square 3) (doc square) (greeting) (doc greeting)))
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(9 Squares x. hello Void)"
+        );
+        assert_eq!(env.borrow().doc("square"), Some("Squares x.".to_string()));
+
+        // 内側で束縛し直した名前や、説明なしで定義し直した名前には説明が無い
+        assert_eq!(
+            eval("(let ((square 1)) (doc square))", &mut env),
+            Ok(Object::Void)
+        );
+        assert_eq!(
+            eval("(begin (define (square x) (* x x)) (doc square))", &mut env),
+            Ok(Object::Void)
+        );
+        assert_eq!(
+            eval("(doc missing)", &mut env),
+            Err("doc: missing is not defined".to_string())
+        );
+    }
+
+    #[test]
+    fn test_internal_defines() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define b 100)
+            (define (f x)
+                (define (even? n) (if (< n 1) (< 0 1) (odd? (- n 1))))
+                (define (odd? n) (if (< n 1) (< 1 0) (even? (- n 1))))
+                (define y (* x 2))
+                (define z (+ y 1))
+                (list (even? x) y z))
+            (define (g) (define a b) (define b 1) a)
+            (define (h) (set! c 1) (define c 2) c)
+            (define (twice) (define n 1) (define n 2) n)
+            (f 3))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap().to_string(), "(false 6 7)");
+        // 外側の b ではなく、まだ値の無い内側の b を指す
+        assert_eq!(
+            eval("(g)", &mut env),
+            Err("b: used before initialization".to_string())
+        );
+        assert_eq!(
+            eval("(h)", &mut env),
+            Err("c: used before initialization".to_string())
+        );
+        assert_eq!(
+            eval("(let ((x 1)) (define y (+ x 1)) (* y 10))", &mut env),
+            Ok(Object::Integer(20))
+        );
+        assert_eq!(
+            eval("(twice)", &mut env),
+            Err("define: n is defined more than once in the same body".to_string())
+        );
+        // 呼び出しのたびに新しい Env なので、2 回目の呼び出しでも値の無い状態から始まる
+        assert!(eval("(g)", &mut env).is_err());
+        assert_eq!(eval("b", &mut env), Ok(Object::Integer(100)));
+    }
+
+    #[test]
+    fn test_bigint() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (fib n)
+                (define (go a b k) (if (< k 1) a (go b (+ a b) (- k 1))))
+                (go 0 1 n))
+            (define (fact n) (if (< n 1) 1 (* n (fact (- n 1)))))
+            (list (fib 90) (fib 100) (fact 30) (- 0 (fact 25))))
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(2880067194370816120 354224848179261915075 265252859812191058636308480000000 -15511210043330985984000000)"
+        );
+        // i64 に収まる結果は Integer に戻る
+        assert_eq!(
+            eval("(/ (* 4611686018427387904 4) 8)", &mut env),
+            Ok(Object::Integer(2305843009213693952))
+        );
+        assert_eq!(
+            eval("(- (fact 21) (* 21 (fact 20)))", &mut env),
+            Ok(Object::Integer(0))
+        );
+        assert_eq!(
+            eval(
+                "(list (< (fib 100) (fib 99)) (> (fib 100) 1) (< (- 0 (fact 30)) 0))",
+                &mut env
+            )
+            .unwrap()
+            .to_string(),
+            "(false true true)"
+        );
+        assert_eq!(
+            eval("(/ (fact 30) (fact 28))", &mut env),
+            Ok(Object::Integer(870))
+        );
+        assert_eq!(
+            eval("(+ (fact 25) 0.5)", &mut env),
+            Ok(Object::Float(1.5511210043330986e25))
+        );
+        assert_eq!(
+            eval("(/ (fact 25) 0)", &mut env),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cond() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (classify n)
+                (cond ((< n 0) 'negative)
+                      ((< n 10) 'small)
+                      (else 'large)))
+            (list (classify (- 0 5)) (classify 3) (classify 50)))
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(negative small large)"
+        );
+        let program = "
+        (begin
+            (define (double x) (* x 2))
+            (list
+                (cond ((< 1 0) 1) (21 => double))
+                (cond ((< 1 0) 1) ((+ 1 2)))
+                (cond ((< 1 0) 1))))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap().to_string(), "(42 3 Void)");
+        assert!(eval("(cond 1)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_case_lambda() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define area
+                (case-lambda
+                    ((r) (* 3 (* r r)))
+                    ((w h) (* w h))))
+            (list (area 2) (area 3 4)))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap().to_string(), "(12 12)");
+        assert_eq!(
+            eval("(area 1 2 3)", &mut env),
+            Err("case-lambda: no clause accepts 3 arguments".to_string())
+        );
+        assert_eq!(
+            eval("(call-with-values (lambda () (values 5 6)) area)", &mut env),
+            Ok(Object::Integer(30))
+        );
+    }
+
+    #[test]
+    fn test_keyword_args() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (make-window title width height) (list title width height))
+            (list
+                (make-window #:width 100 #:height 50 #:title 'main)
+                (make-window 'side #:height 20 10)))
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "((main 100 50) (side 10 20))"
+        );
+        assert_eq!(
+            eval("(make-window 'a #:depth 1 2 3)", &mut env),
+            Err("make-window: unknown keyword argument #:depth".to_string())
+        );
+        assert_eq!(
+            eval("(make-window 'a #:width 1 #:width 2)", &mut env),
+            Err("make-window: duplicate keyword argument #:width".to_string())
+        );
+        assert_eq!(
+            eval("(make-window 'a #:width 1)", &mut env),
+            Err("make-window: missing argument height".to_string())
+        );
+        assert_eq!(
+            eval("(make-window 'a 1 #:title 'b)", &mut env),
+            Err("make-window: argument title given both positionally and by keyword".to_string())
+        );
+        assert_eq!(
+            eval("(make-window 'a 1 2 #:height 3)", &mut env),
+            Err("make-window: argument height given both positionally and by keyword".to_string())
+        );
+
+        let program = "
+        (begin
+            (define (f height width) (list height width))
+            (f 1 #:height 2))
+        ";
+        assert_eq!(
+            eval(program, &mut env),
+            Err("f: argument height given both positionally and by keyword".to_string())
+        );
+        assert_eq!(
+            eval("(f 1 #:depth 2)", &mut env),
+            Err("f: unknown keyword argument #:depth".to_string())
+        );
+        assert_eq!(
+            eval("((lambda (x) x) #:y 1)", &mut env),
+            Err("lambda: unknown keyword argument #:y".to_string())
+        );
+        assert!(eval("(make-window 'a #:width 1 2 3)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (count-down n)
+                (if (< n 1)
+                    (quote done)
+                    (begin (define m (- n 1)) (count-down m))))
+            (define (even n) (or (< n 1) (and (> n 0) (odd (- n 1)))))
+            (define (odd n) (and (> n 0) (even (- n 1))))
+            (let loop ((i 0))
+                (if (< i 1000000) (loop (+ i 1)) (count-down i)))
+        )
+        ";
+
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result, Object::Symbol("done".into()));
+        assert_eq!(
+            eval("(even 100001)", &mut env).unwrap(),
+            Object::truth(false)
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define i 0)
+            (define sum 0)
+            (while (< i 100000)
+                (set! sum (+ sum i))
+                (set! i (+ i 1)))
+            sum
+        )
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap(),
+            Object::Integer(4999950000)
+        );
+
+        let program = "
+        (do ((i 0 (+ i 1)) (acc 1) (fact 1 (* fact (+ i 1))))
+            ((> i 9) acc fact)
+            (set! acc 2))
+        ";
+        assert_eq!(eval(program, &mut env).unwrap(), Object::Integer(3628800));
+        assert_eq!(
+            eval("(do ((i 0 (+ i 1))) ((> i 2)))", &mut env).unwrap(),
+            Object::Void
+        );
+
+        let program = "
+        (begin
+            (define (make-counter)
+                (let ((n 0))
+                    (lambda () (set! n (+ n 1)) n)))
+            (define c (make-counter))
+            (c)
+            (c)
+        )
+        ";
+        assert_eq!(eval(program, &mut env).unwrap(), Object::Integer(2));
+        assert_eq!(eval("n", &mut env).unwrap_err(), "Undefined symbol: n");
+        assert_eq!(
+            eval("(set! undefined-var 1)", &mut env).unwrap_err(),
+            "Undefined symbol: undefined-var"
+        );
+        assert!(eval("(while 1)", &mut env).is_err());
+        assert!(eval("(do (i 0) ((> i 1)))", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_when_unless() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define n 0)
+            (when (< n 1) (set! n (+ n 1)) (set! n (* n 10)))
+            (unless (< n 1) (set! n (+ n 5)))
+            (unless (< 1 n) (set! n 0))
+            n
+        )
+        ";
+        assert_eq!(eval(program, &mut env).unwrap(), Object::Integer(15));
+        assert_eq!(eval("(when (< 2 1) 1)", &mut env).unwrap(), Object::Void);
+        assert_eq!(eval("(unless (< 2 1))", &mut env).unwrap(), Object::Void);
+        assert_eq!(
+            eval("(unless (< 2 1) 'a 'b)", &mut env).unwrap(),
+            Object::Symbol("b".into())
+        );
+        assert!(eval("(when)", &mut env).is_err());
+        assert!(eval("(when 1 2)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_delay_force() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define n 0)
+            (define p (delay (begin (set! n (+ n 1)) (* n 10))))
+            (list n (force p) (force p) n (force 3))
+        )
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(0 10 10 1 3)"
+        );
+
+        let program = "
+        (begin
+            (define (integers-from k)
+                (lambda (head?) (if head? k (delay (integers-from (+ k 1))))))
+            (define (stream-ref s i)
+                (if (< i 1) (s (< 0 1)) (stream-ref (force (s (< 1 0))) (- i 1))))
+            (stream-ref (integers-from 0) 1000)
+        )
+        ";
+        assert_eq!(eval(program, &mut env).unwrap(), Object::Integer(1000));
+        assert_eq!(
+            eval("(list (promise? p) (promise? 1))", &mut env)
+                .unwrap()
+                .to_string(),
+            "(true false)"
+        );
+        assert_eq!(eval("p", &mut env).unwrap().to_string(), "#<promise>");
+        assert!(eval("(force (delay undefined-var))", &mut env).is_err());
+        assert!(eval("(delay)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_define_macro() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define-macro (unless-else c body otherwise) (list 'if c otherwise body))
+            (define-macro (swap! a b) (list 'quote (list b a)))
+            (define (safe-div x y) (unless-else (< y 1) (/ x y) 'inf))
+            (list (safe-div 6 2) (safe-div 1 0) (swap! 1 (+ 1 2)) 'unless-else)
+        )
+        ";
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result.to_string(), "(3 inf ((+ 1 2) 1) unless-else)");
+        assert_eq!(
+            eval("(unless-else (< 1 2) 1 2)", &mut env).unwrap(),
+            Object::Integer(2)
+        );
+        assert_eq!(
+            eval("'(unless-else c 1 2)", &mut env).unwrap().to_string(),
+            "(unless-else c 1 2)"
+        );
+        assert!(eval("(unless-else 1)", &mut env).is_err());
+        assert!(eval("(define-macro unless-else 1)", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_syntax_rules() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define-syntax my-or
+                (syntax-rules ()
+                    ((_) (< 1 0))
+                    ((_ e) e)
+                    ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))
+            (define-syntax my-when
+                (syntax-rules ()
+                    ((_ c body ...) (if c (begin body ...) (quote skipped)))))
+            (define t 5)
+            (list (my-or (< 2 1) t) (my-or) (my-or (< 2 1) (< 3 1) 7) (my-when (< t 1) (print t) t))
+        )
+        ";
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(result.to_string(), "(5 false 7 skipped)");
+        assert_eq!(
+            eval("(my-when (< 1 t) 1 2)", &mut env).unwrap(),
+            Object::Integer(2)
+        );
+        assert!(eval("(my-when)", &mut env).is_err());
+        assert!(eval("(define-syntax bad (syntax-rules))", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define x 2)
+            (define xs (list 3 4))
+            (define-macro (my-unless c then otherwise) `(if ,c ,otherwise ,then))
+            (list
+                `(1 ,x ,@xs (x ,(+ x 1)) ,@(list))
+                `(1 `(,x ,,x))
+                (my-unless (< x 1) 'yes 'no))
+        )
+        ";
+        let result = eval(program, &mut env).unwrap();
+        assert_eq!(
+            result.to_string(),
+            "((1 2 3 4 (x 3)) (1 (quasiquote ((unquote x) (unquote 2)))) yes)"
+        );
+        assert!(eval("`(1 ,@x)", &mut env).is_err());
+        assert!(eval("`,@xs", &mut env).is_err());
+        assert_eq!(
+            eval("(list `(1 . ,x) `(0 ,@xs . ,(+ x 3)) `(a . b))", &mut env)
+                .unwrap()
+                .to_string(),
+            "((1 . 2) (0 3 4 . 5) (a . b))"
+        );
+    }
+
+    #[test]
+    fn test_rest_params() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (define (f . args) args)
+            (define (g a b . more) (list a b more))
+            (define-macro (my-when c . body) (list 'if c (cons 'begin body) #f))
+            (define h (case-lambda ((a) (list 'one a)) ((a . rest) (list 'many a rest))))
+            (list (f) (f 1 2) (g 1 2) (g 1 2 3 4) ((lambda (x . r) r) 1 2)
+                  ((lambda args args) 3) (my-when #t 1 2) (h 1) (h 1 2 3)))
+        ";
+        assert_eq!(
+            eval(program, &mut env).unwrap().to_string(),
+            "(() (1 2) (1 2 ()) (1 2 (3 4)) (2) (3) 2 (one 1) (many 1 (2 3)))"
+        );
+        assert_eq!(
+            eval("(g 1)", &mut env),
+            Err(
+                "Expected at least 2 arguments, got 1: Lambda(a b . more) (list a b more)"
+                    .to_string()
+            )
+        );
+        assert!(eval("(lambda (x . 1) x)", &mut env).is_err());
+    }
 }
@@ -0,0 +1,155 @@
+// raise と try による例外処理。
+//
+//   (try (/ 1 0)
+//     (catch (e) (print "failed:" (error-message e)) 0))
+//
+// 評価のエラーは String で伝わるので、raise した値はここに取っておき、try で受け取ったエラーと突き合わせて取り出す。
+// 組み込み関数などのエラーは、そのメッセージを持つエラーオブジェクトとして受け取る。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::builtins::expect_string;
+use crate::eval::Env;
+use crate::parser::{Foreign, Object};
+
+#[derive(Debug)]
+struct ErrorObject {
+    message: String,
+}
+
+impl Foreign for ErrorObject {
+    fn type_name(&self) -> &str {
+        "error"
+    }
+}
+
+thread_local! {
+    // 最後に raise した値と、そのときのエラーメッセージ
+    static RAISED: RefCell<Option<(String, Object)>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn error_object(message: String) -> Object {
+    Object::Foreign(Rc::new(ErrorObject { message }))
+}
+
+fn expect_error<'a>(name: &str, obj: &'a Object) -> Result<&'a ErrorObject, String> {
+    let error = match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<ErrorObject>(),
+        _ => None,
+    };
+    error.ok_or_else(|| format!("{}: expected an error object, got {}", name, obj))
+}
+
+// try で受け取ったエラーを Lisp の値にする。raise した値がそのまま伝わってきたならその値を返す。
+// 途中の組み込み関数がメッセージの前に名前を付けていることがあるので、末尾が一致すればよい。
+pub(crate) fn caught(message: String) -> Object {
+    match RAISED.take() {
+        Some((raised, obj)) if message.ends_with(&raised) => obj,
+        _ => error_object(message),
+    }
+}
+
+fn raise_object(message: String, obj: Object) -> Result<Object, String> {
+    RAISED.set(Some((message.clone(), obj)));
+    Err(message)
+}
+
+// (raise obj) は obj を例外として投げる。try の catch で obj を受け取れる。
+pub(crate) fn raise(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let obj = &args[0];
+    let message = match expect_error("raise", obj) {
+        Ok(error) => error.message.clone(),
+        Err(_) => format!("raise: {}", obj),
+    };
+    raise_object(message, obj.clone())
+}
+
+// (error "message" irritants...) はメッセージに irritants を空白区切りで続けたエラーオブジェクトを投げる。
+pub(crate) fn error(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut message = format!("error: {}", expect_string("error", &args[0])?);
+    for irritant in &args[1..] {
+        message.push(' ');
+        message.push_str(&irritant.to_string());
+    }
+    raise_object(message.clone(), error_object(message))
+}
+
+// (error-message e) はエラーオブジェクトのメッセージ。
+pub(crate) fn error_message(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    Ok(Object::String(
+        expect_error("error-message", &args[0])?
+            .message
+            .as_str()
+            .into(),
+    ))
+}
+
+pub(crate) fn is_error_object(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    Ok(Object::truth(
+        expect_error("error-object?", &args[0]).is_ok(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_try() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define (safe-div x y)
+                (try (/ x y)
+                    (catch (e) (error-message e))))
+            (list (safe-div 6 2) (safe-div 1 0))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "(3 Division by zero)"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(try (raise 'oops) (catch (e) (list e (error-object? e))))")
+                .unwrap()
+                .to_string(),
+            "(oops false)"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(try (eval '(error \"bad value:\" 42)) (catch (e) (error-message e)))")
+                .unwrap(),
+            Object::String("error: bad value: 42".into())
+        );
+        // catch の中で投げ直した値は外側の try に届く
+        assert_eq!(
+            interpreter
+                .eval(
+                    "(try
+                        (try (raise 1) (catch (e) (raise (+ e 1))))
+                        (catch (e) (* e 10)))"
+                )
+                .unwrap(),
+            Object::Integer(20)
+        );
+        assert_eq!(
+            interpreter.eval("(try 1 2 (catch (e) 0))"),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(interpreter.eval("(raise 1)"), Err("raise: 1".to_string()));
+        assert_eq!(
+            interpreter.eval("(error \"failed\")"),
+            Err("error: failed".to_string())
+        );
+        assert!(interpreter.eval("(try 1)").is_err());
+        assert!(interpreter.eval("(try 1 (catch e 0))").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+// make-generator によるジェネレーター。
+//
+//   (define g (make-generator (lambda (yield)
+//     (do ((i 0 (+ i 1))) ((> i 2) 'done)
+//       (yield (* i i))))))
+//   (g) (g) (g)            ; 0 1 4
+//   (g)                    ; Void。以降も Void を返し、(generator-done? g) が true になる
+//
+// 評価器は途中の状態を保存できないので、(g) を呼ぶたびに関数を最初から評価し直し、
+// 既に返した分の yield は何もせずに戻って、次の yield の値で継続を使って (g) から脱出する。
+// そのため n 個の値を取り出すのに O(n²) の評価が要り、yield の間にある副作用は呼ぶたびに繰り返される。
+// 関数は yield 以外の副作用を持たないように書くこと。
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::continuation::with_escape;
+use crate::eval::{Env, apply};
+use crate::parser::{Foreign, Object};
+
+#[derive(Debug)]
+struct Generator {
+    producer: Object,
+    delivered: Cell<usize>, // これまでに返した値の数
+    done: Cell<bool>,
+}
+
+impl Foreign for Generator {
+    fn type_name(&self) -> &str {
+        "generator"
+    }
+}
+
+// producer に渡す yield。(g) の呼び出し 1 回ごとに作る。
+#[derive(Debug)]
+struct Yield {
+    k: Object,   // この回の (g) から脱出する継続
+    skip: usize, // 何もせずに戻る yield の数
+    seen: Cell<usize>,
+}
+
+impl Foreign for Yield {
+    fn type_name(&self) -> &str {
+        "procedure"
+    }
+}
+
+fn downcast<T: 'static>(obj: &Object) -> Option<&T> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<T>(),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_generator_procedure(obj: &Object) -> bool {
+    downcast::<Generator>(obj).is_some() || downcast::<Yield>(obj).is_some()
+}
+
+// ジェネレーターか yield を呼ぶ。func がそのどちらでもなければ None。
+pub(crate) fn invoke(
+    func: &Object,
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Option<Result<Object, String>> {
+    if let Some(generator) = downcast::<Generator>(func) {
+        return Some(match args {
+            [] => generator.next(env),
+            _ => Err(format!(
+                "generator: expected 0 arguments, got {}",
+                args.len()
+            )),
+        });
+    }
+    let y = downcast::<Yield>(func)?;
+    let [value] = args else {
+        return Some(Err(format!(
+            "yield: expected 1 argument, got {}",
+            args.len()
+        )));
+    };
+    y.seen.set(y.seen.get() + 1);
+    if y.seen.get() <= y.skip {
+        return Some(Ok(Object::Void));
+    }
+    crate::continuation::invoke(&y.k, std::slice::from_ref(value))
+}
+
+impl Generator {
+    fn next(&self, env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+        if self.done.get() {
+            return Ok(Object::Void);
+        }
+        let (value, yielded) = with_escape("generator", |k| {
+            let y = Yield {
+                k,
+                skip: self.delivered.get(),
+                seen: Cell::new(0),
+            };
+            apply(&self.producer, &[Object::Foreign(Rc::new(y))], env)
+        })?;
+        if yielded {
+            self.delivered.set(self.delivered.get() + 1);
+            Ok(value)
+        } else {
+            self.done.set(true);
+            Ok(Object::Void)
+        }
+    }
+}
+
+// (make-generator (lambda (yield) ...))
+pub(crate) fn make_generator(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    Ok(Object::Foreign(Rc::new(Generator {
+        producer: args[0].clone(),
+        delivered: Cell::new(0),
+        done: Cell::new(false),
+    })))
+}
+
+pub(crate) fn is_generator_done(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let generator = &args[0];
+    match downcast::<Generator>(generator) {
+        Some(generator) => Ok(Object::truth(generator.done.get())),
+        None => Err(format!(
+            "generator-done?: expected a generator, got {}",
+            generator
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_make_generator() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define (squares limit)
+                (make-generator (lambda (yield)
+                    (do ((i 0 (+ i 1))) ((> i limit) 'done)
+                        (yield (* i i))))))
+            (define g (squares 3))
+            (list (g) (g) (g) (generator-done? g) (g) (g) (generator-done? g))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "(0 1 4 false 9 Void true)"
+        );
+
+        // 無限に値を作るジェネレーターも、必要な分だけ取り出せる
+        let program = "
+            (define naturals
+                (make-generator (lambda (yield)
+                    (let loop ((n 0)) (yield n) (loop (+ n 1))))))
+            (begin (naturals) (list (naturals) (naturals) (naturals)))
+        ";
+        assert_eq!(interpreter.eval(program).unwrap().to_string(), "(1 2 3)");
+        assert_eq!(
+            interpreter.eval("(g 1)"),
+            Err("generator: expected 0 arguments, got 1".to_string())
+        );
+        assert!(
+            interpreter
+                .eval("((make-generator (lambda (yield) (undefined))))")
+                .unwrap_err()
+                .starts_with("generator: ")
+        );
+    }
+}
@@ -0,0 +1,425 @@
+// 教材向けの小さなキャンバスとタートルグラフィックス。描いた絵は PNG に保存できる。
+//
+//   (canvas 200 200 #:background "white")
+//   (line 0 0 199 199 #:color "red")
+//   (forward 50) (right 90) (forward 50)
+//   (save-png "out.png")
+//
+// 座標は左上が原点で、y は下向きに増える。描画系の組み込み関数は最後に作ったキャンバスに描く。
+// タートルはキャンバスの中央で上を向き、ペンを下ろした状態から始まる。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::builtins::{expect_string, expect_usize, split_keyword_args};
+use crate::eval::Env;
+use crate::parser::{Foreign, Object};
+
+type Color = [u8; 3];
+
+const MAX_SIZE: usize = 16384;
+
+#[derive(Debug)]
+struct Turtle {
+    x: f64,
+    y: f64,
+    heading: f64, // 度。0 が上で、時計回りに増える
+    pen_down: bool,
+    color: Color,
+}
+
+#[derive(Debug)]
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: RefCell<Vec<Color>>,
+    turtle: RefCell<Turtle>,
+}
+
+impl Foreign for Canvas {
+    fn type_name(&self) -> &str {
+        "canvas"
+    }
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize, background: Color) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: RefCell::new(vec![background; width * height]),
+            turtle: RefCell::new(Turtle {
+                x: width as f64 / 2.0,
+                y: height as f64 / 2.0,
+                heading: 0.0,
+                pen_down: true,
+                color: [0, 0, 0],
+            }),
+        }
+    }
+
+    fn set_pixel(&self, x: i64, y: i64, color: Color) {
+        if (0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&y) {
+            self.pixels.borrow_mut()[y as usize * self.width + x as usize] = color;
+        }
+    }
+
+    // Bresenham のアルゴリズム。キャンバスの外にはみ出た部分は描かない。
+    fn line(&self, (x1, y1): (f64, f64), (x2, y2): (f64, f64), color: Color) {
+        let (mut x, mut y) = (x1.round() as i64, y1.round() as i64);
+        let (x2, y2) = (x2.round() as i64, y2.round() as i64);
+        let (dx, dy) = ((x2 - x).abs(), -(y2 - y).abs());
+        let (sx, sy) = (if x < x2 { 1 } else { -1 }, if y < y2 { 1 } else { -1 });
+        let mut err = dx + dy;
+        loop {
+            self.set_pixel(x, y, color);
+            if x == x2 && y == y2 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Canvas>>> = const { RefCell::new(None) };
+}
+
+fn current(name: &str) -> Result<Rc<Canvas>, String> {
+    CURRENT
+        .with_borrow(|canvas| canvas.clone())
+        .ok_or_else(|| format!("{}: no canvas, call (canvas width height) first", name))
+}
+
+// 数であることは引数の型で確かめてある。
+fn as_f64(obj: &Object) -> f64 {
+    match obj {
+        Object::Integer(n) => *n as f64,
+        Object::BigInt(n) => n.to_f64(),
+        Object::Float(f) => *f,
+        _ => unreachable!("checked by the signature"),
+    }
+}
+
+// "red" のような名前か "#rrggbb" 形式の色。
+fn parse_color(name: &str, obj: &Object) -> Result<Color, String> {
+    let s = expect_string(name, obj)?;
+    let color = match s {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "gray" => [128, 128, 128],
+        "red" => [255, 0, 0],
+        "green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "orange" => [255, 165, 0],
+        "purple" => [128, 0, 128],
+        _ => match s.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.is_ascii() => {
+                let channel = |i| u8::from_str_radix(&hex[i..i + 2], 16);
+                match (channel(0), channel(2), channel(4)) {
+                    (Ok(r), Ok(g), Ok(b)) => [r, g, b],
+                    _ => return Err(format!("{}: invalid color {:?}", name, s)),
+                }
+            }
+            _ => return Err(format!("{}: invalid color {:?}", name, s)),
+        },
+    };
+    Ok(color)
+}
+
+// (canvas width height #:background "white")
+pub(crate) fn canvas(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("canvas", args)?;
+    let [width, height] = positional[..] else {
+        return Err(format!(
+            "canvas: expected 2 arguments, got {}",
+            positional.len()
+        ));
+    };
+    let (width, height) = (
+        expect_usize("canvas", width)?,
+        expect_usize("canvas", height)?,
+    );
+    if !(1..=MAX_SIZE).contains(&width) || !(1..=MAX_SIZE).contains(&height) {
+        return Err(format!("canvas: invalid size {}x{}", width, height));
+    }
+
+    let mut background = [255, 255, 255];
+    for (kw, value) in keywords {
+        match kw {
+            "background" => background = parse_color("canvas", value)?,
+            _ => return Err(format!("canvas: unknown keyword #:{}", kw)),
+        }
+    }
+
+    let canvas = Rc::new(Canvas::new(width, height, background));
+    CURRENT.set(Some(Rc::clone(&canvas)));
+    Ok(Object::Foreign(canvas))
+}
+
+// (line x1 y1 x2 y2 #:color "black")
+pub(crate) fn line(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("line", args)?;
+    let [x1, y1, x2, y2] = positional[..] else {
+        return Err(format!(
+            "line: expected 4 arguments, got {}",
+            positional.len()
+        ));
+    };
+    let mut color = [0, 0, 0];
+    for (kw, value) in keywords {
+        match kw {
+            "color" => color = parse_color("line", value)?,
+            _ => return Err(format!("line: unknown keyword #:{}", kw)),
+        }
+    }
+    let from = (as_f64(x1), as_f64(y1));
+    let to = (as_f64(x2), as_f64(y2));
+    current("line")?.line(from, to, color);
+    Ok(Object::Void)
+}
+
+// (forward distance) タートルを向いている方向に進める。ペンが下りていれば線を引く。
+pub(crate) fn forward(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let distance = as_f64(&args[0]);
+    let canvas = current("forward")?;
+    let mut turtle = canvas.turtle.borrow_mut();
+    let radians = turtle.heading.to_radians();
+    let from = (turtle.x, turtle.y);
+    turtle.x += distance * radians.sin();
+    turtle.y -= distance * radians.cos();
+    if turtle.pen_down {
+        canvas.line(from, (turtle.x, turtle.y), turtle.color);
+    }
+    Ok(Object::Void)
+}
+
+fn turn(name: &str, args: &[Object], sign: f64) -> Result<Object, String> {
+    let degrees = as_f64(&args[0]);
+    let canvas = current(name)?;
+    let mut turtle = canvas.turtle.borrow_mut();
+    turtle.heading = (turtle.heading + sign * degrees).rem_euclid(360.0);
+    Ok(Object::Void)
+}
+
+// (right degrees) と (left degrees)
+pub(crate) fn right(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    turn("right", args, 1.0)
+}
+
+pub(crate) fn left(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    turn("left", args, -1.0)
+}
+
+pub(crate) fn pen_up(_args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    current("pen-up")?.turtle.borrow_mut().pen_down = false;
+    Ok(Object::Void)
+}
+
+pub(crate) fn pen_down(_args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    current("pen-down")?.turtle.borrow_mut().pen_down = true;
+    Ok(Object::Void)
+}
+
+pub(crate) fn pen_color(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let color = parse_color("pen-color", &args[0])?;
+    current("pen-color")?.turtle.borrow_mut().color = color;
+    Ok(Object::Void)
+}
+
+// (save-png "out.png")
+pub(crate) fn save_png(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let path = expect_string("save-png", &args[0])?;
+    let canvas = current("save-png")?;
+    let png = encode_png(canvas.width, canvas.height, &canvas.pixels.borrow());
+    std::fs::write(path, png).map_err(|e| format!("save-png: {}: {}", path, e))?;
+    Ok(Object::Void)
+}
+
+const ASCII_RAMP: &[u8] = b"@%#*+=-:. ";
+const ASCII_WIDTH: usize = 64;
+
+// REPL 用に、キャンバスを暗いほど詰まった文字で描いたアスキーアートにする。
+// 端末の文字は縦長なので、1 文字に縦 2 倍のピクセルを割り当てる。
+pub(crate) fn render_canvas(obj: &Object) -> Option<String> {
+    let canvas = match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<Canvas>()?,
+        _ => return None,
+    };
+    let cell_width = canvas.width.div_ceil(ASCII_WIDTH);
+    let cell_height = cell_width * 2;
+    let pixels = canvas.pixels.borrow();
+
+    let mut out = format!("#<canvas {}x{}>", canvas.width, canvas.height);
+    for top in (0..canvas.height).step_by(cell_height) {
+        out.push('\n');
+        for left in (0..canvas.width).step_by(cell_width) {
+            let (mut sum, mut count) = (0, 0);
+            for y in top..(top + cell_height).min(canvas.height) {
+                for x in left..(left + cell_width).min(canvas.width) {
+                    let [r, g, b] = pixels[y * canvas.width + x];
+                    sum += 299 * r as usize + 587 * g as usize + 114 * b as usize;
+                    count += 1;
+                }
+            }
+            let luminance = sum / count / 1000; // 0..=255
+            out.push(ASCII_RAMP[luminance * (ASCII_RAMP.len() - 1) / 255] as char);
+        }
+    }
+    Some(out)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// 8bit RGB の PNG。圧縮ライブラリは使わず、zlib の無圧縮ブロックで IDAT を作る。
+fn encode_png(width: usize, height: usize, pixels: &[Color]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width) {
+        raw.push(0); // フィルタなし
+        for pixel in row {
+            raw.extend_from_slice(pixel);
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8bit、RGB、deflate、標準フィルタ、インターレースなし
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval;
+
+    fn pixel(x: usize, y: usize) -> Color {
+        let canvas = current("test").unwrap();
+        canvas.pixels.borrow()[y * canvas.width + x]
+    }
+
+    #[test]
+    fn test_line() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert!(eval("(line 0 0 1 1)", &mut env).is_err());
+        eval("(canvas 10 10 #:background \"#102030\")", &mut env).unwrap();
+        eval("(line 0 0 9 9 #:color \"red\")", &mut env).unwrap();
+        eval("(line (- 0 5) 2 20 2)", &mut env).unwrap();
+        assert_eq!(pixel(0, 0), [255, 0, 0]);
+        assert_eq!(pixel(9, 9), [255, 0, 0]);
+        assert_eq!(pixel(1, 0), [0x10, 0x20, 0x30]);
+        assert!((0..10).all(|x| pixel(x, 2) == [0, 0, 0]));
+        assert!(eval("(line 0 0 1 1 #:color \"#12345\")", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_turtle() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = "
+        (begin
+            (canvas 20 20)
+            (pen-color \"blue\")
+            (forward 5)
+            (right 90)
+            (pen-up)
+            (forward 5)
+            (pen-down)
+            (right 90)
+            (forward 5)
+        )
+        ";
+        eval(program, &mut env).unwrap();
+        assert!((5..=10).all(|y| pixel(10, y) == [0, 0, 255]));
+        assert_eq!(pixel(12, 5), [255, 255, 255]);
+        assert!((5..=10).all(|y| pixel(15, y) == [0, 0, 255]));
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let png = encode_png(2, 1, &[[255, 0, 0], [0, 0, 255]]);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x02\0\0\0\x01\x08\x02"));
+        assert!(png.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let path = std::env::temp_dir().join(format!("mr-lisp-{}.png", std::process::id()));
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        eval("(canvas 2 1)", &mut env).unwrap();
+        eval(
+            &format!("(save-png {:?})", path.to_str().unwrap()),
+            &mut env,
+        )
+        .unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(saved.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_render_canvas() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let canvas = eval("(canvas 4 4)", &mut env).unwrap();
+        eval("(line 0 0 3 0)", &mut env).unwrap();
+        eval("(line 0 1 3 1)", &mut env).unwrap();
+        eval("(line 0 2 0 3 #:color \"gray\")", &mut env).unwrap();
+        assert_eq!(render_canvas(&canvas).unwrap(), "#<canvas 4x4>\n@@@@\n+   ");
+        assert_eq!(render_canvas(&Object::Integer(1)), None);
+    }
+}
@@ -0,0 +1,121 @@
+// 名前や説明から関数を探す apropos。REPL の :apropos と組み込み関数の apropos から使う。
+//
+//   (apropos "string")   ; ((string->number "Parses a number ...") (string-join "Joins ...") ...)
+//
+// 組み込み関数は Builtin の doc を、define した束縛は (define (f) "説明" ...) の説明の 1 行目を見出しにする。
+// 大文字と小文字は区別せずに、名前か説明の全体に query を含むものを名前順に返す。
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::builtins::{BUILTINS, expect_string};
+use crate::eval::Env;
+use crate::parser::Object;
+use crate::printer::debug;
+
+// (名前, 1 行の説明) の組。説明の無い束縛は、関数なら引数を、それ以外なら型の名前を説明の代わりにする。
+pub fn apropos(env: &Env, query: &str) -> Vec<(String, String)> {
+    let query = query.to_lowercase();
+    let contains = |text: &str| text.to_lowercase().contains(&query);
+    let mut found = BTreeMap::new();
+    for (name, value) in env.user_bindings() {
+        let doc = env.doc(&name);
+        if contains(&name) || doc.as_deref().is_some_and(contains) {
+            let summary = match (doc, &value) {
+                (Some(doc), _) => doc.lines().next().unwrap_or("").to_string(),
+                (None, Object::Lambda(_) | Object::Macro(_)) => debug(&value),
+                (None, _) => value.type_name().to_string(),
+            };
+            found.insert(name, summary);
+        }
+    }
+    // 同じ名前を define し直していれば、そちらだけを出す
+    for builtin in BUILTINS {
+        if (contains(builtin.name) || contains(builtin.doc)) && !found.contains_key(builtin.name) {
+            found.insert(builtin.name.to_string(), builtin.doc.to_string());
+        }
+    }
+    found.into_iter().collect()
+}
+
+pub(crate) fn apropos_builtin(
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let query = expect_string("apropos", &args[0])?;
+    let entries = apropos(&env.borrow(), query)
+        .into_iter()
+        .map(|(name, summary)| {
+            Object::ListData(Rc::new(vec![
+                Object::Symbol(name.into()),
+                Object::String(summary.into()),
+            ]))
+        })
+        .collect();
+    Ok(Object::ListData(Rc::new(entries)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_apropos() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define (vector-sum v) \"Adds up the numbers in a VECTOR.\nSecond line.\" 0)
+            (define (vector-helper v) v)
+            (define vector-size 3)
+            (define (length x) \"Shadows the builtin.\" 0)
+        ";
+        interpreter.eval(program).unwrap();
+        let found = apropos(&interpreter.env().borrow(), "VECTOR");
+        let names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "make-vector",
+                "vector->list",
+                "vector-helper",
+                "vector-length",
+                "vector-ref",
+                "vector-set!",
+                "vector-size",
+                "vector-sum",
+            ]
+        );
+        let summary = |name: &str| {
+            found
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, summary)| summary.clone())
+        };
+        assert_eq!(
+            summary("vector-sum").as_deref(),
+            Some("Adds up the numbers in a VECTOR.")
+        );
+        assert_eq!(summary("vector-helper").as_deref(), Some("#<lambda (v)>"));
+        assert_eq!(summary("vector-size").as_deref(), Some("integer"));
+
+        assert_eq!(
+            interpreter
+                .eval("(apropos \"shadows\")")
+                .unwrap()
+                .to_string(),
+            "((length Shadows the builtin.))"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(apropos \"code point\")")
+                .unwrap()
+                .to_string(),
+            "((char->integer Returns the Unicode code point of a character) (integer->char Returns the character with a Unicode code point))"
+        );
+        assert_eq!(
+            interpreter.eval("(apropos 'x)"),
+            Err("apropos: expected a string, got x".to_string())
+        );
+    }
+}
@@ -0,0 +1,523 @@
+// (serve 8080 handler) で動く、組み込みの小さな HTTP/1.1 サーバー。
+// 接続を 1 つずつ順番に処理するシングルスレッドの実装で、レスポンスごとに接続を閉じる。
+// 遅いクライアントが他の接続を止めないように、接続から REQUEST_TIMEOUT の間にリクエスト全体を読めなければ 408 を返して閉じる。
+// 1 バイトずつ送り続けるクライアントもいるので、読むたびではなくリクエスト全体で期限を決める。
+// MAX_LINE を超える行は 400 (ヘッダーなら 431)、MAX_HEADERS を超える数のヘッダーは 431 を返す。
+// MAX_BODY を超える Content-Length のリクエストは、本文を読まずに 413 を返す。
+//
+// handler はリクエストを表す連想リストを 1 つ受け取る。
+//   ((method "GET") (path "/hello") (query "a=1") (headers (("host" "localhost")...)) (body ""))
+// handler の戻り値が文字列なら、ステータス 200 の text/html としてそのまま返す。
+// ((status 404) (content-type "text/plain") (headers (("x-foo" "bar"))) (body "not found"))
+// のような連想リストなら、それぞれの項目をレスポンスに使う。
+
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::builtins::{expect_string, expect_usize, split_keyword_args};
+use crate::eval::{Env, apply};
+use crate::pair::list_items;
+use crate::parser::Object;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_LINE: usize = 8 << 10;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY: usize = 1 << 20;
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+struct Response {
+    status: usize,
+    content_type: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn error(status: usize, message: String) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            headers: Vec::new(),
+            body: message,
+        }
+    }
+}
+
+// (serve port handler #:host "127.0.0.1" #:max-requests n)
+// #:max-requests を指定するとその数だけリクエストを処理して戻る。省略すると止まらない。
+pub(crate) fn serve(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("serve", args)?;
+    let [port, handler] = positional[..] else {
+        return Err(format!(
+            "serve: expected 2 arguments, got {}",
+            positional.len()
+        ));
+    };
+    let port = match expect_usize("serve", port)? {
+        port if port <= u16::MAX as usize => port as u16,
+        port => return Err(format!("serve: invalid port {}", port)),
+    };
+
+    let mut host = "127.0.0.1";
+    let mut max_requests = None;
+    for (kw, value) in keywords {
+        match kw {
+            "host" => host = expect_string("serve", value)?,
+            "max-requests" => max_requests = Some(expect_usize("serve", value)?),
+            _ => return Err(format!("serve: unknown keyword #:{}", kw)),
+        }
+    }
+
+    let listener = TcpListener::bind((host, port)).map_err(|e| format!("serve: {}", e))?;
+    serve_listener(&listener, handler, max_requests, REQUEST_TIMEOUT, env)
+}
+
+fn serve_listener(
+    listener: &TcpListener,
+    handler: &Object,
+    max_requests: Option<usize>,
+    timeout: Duration,
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    for (served, stream) in listener.incoming().enumerate() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(&stream, handler, timeout, env) {
+                    eprintln!("serve: {}", e);
+                }
+            }
+            Err(e) => eprintln!("serve: {}", e),
+        }
+        if max_requests.is_some_and(|max| served + 1 >= max) {
+            break;
+        }
+    }
+    Ok(Object::Void)
+}
+
+// 期限までの残りの時間を、読むたびに読み込みのタイムアウトにする。
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+fn handle_connection(
+    stream: &TcpStream,
+    handler: &Object,
+    timeout: Duration,
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<(), String> {
+    let reader = DeadlineReader {
+        stream,
+        deadline: Instant::now() + timeout,
+    };
+    let response = match read_request(&mut BufReader::new(reader)) {
+        Ok(request) => match apply(handler, &[request_object(request)], env) {
+            Ok(value) => response_from(&value).unwrap_or_else(|e| Response::error(500, e)),
+            Err(e) => Response::error(500, e),
+        },
+        Err(response) => response,
+    };
+    write_response(stream, &response).map_err(|e| e.to_string())
+}
+
+// MAX_LINE までしか読まないので、改行の無い長い行でも読み続けない。長すぎれば status のエラーにする。
+fn read_line(reader: &mut impl BufRead, status: usize) -> Result<String, Response> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE as u64 + 1)
+        .read_line(&mut line)
+        .map_err(read_error)?;
+    if line.len() > MAX_LINE {
+        return Err(Response::error(
+            status,
+            format!("Line is longer than {} bytes", MAX_LINE),
+        ));
+    }
+    Ok(line)
+}
+
+// 読めなかったリクエストには、そのまま返すエラーのレスポンスを返す。
+fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let line = read_line(reader, 400)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => {
+            return Err(Response::error(
+                400,
+                format!("Malformed request line: {:?}", line),
+            ));
+        }
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (target.to_string(), String::new()),
+    };
+
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let line = read_line(reader, 431)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(Response::error(
+                431,
+                format!("More than {} headers", MAX_HEADERS),
+            ));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| Response::error(400, format!("Malformed header: {:?}", line)))?;
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+        if name == "content-length" {
+            content_length = value
+                .parse()
+                .map_err(|_| Response::error(400, format!("Invalid Content-Length: {}", value)))?;
+        }
+        headers.push((name, value));
+    }
+
+    if content_length > MAX_BODY {
+        return Err(Response::error(
+            413,
+            format!("Request body is larger than {} bytes", MAX_BODY),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(read_error)?;
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+// REQUEST_TIMEOUT の間に読めなかったら 408、それ以外の読めなかった理由は 400
+fn read_error(e: std::io::Error) -> Response {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Response::error(408, e.to_string()),
+        _ => Response::error(400, e.to_string()),
+    }
+}
+
+fn pair(key: Object, value: Object) -> Object {
+    Object::ListData(Rc::new(vec![key, value]))
+}
+
+fn string(s: String) -> Object {
+    Object::String(s.into())
+}
+
+fn request_object(request: Request) -> Object {
+    let headers = request
+        .headers
+        .into_iter()
+        .map(|(name, value)| pair(string(name), string(value)))
+        .collect();
+    Object::ListData(Rc::new(vec![
+        pair(Object::Symbol("method".into()), string(request.method)),
+        pair(Object::Symbol("path".into()), string(request.path)),
+        pair(Object::Symbol("query".into()), string(request.query)),
+        pair(
+            Object::Symbol("headers".into()),
+            Object::ListData(Rc::new(headers)),
+        ),
+        pair(Object::Symbol("body".into()), string(request.body)),
+    ]))
+}
+
+fn response_from(value: &Object) -> Result<Response, String> {
+    let mut response = Response {
+        status: 200,
+        content_type: "text/html; charset=utf-8".to_string(),
+        headers: Vec::new(),
+        body: String::new(),
+    };
+    let fields = match value {
+        Object::String(body) => {
+            response.body = body.to_string();
+            return Ok(response);
+        }
+        value => list_items(value).ok_or_else(|| format!("Invalid response: {}", value))?,
+    };
+
+    for field in fields.iter() {
+        let (key, value) = match list_items(field).as_deref() {
+            Some([key, value]) => (key.to_string(), value.clone()),
+            _ => return Err(format!("Invalid response field: {}", field)),
+        };
+        match key.as_str() {
+            "status" => response.status = expect_usize("serve", &value)?,
+            "content-type" => response.content_type = header_value(&value)?,
+            "body" => response.body = value.to_string(),
+            "headers" => match list_items(&value) {
+                Some(headers) => {
+                    for header in headers.iter() {
+                        match list_items(header).as_deref() {
+                            Some([name, value]) => response
+                                .headers
+                                .push((header_name(name)?, header_value(value)?)),
+                            _ => return Err(format!("Invalid response header: {}", header)),
+                        }
+                    }
+                }
+                None => return Err(format!("Invalid response headers: {}", value)),
+            },
+            _ => return Err(format!("Unknown response field: {}", key)),
+        }
+    }
+    Ok(response)
+}
+
+// 改行を含む名前や値はヘッダーを増やせてしまうので受け付けない
+fn header_name(name: &Object) -> Result<String, String> {
+    let name = name.to_string();
+    if name.is_empty() || name.contains(['\r', '\n', ':']) {
+        return Err(format!("Invalid response header name: {:?}", name));
+    }
+    Ok(name)
+}
+
+fn header_value(value: &Object) -> Result<String, String> {
+    let value = value.to_string();
+    if value.contains(['\r', '\n']) {
+        return Err(format!("Invalid response header value: {:?}", value));
+    }
+    Ok(value)
+}
+
+fn reason_phrase(status: usize) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::{Builtin, builtin};
+    use crate::eval::eval;
+    use std::io::Read;
+    use std::net::SocketAddr;
+    use std::thread;
+
+    fn request(addr: SocketAddr, raw: &'static str) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(raw.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    fn serve_once(handler: &Object, raw: &'static str) -> String {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = request(listener.local_addr().unwrap(), raw);
+        serve_listener(&listener, handler, Some(1), REQUEST_TIMEOUT, &mut env).unwrap();
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn test_lisp_handler() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let handler = eval("(lambda (request) (format \"<h1>hi</h1>\"))", &mut env).unwrap();
+        let response = serve_once(&handler, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(response.ends_with("\r\n\r\n<h1>hi</h1>"));
+    }
+
+    fn echo(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+        Ok(Object::ListData(Rc::new(vec![
+            pair(Object::Symbol("status".into()), Object::Integer(201)),
+            pair(
+                Object::Symbol("content-type".into()),
+                string("text/plain".to_string()),
+            ),
+            pair(Object::Symbol("body".into()), string(args[0].to_string())),
+        ])))
+    }
+
+    static ECHO: Builtin = builtin!("echo", echo, (Any), "");
+
+    #[test]
+    fn test_request_and_response_fields() {
+        let response = serve_once(
+            &Object::Builtin(&ECHO),
+            "POST /items?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello",
+        );
+        assert!(response.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert!(response.contains("Content-Type: text/plain\r\n"));
+        assert!(response.ends_with(
+            "((method POST) (path /items) (query x=1) \
+             (headers ((host localhost) (content-length 5))) (body hello))"
+        ));
+    }
+
+    #[test]
+    fn test_handler_error_is_500() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let handler = eval("(lambda (request) (+ 1 \"x\"))", &mut env).unwrap();
+        let response = serve_once(&handler, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    }
+
+    #[test]
+    fn test_oversized_body_is_413() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let handler = eval("(lambda (request) \"ok\")", &mut env).unwrap();
+        let response = serve_once(
+            &handler,
+            "POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        let response = serve_once(&handler, "POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_header_injection_is_rejected() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let handler = eval(
+            "(lambda (request) (list (list 'headers (list (list \"x\" \"a\r\nSet-Cookie: b\")))))",
+            &mut env,
+        )
+        .unwrap();
+        let response = serve_once(&handler, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(!response.contains("\r\nSet-Cookie"));
+    }
+
+    #[test]
+    fn test_slow_client_times_out() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let handler = eval("(lambda (request) \"ok\")", &mut env).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // 何も送らないクライアントの後でも、次のクライアントに応答する
+        let slow = TcpStream::connect(addr).unwrap();
+        let client = request(addr, "GET / HTTP/1.1\r\n\r\n");
+        let timeout = Duration::from_millis(100);
+        serve_listener(&listener, &handler, Some(2), timeout, &mut env).unwrap();
+        assert!(client.join().unwrap().ends_with("\r\n\r\nok"));
+        drop(slow);
+    }
+
+    #[test]
+    fn test_trickling_client_times_out() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let handler = eval("(lambda (request) \"ok\")", &mut env).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // 読むたびの期限には間に合う間隔で、ヘッダーを 1 行ずつ送り続ける
+        let trickle = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            for i in 0..40 {
+                if stream
+                    .write_all(format!("X-{}: y\r\n", i).as_bytes())
+                    .is_err()
+                {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        thread::sleep(Duration::from_millis(20));
+        let client = request(addr, "GET / HTTP/1.1\r\n\r\n");
+        let started = Instant::now();
+        serve_listener(
+            &listener,
+            &handler,
+            Some(2),
+            Duration::from_millis(300),
+            &mut env,
+        )
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(client.join().unwrap().ends_with("\r\n\r\nok"));
+        trickle.join().unwrap();
+    }
+
+    fn status_of(raw: &str) -> usize {
+        match read_request(&mut std::io::Cursor::new(raw.as_bytes())) {
+            Ok(_) => 200,
+            Err(response) => response.status,
+        }
+    }
+
+    #[test]
+    fn test_request_limits() {
+        let long = "a".repeat(MAX_LINE);
+        assert_eq!(status_of(&format!("GET /{} HTTP/1.1\r\n\r\n", long)), 400);
+        assert_eq!(
+            status_of(&format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", long)),
+            431
+        );
+        let headers = |n: usize| {
+            let lines: String = (0..n).map(|i| format!("X-{}: y\r\n", i)).collect();
+            format!("GET / HTTP/1.1\r\n{}\r\n", lines)
+        };
+        assert_eq!(status_of(&headers(MAX_HEADERS)), 200);
+        assert_eq!(status_of(&headers(MAX_HEADERS + 1)), 431);
+    }
+}
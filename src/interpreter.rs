@@ -0,0 +1,459 @@
+// Env を持ち、ソースコードを評価する入口。REPL やリモート REPL、Jupyter カーネルなどから使う。
+// ソースには複数の式を書くことができ、先頭から順に評価する。
+// new は環境変数から設定を読む。読む環境変数と優先順位は config.rs を参照。
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, load_file, with_load_path};
+use crate::eval::{
+    Env, apply, capture_output, capture_warnings, eval_toplevel, forget_failed, take_failed,
+    with_fuel,
+};
+use crate::parser::{Object, Parser, Span, line_col, parse_mapped};
+use crate::printer::write_definition;
+use crate::reader::{ReadTable, with_read_table};
+use crate::stdin::filter_lines;
+
+pub struct Interpreter {
+    env: Rc<RefCell<Env>>,
+    config: Config,
+    read_table: ReadTable,
+}
+
+// eval_rich の結果。エディタなどに返せるように、値と一緒に出力や実行時間も持つ。
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct EvalResult {
+    pub value: Result<Object, LispError>,
+    pub output: String, // 評価中の print の出力
+    pub warnings: Vec<String>,
+    pub duration: Duration,
+}
+
+// 埋め込む側に返すエラー。フィールドは今後も増やすので、crate の外では作らずに読むだけにする。
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LispError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span, // エラーになったトップレベルの式のソース上の範囲
+    // エラーになった式のうち、ソースにあるいちばん内側のリストの範囲。わからなければ span と同じ。
+    // 読めなかった場合は、読めなかったトークンから入力の最後まで
+    pub expr_span: Span,
+    // expr_span の先頭の行と列。1 から数える
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    Parse, // ソースを読めなかった。どの式も評価していない
+    Eval,
+    Type, // mr-lisp typecheck が評価せずに見つけた誤り (typecheck.rs)
+}
+
+// line 3, col 10: Undefined symbol: foo
+impl fmt::Display for LispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, col {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl LispError {
+    pub(crate) fn new(
+        kind: ErrorKind,
+        message: String,
+        span: Span,
+        program: &str,
+        expr_span: Span,
+    ) -> Self {
+        let (line, column) = line_col(program, expr_span.start);
+        LispError {
+            kind,
+            message,
+            span,
+            expr_span,
+            line,
+            column,
+        }
+    }
+
+    // expr_span の最初の行と、その下に式の範囲を示す ^ の行。REPL でエラーの位置を見せるのに使う。
+    //
+    //   (+ y (g x))
+    //        ^^^^^
+    pub fn underline(&self, program: &str) -> String {
+        let start = self.expr_span.start.min(program.len());
+        let line_start = program[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = program[start..]
+            .find('\n')
+            .map_or(program.len(), |i| start + i);
+        let end = self.expr_span.end.clamp(start, line_end);
+        format!(
+            "{}\n{}{}",
+            &program[line_start..line_end],
+            " ".repeat(program[line_start..start].chars().count()),
+            "^".repeat(program[start..end].chars().count().max(1))
+        )
+    }
+}
+
+impl Error for LispError {}
+
+impl Interpreter {
+    // 環境変数の値が正しくない場合や MR_LISP_INIT の評価に失敗した場合は、標準エラー出力に書いて続ける。
+    pub fn new() -> Self {
+        let config = Config::from_env().unwrap_or_else(|e| {
+            eprintln!("mr-lisp: {}", e);
+            Config::default()
+        });
+        let mut interpreter = Interpreter {
+            env: Rc::new(RefCell::new(Env::new())),
+            config,
+            read_table: ReadTable::new(),
+        };
+        if let Err(e) = interpreter.load_init() {
+            eprintln!("mr-lisp: MR_LISP_INIT: {}", e);
+        }
+        interpreter
+    }
+
+    // 環境変数は読まずに config の設定で作る。config.init の評価に失敗した場合はそのエラーを返す。
+    pub fn with_config(config: Config) -> Result<Self, String> {
+        let mut interpreter = Interpreter {
+            env: Rc::new(RefCell::new(Env::new())),
+            config,
+            read_table: ReadTable::new(),
+        };
+        interpreter.load_init()?;
+        Ok(interpreter)
+    }
+
+    fn load_init(&mut self) -> Result<(), String> {
+        if let Some(init) = self.config.init.clone() {
+            self.run(|env| load_file(&init, env))?;
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // fuel や path の変更は次の eval から効く。init は作ったときにしか評価しない。
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.config.fuel = fuel;
+    }
+
+    pub fn env(&mut self) -> &mut Rc<RefCell<Env>> {
+        &mut self.env
+    }
+
+    // 大域の define を許すかどうかを決める関数を設定する。Err を返すと define はそのメッセージのエラーになる。
+    // プラグインには plugin/ で始まる名前しか定義させない、といった制限に使う。
+    pub fn set_binding_policy(
+        &mut self,
+        policy: impl Fn(&str, &Object) -> Result<(), String> + 'static,
+    ) {
+        self.env.borrow_mut().set_binding_policy(Rc::new(policy));
+    }
+
+    // #dispatch で始まるソースを reader で読むようにする。reader には # と dispatch の後のソースが渡る (reader.rs)。
+    pub fn register_reader_macro(
+        &mut self,
+        dispatch: char,
+        reader: impl Fn(&str) -> Result<Object, String> + 'static,
+    ) -> Result<(), String> {
+        self.read_table.register(dispatch, reader)
+    }
+
+    // この Interpreter のリーダーマクロも読む、少しずつ入力を足せる Parser。REPL が続きの行を待つのに使う。
+    pub fn parser(&self) -> Parser {
+        Parser::with_read_table(self.read_table.clone())
+    }
+
+    // 組み込み関数以外の大域の束縛を、名前順に (define name value) の形で 1 行ずつ書く。
+    // 同じ束縛からは毎回同じ文字列になるので、テストで期待する環境との差分を見るのに使う。
+    pub fn dump_canonical(&self) -> String {
+        self.env
+            .borrow()
+            .user_bindings()
+            .iter()
+            .map(|(name, value)| write_definition(name, value) + "\n")
+            .collect()
+    }
+
+    // 式を順に評価して最後の値を返す。式が 1 つもなければ Void を返す。
+    pub fn eval(&mut self, program: &str) -> Result<Object, String> {
+        self.eval_spanned(program).map_err(|e| e.message)
+    }
+
+    // パース済みの式を 1 つ評価する。builder で組み立てた式などに使う。
+    pub fn eval_object(&mut self, obj: &Object) -> Result<Object, String> {
+        self.run(|env| eval_toplevel(obj, env))
+    }
+
+    // 評価して得た関数を args で呼ぶ。mr-lisp test でテストの関数を実行するのに使う。
+    pub fn apply(&mut self, func: &Object, args: &[Object]) -> Result<Object, String> {
+        self.run(|env| apply(func, args, env))
+    }
+
+    // eval と同じように評価し、print の出力と警告、実行時間も集める。print の出力は標準出力には出さない。
+    pub fn eval_rich(&mut self, program: &str) -> EvalResult {
+        let start = Instant::now();
+        let ((value, output), warnings) =
+            capture_warnings(|| capture_output(|| self.eval_spanned(program)));
+        EvalResult {
+            value,
+            output,
+            warnings,
+            duration: start.elapsed(),
+        }
+    }
+
+    // eval と同じように評価し、エラーはソース上の位置と一緒に返す。
+    pub fn eval_spanned(&mut self, program: &str) -> Result<Object, LispError> {
+        let parsed = with_read_table(&self.read_table, || parse_mapped(program));
+        let (forms, source_map) = parsed.map_err(|(e, span)| {
+            LispError::new(ErrorKind::Parse, e.to_string(), span.clone(), program, span)
+        })?;
+        self.run(|env| {
+            let mut result = Object::Void;
+            for (form, span) in forms {
+                forget_failed();
+                result = eval_toplevel(&form, env).map_err(|message| {
+                    // エラーが通ったリストのうち、いちばん内側のソースにあるもの
+                    let at = take_failed()
+                        .into_iter()
+                        .find_map(|list| source_map.span(list))
+                        .unwrap_or_else(|| span.clone());
+                    LispError::new(ErrorKind::Eval, message, span.clone(), program, at)
+                })?;
+            }
+            Ok(result)
+        })
+    }
+
+    // script を評価した値の関数を標準入力の各行に適用し、結果を 1 行ずつ書く。mr-lisp --filter で使う。
+    pub fn filter(&mut self, script: &str) -> Result<(), String> {
+        let func = self.eval(script)?;
+        self.run(|env| filter_lines(&func, env))
+    }
+
+    // fuel と load の探索パス、読み取り表を設定に合わせてから評価する。
+    fn run<T>(&mut self, f: impl FnOnce(&mut Rc<RefCell<Env>>) -> T) -> T {
+        let mut env = Rc::clone(&self.env);
+        self.run_in(&mut env, f)
+    }
+
+    // run と同じ設定で、この Interpreter の Env の子の env で評価する。プラグインの Env に使う (plugin.rs)。
+    pub(crate) fn run_in<T>(
+        &self,
+        env: &mut Rc<RefCell<Env>>,
+        f: impl FnOnce(&mut Rc<RefCell<Env>>) -> T,
+    ) -> T {
+        with_fuel(self.config.fuel, || {
+            with_load_path(&self.config.path, || {
+                with_read_table(&self.read_table, || f(env))
+            })
+        })
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_rich() {
+        let mut interpreter = Interpreter::new();
+        let result = interpreter.eval_rich("(define (sq x) (* x x))\n(print \"hi\")\n(sq 3)");
+        assert_eq!(result.value, Ok(Object::Integer(9)));
+        assert_eq!(result.output, "hi\n");
+        assert_eq!(interpreter.eval("(sq 4)"), Ok(Object::Integer(16)));
+        assert_eq!(interpreter.eval(""), Ok(Object::Void));
+
+        let program = "(print 1)\n(sq y)\n(print 2)";
+        let result = interpreter.eval_rich(program);
+        let error = result.value.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Eval);
+        assert_eq!(error.to_string(), "line 2, col 1: Undefined symbol: y");
+        assert_eq!(&program[error.span], "(sq y)");
+        assert_eq!(result.output, "1\n");
+
+        let error = interpreter.eval_rich("(sq 1) (sq").value.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Parse);
+        assert!(error.message.starts_with("ParseError"));
+        assert_eq!(error.span, 7..10);
+        assert_eq!((error.line, error.column), (1, 8));
+        assert_eq!(error.underline("(sq 1) (sq"), "(sq 1) (sq\n       ^^^");
+    }
+
+    #[test]
+    fn test_dump_canonical() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.dump_canonical(), "");
+        interpreter
+            .eval(
+                "(define z '(a \"b\" (quote c))) (define (sq x) (* x x)) (define s \"hi\")
+                 (define v #(1 x)) (define car 1.5) (define y 'sym) (define p (delay 1))
+                 (define my-cdr cdr)",
+            )
+            .unwrap();
+        let dump = interpreter.dump_canonical();
+        assert_eq!(
+            dump,
+            "(define car 1.5)\n\
+             (define my-cdr cdr)\n\
+             (define p #<promise>)\n\
+             (define s \"hi\")\n\
+             (define sq (lambda (x) (* x x)))\n\
+             (define v '#(1 x))\n\
+             (define y 'sym)\n\
+             (define z '(a \"b\" 'c))\n"
+        );
+        // 書いた定義を評価し直すと同じ束縛になる
+        let mut copy = Interpreter::new();
+        copy.eval(&dump.replace("#<promise>", "(delay 1)")).unwrap();
+        assert_eq!(copy.dump_canonical(), dump);
+    }
+
+    #[test]
+    fn test_error_location() {
+        let mut interpreter = Interpreter::new();
+        let program = "(define (f x)\n  (let ((y 1))\n    (+ y (g x))))\n\n(print (f 2))";
+        let error = interpreter.eval_spanned(program).unwrap_err();
+        assert_eq!(error.to_string(), "line 3, col 10: Undefined function: g");
+        assert_eq!(&program[error.expr_span.clone()], "(g x)");
+        assert_eq!(
+            error.underline(program),
+            "    (+ y (g x))))\n         ^^^^^"
+        );
+        assert_eq!(&program[error.span], "(print (f 2))");
+
+        // syntax-rules は引数の式をそのまま置くので、その位置を示す。
+        // define-macro の引数はデータにして渡し、式に戻したものはソースに無いので、呼び出した式の位置になる
+        interpreter
+            .eval("(define-syntax twice (syntax-rules () ((_ e) (begin e e))))")
+            .unwrap();
+        let error = interpreter.eval_spanned("(twice\n  (car 1))").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 3));
+        // 2 行目の式なら、その行を出して下線を引く
+        assert_eq!(
+            error.underline("(twice\n  (car 1))"),
+            "  (car 1))\n  ^^^^^^^"
+        );
+        interpreter
+            .eval("(define-macro (twice! e) `(begin ,e ,e))")
+            .unwrap();
+        let error = interpreter.eval_spanned("(twice!\n  (car 1))").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+
+        // try で捕まえたエラーの位置は、後のエラーの位置にしない
+        let error = interpreter
+            .eval_spanned("(begin (try (car 1) (catch (e) 0))\n (cdr 1))")
+            .unwrap_err();
+        assert_eq!((error.line, error.column), (2, 2));
+        assert_eq!(
+            interpreter
+                .eval_spanned("(+ 1\n (\"s\" 2))")
+                .unwrap_err()
+                .column,
+            2
+        );
+    }
+
+    #[test]
+    fn test_binding_policy() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("(define (helper) 1)").unwrap();
+        interpreter.set_binding_policy(|name, value| {
+            if !name.starts_with("plugin/") {
+                return Err("plugins may only define plugin/* names".to_string());
+            }
+            match value {
+                Object::Macro(_) => Err("macros are not allowed".to_string()),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(
+            interpreter.eval("(define helper 2)"),
+            Err("Cannot define helper: plugins may only define plugin/* names".to_string())
+        );
+        assert_eq!(interpreter.eval("(helper)"), Ok(Object::Integer(1)));
+        assert!(interpreter.eval("(set! helper 2)").is_err());
+        assert!(interpreter.eval("(define-macro (plugin/m x) x)").is_err());
+        assert!(interpreter.eval("(eval '(define x 1))").is_err());
+        interpreter
+            .eval("(define (plugin/run x) (define tmp (* x 2)) (+ tmp (helper)))")
+            .unwrap();
+        assert_eq!(interpreter.eval("(plugin/run 3)"), Ok(Object::Integer(7)));
+    }
+
+    #[test]
+    fn test_config() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-config-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("lib/square.lisp"), "(define (square x) (* x x))").unwrap();
+        std::fs::write(
+            dir.join("init.lisp"),
+            "(load \"square.lisp\")\n(define ready 1)",
+        )
+        .unwrap();
+
+        let config = Config {
+            path: vec![dir.join("lib")],
+            init: Some(dir.join("init.lisp")),
+            fuel: Some(1000),
+            ..Config::default()
+        };
+        let mut interpreter = Interpreter::with_config(config).unwrap();
+        assert_eq!(
+            interpreter.eval("(+ ready (square 4))"),
+            Ok(Object::Integer(17))
+        );
+        assert_eq!(
+            interpreter.eval("(define (spin) (spin)) (spin)"),
+            Err("Out of fuel".to_string())
+        );
+        // fuel は eval ごとに補充される
+        assert_eq!(interpreter.eval("(square 5)"), Ok(Object::Integer(25)));
+        interpreter.set_fuel(None);
+        assert_eq!(
+            interpreter.eval("(define n 0) (while (< n 2000) (set! n (+ n 1))) n"),
+            Ok(Object::Integer(2000))
+        );
+        assert!(
+            interpreter
+                .eval("(load \"missing.lisp\")")
+                .unwrap_err()
+                .starts_with("load: missing.lisp: ")
+        );
+
+        let config = Config {
+            init: Some(dir.join("missing.lisp")),
+            ..Config::default()
+        };
+        assert!(Interpreter::with_config(config).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
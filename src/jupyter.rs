@@ -0,0 +1,844 @@
+// Jupyter カーネル。mr-lisp-kernel バイナリから接続ファイルのパスを渡して起動する。
+// kernelspec の kernel.json は次のようにする。
+//
+//   {"argv": ["mr-lisp-kernel", "{connection_file}"], "display_name": "mr-lisp", "language": "mr-lisp"}
+//
+// ZeroMQ のライブラリは使わず、ZMTP 3.0 (NULL mechanism) を TCP の上で直接話す。
+// 接続ごとにスレッドを立ててメッセージを読み、評価はすべてメインスレッドで順番に行う。
+// 対応しているのは kernel_info_request、execute_request、shutdown_request で、
+// 評価中の print の出力は stream メッセージとして送る。
+// 1 つのフレームは MAX_FRAME バイトまで。相手がそれより長いと言ってきたら、確保する前にエラーにする。
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::interpreter::Interpreter;
+use crate::json::{Json, object};
+use crate::parser::Object;
+use crate::render::Renderers;
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+const MAX_FRAME: u64 = 16 << 20;
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (hi, v) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *hi = hi.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (chunk, hi) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&hi.to_be_bytes());
+    }
+    digest
+}
+
+// parts を連結したものに対する HMAC-SHA256
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    for part in parts {
+        inner.extend_from_slice(part);
+    }
+    let mut outer: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+    sha256(&outer)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+const FLAG_MORE: u8 = 0x1;
+const FLAG_LONG: u8 = 0x2;
+const FLAG_COMMAND: u8 = 0x4;
+
+fn write_frame(out: &mut Vec<u8>, flags: u8, body: &[u8]) {
+    if body.len() > u8::MAX as usize {
+        out.push(flags | FLAG_LONG);
+        out.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        out.push(flags);
+        out.push(body.len() as u8);
+    }
+    out.extend_from_slice(body);
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0; 1];
+    stream.read_exact(&mut flags)?;
+    let size = if flags[0] & FLAG_LONG != 0 {
+        let mut size = [0; 8];
+        stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0; 1];
+        stream.read_exact(&mut size)?;
+        size[0] as u64
+    };
+    if size > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame is larger than {} bytes", MAX_FRAME),
+        ));
+    }
+    let mut body = vec![0; size as usize];
+    stream.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+// 複数フレームのメッセージを 1 回の write でまとめて送る。
+fn write_message(stream: &mut impl Write, frames: &[Vec<u8>]) -> io::Result<()> {
+    let mut out = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let flags = if i + 1 < frames.len() { FLAG_MORE } else { 0 };
+        write_frame(&mut out, flags, frame);
+    }
+    stream.write_all(&out)
+}
+
+fn read_message(stream: &mut impl Read) -> io::Result<Vec<Vec<u8>>> {
+    let mut frames = Vec::new();
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & FLAG_COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & FLAG_MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+// ZMTP 3.0 の greeting と NULL mechanism の READY コマンドを交換する。
+fn handshake(stream: &mut TcpStream, socket_type: &str) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting)?;
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xff || peer[9] & 1 == 0 || peer[10] < 3 {
+        return Err(invalid("unsupported ZMTP version"));
+    }
+    if &peer[12..17] != b"NULL\0" {
+        return Err(invalid("unsupported ZMTP mechanism"));
+    }
+
+    let mut ready = b"\x05READY\x0bSocket-Type".to_vec();
+    ready.extend_from_slice(&(socket_type.len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket_type.as_bytes());
+    let mut out = Vec::new();
+    write_frame(&mut out, FLAG_COMMAND, &ready);
+    stream.write_all(&out)?;
+
+    let (flags, body) = read_frame(stream)?;
+    if flags & FLAG_COMMAND == 0 || !body.starts_with(b"\x05READY") {
+        return Err(invalid("expected READY command"));
+    }
+    Ok(())
+}
+
+struct Sockets {
+    shell: TcpListener,
+    control: TcpListener,
+    stdin: TcpListener,
+    iopub: TcpListener,
+    hb: TcpListener,
+}
+
+fn bind(connection: &Json) -> Result<Sockets, String> {
+    let field = |name: &str| {
+        connection
+            .get(name)
+            .ok_or_else(|| format!("connection file: missing {}", name))
+    };
+    if field("transport")?.as_str() != Some("tcp") {
+        return Err("connection file: only the tcp transport is supported".to_string());
+    }
+    let ip = field("ip")?.as_str().ok_or("connection file: invalid ip")?;
+    let listen = |name: &str| -> Result<TcpListener, String> {
+        let port = field(name)?
+            .as_f64()
+            .ok_or_else(|| format!("connection file: invalid {}", name))?;
+        TcpListener::bind((ip, port as u16)).map_err(|e| format!("{}: {}", name, e))
+    };
+    Ok(Sockets {
+        shell: listen("shell_port")?,
+        control: listen("control_port")?,
+        stdin: listen("stdin_port")?,
+        iopub: listen("iopub_port")?,
+        hb: listen("hb_port")?,
+    })
+}
+
+// 接続ファイルを読んでカーネルを起動する。shutdown_request を受け取るまで戻らない。
+pub fn run(connection_file: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(connection_file)
+        .map_err(|e| format!("{}: {}", connection_file, e))?;
+    let connection = Json::parse(&text)?;
+    if let Some(scheme) = connection.get("signature_scheme").and_then(Json::as_str)
+        && scheme != "hmac-sha256"
+    {
+        return Err(format!("unsupported signature scheme: {}", scheme));
+    }
+    let key = connection.get("key").and_then(Json::as_str).unwrap_or("");
+    serve(bind(&connection)?, key)
+}
+
+type Writer = Arc<Mutex<TcpStream>>;
+
+// ROUTER ソケット。届いたメッセージは返信先の接続と一緒にメインスレッドへ送る。
+fn spawn_router(listener: TcpListener, requests: Sender<(Vec<Vec<u8>>, Writer)>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let requests = requests.clone();
+            thread::spawn(move || -> io::Result<()> {
+                let mut stream = stream;
+                handshake(&mut stream, "ROUTER")?;
+                let writer = Arc::new(Mutex::new(stream.try_clone()?));
+                loop {
+                    let frames = read_message(&mut stream)?;
+                    if requests.send((frames, Arc::clone(&writer))).is_err() {
+                        return Ok(());
+                    }
+                }
+            });
+        }
+    });
+}
+
+// PUB ソケット。購読の登録などの受信メッセージは読み捨て、全員に同じものを送る。
+fn spawn_publisher(listener: TcpListener, subscribers: Arc<Mutex<Vec<TcpStream>>>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let subscribers = Arc::clone(&subscribers);
+            thread::spawn(move || -> io::Result<()> {
+                let mut stream = stream;
+                handshake(&mut stream, "PUB")?;
+                subscribers.lock().unwrap().push(stream.try_clone()?);
+                loop {
+                    read_message(&mut stream)?;
+                }
+            });
+        }
+    });
+}
+
+// 受け取ったメッセージをそのまま送り返す。heartbeat (REP) と、入力要求を使わない stdin で使う。
+fn spawn_echo(listener: TcpListener, socket_type: &'static str, echo: bool) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || -> io::Result<()> {
+                let mut stream = stream;
+                handshake(&mut stream, socket_type)?;
+                loop {
+                    let frames = read_message(&mut stream)?;
+                    if echo {
+                        write_message(&mut stream, &frames)?;
+                    }
+                }
+            });
+        }
+    });
+}
+
+struct Message {
+    identities: Vec<Vec<u8>>,
+    header: Json,
+    content: Json,
+}
+
+impl Message {
+    fn msg_type(&self) -> &str {
+        self.header
+            .get("msg_type")
+            .and_then(Json::as_str)
+            .unwrap_or("")
+    }
+}
+
+struct Session {
+    key: Vec<u8>,
+    id: String,
+    counter: Cell<u64>,
+}
+
+impl Session {
+    fn new(key: &str) -> Self {
+        let mut session = Session {
+            key: key.as_bytes().to_vec(),
+            id: String::new(),
+            counter: Cell::new(0),
+        };
+        session.id = session.new_id();
+        session
+    }
+
+    fn new_id(&self) -> String {
+        self.counter.set(self.counter.get() + 1);
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let mut id = String::new();
+        for _ in 0..2 {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(nanos);
+            hasher.write_u64(self.counter.get());
+            id.push_str(&format!("{:016x}", hasher.finish()));
+        }
+        id
+    }
+
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        if self.key.is_empty() {
+            String::new()
+        } else {
+            hex(&hmac_sha256(&self.key, parts))
+        }
+    }
+
+    fn decode(&self, frames: Vec<Vec<u8>>) -> Result<Message, String> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or("missing <IDS|MSG> delimiter")?;
+        let Some([signature, header, parent_header, metadata, content]) =
+            frames.get(delimiter + 1..delimiter + 6)
+        else {
+            return Err("incomplete message".to_string());
+        };
+        let expected = self.sign(&[header, parent_header, metadata, content]);
+        if signature != expected.as_bytes() {
+            return Err("invalid signature".to_string());
+        }
+        let json = |frame: &[u8]| Json::parse(&String::from_utf8_lossy(frame));
+        Ok(Message {
+            identities: frames[..delimiter].to_vec(),
+            header: json(header)?,
+            content: json(content)?,
+        })
+    }
+
+    fn encode(
+        &self,
+        identities: &[Vec<u8>],
+        msg_type: &str,
+        parent_header: &Json,
+        content: Json,
+    ) -> Vec<Vec<u8>> {
+        let header = object([
+            ("msg_id", self.new_id().into()),
+            ("session", self.id.clone().into()),
+            ("username", "kernel".into()),
+            ("date", now_iso8601().into()),
+            ("msg_type", msg_type.into()),
+            ("version", PROTOCOL_VERSION.into()),
+        ]);
+        let parts = [
+            header.to_string().into_bytes(),
+            parent_header.to_string().into_bytes(),
+            b"{}".to_vec(),
+            content.to_string().into_bytes(),
+        ];
+        let signature = self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+        let mut frames = identities.to_vec();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend(parts);
+        frames
+    }
+}
+
+// 2024-01-02T03:04:05.000006Z の形式の UTC の現在時刻
+fn now_iso8601() -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // days from civil の逆変換 (proleptic グレゴリオ暦)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        now.subsec_micros()
+    )
+}
+
+struct Kernel {
+    session: Session,
+    subscribers: Arc<Mutex<Vec<TcpStream>>>,
+    interpreter: Interpreter,
+    renderers: Renderers,
+    execution_count: usize,
+}
+
+impl Kernel {
+    fn publish(&self, parent: &Message, msg_type: &str, content: Json) {
+        let topic = format!("kernel.{}.{}", self.session.id, msg_type).into_bytes();
+        let frames = self
+            .session
+            .encode(&[topic], msg_type, &parent.header, content);
+        // 書き込めなくなった購読者は切断されたものとして外す。
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|stream| write_message(stream, &frames).is_ok());
+    }
+
+    fn reply(&self, writer: &Writer, parent: &Message, msg_type: &str, content: Json) {
+        let frames = self
+            .session
+            .encode(&parent.identities, msg_type, &parent.header, content);
+        if let Err(e) = write_message(&mut *writer.lock().unwrap(), &frames) {
+            eprintln!("mr-lisp-kernel: {}", e);
+        }
+    }
+
+    fn kernel_info(&self) -> Json {
+        let version = env!("CARGO_PKG_VERSION");
+        object([
+            ("status", "ok".into()),
+            ("protocol_version", PROTOCOL_VERSION.into()),
+            ("implementation", "mr-lisp".into()),
+            ("implementation_version", version.into()),
+            (
+                "language_info",
+                object([
+                    ("name", "mr-lisp".into()),
+                    ("version", version.into()),
+                    ("mimetype", "text/x-scheme".into()),
+                    ("file_extension", ".lisp".into()),
+                ]),
+            ),
+            ("banner", format!("mr-lisp {}", version).into()),
+            ("help_links", Json::Array(Vec::new())),
+        ])
+    }
+
+    fn execute(&mut self, writer: &Writer, request: &Message) {
+        let code = request
+            .content
+            .get("code")
+            .and_then(Json::as_str)
+            .unwrap_or("");
+        let silent = request.content.get("silent") == Some(&Json::Bool(true));
+        if !silent {
+            self.execution_count += 1;
+        }
+        let count = Json::Number(self.execution_count as f64);
+        self.publish(
+            request,
+            "execute_input",
+            object([("code", code.into()), ("execution_count", count.clone())]),
+        );
+
+        let result = self.interpreter.eval_rich(code);
+        let output = result.output;
+        if !output.is_empty() && !silent {
+            self.publish(
+                request,
+                "stream",
+                object([("name", "stdout".into()), ("text", output.into())]),
+            );
+        }
+
+        match result.value {
+            Ok(value) => {
+                // レンダラーが表示を受け持つ値は display_data、それ以外は execute_result で返す。
+                if value != Object::Void && !silent {
+                    match self.renderers.render(&value) {
+                        Some(rendered) => self.publish(
+                            request,
+                            "display_data",
+                            object([
+                                ("data", object([("text/plain", rendered.into())])),
+                                ("metadata", object([])),
+                            ]),
+                        ),
+                        None => self.publish(
+                            request,
+                            "execute_result",
+                            object([
+                                ("execution_count", count.clone()),
+                                ("data", object([("text/plain", value.to_string().into())])),
+                                ("metadata", object([])),
+                            ]),
+                        ),
+                    }
+                }
+                self.reply(
+                    writer,
+                    request,
+                    "execute_reply",
+                    object([
+                        ("status", "ok".into()),
+                        ("execution_count", count),
+                        ("user_expressions", object([])),
+                        ("payload", Json::Array(Vec::new())),
+                    ]),
+                );
+            }
+            Err(e) => {
+                // traceback には位置を付ける
+                let error = || {
+                    [
+                        ("ename", Json::from("Error")),
+                        ("evalue", e.message.clone().into()),
+                        ("traceback", Json::Array(vec![e.to_string().into()])),
+                    ]
+                };
+                self.publish(request, "error", object(error()));
+                let [ename, evalue, traceback] = error();
+                self.reply(
+                    writer,
+                    request,
+                    "execute_reply",
+                    object([
+                        ("status", "error".into()),
+                        ("execution_count", count),
+                        ename,
+                        evalue,
+                        traceback,
+                    ]),
+                );
+            }
+        }
+    }
+
+    // shutdown_request を受け取ったら false を返す。
+    fn handle(&mut self, writer: &Writer, request: &Message) -> bool {
+        self.publish(
+            request,
+            "status",
+            object([("execution_state", "busy".into())]),
+        );
+        let running = match request.msg_type() {
+            "kernel_info_request" => {
+                self.reply(writer, request, "kernel_info_reply", self.kernel_info());
+                true
+            }
+            "execute_request" => {
+                self.execute(writer, request);
+                true
+            }
+            "shutdown_request" => {
+                let restart = request
+                    .content
+                    .get("restart")
+                    .cloned()
+                    .unwrap_or(Json::Bool(false));
+                self.reply(
+                    writer,
+                    request,
+                    "shutdown_reply",
+                    object([("status", "ok".into()), ("restart", restart)]),
+                );
+                false
+            }
+            msg_type => {
+                eprintln!("mr-lisp-kernel: unsupported message type {}", msg_type);
+                true
+            }
+        };
+        self.publish(
+            request,
+            "status",
+            object([("execution_state", "idle".into())]),
+        );
+        running
+    }
+}
+
+fn serve(sockets: Sockets, key: &str) -> Result<(), String> {
+    let (sender, requests) = mpsc::channel();
+    let subscribers = Arc::new(Mutex::new(Vec::new()));
+    spawn_router(sockets.shell, sender.clone());
+    spawn_router(sockets.control, sender);
+    spawn_publisher(sockets.iopub, Arc::clone(&subscribers));
+    spawn_echo(sockets.hb, "REP", true);
+    spawn_echo(sockets.stdin, "ROUTER", false);
+
+    let mut kernel = Kernel {
+        session: Session::new(key),
+        subscribers,
+        interpreter: Interpreter::new(),
+        renderers: Renderers::new(),
+        execution_count: 0,
+    };
+    for (frames, writer) in requests {
+        match kernel.session.decode(frames) {
+            Ok(request) => {
+                if !kernel.handle(&writer, &request) {
+                    return Ok(());
+                }
+            }
+            Err(e) => eprintln!("mr-lisp-kernel: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                b"Jefe",
+                &[b"what do ya ", b"want for nothing?"]
+            )),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_frame_size_limit() {
+        let mut out = Vec::new();
+        write_frame(&mut out, 0, &[7; 300]);
+        assert_eq!(
+            read_frame(&mut out.as_slice()).unwrap(),
+            (FLAG_LONG, vec![7; 300])
+        );
+
+        let mut huge = vec![FLAG_LONG];
+        huge.extend_from_slice(&u64::MAX.to_be_bytes());
+        let err = read_frame(&mut huge.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "frame is larger than 16777216 bytes");
+    }
+
+    fn connect(addr: SocketAddr, socket_type: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        handshake(&mut stream, socket_type).unwrap();
+        stream
+    }
+
+    fn request(session: &Session, stream: &mut TcpStream, msg_type: &str, content: Json) {
+        let frames = session.encode(&[], msg_type, &object([]), content);
+        write_message(stream, &frames).unwrap();
+    }
+
+    fn receive(session: &Session, stream: &mut TcpStream) -> io::Result<Message> {
+        let frames = read_message(stream)?;
+        Ok(session.decode(frames).unwrap())
+    }
+
+    // status が idle になるまでの iopub のメッセージ
+    fn iopub_messages(session: &Session, iopub: &mut TcpStream) -> Vec<Message> {
+        let mut messages = Vec::new();
+        loop {
+            let message = receive(session, iopub).unwrap();
+            let idle = message.content.get("execution_state") == Some(&"idle".into());
+            messages.push(message);
+            if idle {
+                return messages;
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernel() {
+        let localhost = || TcpListener::bind("127.0.0.1:0").unwrap();
+        let sockets = Sockets {
+            shell: localhost(),
+            control: localhost(),
+            stdin: localhost(),
+            iopub: localhost(),
+            hb: localhost(),
+        };
+        let addr = |listener: &TcpListener| listener.local_addr().unwrap();
+        let (shell, iopub, hb) = (
+            addr(&sockets.shell),
+            addr(&sockets.iopub),
+            addr(&sockets.hb),
+        );
+        let kernel = thread::spawn(move || serve(sockets, "secret"));
+        let mut shell = connect(shell, "DEALER");
+        let mut iopub = connect(iopub, "SUB");
+        let mut hb = connect(hb, "REQ");
+        let session = Session::new("secret");
+
+        write_message(&mut iopub, &[b"\x01".to_vec()]).unwrap();
+        write_message(&mut hb, &[Vec::new(), b"ping".to_vec()]).unwrap();
+        assert_eq!(
+            read_message(&mut hb).unwrap(),
+            [Vec::new(), b"ping".to_vec()]
+        );
+
+        // 購読が登録されるまで kernel_info_request を送り直す
+        iopub
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        loop {
+            request(&session, &mut shell, "kernel_info_request", object([]));
+            let reply = receive(&session, &mut shell).unwrap();
+            assert_eq!(reply.msg_type(), "kernel_info_reply");
+            if receive(&session, &mut iopub).is_ok() {
+                break;
+            }
+        }
+        iopub.set_read_timeout(None).unwrap();
+        iopub_messages(&session, &mut iopub);
+
+        let code = "(define (square x) (* x x))\n(print \"hi\")\n(square 12)";
+        request(
+            &session,
+            &mut shell,
+            "execute_request",
+            object([("code", code.into())]),
+        );
+        let reply = receive(&session, &mut shell).unwrap();
+        assert_eq!(reply.msg_type(), "execute_reply");
+        assert_eq!(reply.content.get("status"), Some(&"ok".into()));
+        assert_eq!(
+            reply.content.get("execution_count"),
+            Some(&Json::Number(1.0))
+        );
+        let messages = iopub_messages(&session, &mut iopub);
+        let types: Vec<&str> = messages.iter().map(Message::msg_type).collect();
+        assert_eq!(
+            types,
+            [
+                "status",
+                "execute_input",
+                "stream",
+                "execute_result",
+                "status"
+            ]
+        );
+        assert_eq!(messages[2].content.get("text"), Some(&"hi\n".into()));
+        assert_eq!(
+            messages[3].content.get("data").unwrap().get("text/plain"),
+            Some(&"144".into())
+        );
+
+        request(
+            &session,
+            &mut shell,
+            "execute_request",
+            object([("code", "(+ 1 \"x\")".into())]),
+        );
+        let reply = receive(&session, &mut shell).unwrap();
+        assert_eq!(reply.content.get("status"), Some(&"error".into()));
+        let types: Vec<String> = iopub_messages(&session, &mut iopub)
+            .iter()
+            .map(|message| message.msg_type().to_string())
+            .collect();
+        assert_eq!(types, ["status", "execute_input", "error", "status"]);
+
+        request(
+            &session,
+            &mut shell,
+            "shutdown_request",
+            object([("restart", Json::Bool(false))]),
+        );
+        assert_eq!(
+            receive(&session, &mut shell).unwrap().msg_type(),
+            "shutdown_reply"
+        );
+        kernel.join().unwrap().unwrap();
+    }
+}
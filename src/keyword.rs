@@ -0,0 +1,129 @@
+// 特殊形式の名前と二項演算子。字句解析の時点で enum にするので、評価器は文字列を比べずに分岐できる。
+//
+// 名前と enum の対応はここの表にだけ書く。新しい特殊形式を足すときは、表に 1 行足して eval_keyword に分岐を書く。
+
+use std::fmt;
+
+macro_rules! table {
+    ($(#[$meta:meta])* $vis:vis enum $ty:ident { $($variant:ident => $name:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $vis enum $ty {
+            $($variant,)*
+        }
+
+        impl $ty {
+            pub const ALL: &[$ty] = &[$($ty::$variant,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($ty::$variant => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<$ty> {
+                match name {
+                    $($name => Some($ty::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+    };
+}
+
+table! {
+    // シンボルではなく特殊形式として読む名前。else、=>、unquote などは他の特殊形式の中でだけ意味を持つ。
+    #[non_exhaustive]
+    pub enum SpecialForm {
+        Define => "define",
+        DefinePrivate => "define-private",
+        Doc => "doc",
+        List => "list",
+        Print => "print",
+        Lambda => "lambda",
+        Range => "range",
+        Begin => "begin",
+        Let => "let",
+        If => "if",
+        Else => "else",
+        Arrow => "=>",
+        Cond => "cond",
+        Quote => "quote",
+        And => "and",
+        Or => "or",
+        DefineMacro => "define-macro",
+        DefineSyntax => "define-syntax",
+        Quasiquote => "quasiquote",
+        Unquote => "unquote",
+        UnquoteSplicing => "unquote-splicing",
+        Set => "set!",
+        While => "while",
+        Do => "do",
+        When => "when",
+        Unless => "unless",
+        Try => "try",
+        Delay => "delay",
+        LetValues => "let-values",
+        LetStar => "let*",
+        Letrec => "letrec",
+        LetrecStar => "letrec*",
+        DefineRecordType => "define-record-type",
+        Module => "module",
+        Import => "import",
+        CaseLambda => "case-lambda",
+        Parameterize => "parameterize",
+    }
+}
+
+table! {
+    // 二項演算子。評価器が実装していないものも、字句解析ではこの enum になる。
+    // <= のような 2 文字の演算子は、< と = に分けずに 1 つのトークンとして読む。
+    #[non_exhaustive]
+    pub enum Op {
+        Add => "+",
+        Sub => "-",
+        Mul => "*",
+        Div => "/",
+        Rem => "%",
+        Lt => "<",
+        Gt => ">",
+        Eq => "=",
+        BitOr => "|",
+        BitAnd => "&",
+        Le => "<=",
+        Ge => ">=",
+        EqEq => "==",
+        Ne => "!=",
+    }
+}
+
+impl Op {
+    pub(crate) fn from_char(c: u8) -> Option<Op> {
+        Op::from_name(std::str::from_utf8(std::slice::from_ref(&c)).ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        for &form in SpecialForm::ALL {
+            assert_eq!(SpecialForm::from_name(form.name()), Some(form));
+        }
+        for &op in Op::ALL {
+            assert_eq!(Op::from_name(&op.to_string()), Some(op));
+        }
+        assert_eq!(SpecialForm::from_name("car"), None);
+        assert_eq!(Op::from_char(b'*'), Some(Op::Mul));
+        assert_eq!(Op::from_char(b'a'), None);
+        assert_eq!(Op::from_name("!="), Some(Op::Ne));
+    }
+}
@@ -1,170 +1,941 @@
-use std::{collections::HashSet, str::Chars};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
+use crate::keyword::{Op, SpecialForm};
+use crate::parser::Object;
+use crate::reader::reader_macro;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+pub(crate) enum Token {
     Integer(i64),
     Symbol(String),
     LParen,
     RParen,
-    Float(f64),
+    VectorOpen, // #(
+    Float(f64), // +inf.0 -inf.0 +nan.0 も
     String(String),
-    BinaryOp(String), //  今後、　enum にするかも
-    Keyword(String),
+    Char(char), // #\a
+    Bool(bool), // #t #f true false
+    BinaryOp(Op),
+    Keyword(SpecialForm),
+    KeywordArg(String), // #:name
+    Quote,              // '
+    Quasiquote,         // `
+    Unquote,            // ,
+    UnquoteSplicing,    // ,@
+    Datum(Object),      // 登録したリーダーマクロが返した値 (reader.rs)
+}
+
+// 読めないトークン。pos はそのトークンの先頭の、入力中のバイトオフセット。
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LexError {
+    pub kind: LexErrorKind,
+    pub pos: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub(crate) enum LexErrorKind {
+    InvalidCharacter(char),
+    MalformedNumber(String),   // 1.2.3 や #xfg
+    UnknownHashSyntax(String), // #x や #\foo のような # の後の読めない並び
+    UnterminatedComment,       // |# で閉じていない #|
+    MissingDatum,              // #; の後に式が無い
+    ReaderMacro(char, String), // 登録したリーダーマクロが読めなかった
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::InvalidCharacter(c) => write!(f, "Invalid character {:?}", c),
+            LexErrorKind::MalformedNumber(text) => write!(f, "Invalid number: {}", text),
+            LexErrorKind::UnknownHashSyntax(text) => write!(f, "Invalid # syntax: {}", text),
+            LexErrorKind::UnterminatedComment => f.write_str("Unterminated #| comment"),
+            LexErrorKind::MissingDatum => f.write_str("Expected an expression after #;"),
+            LexErrorKind::ReaderMacro(dispatch, message) => write!(f, "#{}: {}", dispatch, message),
+        }
+    }
 }
 
+// 入力をバイト列として走査する。区切りになる文字はすべて ASCII なので、
+// 文字列やコメントの終わりはバイトで探し、トークンの中身は入力をそのまま切り出す。
+// 非 ASCII の文字は、空白や英字かどうかを調べるときだけ char に戻す。
 struct Tokenizer<'a> {
-    input: Chars<'a>,
-    current_char: Option<char>,
-    keywords: HashSet<&'a str>,
-    binary_ops: HashSet<char>,
+    input: &'a str,
+    bytes: &'a [u8],
+    pos: usize,      // 次に読むバイトのオフセット
+    truncated: bool, // 閉じていない文字列やコメントを、入力の最後まで読んだ
 }
 
 impl<'a> Tokenizer<'a> {
     fn new(input: &'a str) -> Self {
-        let mut chars = input.chars();
-        let current_char = chars.next();
-        let tokenizer = Tokenizer {
-            input: chars,
-            current_char: current_char,
-            keywords: [
-                "define", "list", "print", "lambda", "range", "cons", "car", "cdr", "length",
-                "null?", "begin", "let", "if", "else", "cond",
-            ]
-            .into_iter()
-            .collect(),
-            binary_ops: ['+', '-', '*', '/', '%', '<', '>', '=', '|', '&']
-                .into_iter()
-                .collect(),
-        };
-        tokenizer
+        Tokenizer {
+            input,
+            bytes: input.as_bytes(),
+            pos: 0,
+            truncated: false,
+        }
     }
 
-    fn advance(&mut self) -> Option<char> {
-        self.current_char = self.input.next();
-        self.current_char
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
     }
 
-    fn eat_whitespace(&mut self) {
-        while let Some(c) = self.current_char {
-            if c.is_whitespace() {
-                self.advance();
+    fn current_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    // from 以降で最初に byte が現れる位置
+    fn find_byte(&self, from: usize, byte: u8) -> Option<usize> {
+        self.bytes[from..]
+            .iter()
+            .position(|&b| b == byte)
+            .map(|i| from + i)
+    }
+
+    // 空白とコメントを読み飛ばす。コメントは ; から行末まで、入れ子にできる #| ... |#、
+    // それと #; の後の式 1 つ。#; (f x) のようにリストなら、閉じ括弧までをまとめて読み飛ばす。
+    fn eat_whitespace(&mut self) -> Result<(), LexError> {
+        while let Some(b) = self.peek() {
+            if b == b';' {
+                self.pos = self.find_byte(self.pos, b'\n').unwrap_or(self.bytes.len());
+            } else if self.bytes[self.pos..].starts_with(b"#|") {
+                self.skip_block_comment()?;
+            } else if self.bytes[self.pos..].starts_with(b"#;") {
+                self.skip_datum()?;
+            } else if b.is_ascii() {
+                if !(b as char).is_whitespace() {
+                    break;
+                }
+                self.pos += 1;
             } else {
-                break;
+                match self.current_char() {
+                    Some(c) if c.is_whitespace() => self.pos += c.len_utf8(),
+                    _ => break,
+                }
             }
         }
+        Ok(())
     }
 
-    fn read_symbol(&mut self) -> String {
-        let mut symbol = String::new();
-        while let Some(c) = self.current_char {
-            if !c.is_whitespace() && c != '(' && c != ')' {
-                symbol.push(c);
-                self.advance();
+    fn skip_block_comment(&mut self) -> Result<(), LexError> {
+        let start = self.pos;
+        let mut depth = 0;
+        while self.pos < self.bytes.len() {
+            if self.bytes[self.pos..].starts_with(b"#|") {
+                depth += 1;
+                self.pos += 2;
+            } else if self.bytes[self.pos..].starts_with(b"|#") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return Ok(());
+                }
             } else {
-                break;
+                self.pos += 1;
+            }
+        }
+        self.truncated = true;
+        Err(LexError {
+            kind: LexErrorKind::UnterminatedComment,
+            pos: start,
+        })
+    }
+
+    // #; の後の式 1 つ分のトークンを読んで捨てる。'x のような前置きの記号は次の式と合わせて 1 つ。
+    fn skip_datum(&mut self) -> Result<(), LexError> {
+        let start = self.pos;
+        let missing = LexError {
+            kind: LexErrorKind::MissingDatum,
+            pos: start,
+        };
+        self.pos += 2;
+        let mut depth = 0;
+        loop {
+            match self.next_token()? {
+                None => {
+                    self.truncated = true;
+                    return Err(missing);
+                }
+                Some(Token::LParen | Token::VectorOpen) => depth += 1,
+                Some(Token::RParen) if depth == 0 => return Err(missing),
+                Some(Token::RParen) => depth -= 1,
+                Some(
+                    Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing,
+                ) => continue,
+                Some(_) => {}
+            }
+            if depth == 0 {
+                return Ok(());
             }
         }
-        symbol
     }
 
-    fn read_number(&mut self) -> String {
-        let mut number = String::new();
-        while let Some(c) = self.current_char {
-            if c.is_digit(10) || c == '.' {
-                number.push(c);
-                self.advance();
+    // 空白、括弧、; の手前までを読む
+    fn read_symbol(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if b.is_ascii() {
+                if matches!(b, b'(' | b')' | b';') || (b as char).is_whitespace() {
+                    break;
+                }
+                self.pos += 1;
             } else {
-                break;
+                match self.current_char() {
+                    Some(c) if !c.is_whitespace() => self.pos += c.len_utf8(),
+                    _ => break,
+                }
+            }
+        }
+        &self.input[start..self.pos]
+    }
+
+    // 符号と、1e-3 のような指数も読む。e の後に数字が無ければ、e からは数の一部にしない
+    fn read_number(&mut self) -> &'a str {
+        let start = self.pos;
+        if matches!(self.peek(), Some(b'-' | b'+')) {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some(b'0'..=b'9' | b'.')) {
+            self.pos += 1;
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            let digits = match self.bytes.get(self.pos + 1) {
+                Some(b'-' | b'+') => self.pos + 2,
+                _ => self.pos + 1,
+            };
+            if self.bytes.get(digits).is_some_and(u8::is_ascii_digit) {
+                self.pos = digits;
+                while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+                    self.pos += 1;
+                }
             }
         }
-        number
+        &self.input[start..self.pos]
     }
 
+    fn read_hash(&mut self) -> Option<Token> {
+        self.pos += 1; // Skip '#'
+        match self.peek()? {
+            b':' => {
+                self.pos += 1;
+                Some(Token::KeywordArg(self.read_symbol().to_string()))
+            }
+            b'(' => {
+                self.pos += 1;
+                Some(Token::VectorOpen)
+            }
+            b'\\' => {
+                self.pos += 1;
+                self.read_char()
+            }
+            b't' | b'f' => boolean(self.read_symbol()).map(Token::Bool),
+            _ => None,
+        }
+    }
+
+    // #\ の後。#\a や #\( のような 1 文字か、#\space のような名前の付いた文字。
+    fn read_char(&mut self) -> Option<Token> {
+        let start = self.pos;
+        let c = self.current_char()?;
+        self.pos += c.len_utf8();
+        if c.is_alphabetic() {
+            self.read_symbol();
+        }
+        match &self.input[start..self.pos] {
+            "space" => Some(Token::Char(' ')),
+            "newline" => Some(Token::Char('\n')),
+            "tab" => Some(Token::Char('\t')),
+            name if name.len() == c.len_utf8() => Some(Token::Char(c)),
+            _ => None,
+        }
+    }
+
+    // 閉じる " が無ければ入力の最後までを文字列とする。
+    // \" と \\ は " と \ になる。ほかの \ は正規表現の "\d" などのためにそのまま残す。
     fn read_string(&mut self) -> String {
+        self.pos += 1; // Skip the opening quote
+        if self.bytes[self.pos..].starts_with(b"\"\"") {
+            self.pos += 2;
+            return self.read_block_string();
+        }
         let mut string = String::new();
-        self.advance(); // Skip the opening quote
-        while let Some(c) = self.current_char {
-            if c != '"' {
-                string.push(c);
-                self.advance();
-            } else {
-                break;
+        let mut start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    string.push_str(&self.input[start..self.pos]);
+                    self.pos += 1;
+                    return string;
+                }
+                Some(b'\\') if matches!(self.bytes.get(self.pos + 1), Some(b'"' | b'\\')) => {
+                    string.push_str(&self.input[start..self.pos]);
+                    // エスケープされた文字から次の部分を始める
+                    start = self.pos + 1;
+                    self.pos += 2;
+                }
+                Some(_) => self.pos += 1,
+                None => {
+                    string.push_str(&self.input[start..]);
+                    self.truncated = true;
+                    return string;
+                }
             }
         }
-        self.advance(); // Skip the closing quote
-        string
     }
 
-    fn next_token(&mut self) -> Option<Token> {
-        self.eat_whitespace();
-        match self.current_char? {
-            '(' => {
-                self.advance();
-                Some(Token::LParen)
+    // """...""" の中身をそのまま読む。""" を含まない限り " をエスケープする必要はない。
+    // 開始の """ の直後の改行は読み飛ばす。
+    fn read_block_string(&mut self) -> String {
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+        let rest = &self.input[self.pos..];
+        match rest.find("\"\"\"") {
+            Some(end) => {
+                self.pos += end + 3;
+                rest[..end].to_string()
             }
-            ')' => {
-                self.advance();
-                Some(Token::RParen)
+            None => {
+                self.pos = self.bytes.len();
+                self.truncated = true;
+                rest.to_string()
             }
-            '"' => {
-                let string = self.read_string();
-                Some(Token::String(string))
+        }
+    }
+
+    // 入力の終わりなら None。
+    fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        self.eat_whitespace()?;
+        let start = self.pos;
+        let Some(b) = self.peek() else {
+            return Ok(None);
+        };
+        let error = |kind| LexError { kind, pos: start };
+        let token = match b {
+            b'(' => {
+                self.pos += 1;
+                Token::LParen
             }
-            c if c.is_digit(10) => {
-                let number_str = self.read_number();
-                if number_str.contains('.') {
-                    Some(Token::Float(number_str.parse().unwrap()))
+            b')' => {
+                self.pos += 1;
+                Token::RParen
+            }
+            b'"' => Token::String(self.read_string()),
+            // Interpreter::register_reader_macro で登録した #d や #{
+            b'#' if let Some((dispatch, reader)) = self.input[self.pos + 1..]
+                .chars()
+                .next()
+                .and_then(|c| Some((c, reader_macro(c)?))) =>
+            {
+                self.pos += 1 + dispatch.len_utf8();
+                let datum = self
+                    .read_macro_argument()
+                    .and_then(|text| reader(text))
+                    .map_err(|message| error(LexErrorKind::ReaderMacro(dispatch, message)))?;
+                Token::Datum(datum)
+            }
+            // #xff、#b1010、#o17。10 進数と同じく、i64 に収まらなければ BigInt の値として読む
+            b'#' if let Some(radix) = self.bytes.get(self.pos + 1).and_then(|&b| radix(b)) => {
+                self.pos += 2;
+                let digits = self.read_symbol();
+                i64::from_str_radix(digits, radix)
+                    .ok()
+                    .map(Token::Integer)
+                    .or_else(|| {
+                        let n = BigInt::from_str_radix(digits, radix)?;
+                        Some(Token::Datum(Object::BigInt(Rc::new(n))))
+                    })
+                    .ok_or_else(|| {
+                        error(LexErrorKind::MalformedNumber(
+                            self.input[start..self.pos].to_string(),
+                        ))
+                    })?
+            }
+            b'#' => match self.read_hash() {
+                Some(token) => token,
+                None => {
+                    self.pos = start;
+                    return Err(error(LexErrorKind::UnknownHashSyntax(
+                        self.read_symbol().to_string(),
+                    )));
+                }
+            },
+            b'\'' => {
+                self.pos += 1;
+                Token::Quote
+            }
+            b'`' => {
+                self.pos += 1;
+                Token::Quasiquote
+            }
+            b',' => {
+                self.pos += 1;
+                if self.peek() == Some(b'@') {
+                    self.pos += 1;
+                    Token::UnquoteSplicing
                 } else {
-                    Some(Token::Integer(number_str.parse().unwrap()))
+                    Token::Unquote
                 }
             }
-            c if self.binary_ops.contains(&c) => {
-                let op = c.to_string();
-                self.advance();
-                Some(Token::BinaryOp(op))
+            // -3 や +1.5 は符号付きの数。(- 3) のように符号の後に空白があれば演算子
+            b if b.is_ascii_digit()
+                || (matches!(b, b'-' | b'+')
+                    && self.bytes.get(self.pos + 1).is_some_and(u8::is_ascii_digit)) =>
+            {
+                let number_str = self.read_number();
+                let token = if number_str.contains(['.', 'e', 'E']) {
+                    number_str.parse().ok().map(Token::Float)
+                } else {
+                    // i64 に収まらない整数は BigInt の値として読む
+                    number_str.parse().ok().map(Token::Integer).or_else(|| {
+                        let n: BigInt = number_str.parse().ok()?;
+                        Some(Token::Datum(Object::BigInt(Rc::new(n))))
+                    })
+                };
+                token.ok_or_else(|| error(LexErrorKind::MalformedNumber(number_str.to_string())))?
             }
-            c if c.is_alphabetic() || c == '_' => {
-                let symbol = self.read_symbol();
-                if self.keywords.contains(symbol.as_str()) {
-                    Some(Token::Keyword(symbol))
+            // cond の (test => receiver)。= と > の二項演算子には分けない
+            b'=' if self.bytes.get(self.pos + 1) == Some(&b'>') => {
+                self.pos += 2;
+                Token::Keyword(SpecialForm::Arrow)
+            }
+            // <= や != は 1 文字の演算子より先に試す。->string や *x* のように、
+            // 演算子の直後に区切りが無ければ、演算子の記号で始まるシンボルとして読む
+            _ if let Some((op, len)) = self.operator() => {
+                if self.input[self.pos + len..]
+                    .chars()
+                    .next()
+                    .is_some_and(is_symbol_char)
+                {
+                    self.read_word()
                 } else {
-                    Some(Token::Symbol(symbol))
+                    self.pos += len;
+                    Token::BinaryOp(op)
                 }
             }
-            _ => None,
+            b if b.is_ascii_alphabetic()
+                || matches!(b, b'_' | b'.' | b'?' | b'!' | b'$' | b':') =>
+            {
+                self.read_word()
+            }
+            // 日本語などの文字や、λ や → のような記号でも始められる
+            _ => {
+                let c = self.current_char().unwrap_or_default();
+                if c.is_ascii() || c.is_control() {
+                    return Err(error(LexErrorKind::InvalidCharacter(c)));
+                }
+                self.read_word()
+            }
+        };
+        Ok(Some(token))
+    }
+
+    // リーダーマクロに渡すソース。#{ のように登録した文字が開き括弧なら、その括弧から読む。
+    fn read_macro_argument(&mut self) -> Result<&'a str, String> {
+        if matches!(self.bytes[self.pos - 1], b'(' | b'[' | b'{') {
+            self.pos -= 1;
         }
+        let start = self.pos;
+        let (open, close) = match self.peek() {
+            Some(b'(') => (b'(', b')'),
+            Some(b'[') => (b'[', b']'),
+            Some(b'{') => (b'{', b'}'),
+            Some(b'"') => {
+                self.read_string();
+                return Ok(&self.input[start..self.pos]);
+            }
+            _ => return Ok(self.read_symbol()),
+        };
+        let mut depth = 0;
+        while let Some(b) = self.peek() {
+            if b == b'"' {
+                self.read_string();
+                continue;
+            }
+            self.pos += 1;
+            if b == open {
+                depth += 1;
+            } else if b == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(&self.input[start..self.pos]);
+                }
+            }
+        }
+        self.truncated = true;
+        Err(format!("unterminated {}", open as char))
+    }
+
+    // 次の演算子と、そのバイト数
+    fn operator(&self) -> Option<(Op, usize)> {
+        if let Some(op) = self
+            .input
+            .get(self.pos..self.pos + 2)
+            .and_then(Op::from_name)
+        {
+            return Some((op, 2));
+        }
+        Op::from_char(self.peek()?).map(|op| (op, 1))
+    }
+
+    fn read_word(&mut self) -> Token {
+        let symbol = self.read_symbol();
+        if let Some(form) = SpecialForm::from_name(symbol) {
+            return Token::Keyword(form);
+        }
+        match symbol {
+            "true" => Token::Bool(true),
+            "false" => Token::Bool(false),
+            "+inf.0" => Token::Float(f64::INFINITY),
+            "-inf.0" => Token::Float(f64::NEG_INFINITY),
+            "+nan.0" | "-nan.0" => Token::Float(f64::NAN),
+            _ => Token::Symbol(symbol.to_string()),
+        }
+    }
+}
+
+// シンボルの途中に置ける文字。read_symbol が止まる文字と、クォートの記号や " 以外
+fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | ';' | '"' | '\'' | '`' | ',')
+}
+
+// #x や #b の基数
+fn radix(b: u8) -> Option<u32> {
+    match b {
+        b'x' | b'X' => Some(16),
+        b'b' | b'B' => Some(2),
+        b'o' | b'O' => Some(8),
+        _ => None,
+    }
+}
+
+// # の後の t、f、true、false
+fn boolean(name: &str) -> Option<bool> {
+    match name {
+        "t" | "true" => Some(true),
+        "f" | "false" => Some(false),
+        _ => None,
     }
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-    // Result型にするべきかも。今不正な入力をした時にどうなるか不明。
+pub(crate) fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    Ok(tokenize_spanned(input)?
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect())
+}
+
+// トークンと、入力の中でそのトークンが占めるバイト範囲。読めないトークンがあれば、そこで止めてエラーを返す。
+pub(crate) fn tokenize_spanned(input: &str) -> Result<Vec<(Token, Range<usize>)>, LexError> {
     let mut tokenizer = Tokenizer::new(input);
     let mut tokens = Vec::new();
-    while let Some(token) = tokenizer.next_token() {
-        tokens.push(token);
+    loop {
+        tokenizer.eat_whitespace()?;
+        let start = tokenizer.pos;
+        match tokenizer.next_token()? {
+            Some(token) => tokens.push((token, start..tokenizer.pos)),
+            None => return Ok(tokens),
+        }
     }
-    tokens
+}
+
+// 入力が文字列や #| コメント、リーダーマクロの引数の途中で終わっているか。続きを足せば読めるかもしれない。
+// 閉じていない文字列は tokenize がエラーにしないので、読めたかどうかとは別に調べる。
+pub(crate) fn ends_inside_token(input: &str) -> bool {
+    let mut tokenizer = Tokenizer::new(input);
+    while let Ok(Some(_)) = tokenizer.next_token() {}
+    tokenizer.truncated
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lexer::{Token, tokenize};
+    use std::rc::Rc;
+
+    use crate::bigint::BigInt;
+    use crate::keyword::{Op, SpecialForm};
+    use crate::lexer::{
+        LexError, LexErrorKind, Token, ends_inside_token, tokenize, tokenize_spanned,
+    };
+    use crate::parser::Object;
+
+    // バイト列で走査する前の、Chars で 1 文字ずつ読む実装。新しい実装と同じトークンを返すことを確かめるのに使う。
+    mod reference {
+        use std::{collections::HashSet, ops::Range, rc::Rc, str::Chars};
+
+        use crate::bigint::BigInt;
+        use crate::keyword::{Op, SpecialForm};
+        use crate::lexer::Token;
+        use crate::parser::Object;
+
+        struct Tokenizer<'a> {
+            input: Chars<'a>,
+            current_char: Option<char>,
+            pos: usize, // current_char の入力中のバイトオフセット
+            binary_ops: HashSet<char>,
+        }
+
+        impl<'a> Tokenizer<'a> {
+            fn new(input: &'a str) -> Self {
+                let mut chars = input.chars();
+                let current_char = chars.next();
+                Tokenizer {
+                    input: chars,
+                    current_char,
+                    pos: 0,
+                    binary_ops: ['+', '-', '*', '/', '%', '<', '>', '=', '|', '&']
+                        .into_iter()
+                        .collect(),
+                }
+            }
+
+            fn advance(&mut self) -> Option<char> {
+                if let Some(c) = self.current_char {
+                    self.pos += c.len_utf8();
+                }
+                self.current_char = self.input.next();
+                self.current_char
+            }
+
+            // 空白とコメントを読み飛ばす。閉じていない #| や、式の無い #; は入力の最後まで読み飛ばす
+            fn eat_whitespace(&mut self) {
+                while let Some(c) = self.current_char {
+                    let next = self.input.clone().next();
+                    if c == ';' {
+                        while !matches!(self.current_char, None | Some('\n')) {
+                            self.advance();
+                        }
+                    } else if c == '#' && next == Some('|') {
+                        let mut depth = 0;
+                        while let Some(c) = self.current_char {
+                            let next = self.input.clone().next();
+                            if c == '#' && next == Some('|') {
+                                depth += 1;
+                                self.advance();
+                            } else if c == '|' && next == Some('#') {
+                                depth -= 1;
+                                self.advance();
+                            }
+                            self.advance();
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                    } else if c == '#' && next == Some(';') {
+                        self.advance();
+                        self.advance();
+                        let mut depth = 0;
+                        loop {
+                            match self.next_token() {
+                                Some(Token::LParen | Token::VectorOpen) => depth += 1,
+                                Some(Token::RParen) if depth > 0 => depth -= 1,
+                                Some(
+                                    Token::Quote
+                                    | Token::Quasiquote
+                                    | Token::Unquote
+                                    | Token::UnquoteSplicing,
+                                ) => continue,
+                                Some(Token::RParen) | None => {
+                                    while self.advance().is_some() {}
+                                    break;
+                                }
+                                Some(_) => {}
+                            }
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                    } else if c.is_whitespace() {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+            }
+
+            fn read_symbol(&mut self) -> String {
+                let mut symbol = String::new();
+                while let Some(c) = self.current_char {
+                    if !c.is_whitespace() && c != '(' && c != ')' && c != ';' {
+                        symbol.push(c);
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+                symbol
+            }
+
+            fn read_number(&mut self) -> String {
+                let mut number = String::new();
+                if let Some(sign @ ('-' | '+')) = self.current_char {
+                    number.push(sign);
+                    self.advance();
+                }
+                while let Some(c) = self.current_char {
+                    if c.is_ascii_digit() || c == '.' {
+                        number.push(c);
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+                if let Some(e @ ('e' | 'E')) = self.current_char {
+                    let mut lookahead = self.input.clone();
+                    let mut exponent = e.to_string();
+                    let mut next = lookahead.next();
+                    if let Some(sign @ ('-' | '+')) = next {
+                        exponent.push(sign);
+                        next = lookahead.next();
+                    }
+                    if next.is_some_and(|c| c.is_ascii_digit()) {
+                        for _ in 0..exponent.len() {
+                            self.advance();
+                        }
+                        while let Some(c) = self.current_char {
+                            if !c.is_ascii_digit() {
+                                break;
+                            }
+                            exponent.push(c);
+                            self.advance();
+                        }
+                        number.push_str(&exponent);
+                    }
+                }
+                number
+            }
+
+            fn read_hash(&mut self) -> Option<Token> {
+                self.advance(); // Skip '#'
+                match self.current_char? {
+                    ':' => {
+                        self.advance();
+                        Some(Token::KeywordArg(self.read_symbol()))
+                    }
+                    '(' => {
+                        self.advance();
+                        Some(Token::VectorOpen)
+                    }
+                    '\\' => {
+                        self.advance();
+                        let c = self.current_char?;
+                        self.advance();
+                        let mut name = c.to_string();
+                        if c.is_alphabetic() {
+                            name.push_str(&self.read_symbol());
+                        }
+                        match name.as_str() {
+                            "space" => Some(Token::Char(' ')),
+                            "newline" => Some(Token::Char('\n')),
+                            "tab" => Some(Token::Char('\t')),
+                            _ if name.chars().count() == 1 => Some(Token::Char(c)),
+                            _ => None,
+                        }
+                    }
+                    c @ ('x' | 'X' | 'b' | 'B' | 'o' | 'O') => {
+                        self.advance();
+                        let radix = match c.to_ascii_lowercase() {
+                            'x' => 16,
+                            'b' => 2,
+                            _ => 8,
+                        };
+                        let digits = self.read_symbol();
+                        i64::from_str_radix(&digits, radix)
+                            .ok()
+                            .map(Token::Integer)
+                            .or_else(|| {
+                                let n = BigInt::from_str_radix(&digits, radix)?;
+                                Some(Token::Datum(Object::BigInt(Rc::new(n))))
+                            })
+                    }
+                    't' | 'f' => match self.read_symbol().as_str() {
+                        "t" | "true" => Some(Token::Bool(true)),
+                        "f" | "false" => Some(Token::Bool(false)),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+
+            fn read_string(&mut self) -> String {
+                let mut string = String::new();
+                self.advance(); // Skip the opening quote
+                if self.current_char == Some('"') && self.input.clone().next() == Some('"') {
+                    self.advance();
+                    self.advance();
+                    return self.read_block_string();
+                }
+                while let Some(c) = self.current_char {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(self.input.clone().next(), Some('"' | '\\')) => {
+                            self.advance();
+                            string.push(self.current_char.unwrap());
+                            self.advance();
+                        }
+                        c => {
+                            string.push(c);
+                            self.advance();
+                        }
+                    }
+                }
+                self.advance(); // Skip the closing quote
+                string
+            }
+
+            // """...""" の中身をそのまま読む。""" を含まない限り " をエスケープする必要はない。
+            // 開始の """ の直後の改行は読み飛ばす。
+            fn read_block_string(&mut self) -> String {
+                let mut string = String::new();
+                if self.current_char == Some('\n') {
+                    self.advance();
+                }
+                while let Some(c) = self.current_char {
+                    if c == '"' && string.ends_with("\"\"") {
+                        string.truncate(string.len() - 2);
+                        self.advance(); // Skip the closing quotes
+                        break;
+                    }
+                    string.push(c);
+                    self.advance();
+                }
+                string
+            }
+
+            fn next_token(&mut self) -> Option<Token> {
+                self.eat_whitespace();
+                match self.current_char? {
+                    '(' => {
+                        self.advance();
+                        Some(Token::LParen)
+                    }
+                    ')' => {
+                        self.advance();
+                        Some(Token::RParen)
+                    }
+                    '"' => {
+                        let string = self.read_string();
+                        Some(Token::String(string))
+                    }
+                    '#' => self.read_hash(),
+                    '\'' => {
+                        self.advance();
+                        Some(Token::Quote)
+                    }
+                    '`' => {
+                        self.advance();
+                        Some(Token::Quasiquote)
+                    }
+                    ',' => {
+                        if self.advance() == Some('@') {
+                            self.advance();
+                            Some(Token::UnquoteSplicing)
+                        } else {
+                            Some(Token::Unquote)
+                        }
+                    }
+                    c if c.is_ascii_digit()
+                        || (matches!(c, '-' | '+')
+                            && self
+                                .input
+                                .clone()
+                                .next()
+                                .is_some_and(|c| c.is_ascii_digit())) =>
+                    {
+                        let number_str = self.read_number();
+                        if number_str.contains(['.', 'e', 'E']) {
+                            Some(Token::Float(number_str.parse().unwrap()))
+                        } else if let Ok(n) = number_str.parse() {
+                            Some(Token::Integer(n))
+                        } else {
+                            let n = number_str.parse().unwrap();
+                            Some(Token::Datum(Object::BigInt(Rc::new(n))))
+                        }
+                    }
+                    // cond の (test => receiver)。= と > の二項演算子には分けない
+                    '=' if self.input.clone().next() == Some('>') => {
+                        self.advance();
+                        self.advance();
+                        Some(Token::Keyword(SpecialForm::Arrow))
+                    }
+                    c if self.binary_ops.contains(&c) || c == '!' => {
+                        let mut lookahead = self.input.clone();
+                        let next = lookahead.next();
+                        let (name, after) = match next {
+                            Some(next) if Op::from_name(&format!("{}{}", c, next)).is_some() => {
+                                (format!("{}{}", c, next), lookahead.next())
+                            }
+                            _ => (c.to_string(), next),
+                        };
+                        let glued =
+                            after.is_some_and(|c| !c.is_whitespace() && !"();\"'`,".contains(c));
+                        match Op::from_name(&name) {
+                            Some(op) if !glued => {
+                                for _ in name.chars() {
+                                    self.advance();
+                                }
+                                Some(Token::BinaryOp(op))
+                            }
+                            _ => Some(word(self.read_symbol())),
+                        }
+                    }
+                    c if c.is_alphabetic()
+                        || "_.?!$:".contains(c)
+                        || (!c.is_ascii() && !c.is_control()) =>
+                    {
+                        let symbol = self.read_symbol();
+                        match SpecialForm::from_name(&symbol) {
+                            Some(form) => Some(Token::Keyword(form)),
+                            None if symbol == "true" => Some(Token::Bool(true)),
+                            None if symbol == "false" => Some(Token::Bool(false)),
+                            None => Some(word(symbol)),
+                        }
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        fn word(symbol: String) -> Token {
+            match symbol.as_str() {
+                "+inf.0" => Token::Float(f64::INFINITY),
+                "-inf.0" => Token::Float(f64::NEG_INFINITY),
+                "+nan.0" | "-nan.0" => Token::Float(f64::NAN),
+                _ => Token::Symbol(symbol),
+            }
+        }
+
+        pub(super) fn tokenize_spanned(input: &str) -> Vec<(Token, Range<usize>)> {
+            let mut tokenizer = Tokenizer::new(input);
+            let mut tokens = Vec::new();
+            loop {
+                tokenizer.eat_whitespace();
+                let start = tokenizer.pos;
+                match tokenizer.next_token() {
+                    Some(token) => tokens.push((token, start..tokenizer.pos)),
+                    None => return tokens,
+                }
+            }
+        }
+    }
 
     #[test]
     fn test_tokenize() {
         let input = "(define sqr (* x x))";
         let tokens = vec![
             Token::LParen,
-            Token::Keyword("define".to_string()),
+            Token::Keyword(SpecialForm::Define),
             Token::Symbol("sqr".to_string()),
             Token::LParen,
-            Token::BinaryOp("*".to_string()),
+            Token::BinaryOp(Op::Mul),
             Token::Symbol("x".to_string()),
             Token::Symbol("x".to_string()),
             Token::RParen,
             Token::RParen,
         ];
-        assert_eq!(tokenize(input), tokens);
+        assert_eq!(tokenize(input).unwrap(), tokens);
     }
 
     #[test]
@@ -176,26 +947,26 @@ mod tests {
                 (* pi (* r r))
             )
         ";
-        let tokens = tokenize(program);
+        let tokens = tokenize(program).unwrap();
         assert_eq!(
             tokens,
             vec![
                 Token::LParen,
                 Token::LParen,
-                Token::Keyword("define".to_string()),
+                Token::Keyword(SpecialForm::Define),
                 Token::Symbol("r".to_string()),
                 Token::Integer(10),
                 Token::RParen,
                 Token::LParen,
-                Token::Keyword("define".to_string()),
+                Token::Keyword(SpecialForm::Define),
                 Token::Symbol("pi".to_string()),
                 Token::Integer(314),
                 Token::RParen,
                 Token::LParen,
-                Token::BinaryOp("*".to_string()),
+                Token::BinaryOp(Op::Mul),
                 Token::Symbol("pi".to_string()),
                 Token::LParen,
-                Token::BinaryOp("*".to_string()),
+                Token::BinaryOp(Op::Mul),
                 Token::Symbol("r".to_string()),
                 Token::Symbol("r".to_string()),
                 Token::RParen,
@@ -204,4 +975,470 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(
+            tokenize(r#"("a\"b" "c\\" "\d" "\")"#).unwrap(),
+            vec![
+                Token::LParen,
+                Token::String("a\"b".to_string()),
+                Token::String("c\\".to_string()),
+                Token::String("\\d".to_string()),
+                Token::String("\")".to_string()),
+            ]
+        );
+        assert!(ends_inside_token(r#"(print "a\")"#));
+    }
+
+    #[test]
+    fn test_block_string() {
+        let input = r#"(print """
+<a href="x">"quoted"</a>
+""" "")"#;
+        assert_eq!(
+            tokenize(input).unwrap(),
+            vec![
+                Token::LParen,
+                Token::Keyword(SpecialForm::Print),
+                Token::String("<a href=\"x\">\"quoted\"</a>\n".to_string()),
+                Token::String("".to_string()),
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keyword_arg() {
+        assert_eq!(
+            tokenize("(f #:precision 2)").unwrap(),
+            vec![
+                Token::LParen,
+                Token::Symbol("f".to_string()),
+                Token::KeywordArg("precision".to_string()),
+                Token::Integer(2),
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_signed_and_exponent() {
+        assert_eq!(
+            tokenize("(- 5 -3 +2 1.5e3 2E-2 7e 1e+x)").unwrap(),
+            vec![
+                Token::LParen,
+                Token::BinaryOp(Op::Sub),
+                Token::Integer(5),
+                Token::Integer(-3),
+                Token::Integer(2),
+                Token::Float(1500.0),
+                Token::Float(0.02),
+                Token::Integer(7),
+                Token::Symbol("e".to_string()),
+                Token::Integer(1),
+                Token::Symbol("e+x".to_string()),
+                Token::RParen,
+            ]
+        );
+        assert_eq!(
+            crate::interpreter::Interpreter::new()
+                .eval("(list (- 10 -3) (* -2 1e2) (+ 1 -0.5))")
+                .map(|value| value.to_string()),
+            Ok("(13 -200.0 0.5)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_multi_char_operators() {
+        assert_eq!(
+            tokenize("(<= >= == != < = => string<=?)").unwrap(),
+            vec![
+                Token::LParen,
+                Token::BinaryOp(Op::Le),
+                Token::BinaryOp(Op::Ge),
+                Token::BinaryOp(Op::EqEq),
+                Token::BinaryOp(Op::Ne),
+                Token::BinaryOp(Op::Lt),
+                Token::BinaryOp(Op::Eq),
+                Token::Keyword(SpecialForm::Arrow),
+                Token::Symbol("string<=?".to_string()),
+                Token::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        assert_eq!(
+            tokenize("#xFF #x-1a #b1010 #o17 #X7fffffffffffffff").unwrap(),
+            vec![
+                Token::Integer(255),
+                Token::Integer(-26),
+                Token::Integer(10),
+                Token::Integer(15),
+                Token::Integer(i64::MAX),
+            ]
+        );
+        assert_eq!(
+            tokenize(
+                "#x8000000000000000 #xFFFFFFFFFFFFFFFFFFFF #b-1000000000000000000000000000000000000000000000000000000000000001"
+            ),
+            Ok(vec![
+                Token::Datum(Object::BigInt(Rc::new(
+                    &BigInt::from(i64::MAX) + &BigInt::from(1)
+                ))),
+                Token::Datum(Object::BigInt(Rc::new(
+                    BigInt::from_str_radix("ffffffffffffffffffff", 16).unwrap()
+                ))),
+                Token::Datum(Object::BigInt(Rc::new(
+                    &BigInt::from(i64::MIN) - &BigInt::from(1)
+                ))),
+            ])
+        );
+        let program = "(list (+ #b1100 #b0011) (number->string #xff 16) (number->string -10 2) (number->string (* #x100000000 #x100000000) 16) (number->string #xFFFFFFFFFFFFFFFFFFFF))";
+        assert_eq!(
+            crate::interpreter::Interpreter::new()
+                .eval(program)
+                .unwrap()
+                .to_string(),
+            "(15 ff -1010 10000000000000000 1208925819614629174706175)"
+        );
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let symbols: Vec<Token> = [
+            "set-car!", "?x", "!", "$v", "-x", "->string", "*global*", "<=>", "面積", "→", "x²",
+        ]
+        .iter()
+        .map(|s| Token::Symbol(s.to_string()))
+        .collect();
+        assert_eq!(
+            tokenize("set-car! ?x ! $v -x ->string *global* <=> 面積 → x²").unwrap(),
+            symbols
+        );
+        assert_eq!(
+            tokenize("(- x) -3 (* 2 x)(<= a b)").unwrap(),
+            vec![
+                Token::LParen,
+                Token::BinaryOp(Op::Sub),
+                Token::Symbol("x".to_string()),
+                Token::RParen,
+                Token::Integer(-3),
+                Token::LParen,
+                Token::BinaryOp(Op::Mul),
+                Token::Integer(2),
+                Token::Symbol("x".to_string()),
+                Token::RParen,
+                Token::LParen,
+                Token::BinaryOp(Op::Le),
+                Token::Symbol("a".to_string()),
+                Token::Symbol("b".to_string()),
+                Token::RParen,
+            ]
+        );
+        let program = "
+            (define (面積 幅 高さ) (* 幅 高さ))
+            (define (->string x) (number->string x))
+            (define *scale* 2)
+            (->string (* *scale* (面積 3 4)))
+        ";
+        assert_eq!(
+            crate::interpreter::Interpreter::new().eval(program),
+            Ok(crate::parser::Object::String("24".into()))
+        );
+    }
+
+    #[test]
+    fn test_lex_errors() {
+        let error = |kind, pos| Err(LexError { kind, pos });
+        assert_eq!(
+            tokenize("(a ~ b)"),
+            error(LexErrorKind::InvalidCharacter('~'), 3)
+        );
+        assert_eq!(
+            tokenize("(+ 1.2.3 1)"),
+            error(LexErrorKind::MalformedNumber("1.2.3".to_string()), 3)
+        );
+        assert_eq!(
+            tokenize("99999999999999999999 -99999999999999999999"),
+            Ok(vec![
+                Token::Datum(Object::BigInt(Rc::new(
+                    "99999999999999999999".parse().unwrap()
+                ))),
+                Token::Datum(Object::BigInt(Rc::new(
+                    "-99999999999999999999".parse().unwrap()
+                ))),
+            ])
+        );
+        assert_eq!(
+            tokenize("x #zz"),
+            error(LexErrorKind::UnknownHashSyntax("#zz".to_string()), 2)
+        );
+        assert_eq!(
+            tokenize("(#xfg)"),
+            error(LexErrorKind::MalformedNumber("#xfg".to_string()), 1)
+        );
+        assert_eq!(
+            tokenize("#\\spaceship)"),
+            error(
+                LexErrorKind::UnknownHashSyntax("#\\spaceship".to_string()),
+                0
+            )
+        );
+
+        let e = crate::parser::parse("(f 1.2.3)").unwrap_err();
+        assert_eq!(e.to_string(), "ParseError: Invalid number: 1.2.3");
+        let e = crate::interpreter::Interpreter::new()
+            .eval_spanned("(define x 1)\n(print ~x)")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 2, col 8: ParseError: Invalid character '~'"
+        );
+    }
+
+    #[test]
+    fn test_booleans() {
+        assert_eq!(
+            tokenize("(#t #f true false #true truthy)").unwrap(),
+            vec![
+                Token::LParen,
+                Token::Bool(true),
+                Token::Bool(false),
+                Token::Bool(true),
+                Token::Bool(false),
+                Token::Bool(true),
+                Token::Symbol("truthy".to_string()),
+                Token::RParen,
+            ]
+        );
+        let program =
+            "(define (f x) (if x 'yes 'no)) (list (f #t) (f #f) (f false) (if #f 1 2) '(#t))";
+        assert_eq!(
+            crate::interpreter::Interpreter::new()
+                .eval(program)
+                .unwrap()
+                .to_string(),
+            "(yes no no 2 (true))"
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "(f \"é\" 12) 'x";
+        let spans: Vec<_> = tokenize_spanned(input)
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| &input[span])
+            .collect();
+        assert_eq!(spans, ["(", "f", "\"é\"", "12", ")", "'", "x"]);
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            tokenize(";;; header\n(f x) ; trailing\n\"a;b\"; last").unwrap(),
+            vec![
+                Token::LParen,
+                Token::Symbol("f".to_string()),
+                Token::Symbol("x".to_string()),
+                Token::RParen,
+                Token::String("a;b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_and_datum_comments() {
+        assert_eq!(
+            tokenize("(f #| a #| nested |# (b |# x #;(g 1) #; 'y #;#;1 2 z)").unwrap(),
+            vec![
+                Token::LParen,
+                Token::Symbol("f".to_string()),
+                Token::Symbol("x".to_string()),
+                Token::Symbol("z".to_string()),
+                Token::RParen,
+            ]
+        );
+        let error = |kind, pos| Err(LexError { kind, pos });
+        assert_eq!(
+            tokenize("x #| a #| b |#"),
+            error(LexErrorKind::UnterminatedComment, 2)
+        );
+        assert_eq!(tokenize("(f #;)"), error(LexErrorKind::MissingDatum, 3));
+        assert_eq!(tokenize("#;(f"), error(LexErrorKind::MissingDatum, 0));
+        assert_eq!(
+            crate::parser::parse_all("#;(define x 1) (define y 2) #| (define z 3) |#")
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_ends_inside_token() {
+        for input in ["\"abc", "(f \"\"\"x\"", "#| a |# #| b", "(f #;", "#;'"] {
+            assert!(ends_inside_token(input), "input: {:?}", input);
+        }
+        for input in [
+            "\"abc\"",
+            "(f x",
+            "#| a |#",
+            "(f #;)",
+            "x ; \"",
+            "#zz \"a\"",
+        ] {
+            assert!(!ends_inside_token(input), "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        assert_eq!(
+            tokenize("`(a ,b ,@c)").unwrap(),
+            vec![
+                Token::Quasiquote,
+                Token::LParen,
+                Token::Symbol("a".to_string()),
+                Token::Unquote,
+                Token::Symbol("b".to_string()),
+                Token::UnquoteSplicing,
+                Token::Symbol("c".to_string()),
+                Token::RParen,
+            ]
+        );
+    }
+
+    // 参照実装は読めないトークンの手前で黙って止まる。エラーになる入力では、エラーの手前までが同じであることを確かめる。
+    // #; の後の式の中で読めなければ、#; の手前までを比べる
+    fn assert_same_tokens(input: &str) {
+        let mut readable = input;
+        while let Err(e) = tokenize_spanned(readable) {
+            readable = &readable[..e.pos];
+        }
+        assert_eq!(
+            tokenize_spanned(readable).unwrap(),
+            reference::tokenize_spanned(input),
+            "input: {:?}",
+            input
+        );
+    }
+
+    #[test]
+    fn test_same_as_reference() {
+        let inputs = [
+            "",
+            "   \t\n",
+            "(define (f x) (* x 1.5)) ; done",
+            "(print \"\"\"\nblock \"x\" \"\"\" \"\" \"a;b\")",
+            "\"\"\"\"\"\"\"",
+            "\"unterminated",
+            "\"\"\"unterminated block",
+            "(f \"a\\\"b\" \"c\\\\\" \"\\d\\\\\\\"\")",
+            "\"ends with \\\"",
+            "(cond (x => f) (else 'y)) `(a ,b ,@c)",
+            "(f #:name 1 #:)#",
+            "(λ 日本語\u{3000}x\u{a0}y\u{b}z) é",
+            "12abc 3.5x .5 _x x.y",
+            "(a) ~ (b)",
+            "#(1 2)",
+            "(#\\a #\\( #\\) #\\space #\\newline #\\tab #\\λ #\\ x)",
+            "#\\日本 x",
+            "#\\spaceship",
+            "#\\",
+            "(#xff #b102 #o7 #x #X1F #b-1)",
+            "(#t #f #true #false true false #tx #t(1) truex)",
+            "(- -3 +2.5 1e-3 2E+10 1e 1e+ 3e2x -x a-1 - 1 (-1))",
+            "(+inf.0 -inf.0 +inf.0x inf.0 12345678901234567890 -9223372036854775809)",
+            "<=>= || && %",
+            "#| a #| (nested) |# \"b |# (f x) #|| |# y",
+            "(a #;(b c) #; 'd #; #; e f g) #;",
+            "#;(a ~ b) c #| unterminated",
+            "(a #;) b",
+            "(set-car! ?x ! $v -x ->string *g* <=> 面積 → x² -\u{3000}y (-(f)) '-a)",
+        ];
+        for input in inputs {
+            assert_same_tokens(input);
+        }
+    }
+
+    #[test]
+    fn test_same_as_reference_random() {
+        // 断片をでたらめにつなげた入力で比べる。数が隣り合うと 1.2.3 のような解釈できない数になるので、間には必ず区切りを入れる
+        let fragments = [
+            "(",
+            ")",
+            "'x",
+            "`(",
+            ",y",
+            ",@z",
+            "\"s t\"",
+            "\"q\\\"\\\\\"",
+            "\"\"\"b\"\"\"",
+            "12",
+            "3.5",
+            "define",
+            "lambda",
+            "foo-bar?",
+            "=>",
+            "=",
+            "<",
+            "<=",
+            "==",
+            "!=",
+            ">=",
+            "-",
+            "#:k",
+            "#\\a",
+            "#\\space",
+            "#\\(",
+            "#t",
+            "#f",
+            "#xA",
+            "#b1",
+            "-7",
+            "+2",
+            "1e-3",
+            "4E2",
+            "e",
+            "+",
+            "false",
+            ";c\n",
+            "#|c|#",
+            "#|",
+            "|#",
+            "#;",
+            "日本",
+            "λ",
+            "\u{3000}",
+            "é",
+            "_",
+            ".",
+            "~",
+            "?x",
+            "!",
+            "set-car!",
+            "->s",
+            "*g*",
+            "→",
+        ];
+        let separators = [" ", "\n", "(", ")", "\t"];
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        for _ in 0..2000 {
+            let mut input = String::new();
+            for _ in 0..next(30) {
+                input.push_str(fragments[next(fragments.len())]);
+                input.push_str(separators[next(separators.len())]);
+            }
+            assert_same_tokens(&input);
+        }
+    }
 }
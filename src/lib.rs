@@ -1,3 +1,60 @@
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod bigint;
+pub mod builder;
+pub mod builtins;
+pub mod config;
+mod continuation;
+pub mod deprecation;
+pub mod dump;
+mod environment;
 pub mod eval;
+mod exception;
+mod generator;
+#[cfg(feature = "graphics")]
+mod graphics;
+pub mod help;
+#[cfg(feature = "http")]
+mod http;
+pub mod interpreter;
+#[cfg(any(feature = "jupyter", feature = "remote"))]
+mod json;
+#[cfg(feature = "jupyter")]
+pub mod jupyter;
+pub mod keyword;
 mod lexer;
+pub mod manifest;
+mod module;
+#[cfg(feature = "osc")]
+mod osc;
+mod pair;
+pub mod parallel;
+mod parameter;
 pub mod parser;
+pub mod plugin;
+pub mod prelude;
+pub mod printer;
+pub mod reader;
+mod record;
+mod regex;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod render;
+mod sandbox;
+#[cfg(feature = "serde")]
+pub mod serde_object;
+#[cfg(all(unix, feature = "signals"))]
+pub mod signal;
+pub mod stdin;
+pub mod string;
+mod syntax_rules;
+#[cfg(feature = "tagged-value")]
+pub mod tagged;
+pub mod test_runner;
+pub mod testing;
+mod timer;
+pub mod typecheck;
+mod values;
+mod vector;
+#[cfg(feature = "websocket")]
+mod websocket;
@@ -1,96 +1,244 @@
-use mr_lisp::eval::*;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use linefeed::{Interface, ReadResult};
-use mr_lisp::parser::Object;
+use mr_lisp::config::ColorChoice;
+use mr_lisp::parser::Parsed;
+use mr_lisp::prelude::{Config, Interpreter, Object};
+use mr_lisp::printer::{self, Mode};
+use mr_lisp::render::Renderers;
+use mr_lisp::test_runner;
 
 const PROMPT: &str = "mr-lisp> ";
 const CONTINUATION_PROMPT: &str = "....> ";
 
-fn update_paren_balance(line: &str, balance: &mut i32, in_string: &mut bool) {
-    for ch in line.chars() {
-        match ch {
-            '"' => {
-                *in_string = !*in_string;
+#[cfg(feature = "remote")]
+fn listen(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+    mr_lisp::remote::listen(addr)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "remote"))]
+fn listen(_addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err("--listen requires the remote feature".into())
+}
+
+const USAGE: &str = "usage: mr-lisp [--fuel N] [--color WHEN] [--listen ADDR | --filter (FILE | -e EXPR) | --dump-tokens (FILE | -e EXPR) | --dump-ast (FILE | -e EXPR) | test [--filter NAME] [PATH...] | typecheck FILE...]";
+
+// 先頭の --fuel と --color を config に反映し、残りの引数を返す。環境変数の値より優先する。
+fn apply_options(
+    mut args: &[String],
+    config: &mut Config,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    loop {
+        match args {
+            [flag, n, rest @ ..] if flag == "--fuel" => {
+                config.fuel =
+                    Some(n.parse().map_err(|_| {
+                        format!("--fuel: expected a non-negative integer, got {}", n)
+                    })?);
+                args = rest;
             }
-            '(' if !*in_string => {
-                *balance += 1;
+            [flag, when, rest @ ..] if flag == "--color" => {
+                config.color = ColorChoice::parse(when).map_err(|e| format!("--color: {}", e))?;
+                args = rest;
+            }
+            _ => return Ok(args.to_vec()),
+        }
+    }
+}
+
+// --dump-tokens などに渡されたファイル名、または -e に続く式。
+fn read_source(args: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+    match args {
+        [flag, expr] if flag == "-e" => Ok(expr.clone()),
+        [path] => Ok(std::fs::read_to_string(path)?),
+        _ => Err(USAGE.into()),
+    }
+}
+
+// mr-lisp test [--filter NAME] [PATH...]。ファイルごとに結果を書き、失敗があれば終了コード 1 で終わる。
+fn run_tests(mut args: &[String], config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut filter = None;
+    let mut paths = Vec::new();
+    loop {
+        match args {
+            [] => break,
+            [flag, name, rest @ ..] if flag == "--filter" => {
+                filter = Some(name.as_str());
+                args = rest;
             }
-            ')' if !*in_string => {
-                *balance -= 1;
+            [flag] if flag == "--filter" => return Err(USAGE.into()),
+            [path, rest @ ..] => {
+                paths.push(PathBuf::from(path));
+                args = rest;
             }
-            _ => {}
         }
     }
+    let files = test_runner::discover(&paths)?;
+    if files.is_empty() {
+        return Err("test: no test files found".into());
+    }
+    let color = config.color.enabled();
+    let start = Instant::now();
+    let mut results = Vec::new();
+    for file in &files {
+        let result = test_runner::run_file(file, &config, filter);
+        if filter.is_none() || !result.tests.is_empty() || result.error.is_some() {
+            print!("{}", test_runner::format_file(&result, color));
+        }
+        results.push(result);
+    }
+    println!();
+    println!(
+        "{}",
+        test_runner::format_summary(&results, start.elapsed(), color)
+    );
+    if results.iter().any(|result| result.failed() > 0) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// mr-lisp typecheck FILE...。評価せずに見つけた誤りを 1 行ずつ書き、1 つでもあれば終了コード 1 で終わる。
+fn typecheck(files: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut found = false;
+    for file in files {
+        let source =
+            std::fs::read_to_string(file).map_err(|e| format!("typecheck: {}: {}", file, e))?;
+        for error in mr_lisp::typecheck::typecheck(&source) {
+            println!(
+                "{}:{}:{}: {}",
+                file, error.line, error.column, error.message
+            );
+            found = true;
+        }
+    }
+    if found {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// parallel-map/process の子プロセス。標準入力のプログラムを評価して、結果を標準出力に書く。
+fn worker() -> Result<(), Box<dyn std::error::Error>> {
+    let mut program = String::new();
+    std::io::stdin().read_to_string(&mut program)?;
+    match mr_lisp::parallel::run_worker(&program) {
+        Ok(reply) => print!("{}", reply),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+// :apropos query。名前をそろえて、説明と一緒に 1 行ずつ書く。
+fn print_apropos(interpreter: &mut Interpreter, query: &str) {
+    let found = mr_lisp::help::apropos(&interpreter.env().borrow(), query);
+    if found.is_empty() {
+        println!("No matches for {:?}", query);
+    }
+    let width = found.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, summary) in found {
+        println!("{:width$}  {}", name, summary, width = width);
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config = Config::from_env()?;
+    let args = apply_options(&args, &mut config)?;
+    match args.as_slice() {
+        [] => {}
+        [flag, addr] if flag == "--listen" => return listen(addr),
+        [flag] if flag == "--worker" => return worker(),
+        [command, rest @ ..] if command == "test" => return run_tests(rest, config),
+        [command, files @ ..] if command == "typecheck" && !files.is_empty() => {
+            return typecheck(files);
+        }
+        [flag, source @ ..] if flag == "--filter" => {
+            let script = read_source(source)?;
+            Interpreter::with_config(config)?.filter(&script)?;
+            return Ok(());
+        }
+        [flag, source @ ..] if flag == "--dump-tokens" => {
+            print!("{}", mr_lisp::dump::dump_tokens(&read_source(source)?)?);
+            return Ok(());
+        }
+        [flag, source @ ..] if flag == "--dump-ast" => {
+            print!("{}", mr_lisp::dump::dump_ast(&read_source(source)?)?);
+            return Ok(());
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    // 評価中の Ctrl-C で REPL ごと終わらずに、その式の評価だけを中断する
+    #[cfg(all(unix, feature = "signals"))]
+    mr_lisp::signal::install_interrupt_handler();
+
     let reader = Interface::new(PROMPT).unwrap();
-    let mut env = Rc::new(RefCell::new(Env::new()));
-    let mut buffer = String::new();
-    let mut paren_balance: i32 = 0;
-    let mut in_string = false;
+    let color = config.color.enabled();
+    let mut interpreter = Interpreter::with_config(config)?;
+    let renderers = Renderers::new();
+    let mut parser = interpreter.parser();
 
-    reader.set_prompt(format!("{}", PROMPT).as_ref()).unwrap();
+    reader.set_prompt(PROMPT).unwrap();
 
     while let ReadResult::Input(input) = reader.read_line().unwrap() {
-        if buffer.is_empty() && input.eq("exit") {
+        if parser.is_empty() && input.eq("exit") {
             break;
         }
-
-        update_paren_balance(&input, &mut paren_balance, &mut in_string);
-        if !buffer.is_empty() {
-            buffer.push('\n');
-        }
-        buffer.push_str(&input);
-
-        if in_string || paren_balance > 0 {
-            reader.set_prompt(format!("{}", CONTINUATION_PROMPT).as_ref()).unwrap();
+        if parser.is_empty()
+            && let Some(query) = input.strip_prefix(":apropos")
+        {
+            print_apropos(&mut interpreter, query.trim());
             continue;
         }
 
-        if paren_balance < 0 {
-            eprintln!("ParseError: Unexpected ')'");
-            buffer.clear();
-            paren_balance = 0;
-            in_string = false;
-            reader.set_prompt(format!("{}", PROMPT).as_ref()).unwrap();
-            continue;
-        }
+        // 読めなかった入力も評価に回し、エラーを位置と一緒に表示する
+        let source = match parser.feed(&format!("{}\n", input)) {
+            Parsed::NeedMoreInput => {
+                reader.set_prompt(CONTINUATION_PROMPT).unwrap();
+                continue;
+            }
+            Parsed::Complete { source, .. } | Parsed::Error { source, .. } => source,
+        };
+        reader.set_prompt(PROMPT).unwrap();
 
-        let program = buffer.trim();
+        let program = source.trim();
         if program.is_empty() {
-            buffer.clear();
-            paren_balance = 0;
-            in_string = false;
-            reader.set_prompt(format!("{}", PROMPT).as_ref()).unwrap();
             continue;
         }
 
-        let val = eval(program, &mut env)?;
-        match val {
-            Object::Void => {}
-            Object::Integer(n) => println!("{}", n),
-            Object::Bool(b) => println!("{}", b),
-            Object::Symbol(s) => println!("{}", s),
-            Object::Lambda(params, body) => {
-                println!("Lambda(");
-                for param in params {
-                    println!("{} ", param);
+        // Ctrl-C で中断した場合も含め、エラーは表示して次の入力を待つ。複数行の入力なら位置も書き、
+        // 入力の中の式でエラーになったなら、その式に下線を引く
+        let val = match interpreter.eval_spanned(program) {
+            Ok(val) => val,
+            Err(e) => {
+                let mut message = if program.contains('\n') {
+                    e.to_string()
+                } else {
+                    e.message.clone()
+                };
+                if e.expr_span != (0..program.len()) {
+                    message = format!("{}\n{}", message, e.underline(program));
                 }
-                println!(")");
-                for expr in (*body).iter() {
-                    println!(" {}", expr);
+                if color {
+                    eprintln!("\x1b[31m{}\x1b[0m", message);
+                } else {
+                    eprintln!("{}", message);
                 }
+                Object::Void
             }
-            _ => println!("{}", val),
+        };
+        if let Some(rendered) = renderers.render(&val) {
+            println!("{}", rendered);
+        } else if val != Object::Void {
+            println!("{}", printer::to_string(&val, Mode::Pretty));
         }
-
-        buffer.clear();
-        paren_balance = 0;
-        in_string = false;
-        reader.set_prompt(format!("{}", PROMPT).as_ref()).unwrap();
     }
 
     println!("Good bye");
@@ -0,0 +1,131 @@
+// スクリプトの先頭に書くメタデータのヘッダー。
+//
+//   ;;; name: hello
+//   ;;; version: 1.0
+//   ;;; requires: net, graphics
+//
+// ファイルの先頭から続く ;;; の行をヘッダーとして読む。key: value の形でない行は説明として読み飛ばす。
+// プラグインのホストが、スクリプトを評価せずに一覧を作ったり、必要な機能があるか確かめたりするのに使う。
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub requires: Vec<String>,
+    pub fields: Vec<(String, String)>, // name、version、requires 以外の項目。書かれた順に並ぶ
+}
+
+// このビルドで有効になっている機能。requires と比べるのに使う。
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "unicode")]
+    "unicode",
+    #[cfg(feature = "graphics")]
+    "graphics",
+    #[cfg(feature = "http")]
+    "http",
+    #[cfg(feature = "websocket")]
+    "websocket",
+    #[cfg(feature = "remote")]
+    "remote",
+    #[cfg(all(feature = "http", feature = "websocket", feature = "remote"))]
+    "net",
+    #[cfg(feature = "osc")]
+    "osc",
+    #[cfg(feature = "jupyter")]
+    "jupyter",
+    #[cfg(all(unix, feature = "signals"))]
+    "signals",
+];
+
+pub fn parse(path: impl AsRef<Path>) -> Result<Manifest, String> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(parse_str(&source))
+}
+
+pub fn parse_str(source: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+    let lines = source
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty());
+    for line in lines {
+        let Some(header) = line.strip_prefix(";;;") else {
+            break;
+        };
+        let Some((key, value)) = header.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_lowercase(), value.trim());
+        match key.as_str() {
+            "name" => manifest.name = Some(value.to_string()),
+            "version" => manifest.version = Some(value.to_string()),
+            "requires" => manifest.requires.extend(
+                value
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string),
+            ),
+            _ => manifest.fields.push((key, value.to_string())),
+        }
+    }
+    manifest
+}
+
+impl Manifest {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "name" => self.name.as_deref(),
+            "version" => self.version.as_deref(),
+            _ => self
+                .fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str()),
+        }
+    }
+
+    // requires のうち capabilities に無いもの。ホスト独自の機能名を足して FEATURES と一緒に渡してもよい。
+    pub fn missing<'a>(&'a self, capabilities: &[&str]) -> Vec<&'a str> {
+        self.requires
+            .iter()
+            .map(String::as_str)
+            .filter(|r| !capabilities.contains(r))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_parse_manifest() {
+        let source = "
+;;; name: hello
+;;; A friendly greeting plugin.
+;;; Version: 1.2
+;;; requires: net, graphics
+;;; requires: fs
+;;; author: someone
+(define x 1) ;;; name: ignored
+x
+";
+        let manifest = parse_str(source);
+        assert_eq!(manifest.name.as_deref(), Some("hello"));
+        assert_eq!(manifest.get("version"), Some("1.2"));
+        assert_eq!(manifest.requires, ["net", "graphics", "fs"]);
+        assert_eq!(manifest.get("author"), Some("someone"));
+        assert_eq!(manifest.get("license"), None);
+        assert_eq!(manifest.missing(&["graphics", "net"]), ["fs"]);
+        assert_eq!(parse_str("(f)\n;;; name: late"), Manifest::default());
+        assert!(parse("/nonexistent/script.lisp").is_err());
+
+        // ヘッダーはコメントなので、そのまま評価できる
+        assert_eq!(Interpreter::new().eval(source), Ok(Object::Integer(1)));
+    }
+}
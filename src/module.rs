@@ -0,0 +1,472 @@
+// module と import による名前空間。
+//
+//   (module math (export square cube)
+//     (define (square x) (* x x))
+//     (define (cube x) (* x (square x))))
+//   (math/square 3)    ; 修飾した名前で参照する
+//   (import math)      ; export した名前をこの Env に定義する
+//   (cube 2)
+//
+// import した名前はモジュールの束縛の別名になるので、モジュールの関数が set! で変えた値は import した側にも見える。
+//
+// モジュールの本体は、module を評価した Env の子の Env で評価するので、本体の define は外に漏れない。
+// export に書いた名前は本体で定義しなければならない。(export ...) を省くと、本体で束縛した名前をすべて export する。
+// 本体のトップレベルの define-private で定義した名前は、どちらの場合も export しない。
+//
+//   (module text
+//     (define-private (trim-left s) ...)
+//     (define (trim s) (trim-left ...)))
+//   (import text trim)  ; 名前を挙げるとその名前だけを定義する。export していない名前ならエラー
+// import するモジュールが見つからなければ、name.lisp を今のディレクトリと MR_LISP_PATH から探して読み込む。
+// ファイルは大域の Env の子の Env で評価し、結果を大域の Env に覚えておく。いくつのモジュールやファイルから
+// import しても、同じ実行の中ではファイルを 1 度だけ評価する。
+// (module-reload 'name) はファイルを読み直し、name の束縛を新しいモジュールにする。math/square のような
+// 修飾した名前は新しい定義を指すようになるが、import で定義した名前は import し直すまで古い値のまま。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::config::{find_file, load_file};
+use crate::eval::{Binding, Env, defined_name, eval_toplevel};
+use crate::keyword::SpecialForm;
+use crate::parser::{Foreign, Object};
+use crate::printer::debug_form;
+
+struct Module {
+    env: Rc<RefCell<Env>>,
+    exports: Vec<String>,
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Module")
+            .field("exports", &self.exports)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Foreign for Module {
+    fn type_name(&self) -> &str {
+        "module"
+    }
+}
+
+impl Module {
+    fn export(&self, name: &str) -> Option<Object> {
+        if !self.exports.iter().any(|export| export == name) {
+            return None;
+        }
+        self.env.borrow().get_local(name)
+    }
+
+    // import で別名にする、モジュールの本体の束縛の箱。
+    fn export_binding(&self, name: &str) -> Option<Binding> {
+        if !self.exports.iter().any(|export| export == name) {
+            return None;
+        }
+        self.env.borrow().binding(name)
+    }
+}
+
+fn as_module(obj: &Object) -> Option<&Module> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<Module>(),
+        _ => None,
+    }
+}
+
+// (module name (export a b ...) body...) または (module name body...)
+pub(crate) fn eval_module(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let invalid = || format!("Invalid module syntax: {}", debug_form(list));
+    let [_, Object::Symbol(name), rest @ ..] = list else {
+        return Err(invalid());
+    };
+    let (exports, body) = match rest {
+        [Object::List(header), body @ ..] if matches!(header.first(), Some(Object::Symbol(head)) if head.as_ref() == "export") =>
+        {
+            let exports = header[1..]
+                .iter()
+                .map(|export| match export {
+                    Object::Symbol(s) => Ok(s.to_string()),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            (Some(exports), body)
+        }
+        body => (None, body),
+    };
+
+    let mut module_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    for expr in body {
+        eval_toplevel(expr, &mut module_env).map_err(|e| format!("module {}: {}", name, e))?;
+    }
+    let private: Vec<&str> = body
+        .iter()
+        .filter_map(|form| defined_name(form, SpecialForm::DefinePrivate))
+        .collect();
+    let exports = match exports {
+        Some(exports) => {
+            if let Some(missing) = exports
+                .iter()
+                .find(|export| module_env.borrow().get_local(export).is_none())
+            {
+                return Err(format!(
+                    "module {}: exported {} is not defined",
+                    name, missing
+                ));
+            }
+            if let Some(export) = exports
+                .iter()
+                .find(|export| private.contains(&export.as_str()))
+            {
+                return Err(format!("module {}: exported {} is private", name, export));
+            }
+            exports
+        }
+        None => {
+            let mut names = module_env.borrow().local_names();
+            names.retain(|name| !private.contains(&name.as_str()));
+            names
+        }
+    };
+    let module = Module {
+        env: module_env,
+        exports,
+    };
+    env.borrow_mut()
+        .define(name, Object::Foreign(Rc::new(module)))?;
+    Ok(Object::Void)
+}
+
+// (import name) は、モジュールが export した名前をすべてこの Env に定義する。
+// (import name a b ...) は a b ... だけを定義する。export していない名前があれば、何も定義せずにエラーにする。
+pub(crate) fn eval_import(list: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let invalid = || format!("Invalid import syntax: {}", debug_form(list));
+    let [_, Object::Symbol(name), only @ ..] = list else {
+        return Err(invalid());
+    };
+    let only = only
+        .iter()
+        .map(|obj| match obj {
+            Object::Symbol(s) => Ok(s.as_ref()),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<&str>, _>>()?;
+    let module = env.borrow().get(name);
+    let module = match module {
+        Some(module) => module,
+        None => load_module(name, env)?,
+    };
+    let module = as_module(&module).ok_or_else(|| format!("import: {} is not a module", name))?;
+    let names: Vec<&str> = if only.is_empty() {
+        module.exports.iter().map(String::as_str).collect()
+    } else {
+        if let Some(missing) = only
+            .iter()
+            .find(|&&member| !module.exports.iter().any(|export| export == member))
+        {
+            return Err(format!("import: {} does not export {}", name, missing));
+        }
+        only
+    };
+    for export in names {
+        if let Some(binding) = module.export_binding(export) {
+            env.borrow_mut().define_alias(export, binding)?;
+        }
+    }
+    Ok(Object::Void)
+}
+
+// name.lisp の name のモジュールを env に定義して返す。まだ読み込んでいなければファイルを評価する。
+fn load_module(name: &str, env: &Rc<RefCell<Env>>) -> Result<Object, String> {
+    let path = module_path("import", name)?;
+    let cached = env.borrow().loaded_module(&path);
+    let module = match cached {
+        Some(module) if !matches!(module, Object::Void) => module,
+        _ => eval_module_file("import", name, &path, env)?,
+    };
+    env.borrow_mut().define(name, module.clone())?;
+    Ok(module)
+}
+
+fn module_path(caller: &str, name: &str) -> Result<PathBuf, String> {
+    let path = find_file(Path::new(&format!("{}.lisp", name)));
+    if !path.exists() {
+        return Err(format!("{}: module {} not found", caller, name));
+    }
+    Ok(path.canonicalize().unwrap_or(path))
+}
+
+// ファイルを評価して、そこで定義された name のモジュールを覚えておく。ファイルのほかの定義は捨てる。
+// 評価している間は Void を覚えておき、評価の途中で同じファイルを読もうとしたら循環した import としてエラーにする。
+fn eval_module_file(
+    caller: &str,
+    name: &str,
+    path: &Path,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let previous = env.borrow().loaded_module(path);
+    if matches!(previous, Some(Object::Void)) {
+        return Err(format!("{}: cyclic import of {}", caller, name));
+    }
+    env.borrow_mut()
+        .cache_module(path.to_path_buf(), Object::Void);
+    let mut scratch = Rc::new(RefCell::new(Env::extend(Env::global(env))));
+    let module = load_file(path, &mut scratch)
+        .map_err(|e| format!("{}: {}", caller, e))
+        .and_then(|_| {
+            let module = scratch.borrow().get_local(name);
+            module.ok_or_else(|| {
+                format!(
+                    "{}: {} does not define module {}",
+                    caller,
+                    path.display(),
+                    name
+                )
+            })
+        });
+    match &module {
+        Ok(module) => env
+            .borrow_mut()
+            .cache_module(path.to_path_buf(), module.clone()),
+        Err(_) => env.borrow_mut().uncache_module(path, previous),
+    }
+    module
+}
+
+// (module-reload 'name)
+pub(crate) fn module_reload(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let Object::Symbol(name) = &args[0] else {
+        unreachable!("checked by the signature")
+    };
+    let path = module_path("module-reload", name)?;
+    let module = eval_module_file("module-reload", name, &path, env)?;
+    let bound = env.borrow().get(name).is_some();
+    if bound {
+        env.borrow_mut().assign(name, module)?;
+    } else {
+        env.borrow_mut().define(name, module)?;
+    }
+    Ok(Object::Void)
+}
+
+// math/square のような修飾した名前を、モジュールが export した値として探す。
+pub(crate) fn lookup_qualified(env: &Rc<RefCell<Env>>, name: &str) -> Option<Object> {
+    let (module, member) = name.split_once('/')?;
+    let module = env.borrow().get(module)?;
+    let module = as_module(&module)?;
+    module.export(member)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_module() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (module math (export square cube)
+                (define (helper x) (* x x))
+                (define (square x) (helper x))
+                (define (cube x) (* x (square x))))
+            (math/square 3)
+        ";
+        assert_eq!(interpreter.eval(program), Ok(Object::Integer(9)));
+        assert_eq!(
+            interpreter.eval("(helper 2)"),
+            Err("Undefined function: helper".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("math/helper"),
+            Err("Undefined symbol: math/helper".to_string())
+        );
+        assert!(interpreter.eval("(cube 2)").is_err());
+        assert_eq!(
+            interpreter.eval("(import math) (cube 2)"),
+            Ok(Object::Integer(8))
+        );
+        assert_eq!(
+            interpreter.eval("(module bad (export missing) (define x 1))"),
+            Err("module bad: exported missing is not defined".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(import nowhere)"),
+            Err("import: module nowhere not found".to_string())
+        );
+    }
+
+    #[test]
+    fn test_private_definitions() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (module text
+                (define-private (pad s) (+ s 100))
+                (define-private limit 3)
+                (define (widen s) (pad s))
+                (define version 2))
+            (list (text/widen 1) text/version)
+        ";
+        assert_eq!(interpreter.eval(program).unwrap().to_string(), "(101 2)");
+        assert_eq!(
+            interpreter.eval("text/pad"),
+            Err("Undefined symbol: text/pad".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(import text pad)"),
+            Err("import: text does not export pad".to_string())
+        );
+        assert!(interpreter.eval("widen").is_err());
+        assert_eq!(
+            interpreter.eval("(import text widen) (widen 2)"),
+            Ok(Object::Integer(102))
+        );
+        assert!(interpreter.eval("version").is_err());
+        assert!(interpreter.eval("limit").is_err());
+        assert_eq!(
+            interpreter.eval("(module bad (export secret) (define-private secret 1))"),
+            Err("module bad: exported secret is private".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(define-private top 1) top"),
+            Ok(Object::Integer(1))
+        );
+    }
+
+    #[test]
+    fn test_import_aliases_bindings() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (module counter (export count bump!)
+                (define count 0)
+                (define (bump!) (set! count (+ count 1))))
+            (import counter)
+            (bump!)
+            (bump!)
+            (list count counter/count)
+        ";
+        assert_eq!(interpreter.eval(program).unwrap().to_string(), "(2 2)");
+        assert_eq!(
+            interpreter.eval("(set! count 10) (bump!) counter/count"),
+            Ok(Object::Integer(11))
+        );
+        assert_eq!(
+            interpreter
+                .eval("(define count 0) (bump!) (list count counter/count)")
+                .unwrap()
+                .to_string(),
+            "(1 1)"
+        );
+    }
+
+    #[test]
+    fn test_import_from_file() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-module-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("strings.lisp"),
+            "(define scratch 1)\n(module strings (export twice) (define (twice s) (+ s s)))",
+        )
+        .unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.config_mut().path = vec![dir.clone()];
+        assert_eq!(
+            interpreter.eval("(import strings) (twice 21)"),
+            Ok(Object::Integer(42))
+        );
+        assert_eq!(
+            interpreter.eval("(strings/twice 2)"),
+            Ok(Object::Integer(4))
+        );
+        assert!(interpreter.eval("scratch").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_cache_and_reload() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write_counter = |value: i64| {
+            std::fs::write(
+                dir.join("counter.lisp"),
+                format!(
+                    "(print \"loading\")\n(module counter (export value) (define value {}))",
+                    value
+                ),
+            )
+            .unwrap();
+        };
+        write_counter(1);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.config_mut().path = vec![dir.clone()];
+        // どちらのモジュールからも counter は見えないので、それぞれ import するが、ファイルは 1 度だけ評価する
+        let program = "
+            (module a (export get-a) (import counter) (define (get-a) counter/value))
+            (module b (export get-b) (import counter) (define (get-b) value))
+            (list (a/get-a) (b/get-b))
+        ";
+        let result = interpreter.eval_rich(program);
+        assert_eq!(result.value.unwrap().to_string(), "(1 1)");
+        assert_eq!(result.output, "loading\n");
+
+        write_counter(2);
+        let result = interpreter.eval_rich("(import counter) value");
+        assert_eq!(result.value, Ok(Object::Integer(1)));
+        assert_eq!(result.output, "");
+        let result = interpreter.eval_rich("(module-reload 'counter) (list counter/value value)");
+        assert_eq!(result.value.unwrap().to_string(), "(2 1)");
+        assert_eq!(result.output, "loading\n");
+        assert_eq!(
+            interpreter.eval("(module-reload 'nowhere)"),
+            Err("module-reload: module nowhere not found".to_string())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cyclic_import() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-cycle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("cyc.lisp"),
+            "(import cyc)
+(module cyc (define x 1))",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("ping.lisp"),
+            "(import pong)
+(module ping (define x 1))",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("pong.lisp"),
+            "(import ping)
+(module pong (define y 2))",
+        )
+        .unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.config_mut().path = vec![dir.clone()];
+        for (module, cycle) in [("cyc", "cyc"), ("ping", "ping")] {
+            let error = interpreter
+                .eval(&format!("(import {})", module))
+                .unwrap_err();
+            assert!(
+                error.contains(&format!("import: cyclic import of {}", cycle)),
+                "{}",
+                error
+            );
+        }
+        // 失敗した import は覚えておかないので、ファイルを直せば読み込める
+        std::fs::write(dir.join("cyc.lisp"), "(module cyc (define x 1))").unwrap();
+        assert_eq!(interpreter.eval("(import cyc) x"), Ok(Object::Integer(1)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
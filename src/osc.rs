@@ -0,0 +1,130 @@
+// OSC (Open Sound Control) 1.0 のメッセージを UDP で送る。SuperCollider や TouchDesigner などとのやり取りに使う。
+//
+//   (osc-send "127.0.0.1:57120" "/synth/freq" 440 0.5 "saw")
+//
+// 引数の型は Integer が i (int32、範囲外なら h の int64)、Float が f (float32)、
+// String と Symbol が s、Bool が T/F に対応する。
+
+use std::cell::RefCell;
+use std::net::UdpSocket;
+use std::rc::Rc;
+
+use crate::builtins::expect_string;
+use crate::eval::Env;
+use crate::parser::Object;
+
+// OSC の文字列は NUL 終端し、4 バイト境界まで NUL で埋める。
+fn push_padded_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+fn encode_message(address: &str, args: &[Object]) -> Result<Vec<u8>, String> {
+    if !address.starts_with('/') {
+        return Err(format!(
+            "osc-send: address must start with '/', got {:?}",
+            address
+        ));
+    }
+    let mut type_tags = String::from(",");
+    let mut payload = Vec::new();
+    for arg in args {
+        match arg {
+            Object::Integer(n) => match i32::try_from(*n) {
+                Ok(n) => {
+                    type_tags.push('i');
+                    payload.extend_from_slice(&n.to_be_bytes());
+                }
+                Err(_) => {
+                    type_tags.push('h');
+                    payload.extend_from_slice(&n.to_be_bytes());
+                }
+            },
+            Object::Float(f) => {
+                type_tags.push('f');
+                payload.extend_from_slice(&(*f as f32).to_be_bytes());
+            }
+            Object::String(s) => {
+                type_tags.push('s');
+                push_padded_str(&mut payload, s);
+            }
+            Object::Symbol(s) => {
+                type_tags.push('s');
+                push_padded_str(&mut payload, s);
+            }
+            Object::Bool(true) => type_tags.push('T'),
+            Object::Bool(false) => type_tags.push('F'),
+            obj => return Err(format!("osc-send: cannot send {} as an OSC argument", obj)),
+        }
+    }
+
+    let mut message = Vec::new();
+    push_padded_str(&mut message, address);
+    push_padded_str(&mut message, &type_tags);
+    message.extend_from_slice(&payload);
+    Ok(message)
+}
+
+// (osc-send "host:port" "/address" args...)
+pub(crate) fn osc_send(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let target = expect_string("osc-send", &args[0])?;
+    let address = expect_string("osc-send", &args[1])?;
+    let message = encode_message(address, &args[2..])?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("osc-send: {}", e))?;
+    socket
+        .send_to(&message, target)
+        .map_err(|e| format!("osc-send: {}", e))?;
+    Ok(Object::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval;
+    use std::time::Duration;
+
+    #[test]
+    fn test_encode_message() {
+        let message = encode_message(
+            "/synth",
+            &[
+                Object::Integer(440),
+                Object::Float(0.5),
+                Object::String("saw".into()),
+                Object::truth(true),
+            ],
+        )
+        .unwrap();
+        let mut expected = b"/synth\0\0,ifsT\0\0\0".to_vec();
+        expected.extend_from_slice(&440i32.to_be_bytes());
+        expected.extend_from_slice(&0.5f32.to_be_bytes());
+        expected.extend_from_slice(b"saw\0");
+        assert_eq!(message, expected);
+
+        let message = encode_message("/big", &[Object::Integer(1 << 40)]).unwrap();
+        assert_eq!(&message[8..12], b",h\0\0");
+        assert!(encode_message("no-slash", &[]).is_err());
+    }
+
+    #[test]
+    fn test_osc_send() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let program = format!(
+            "(osc-send \"{}\" \"/note\" 60 \"on\")",
+            receiver.local_addr().unwrap()
+        );
+
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert_eq!(eval(&program, &mut env).unwrap(), Object::Void);
+        let mut buf = [0; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"/note\0\0\0,is\0\0\0\0\x3con\0\0");
+    }
+}
@@ -0,0 +1,189 @@
+// cons で作るペアと、それを使うリストの操作。
+//
+//   (cons 1 2)              ; (1 . 2)
+//   (cons 1 (cons 2 '()))   ; (1 2)
+//   (cdr (cons 1 '(2 3)))   ; (2 3)
+//
+// 空のリストは Object::nil() で、(cons 1 '()) のようにペアの並びの終わりにも使う。
+// list、quote、quasiquote で作るリストもペアの並びにするので、cons、car、cdr はコピーせずに O(1) で済む。
+// string-split などの組み込み関数が返すリストは Vec のまま持つので、ここの関数はペアと Vec のリストのどちらも受け付ける。
+// Vec のリストの cdr だけは残りの要素をコピーする。
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::parser::{Object, Pair};
+
+pub(crate) fn cons(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::Pair(Rc::new(Pair {
+        car: args[0].clone(),
+        cdr: args[1].clone(),
+    })))
+}
+
+pub(crate) fn car(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    match &args[0] {
+        Object::Pair(pair) => Ok(pair.car.clone()),
+        Object::ListData(list) | Object::List(list) if !list.is_empty() => Ok(list[0].clone()),
+        obj => Err(format!("car: expected a pair, got {}", obj)),
+    }
+}
+
+pub(crate) fn cdr(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    match &args[0] {
+        Object::Pair(pair) => Ok(pair.cdr.clone()),
+        Object::ListData(list) | Object::List(list) if list.len() == 1 => Ok(Object::nil()),
+        Object::ListData(list) | Object::List(list) if !list.is_empty() => {
+            Ok(Object::ListData(Rc::new(list[1..].to_vec())))
+        }
+        obj => Err(format!("cdr: expected a pair, got {}", obj)),
+    }
+}
+
+pub(crate) fn is_pair(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::truth(match &args[0] {
+        Object::Pair(_) => true,
+        Object::ListData(list) | Object::List(list) => !list.is_empty(),
+        _ => false,
+    }))
+}
+
+pub(crate) fn is_null(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    Ok(Object::truth(args[0].is_nil()))
+}
+
+// items を順に car に持ち、最後の cdr が tail になるペアの並び。items が空なら tail を返す。
+pub(crate) fn pair_list(items: Vec<Object>, tail: Object) -> Object {
+    items
+        .into_iter()
+        .rev()
+        .fold(tail, |cdr, car| Object::Pair(Rc::new(Pair { car, cdr })))
+}
+
+// 真リストの要素。ペアの cdr をたどって、最後が Vec のリストで終わらなければ None。
+pub(crate) fn list_items(obj: &Object) -> Option<Vec<Object>> {
+    match pair_items(obj) {
+        (items, None) => Some(items),
+        (_, Some(_)) => None,
+    }
+}
+
+// ペアの cdr をたどった要素と、最後がリストでなければその値。(a b . c) なら (a b) と c。
+pub(crate) fn pair_items(obj: &Object) -> (Vec<Object>, Option<Object>) {
+    let mut items = Vec::new();
+    let mut rest = obj;
+    loop {
+        match rest {
+            Object::Pair(pair) => {
+                items.push(pair.car.clone());
+                rest = &pair.cdr;
+            }
+            Object::List(list) | Object::ListData(list) => {
+                items.extend(list.iter().cloned());
+                return (items, None);
+            }
+            tail => return (items, Some(tail.clone())),
+        }
+    }
+}
+
+pub(crate) fn length(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let obj = &args[0];
+    match list_items(obj) {
+        Some(items) => Ok(Object::Integer(items.len() as i64)),
+        None => Err(format!("length: expected a proper list, got {}", obj)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_nil() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define (build n) (if (< n 1) '() (cons n (build (- n 1)))))
+            (define (sum xs) (if (null? xs) 0 (+ (car xs) (sum (cdr xs)))))
+            (define (count xs) (if (null? xs) 0 (+ 1 (count (cdr xs)))))
+            (list (build 3) (sum (build 4)) (count '(a b c)) (sum '()))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "((3 2 1) 10 3 0)"
+        );
+        for program in ["'()", "()", "(list)", "`()", "(cdr '(x))"] {
+            assert_eq!(interpreter.eval(program), Ok(Object::nil()), "{}", program);
+        }
+        assert_eq!(
+            interpreter
+                .eval("(list (null? ()) (null? (list)) (null? '(())) (null? 0))")
+                .unwrap()
+                .to_string(),
+            "(true true false false)"
+        );
+    }
+
+    #[test]
+    fn test_pairs() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define p (cons 1 2))
+            (define xs (cons 1 (cons 2 '(3 4))))
+            (list p (car p) (cdr p) xs (cdr xs) (length xs) '(a b . c))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "((1 . 2) 1 2 (1 2 3 4) (2 3 4) 4 (a b . c))"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(list (car '(a b)) (cdr '(a b)) (cdr '(a)))")
+                .unwrap()
+                .to_string(),
+            "(a (b) ())"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(list (pair? p) (pair? '()) (null? '()) (null? xs))")
+                .unwrap()
+                .to_string(),
+            "(true false true false)"
+        );
+        assert_eq!(
+            interpreter.eval("(car '())"),
+            Err("car: expected a pair, got ()".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(length p)"),
+            Err("length: expected a proper list, got (1 . 2)".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(call-with-values (lambda () p) cdr)"),
+            Ok(Object::Integer(2))
+        );
+    }
+
+    #[test]
+    fn test_cdr_shares_the_tail() {
+        let mut interpreter = Interpreter::new();
+        let cdr = interpreter.eval("cdr").unwrap();
+        for program in ["(list 1 2 3)", "'(1 2 3)", "`(1 ,(+ 1 1) 3)"] {
+            let list = interpreter.eval(program).unwrap();
+            let Object::Pair(pair) = &list else {
+                panic!("{} should be a pair, got {:?}", program, list);
+            };
+            let rest = interpreter
+                .apply(&cdr, std::slice::from_ref(&list))
+                .unwrap();
+            let (Object::Pair(tail), Object::Pair(rest)) = (&pair.cdr, &rest) else {
+                panic!("the cdr of {} should be a pair", program);
+            };
+            assert!(Rc::ptr_eq(tail, rest), "{}", program);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+// (parallel-map/process f list #:workers 4) は、list を workers 個に分けて mr-lisp --worker の子プロセスで f を呼ぶ。
+// Object は Send ではないのでスレッドは使わず、f とその Env の束縛をソースに書き出して子プロセスに渡す。
+// 子プロセスは結果を同じくソースにして返し、親で読み戻す。print の出力は分けた順に親で出力する。
+//
+// データは Write で書いて ' を付け、lambda は (lambda (x) ...) の式に、組み込み関数は名前に戻す。
+// レコードは子プロセスでも同じ型を define-record-type で定義し、parallel-map/make-point のような
+// すべてのフィールドを取るコンストラクターの呼び出しで書く。親に戻ったレコードは元の型の値になる。
+// それ以外の値 (WebSocket の接続など) を束縛した変数は子プロセスには渡らない。
+// 子プロセスのコマンドは #:command で指定する。省略すると MR_LISP_BIN 環境変数か、今のプロセスの実行ファイルを使う。
+
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use crate::builtins::{expect_string, expect_usize, split_keyword_args};
+use crate::eval::{Env, capture_output, eval, write_output};
+use crate::interpreter::Interpreter;
+use crate::pair::{list_items, pair_items};
+use crate::parser::{Object, RecordType};
+use crate::printer::{Mode, to_string, write_lambda};
+use crate::record::{Operation, constructor, record_procedure};
+
+const NAME: &str = "parallel-map/process";
+
+// 子プロセスとやり取りするソースを書く。途中で出てきたレコードの型を覚えておき、
+// 子プロセスで同じ型を定義したり、親で読み戻すときに元の型のコンストラクターを用意したりするのに使う。
+#[derive(Default)]
+struct Source {
+    record_types: Vec<Rc<RecordType>>,
+}
+
+impl Source {
+    // 評価すると obj になる式。書き出せない値なら None。
+    fn write(&mut self, obj: &Object) -> Option<String> {
+        match obj {
+            Object::Integer(_)
+            | Object::BigInt(_)
+            | Object::Float(_)
+            | Object::Bool(_)
+            | Object::Char(_)
+            | Object::String(_) => Some(to_string(obj, Mode::Write)),
+            _ if is_literal(obj) => Some(format!("'{}", to_string(obj, Mode::Write))),
+            Object::Void => Some("(begin)".to_string()),
+            // 手続きやレコードを含むリストとベクターは、要素の式から作り直す
+            Object::ListData(_) | Object::Pair(_) => {
+                let (items, tail) = pair_items(obj);
+                let items = self.write_all(&items)?;
+                match tail {
+                    None => Some(call("list", &items)),
+                    Some(tail) => Some(
+                        items
+                            .into_iter()
+                            .rev()
+                            .fold(self.write(&tail)?, |tail, item| {
+                                format!("(cons {} {})", item, tail)
+                            }),
+                    ),
+                }
+            }
+            Object::Vector(vector) => {
+                let items = self.write_all(&vector.borrow())?;
+                let mut source = "((lambda (parallel-map/vector)".to_string();
+                for (i, item) in items.iter().enumerate() {
+                    source.push_str(&format!(
+                        " (vector-set! parallel-map/vector {} {})",
+                        i, item
+                    ));
+                }
+                source.push_str(&format!(
+                    " parallel-map/vector) (make-vector {}))",
+                    items.len()
+                ));
+                Some(source)
+            }
+            Object::Lambda(lambda) => Some(write_lambda(lambda)),
+            Object::Builtin(builtin) => Some(builtin.name.to_string()),
+            Object::Record(record) => {
+                self.add_record_type(&record.record_type);
+                let values = self.write_all(&record.values.borrow())?;
+                Some(call(&constructor_name(&record.record_type), &values))
+            }
+            Object::Foreign(_) => {
+                let (record_type, operation) = record_procedure(obj)?;
+                self.add_record_type(record_type);
+                Some(write_record_procedure(record_type, operation))
+            }
+            _ => None,
+        }
+    }
+
+    fn write_all(&mut self, items: &[Object]) -> Option<Vec<String>> {
+        items.iter().map(|item| self.write(item)).collect()
+    }
+
+    fn add_record_type(&mut self, record_type: &Rc<RecordType>) {
+        if !self.record_types.iter().any(|t| Rc::ptr_eq(t, record_type)) {
+            self.record_types.push(Rc::clone(record_type));
+        }
+    }
+
+    // 子プロセスで、出てきたレコードの型を parallel-map/ で始まる名前の関数とともに定義する
+    fn define_record_types(&self) -> String {
+        let mut source = String::new();
+        for record_type in &self.record_types {
+            let name = &record_type.name;
+            let fields = &record_type.fields;
+            source.push_str(&format!(
+                "(define-record-type {} {} parallel-map/{}?",
+                name,
+                call(&constructor_name(record_type), fields),
+                name
+            ));
+            for field in fields {
+                source.push_str(&format!(
+                    " ({} parallel-map/{}-{} parallel-map/set-{}-{}!)",
+                    field, name, field, name, field
+                ));
+            }
+            source.push_str(")\n");
+        }
+        source
+    }
+}
+
+// ' を付けて Write で書けば、読み戻して同じ値になるデータ
+fn is_literal(obj: &Object) -> bool {
+    match obj {
+        Object::Integer(_)
+        | Object::BigInt(_)
+        | Object::Float(_)
+        | Object::Bool(_)
+        | Object::Char(_)
+        | Object::String(_)
+        | Object::Symbol(_)
+        | Object::Keyword(_)
+        | Object::BinaryOp(_)
+        | Object::KeywordArg(_) => true,
+        Object::List(items) | Object::ListData(items) => items.iter().all(is_literal),
+        Object::Pair(_) => {
+            let (items, tail) = pair_items(obj);
+            items.iter().all(is_literal) && tail.is_none_or(|tail| is_literal(&tail))
+        }
+        Object::Vector(vector) => vector.borrow().iter().all(is_literal),
+        _ => false,
+    }
+}
+
+fn call(head: &str, args: &[String]) -> String {
+    let args: String = args.iter().map(|arg| format!(" {}", arg)).collect();
+    format!("({}{})", head, args)
+}
+
+// すべてのフィールドを定義した順に取るコンストラクター
+fn constructor_name(record_type: &RecordType) -> String {
+    format!("parallel-map/make-{}", record_type.name)
+}
+
+// define-record-type で作った関数を、Source::define_record_types で定義した関数で書く
+fn write_record_procedure(record_type: &RecordType, operation: &Operation) -> String {
+    let name = &record_type.name;
+    let fields = &record_type.fields;
+    match operation {
+        Operation::Construct(indices) if indices.iter().copied().eq(0..fields.len()) => {
+            constructor_name(record_type)
+        }
+        // 一部のフィールドだけを取るコンストラクター。残りは Void にする
+        Operation::Construct(indices) => {
+            let params: Vec<&str> = indices.iter().map(|&i| fields[i].as_str()).collect();
+            let args: Vec<String> = (0..fields.len())
+                .map(|i| match indices.contains(&i) {
+                    true => fields[i].clone(),
+                    false => "(begin)".to_string(),
+                })
+                .collect();
+            format!(
+                "(lambda ({}) {})",
+                params.join(" "),
+                call(&constructor_name(record_type), &args)
+            )
+        }
+        Operation::Predicate => format!("parallel-map/{}?", name),
+        Operation::Access(i) => format!("parallel-map/{}-{}", name, fields[*i]),
+        Operation::Modify(i) => format!("parallel-map/set-{}-{}!", name, fields[*i]),
+    }
+}
+
+// f が参照するかもしれない束縛の define と、f を list の各要素に適用した結果のリストを求めるプログラム。
+// 出てきたレコードの型も返す。
+fn worker_program(
+    f: &Object,
+    env: &Rc<RefCell<Env>>,
+    items: &[Object],
+) -> Result<(String, Vec<Rc<RecordType>>), String> {
+    let mut source = Source::default();
+    let mut program = String::new();
+    let scope = match f {
+        Object::Lambda(lambda) => &lambda.env,
+        _ => env,
+    };
+    for (name, value) in scope.borrow().user_bindings() {
+        if let Some(value) = source.write(&value) {
+            program.push_str(&format!("(define {} {})\n", name, value));
+        }
+    }
+    let f = source
+        .write(f)
+        .ok_or_else(|| format!("{}: cannot send {} to a worker", NAME, f))?;
+    program.push_str(&format!("(define parallel-map/f {})\n(list", f));
+    for item in items {
+        let item = source
+            .write(item)
+            .ok_or_else(|| format!("{}: cannot send {} to a worker", NAME, item))?;
+        program.push_str(&format!(" (parallel-map/f {})", item));
+    }
+    program.push(')');
+    Ok((source.define_record_types() + &program, source.record_types))
+}
+
+// mr-lisp --worker の本体。program を評価し、print の出力と値の組をソースにして返す。
+pub fn run_worker(program: &str) -> Result<String, String> {
+    let (result, output) = capture_output(|| Interpreter::new().eval(program));
+    let value = Object::ListData(Rc::new(vec![Object::String(output.into()), result?]));
+    Source::default()
+        .write(&value)
+        .ok_or_else(|| "cannot send the result back to the parent process".to_string())
+}
+
+// 子プロセスが返したソースを、print の出力と結果のリストに戻す。レコードは record_types の元の型の値にする。
+fn read_reply(
+    source: &str,
+    record_types: &[Rc<RecordType>],
+) -> Result<(String, Vec<Object>), String> {
+    let mut scope = Rc::new(RefCell::new(Env::new()));
+    for record_type in record_types {
+        let name = constructor_name(record_type);
+        scope
+            .borrow_mut()
+            .define(&name, constructor(&name, record_type))?;
+    }
+    let reply = eval(source, &mut scope).map_err(|e| format!("{}: {}", NAME, e))?;
+    let reply = list_items(&reply).unwrap_or_default();
+    let [Object::String(printed), values] = reply.as_slice() else {
+        return Err(format!("{}: unexpected reply from a worker", NAME));
+    };
+    let Some(values) = list_items(values) else {
+        return Err(format!("{}: unexpected reply from a worker", NAME));
+    };
+    Ok((printed.to_string(), values))
+}
+
+fn worker_command(command: Option<&str>) -> Result<String, String> {
+    if let Some(command) = command {
+        return Ok(command.to_string());
+    }
+    if let Ok(command) = std::env::var("MR_LISP_BIN") {
+        return Ok(command);
+    }
+    let exe = std::env::current_exe().map_err(|e| format!("{}: {}", NAME, e))?;
+    Ok(exe.to_string_lossy().into_owned())
+}
+
+pub(crate) fn parallel_map(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args(NAME, args)?;
+    let items = match positional.as_slice() {
+        [_, list] => list_items(list),
+        _ => None,
+    };
+    let ([f, _], Some(items)) = (positional.as_slice(), items) else {
+        return Err(format!(
+            "{}: expected a procedure and a list, got {}",
+            NAME,
+            Object::ListData(Rc::new(positional.into_iter().cloned().collect()))
+        ));
+    };
+    if !matches!(f, Object::Lambda(_) | Object::Builtin(_)) {
+        return Err(format!("{}: expected a procedure, got {}", NAME, f));
+    }
+    let mut workers = 4;
+    let mut command = None;
+    for (kw, value) in keywords {
+        match kw {
+            "workers" => workers = expect_usize(NAME, value)?.max(1),
+            "command" => command = Some(expect_string(NAME, value)?),
+            _ => return Err(format!("{}: unknown keyword #:{}", NAME, kw)),
+        }
+    }
+    if items.is_empty() {
+        return Ok(Object::nil());
+    }
+    let command = worker_command(command)?;
+
+    // 先に全部の子プロセスを起動してから、順に結果を待つ
+    let chunk_size = items.len().div_ceil(workers);
+    let mut children = Vec::new();
+    for chunk in items.chunks(chunk_size) {
+        let (program, record_types) = worker_program(f, env, chunk)?;
+        let mut child = Command::new(&command)
+            .arg("--worker")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: cannot start {}: {}", NAME, command, e))?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin
+            .write_all(program.as_bytes())
+            .map_err(|e| format!("{}: {}", NAME, e))?;
+        children.push((child, record_types));
+    }
+
+    let mut results = Vec::with_capacity(items.len());
+    for (child, record_types) in children {
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("{}: {}", NAME, e))?;
+        if !output.status.success() {
+            let mut message = String::new();
+            output.stderr.as_slice().read_to_string(&mut message).ok();
+            return Err(format!("{}: worker failed: {}", NAME, message.trim()));
+        }
+        let source = String::from_utf8_lossy(&output.stdout);
+        let (printed, values) = read_reply(&source, &record_types)?;
+        write_output(&printed);
+        results.extend(values);
+    }
+    Ok(Object::ListData(Rc::new(results)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 子プロセスを起動せずに、worker_program、run_worker、read_reply の順で f を items に適用する
+    fn map_in_process(
+        interpreter: &mut Interpreter,
+        f: &str,
+        items: &str,
+    ) -> Result<(String, Vec<Object>), String> {
+        let f = interpreter.eval(f).unwrap();
+        let items = list_items(&interpreter.eval(items).unwrap()).unwrap();
+        let (program, record_types) = worker_program(&f, interpreter.env(), &items)?;
+        read_reply(&run_worker(&program)?, &record_types)
+    }
+
+    #[test]
+    fn test_worker_program() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval(
+                "(define offset 10)
+                 (define label \"n=\")
+                 (define (describe x) (print x) (list label (+ x offset) (quote done) (- 0 2) (< 0 x)))",
+            )
+            .unwrap();
+        let f = interpreter.eval("describe").unwrap();
+        let items = [Object::Integer(1), Object::Integer(-3)];
+        let (program, _) = worker_program(&f, interpreter.env(), &items).unwrap();
+        assert!(program.ends_with("(list (parallel-map/f 1) (parallel-map/f -3))"));
+
+        let reply = run_worker(&program).unwrap();
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert_eq!(
+            eval(&reply, &mut env).unwrap().to_string(),
+            "(1\n-3\n ((n= 11 done -2 true) (n= 7 done -2 false)))"
+        );
+        assert!(run_worker("(undefined-function)").is_err());
+        assert!(run_worker("(delay 1)").is_err());
+
+        assert_eq!(
+            interpreter.eval("(parallel-map/process describe '())"),
+            Ok(Object::ListData(Rc::new(vec![])))
+        );
+        assert!(
+            interpreter
+                .eval("(parallel-map/process describe '(1 2) #:command \"/nonexistent/mr-lisp\")")
+                .unwrap_err()
+                .starts_with("parallel-map/process: cannot start /nonexistent/mr-lisp")
+        );
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        let mut interpreter = Interpreter::new();
+        let items = "(list 99999999999999999999 (cons 1 2) (cons 'a (list \"q\\\"\\\\\" 3)) #\\a
+                           #(1 \"b\" #\\c) (make-vector 2 car) (list car (lambda (y) #\\a)) +nan.0 (begin)
+                           (cons 1 (begin)))";
+        let (_, values) = map_in_process(&mut interpreter, "(lambda (x) x)", items).unwrap();
+        assert_eq!(
+            to_string(&Object::ListData(Rc::new(values)), Mode::Write),
+            to_string(&interpreter.eval(items).unwrap(), Mode::Write)
+        );
+
+        let (_, values) =
+            map_in_process(&mut interpreter, "(lambda (x) (cons #\\a x))", "'(1)").unwrap();
+        assert_eq!(
+            to_string(&values[0], Mode::Write),
+            to_string(&interpreter.eval("(cons #\\a 1)").unwrap(), Mode::Write)
+        );
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval(
+                "(define-record-type point
+                     (make-point x y)
+                     point?
+                     (x point-x)
+                     (y point-y set-point-y!)
+                     (label point-label))
+                 (define (shift p)
+                   (set-point-y! p (+ (point-y p) 1))
+                   (list (point? p) p (make-point (point-x p) 0)))",
+            )
+            .unwrap();
+        let (_, values) =
+            map_in_process(&mut interpreter, "shift", "(list (make-point 1 2))").unwrap();
+        interpreter
+            .env()
+            .borrow_mut()
+            .define("result", values[0].clone())
+            .unwrap();
+        assert_eq!(
+            interpreter
+                .eval(
+                    "(define shifted (car (cdr result)))
+                     (define made (car (cdr (cdr result))))
+                     (list (car result) (point? shifted) (point-y shifted) (point-x made) (point-label made))"
+                )
+                .unwrap()
+                .to_string(),
+            "(true true 3 1 Void)"
+        );
+    }
+}
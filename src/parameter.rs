@@ -0,0 +1,190 @@
+// make-parameter と parameterize による動的な束縛。
+//
+//   (define precision (make-parameter 2))
+//   (define (show x) (print (precision)))
+//   (parameterize ((precision 5)) (show 1))   ; 5
+//   (show 1)                                  ; 2
+//
+// パラメーターは引数なしで呼ぶと今の値を返す。parameterize は本体を評価する間だけ値を変え、
+// 本体から戻るときに元の値に戻す。本体がエラーや raise、継続の呼び出しで抜けた場合も元に戻す。
+// make-parameter に変換用の関数を渡すと、初期値と parameterize で渡した値をその関数に通す。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::eval::{Env, apply, eval_obj};
+use crate::parser::{Foreign, Object};
+use crate::printer::{debug, debug_form};
+
+#[derive(Debug)]
+struct Parameter {
+    value: RefCell<Object>,
+    converter: Option<Object>,
+}
+
+impl Foreign for Parameter {
+    fn type_name(&self) -> &str {
+        "parameter"
+    }
+}
+
+fn as_parameter(obj: &Object) -> Option<&Parameter> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<Parameter>(),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_parameter(obj: &Object) -> bool {
+    as_parameter(obj).is_some()
+}
+
+// パラメーターを呼ぶ。func がパラメーターでなければ None。
+pub(crate) fn invoke(func: &Object, args: &[Object]) -> Option<Result<Object, String>> {
+    let parameter = as_parameter(func)?;
+    Some(match args {
+        [] => Ok(parameter.value.borrow().clone()),
+        _ => Err(format!(
+            "parameter: expected 0 arguments, got {}",
+            args.len()
+        )),
+    })
+}
+
+// (make-parameter value) または (make-parameter value converter)
+pub(crate) fn make_parameter(
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let (value, converter) = match args {
+        [value, converter] => (
+            apply(converter, std::slice::from_ref(value), env)
+                .map_err(|e| format!("make-parameter: {}", e))?,
+            Some(converter.clone()),
+        ),
+        _ => (args[0].clone(), None),
+    };
+    Ok(Object::Foreign(Rc::new(Parameter {
+        value: RefCell::new(value),
+        converter,
+    })))
+}
+
+// (parameterize ((param value) ...) body...)
+// 値はすべて評価してから入れ替えるので、値の式からは元の値が見える。
+pub(crate) fn eval_parameterize(
+    list: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let bindings = match list.get(1) {
+        Some(Object::List(bindings)) if list.len() >= 3 => bindings,
+        _ => return Err(format!("Invalid parameterize syntax: {}", debug_form(list))),
+    };
+    let mut new_values = Vec::with_capacity(bindings.len());
+    for binding in bindings.iter() {
+        let Object::List(pair) = binding else {
+            return Err(format!("Invalid parameterize binding: {}", debug(binding)));
+        };
+        let [param, value] = pair.as_slice() else {
+            return Err(format!("Invalid parameterize binding: {}", debug(binding)));
+        };
+        let param = eval_obj(param, env)?;
+        let Some(parameter) = as_parameter(&param) else {
+            return Err(format!("parameterize: expected a parameter, got {}", param));
+        };
+        let mut value = eval_obj(value, env)?;
+        if let Some(converter) = &parameter.converter {
+            value = apply(converter, &[value], env).map_err(|e| format!("parameterize: {}", e))?;
+        }
+        new_values.push((param, value));
+    }
+
+    let saved: Vec<Object> = new_values
+        .iter()
+        .map(|(param, value)| as_parameter(param).unwrap().value.replace(value.clone()))
+        .collect();
+    let mut body_env = Rc::new(RefCell::new(Env::extend(Rc::clone(env))));
+    let result = list[2..]
+        .iter()
+        .try_fold(Object::Void, |_, expr| eval_obj(expr, &mut body_env));
+    for ((param, _), old) in new_values.iter().zip(saved) {
+        *as_parameter(param).unwrap().value.borrow_mut() = old;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_parameterize() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define depth (make-parameter 0))
+            (define label (make-parameter 1 (lambda (x) (* x 100))))
+            (define (show) (list (depth) (label)))
+            (list
+                (show)
+                (parameterize ((depth 1) (label 2))
+                    (parameterize ((depth (+ (depth) 1)))
+                        (show)))
+                (show))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "((0 100) (2 200) (0 100))"
+        );
+        assert!(
+            interpreter
+                .eval("(parameterize ((depth 5)) (undefined))")
+                .is_err()
+        );
+        assert_eq!(interpreter.eval("(depth)"), Ok(Object::Integer(0)));
+        assert_eq!(
+            interpreter.eval("(parameterize ((show 1)) 1)"),
+            Err(
+                "parameterize: expected a parameter, got Lambda() (list (depth) (label))"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            interpreter.eval("(depth 1)"),
+            Err("parameter: expected 0 arguments, got 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parameterize_non_local_exit() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(define request-id (make-parameter 'none))")
+            .unwrap();
+        let program = "
+            (list
+                (try (parameterize ((request-id 1)) (raise 'failed))
+                    (catch (e) (list e (request-id))))
+                (call/cc (lambda (k)
+                    (parameterize ((request-id 2))
+                        (parameterize ((request-id 3)) (k (request-id))))))
+                (request-id)
+                (let ((g (make-generator (lambda (yield)
+                            (parameterize ((request-id 4)) (yield (request-id)))))))
+                    (list (g) (request-id))))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "((failed none) 3 none (4 none))"
+        );
+        assert_eq!(
+            interpreter.eval("(parameterize ((request-id 5)) (/ 1 0))"),
+            Err("Division by zero".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(request-id)").unwrap().to_string(),
+            "none"
+        );
+    }
+}
@@ -1,53 +1,344 @@
-use std::{error::Error, fmt, rc::Rc};
+use std::{any::Any, cell::RefCell, collections::HashMap, error::Error, fmt, ops::Range, rc::Rc};
 
-use crate::lexer::{Token, tokenize};
+use crate::bigint::BigInt;
+use crate::builtins::{ArgType, Builtin};
+use crate::eval::Env;
+use crate::keyword::{Op, SpecialForm};
+use crate::lexer::{LexError, Token, ends_inside_token, tokenize, tokenize_spanned};
+use crate::reader::{ReadTable, with_read_table};
+use crate::string::Str;
+use crate::syntax_rules::SyntaxRules;
 
-#[derive(Debug, Clone, PartialEq)]
+/// 文字列やリストなどの大きいペイロードは全て `Rc` 越しに共有する。
+/// `Object` の clone はポインタのコピーだけで済み、`size_of::<Object>()` は 24 bytes に収まる。
+/// 中身を書き換えたい場合は `Rc::make_mut` で copy-on-write にすること。
+/// 整数や真偽値、Void は値をそのまま持つので、算術のループで作っても確保は起きない。
+/// 確保が要る値のうち空のリストだけは `Object::nil()` で 1 つを共有する。
+/// 今後も型を足すので `#[non_exhaustive]` にしている。crate の外で match するときは `_` の分岐が要る。
+/// 同じ要素のリストは、ペアの並びでも `ListData` でも等しい (`PartialEq` を参照)。
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Object {
     Void,
-    Keyword(String),
-    BinaryOp(String),
+    Keyword(SpecialForm),
+    BinaryOp(Op),
     Integer(i64),
+    BigInt(Rc<BigInt>), // i64 に収まらない整数。i64 に収まる値は常に Integer (bigint.rs)
     Float(f64),
     Bool(bool),
-    String(String),
-    Symbol(String),
-    ListData(Vec<Object>), // 評価後のListというか、データというか、cdrとかの引数になるListのようなイメージ。
-    Lambda(Vec<String>, Vec<Object>),
+    Char(char),  // #\a
+    String(Str), // 部分文字列は元のバッファを共有する (string.rs)
+    Symbol(Rc<str>),
+    Vector(Rc<RefCell<Vec<Object>>>), // #(1 2 3)。vector-set! で書き換えられる (vector.rs)
+    ListData(Rc<Vec<Object>>), // 評価後のListというか、データというか、cdrとかの引数になるListのようなイメージ。
+    Lambda(Rc<Lambda>),
+    Macro(Rc<Lambda>), // define-macro で定義したマクロ。展開時に引数の式をそのまま受け取る
+    SyntaxRules(Rc<SyntaxRules>), // define-syntax で定義したマクロ
+    Promise(Rc<Promise>), // delay で作る遅延評価の値
+    Record(Rc<Record>), // define-record-type で定義した型の値
+    Pair(Rc<Pair>),    // cons や (1 . 2) で作るペア。cdr がリストでなくてもよい
     List(Rc<Vec<Object>>), // S式というかASTというかプログラムを表すList。
+    KeywordArg(Rc<str>), // #:name
+    Builtin(&'static Builtin),
+    Foreign(Rc<dyn Foreign>),
+}
+
+// env は lambda を評価した時点の Env で、呼び出し時の Env の親になる (レキシカルスコープ)。
+#[derive(Clone)]
+pub struct Lambda {
+    pub params: Vec<String>,
+    pub rest: Option<String>, // (x . rest) の rest。params より多い分の引数をリストで受け取る
+    pub types: Vec<ArgType>,  // ((x : integer) s) のように注釈した仮引数の型。注釈が無ければ空
+    pub name: Option<Rc<str>>, // (define (f x) ...) の f。型が合わないときのエラーに使う
+    pub body: Rc<Vec<Object>>, // 本体の式の並び
+    pub env: Rc<RefCell<Env>>,
+}
+
+// Env は循環参照になりうるので、Debug には出さず、比較も同じ Env を指しているかで行う。
+impl fmt::Debug for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lambda")
+            .field("params", &self.params)
+            .field("rest", &self.rest)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params
+            && self.rest == other.rest
+            && self.types == other.types
+            && self.body == other.body
+            && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+// delay した式。最初に force したときに評価して、その値を覚えておく。
+pub struct Promise {
+    pub(crate) state: RefCell<PromiseState>,
+}
+
+pub(crate) enum PromiseState {
+    Delayed(Object, Rc<RefCell<Env>>),
+    Forced(Object),
+}
+
+impl fmt::Debug for Promise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.state.borrow() {
+            PromiseState::Delayed(expr, _) => write!(f, "Promise(delayed {})", expr),
+            PromiseState::Forced(value) => write!(f, "Promise(forced {})", value),
+        }
+    }
+}
+
+impl PartialEq for Promise {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// define-record-type で定義した型。fields はフィールドの名前で、Record の値と同じ順に並ぶ。
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+// フィールドは set-point-x! などで書き換えられるので、比較は同じ値を指しているかで行う。
+#[derive(Debug)]
+pub struct Record {
+    pub record_type: Rc<RecordType>,
+    pub(crate) values: RefCell<Vec<Object>>,
+}
+
+impl Record {
+    pub fn get(&self, field: &str) -> Option<Object> {
+        let index = self.record_type.fields.iter().position(|f| f == field)?;
+        Some(self.values.borrow()[index].clone())
+    }
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// cdr をたどった先が空のリストか、要素のあるリストなら真リストとして表示する。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pair {
+    pub car: Object,
+    pub cdr: Object,
+}
+
+// 長いリストを再帰で解放するとスタックが溢れるので、ほかから参照されていない cdr をループで解放する。
+impl Drop for Pair {
+    fn drop(&mut self) {
+        let mut rest = std::mem::replace(&mut self.cdr, Object::Void);
+        while let Object::Pair(pair) = rest {
+            match Rc::try_unwrap(pair) {
+                Ok(mut pair) => rest = std::mem::replace(&mut pair.cdr, Object::Void),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+// (list 1 2) のペアの並びと、string-split などが返す Vec のリストは、要素が同じなら等しい。
+// 式の List とデータの ListData は区別する。ペアどうしは cdr をたどって比べるので、長いリストでも再帰しない。
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Void, Object::Void) => true,
+            (Object::Keyword(a), Object::Keyword(b)) => a == b,
+            (Object::BinaryOp(a), Object::BinaryOp(b)) => a == b,
+            (Object::Integer(a), Object::Integer(b)) => a == b,
+            (Object::BigInt(a), Object::BigInt(b)) => a == b,
+            (Object::Float(a), Object::Float(b)) => a == b,
+            (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::Char(a), Object::Char(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Symbol(a), Object::Symbol(b)) => a == b,
+            (Object::Vector(a), Object::Vector(b)) => a == b,
+            (Object::ListData(a), Object::ListData(b)) => a == b,
+            (Object::Lambda(a), Object::Lambda(b)) => a == b,
+            (Object::Macro(a), Object::Macro(b)) => a == b,
+            (Object::SyntaxRules(a), Object::SyntaxRules(b)) => a == b,
+            (Object::Promise(a), Object::Promise(b)) => a == b,
+            (Object::Record(a), Object::Record(b)) => a == b,
+            (Object::Pair(_), Object::Pair(_) | Object::ListData(_))
+            | (Object::ListData(_), Object::Pair(_)) => list_eq(self, other),
+            (Object::List(a), Object::List(b)) => a == b,
+            (Object::KeywordArg(a), Object::KeywordArg(b)) => a == b,
+            (Object::Builtin(a), Object::Builtin(b)) => a == b,
+            (Object::Foreign(a), Object::Foreign(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// 先頭の要素と残り。Vec のリストは残りを添字で持つので、コピーせずにたどれる。
+enum Spine<'a> {
+    Pair(&'a Pair),
+    Items(&'a [Object]),
+    Tail(&'a Object),
 }
 
+impl<'a> Spine<'a> {
+    fn new(obj: &'a Object) -> Spine<'a> {
+        match obj {
+            Object::Pair(pair) => Spine::Pair(pair),
+            Object::ListData(list) => Spine::Items(list),
+            obj => Spine::Tail(obj),
+        }
+    }
+}
+
+// cdr をたどった最後が空のリストか Vec のリストなら真。
+fn is_proper_list(obj: &Object) -> bool {
+    let mut rest = obj;
+    while let Object::Pair(pair) = rest {
+        rest = &pair.cdr;
+    }
+    matches!(rest, Object::ListData(_))
+}
+
+fn list_eq(a: &Object, b: &Object) -> bool {
+    let (mut a, mut b) = (Spine::new(a), Spine::new(b));
+    loop {
+        let (car_a, car_b);
+        (car_a, a) = match a {
+            Spine::Pair(pair) => (&pair.car, Spine::new(&pair.cdr)),
+            Spine::Items([first, rest @ ..]) => (first, Spine::Items(rest)),
+            Spine::Items([]) => return matches!(b, Spine::Items([])),
+            Spine::Tail(tail) => return matches!(b, Spine::Tail(other) if tail == other),
+        };
+        (car_b, b) = match b {
+            Spine::Pair(pair) => (&pair.car, Spine::new(&pair.cdr)),
+            Spine::Items([first, rest @ ..]) => (first, Spine::Items(rest)),
+            _ => return false,
+        };
+        if car_a != car_b {
+            return false;
+        }
+    }
+}
+
+/// WebSocket の接続など、Rust 側の値を Lisp の値として持ち回るためのトレイト。
+/// 取り出すときは `&dyn Any` にアップキャストして `downcast_ref` する。
+pub trait Foreign: fmt::Debug + Any {
+    fn type_name(&self) -> &str;
+}
+
+impl PartialEq for dyn Foreign {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+// 型ごとの書き方は printer.rs にまとめてある。
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        crate::printer::write_object(f, self, crate::printer::Mode::Display)
+    }
+}
+
+thread_local! {
+    static NIL: Rc<Vec<Object>> = Rc::new(Vec::new());
+}
+
+impl Object {
+    // 空のリスト。'() や () を評価した値で、再帰でリストをたどるときの終わりになる。
+    // 同じスレッドでは 1 つの Rc を共有するので、作るたびに確保しない。
+    pub fn nil() -> Object {
+        NIL.with(|nil| Object::ListData(Rc::clone(nil)))
+    }
+
+    // 述語の結果などの真偽値。Bool は確保しないので、nil と違って共有する値は持たない。
+    pub fn truth(b: bool) -> Object {
+        Object::Bool(b)
+    }
+
+    // 空のリストか。式としての () も含む。
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Object::ListData(list) | Object::List(list) if list.is_empty())
+    }
+
+    // エラーメッセージやエディタ向けの評価結果で使う型の名前。
+    pub fn type_name(&self) -> &str {
         match self {
-            Object::Void => write!(f, "Void"),
-            Object::Keyword(s) => write!(f, "{}", s),
-            Object::BinaryOp(s) => write!(f, "{}", s),
-            Object::Integer(i) => write!(f, "{}", i),
-            Object::Float(fl) => write!(f, "{}", fl),
-            Object::Bool(b) => write!(f, "{}", b),
-            Object::String(s) => write!(f, "{}", s),
-            Object::Symbol(s) => write!(f, "{}", s),
-            Object::Lambda(params, body) => {
-                let params_str = params.join(" ");
-                let body_str: Vec<String> = body.iter().map(|obj| format!("{}", obj)).collect();
-                write!(f, "Lambda({}) {}", params_str, body_str.join(" "))
-            }
-            Object::List(list) => {
-                let elements: Vec<String> = list.iter().map(|obj| format!("{}", obj)).collect();
-                write!(f, "({})", elements.join(" "))
-            }
-            Object::ListData(list) => {
-                let elements: Vec<String> = list.iter().map(|obj| format!("{}", obj)).collect();
-                write!(f, "({})", elements.join(" "))
-            }
+            Object::Void => "void",
+            Object::Integer(_) | Object::BigInt(_) => "integer",
+            Object::Float(_) => "float",
+            Object::Bool(_) => "boolean",
+            Object::Char(_) => "char",
+            Object::String(_) => "string",
+            Object::Symbol(_) => "symbol",
+            Object::ListData(_) => "list",
+            Object::Vector(_) => "vector",
+            Object::Pair(_) if is_proper_list(self) => "list",
+            Object::Pair(_) => "pair",
+            Object::Lambda(_) | Object::Builtin(_) => "procedure",
+            Object::Macro(_) | Object::SyntaxRules(_) => "macro",
+            Object::Promise(_) => "promise",
+            Object::Record(record) => &record.record_type.name,
+            Object::KeywordArg(_) => "keyword",
+            Object::Keyword(_) | Object::BinaryOp(_) | Object::List(_) => "syntax",
+            Object::Foreign(foreign) => foreign.type_name(),
         }
     }
+
+    // parse で読み戻すと同じ値になる表記。Display と違って文字列は "..." で囲み、(quote x) は 'x と書く。
+    // lambda のように表記の無い値は読み戻せない。
+    pub fn to_write_string(&self) -> String {
+        crate::printer::to_string(self, crate::printer::Mode::Write)
+    }
+
+    // to_write_string と同じ表記で、width に収まらないリストは要素ごとに改行して字下げする。
+    pub fn pretty(&self, width: usize) -> String {
+        crate::printer::pretty(self, width)
+    }
+}
+
+// ソース上のバイト範囲
+pub type Span = Range<usize>;
+
+// offset の位置の行と列。どちらも 1 から数え、列は文字単位で数える。
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+// 読んだリストのソース上の範囲。リストの Rc のアドレスで引くので、読んだ式を持っている間だけ使える。
+// マクロ展開などで作り直したリストは載っていない。
+#[derive(Debug, Default)]
+pub(crate) struct SourceMap {
+    lists: HashMap<*const Vec<Object>, Span>,
+}
+
+impl SourceMap {
+    pub(crate) fn span(&self, list: *const Vec<Object>) -> Option<Span> {
+        self.lists.get(&list).cloned()
+    }
 }
 
+// トップレベルの式と、そのソース上の範囲
+type Forms = Vec<(Object, Span)>;
+
+// 読んだリストのアドレスと、その最初と最後のトークンの位置。位置は残りのトークンの数で数える。
+type ListTokens = Vec<(*const Vec<Object>, usize, usize)>;
+
 #[derive(Debug)]
 pub struct ParseError {
     message: String,
+    incomplete: bool, // 式の途中で入力が終わった。続きを足せば読めるかもしれない
 }
 
 impl fmt::Display for ParseError {
@@ -58,50 +349,256 @@ impl fmt::Display for ParseError {
 
 impl Error for ParseError {}
 
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        ParseError::new(&e.to_string())
+    }
+}
+
+impl ParseError {
+    pub(crate) fn new(message: &str) -> Self {
+        ParseError {
+            message: message.to_string(),
+            incomplete: false,
+        }
+    }
+
+    fn end_of_input(message: &str) -> Self {
+        ParseError {
+            message: message.to_string(),
+            incomplete: true,
+        }
+    }
+
+    // 閉じていない括弧のように、入力が式の途中で終わったために読めなかったか。
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+}
+
 pub fn parse(program: &str) -> Result<Object, ParseError> {
-    let mut tokens = tokenize(program);
+    let mut tokens = tokenize(program)?;
     tokens.reverse(); // トークンを逆順にしてスタックのように扱う
-    let parsed_list = parse_list(&mut tokens)?;
-    Ok(parsed_list)
+    let parsed = parse_expr(&mut tokens, &mut Vec::new())?;
+    Ok(parsed)
+}
+
+// プログラム中のトップレベルの式をすべて読む。(define x 1) (+ x 1) のような並びを begin で囲まずに読める。
+// parse は最初の式しか読まない。
+pub fn parse_all(program: &str) -> Result<Vec<Object>, ParseError> {
+    parse_spanned(program)
+        .map(|forms| forms.into_iter().map(|(obj, _)| obj).collect())
+        .map_err(|(e, _)| e)
+}
+
+// プログラム中のトップレベルの式をすべて読み、それぞれのソース上のバイト範囲と一緒に返す。
+// 読めなかった場合は、読めなかった式の先頭から入力の最後までを範囲とする。
+pub(crate) fn parse_spanned(program: &str) -> Result<Forms, (ParseError, Span)> {
+    parse_mapped(program).map(|(forms, _)| forms)
+}
+
+// parse_spanned と同じように読み、式の中のリストの範囲も返す。
+pub(crate) fn parse_mapped(program: &str) -> Result<(Forms, SourceMap), (ParseError, Span)> {
+    // 読めないトークンは、その位置から入力の最後までを範囲とする
+    let tokens = tokenize_spanned(program).map_err(|e| {
+        let pos = e.pos;
+        (ParseError::from(e), pos..program.len())
+    })?;
+    let (mut tokens, spans): (Vec<Token>, Vec<Span>) = tokens.into_iter().unzip();
+    tokens.reverse();
+    // 残りのトークンの数から、そのトークンのソース上の範囲を引く
+    let span = |remaining: usize| &spans[spans.len() - remaining];
+    let mut forms = Vec::new();
+    let mut lists = Vec::new();
+    while !tokens.is_empty() {
+        let start = span(tokens.len()).start;
+        match parse_expr(&mut tokens, &mut lists) {
+            Ok(obj) => {
+                let end = span(tokens.len() + 1).end;
+                forms.push((obj, start..end));
+            }
+            Err(e) => return Err((e, start..program.len())),
+        }
+    }
+    let lists = lists
+        .into_iter()
+        .map(|(list, first, last)| (list, span(first).start..span(last).end))
+        .collect();
+    Ok((forms, SourceMap { lists }))
+}
+
+// リストに限らず 1 つの式を読む。'expr は (quote expr) に展開する。
+fn parse_expr(tokens: &mut Vec<Token>, lists: &mut ListTokens) -> Result<Object, ParseError> {
+    let first = tokens.len();
+    let token = match tokens.pop() {
+        Some(token) => token,
+        None => {
+            return Err(ParseError::end_of_input("Unexpected end of input"));
+        }
+    };
+    let obj = match token {
+        Token::Integer(i) => Object::Integer(i),
+        Token::Float(f) => Object::Float(f),
+        Token::String(s) => Object::String(s.into()),
+        Token::Char(c) => Object::Char(c),
+        Token::Bool(b) => Object::truth(b),
+        Token::Symbol(s) => Object::Symbol(s.into()),
+        Token::LParen => {
+            tokens.push(Token::LParen);
+            parse_list(tokens, lists)?
+        }
+        Token::VectorOpen => parse_vector(tokens, lists)?,
+        Token::RParen => {
+            return Err(ParseError::new("Unexpected ')'"));
+        }
+        Token::Quote => prefixed(SpecialForm::Quote, tokens, lists)?,
+        Token::Quasiquote => prefixed(SpecialForm::Quasiquote, tokens, lists)?,
+        Token::Unquote => prefixed(SpecialForm::Unquote, tokens, lists)?,
+        Token::UnquoteSplicing => prefixed(SpecialForm::UnquoteSplicing, tokens, lists)?,
+        Token::BinaryOp(op) => Object::BinaryOp(op),
+        Token::Keyword(kw) => Object::Keyword(kw),
+        Token::KeywordArg(kw) => Object::KeywordArg(kw.into()),
+        Token::Datum(obj) => obj,
+    };
+    if let Object::List(list) = &obj {
+        lists.push((Rc::as_ptr(list), first, tokens.len() + 1));
+    }
+    Ok(obj)
 }
 
-fn parse_list(tokens: &mut Vec<Token>) -> Result<Object, ParseError> {
+// 'x や `x、,x、,@x を (quote x) などの形にする。
+fn prefixed(
+    keyword: SpecialForm,
+    tokens: &mut Vec<Token>,
+    lists: &mut ListTokens,
+) -> Result<Object, ParseError> {
+    let expr = parse_expr(tokens, lists)?;
+    Ok(Object::List(Rc::new(vec![Object::Keyword(keyword), expr])))
+}
+
+fn parse_list(tokens: &mut Vec<Token>, lists: &mut ListTokens) -> Result<Object, ParseError> {
     let token = tokens.pop();
     if token != Some(Token::LParen) {
-        return Err(ParseError {
-            message: "Expected '(' at the beginning of list".to_string(),
-        });
+        return Err(ParseError::new("Expected '(' at the beginning of list"));
     }
     let mut list: Vec<Object> = Vec::new();
-    while !tokens.is_empty() {
-        let token = tokens.pop();
-        if token.is_none() {
-            return Err(ParseError {
-                message: "Unexpected end of input while parsing list".to_string(),
-            });
-        }
-
-        let t = token.unwrap();
-        match t {
-            Token::Integer(i) => list.push(Object::Integer(i)),
-            Token::Float(f) => list.push(Object::Float(f)),
-            Token::String(s) => list.push(Object::String(s)),
-            Token::Symbol(s) => list.push(Object::Symbol(s)),
-            Token::LParen => {
-                tokens.push(Token::LParen);
-                let sublist = parse_list(tokens)?;
-                list.push(sublist);
-            }
+    while let Some(token) = tokens.last() {
+        match token {
             Token::RParen => {
+                tokens.pop();
                 return Ok(Object::List(Rc::new(list)));
             }
-            Token::BinaryOp(op) => list.push(Object::BinaryOp(op)),
-            Token::Keyword(kw) => list.push(Object::Keyword(kw)),
+            Token::Symbol(s) if s == "." => {
+                tokens.pop();
+                let tail = parse_expr(tokens, lists)?;
+                match tokens.pop() {
+                    Some(Token::RParen) if !list.is_empty() => {}
+                    None if !list.is_empty() => {
+                        return Err(ParseError::end_of_input(
+                            "Expected one expression after '.'",
+                        ));
+                    }
+                    _ => return Err(ParseError::new("Expected one expression after '.'")),
+                }
+                // (a . (b c)) の (b c) は dotted で作り直して捨てるので、範囲も消す
+                if let Object::List(rest) = &tail {
+                    let rest = Rc::as_ptr(rest);
+                    lists.retain(|(list, _, _)| *list != rest);
+                }
+                return Ok(dotted(list, tail));
+            }
+            _ => list.push(parse_expr(tokens, lists)?),
+        }
+    }
+    Err(ParseError::end_of_input("Expected ')' at the end of list"))
+}
+
+// #( は読んだ後。要素は式のまま持ち、評価するときにデータにする。
+fn parse_vector(tokens: &mut Vec<Token>, lists: &mut ListTokens) -> Result<Object, ParseError> {
+    let mut items = Vec::new();
+    while let Some(token) = tokens.last() {
+        if *token == Token::RParen {
+            tokens.pop();
+            return Ok(Object::Vector(Rc::new(RefCell::new(items))));
+        }
+        items.push(parse_expr(tokens, lists)?);
+    }
+    Err(ParseError::end_of_input(
+        "Expected ')' at the end of vector",
+    ))
+}
+
+// 少しずつ届く入力を、続きと合わせて読む。REPL に 1 行ずつ入力するときのように、式の途中で切れた入力に使う。
+//
+//   let mut parser = Parser::new();
+//   parser.feed("(define (f x)\n")   // NeedMoreInput
+//   parser.feed("  (* x 2))\n")      // Complete { source: "(define (f x)\n  (* x 2))\n", forms }
+//
+// 続きが要るかどうかは読んだ結果で決めるので、文字列の中の括弧やコメント、エスケープも parse と同じに扱う。
+// Complete か Error を返したら、それまでの入力は捨てて次の式を読む。
+#[derive(Debug, Default)]
+pub struct Parser {
+    source: String,
+    read_table: ReadTable,
+}
+
+#[derive(Debug)]
+pub enum Parsed {
+    NeedMoreInput,
+    Complete { source: String, forms: Vec<Object> },
+    Error { source: String, error: ParseError },
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser::default()
+    }
+
+    // #d のようなリーダーマクロも読む。Interpreter::parser で、その Interpreter の読み取り表で作る。
+    pub fn with_read_table(read_table: ReadTable) -> Self {
+        Parser {
+            source: String::new(),
+            read_table,
+        }
+    }
+
+    // まだ読み終えていない入力が無いか
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    pub fn feed(&mut self, chunk: &str) -> Parsed {
+        self.source.push_str(chunk);
+        let (parsed, truncated) = with_read_table(&self.read_table, || {
+            (parse_all(&self.source), ends_inside_token(&self.source))
+        });
+        match parsed {
+            _ if truncated => Parsed::NeedMoreInput,
+            Err(e) if e.is_incomplete() => Parsed::NeedMoreInput,
+            Ok(forms) => Parsed::Complete {
+                source: std::mem::take(&mut self.source),
+                forms,
+            },
+            Err(error) => Parsed::Error {
+                source: std::mem::take(&mut self.source),
+                error,
+            },
+        }
+    }
+}
+
+// (a b . tail) を作る。tail がリストなら (a . (b c)) は (a b c) と同じなので、ただのリストにする。
+pub(crate) fn dotted(mut items: Vec<Object>, tail: Object) -> Object {
+    match tail {
+        Object::List(rest) => {
+            items.extend(rest.iter().cloned());
+            Object::List(Rc::new(items))
         }
+        tail => items
+            .into_iter()
+            .rev()
+            .fold(tail, |cdr, car| Object::Pair(Rc::new(Pair { car, cdr }))),
     }
-    Err(ParseError {
-        message: "Expected ')' at the end of list".to_string(),
-    })
 }
 
 #[cfg(test)]
@@ -114,13 +611,67 @@ mod tests {
         assert_eq!(
             list,
             Object::List(Rc::new(vec![
-                Object::BinaryOp("+".to_string()),
+                Object::BinaryOp(Op::Add),
                 Object::Integer(1),
                 Object::Integer(2),
             ]))
         );
     }
 
+    #[test]
+    fn test_dotted() {
+        let pair = |car, cdr| Object::Pair(Rc::new(Pair { car, cdr }));
+        assert_eq!(
+            parse("(1 . 2)").unwrap(),
+            pair(Object::Integer(1), Object::Integer(2))
+        );
+        assert_eq!(
+            parse("(a b . c)").unwrap(),
+            pair(
+                Object::Symbol("a".into()),
+                pair(Object::Symbol("b".into()), Object::Symbol("c".into()))
+            )
+        );
+        assert_eq!(parse("(a . (b c))").unwrap(), parse("(a b c)").unwrap());
+        assert_eq!(parse("(1 2 . 3)").unwrap().to_string(), "(1 2 . 3)");
+        assert!(parse("(. 1)").is_err());
+        assert!(parse("(1 . 2 3)").is_err());
+        assert!(parse("(1 .)").is_err());
+    }
+
+    #[test]
+    fn test_quote_shorthand() {
+        let quote = |obj| Object::List(Rc::new(vec![Object::Keyword(SpecialForm::Quote), obj]));
+        assert_eq!(parse("'x").unwrap(), quote(Object::Symbol("x".into())));
+        assert_eq!(
+            parse("(f '(1 'a))").unwrap(),
+            Object::List(Rc::new(vec![
+                Object::Symbol("f".into()),
+                quote(Object::List(Rc::new(vec![
+                    Object::Integer(1),
+                    quote(Object::Symbol("a".into())),
+                ]))),
+            ]))
+        );
+        assert!(parse("'").is_err());
+        assert!(parse(")").is_err());
+    }
+
+    #[test]
+    fn test_object_size() {
+        assert!(std::mem::size_of::<Object>() <= 24);
+    }
+
+    #[test]
+    fn test_canonical_values() {
+        let (Object::ListData(a), Object::ListData(b)) = (Object::nil(), Object::nil()) else {
+            panic!("nil should be an empty ListData");
+        };
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(Object::truth(true), Object::Bool(true));
+        assert_eq!(Object::truth(false).to_string(), "false");
+    }
+
     #[test]
     fn test_area_of_a_circle() {
         let program = "(
@@ -133,25 +684,108 @@ mod tests {
             list,
             Object::List(Rc::new(vec![
                 Object::List(Rc::new(vec![
-                    Object::Keyword("define".to_string()),
-                    Object::Symbol("r".to_string()),
+                    Object::Keyword(SpecialForm::Define),
+                    Object::Symbol("r".into()),
                     Object::Integer(10),
                 ])),
                 Object::List(Rc::new(vec![
-                    Object::Keyword("define".to_string()),
-                    Object::Symbol("pi".to_string()),
+                    Object::Keyword(SpecialForm::Define),
+                    Object::Symbol("pi".into()),
                     Object::Integer(314),
                 ])),
                 Object::List(Rc::new(vec![
-                    Object::BinaryOp("*".to_string()),
-                    Object::Symbol("pi".to_string()),
+                    Object::BinaryOp(Op::Mul),
+                    Object::Symbol("pi".into()),
                     Object::List(Rc::new(vec![
-                        Object::BinaryOp("*".to_string()),
-                        Object::Symbol("r".to_string()),
-                        Object::Symbol("r".to_string()),
+                        Object::BinaryOp(Op::Mul),
+                        Object::Symbol("r".into()),
+                        Object::Symbol("r".into()),
                     ])),
                 ])),
             ]))
         );
     }
+
+    #[test]
+    fn test_parse_spanned() {
+        let program = " (define x 1)\n'x 42 ";
+        let forms = parse_spanned(program).unwrap();
+        let spans: Vec<&str> = forms
+            .iter()
+            .map(|(_, span)| &program[span.clone()])
+            .collect();
+        assert_eq!(spans, ["(define x 1)", "'x", "42"]);
+        assert_eq!(forms[2].0, Object::Integer(42));
+
+        let (_, span) = parse_spanned("(+ 1 2) (f (g x)").unwrap_err();
+        assert_eq!(span, 8..16);
+        assert!(parse_spanned("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let forms = parse_all("(define x 1) (+ x 1)\n'y 2").unwrap();
+        let forms: Vec<String> = forms.iter().map(|obj| obj.to_string()).collect();
+        assert_eq!(forms, ["(define x 1)", "(+ x 1)", "(quote y)", "2"]);
+        assert!(parse_all("").unwrap().is_empty());
+        assert!(parse_all("(define x 1) (+ x").is_err());
+        // parse は最初の式だけを読む
+        assert_eq!(parse("1 2").unwrap(), Object::Integer(1));
+    }
+
+    #[test]
+    fn test_incremental_parser() {
+        let mut parser = Parser::new();
+        let mut feed = |chunk: &str| match parser.feed(chunk) {
+            Parsed::NeedMoreInput => "more".to_string(),
+            Parsed::Complete { source, forms } => {
+                let forms: Vec<String> = forms.iter().map(|obj| obj.to_string()).collect();
+                format!("{:?} {}", source, forms.join(" "))
+            }
+            Parsed::Error { source, error } => format!("{:?} {}", source, error),
+        };
+        assert_eq!(feed("(define (f x)\n"), "more");
+        assert_eq!(
+            feed("  (* x 2)) 1\n"),
+            "\"(define (f x)\\n  (* x 2)) 1\\n\" (define (f x) (* x 2)) 1"
+        );
+        // 文字列やコメントの中の括弧は数えない
+        assert_eq!(feed("(f \"(\" ; )\n"), "more");
+        assert_eq!(feed("#| ) |# x \"\n"), "more");
+        assert_eq!(
+            feed("\")"),
+            "\"(f \\\"(\\\" ; )\\n#| ) |# x \\\"\\n\\\")\" (f ( x \n)"
+        );
+        assert_eq!(feed("'"), "more");
+        assert_eq!(feed("x"), "\"'x\" (quote x)");
+        assert_eq!(feed("(1 . 2"), "more");
+        assert_eq!(feed(")"), "\"(1 . 2)\" (1 . 2)");
+        assert_eq!(
+            feed("(f #zz"),
+            "\"(f #zz\" ParseError: Invalid # syntax: #zz"
+        );
+        assert_eq!(feed(")"), "\")\" ParseError: Unexpected ')'");
+        assert_eq!(feed(" "), "\" \" ");
+    }
+
+    #[test]
+    fn test_source_map() {
+        let program = "(f 'x\n   (g (h) . (i)))";
+        let (forms, source_map) = parse_mapped(program).unwrap();
+        let Object::List(f) = &forms[0].0 else {
+            panic!("expected a list")
+        };
+        let span_of = |obj: &Object| match obj {
+            Object::List(list) => source_map.span(Rc::as_ptr(list)).map(|s| &program[s]),
+            _ => None,
+        };
+        assert_eq!(span_of(&forms[0].0), Some(program));
+        assert_eq!(span_of(&f[1]), Some("'x"));
+        assert_eq!(span_of(&f[2]), Some("(g (h) . (i))"));
+        assert_eq!(source_map.lists.len(), 4);
+
+        assert_eq!(line_col(program, 0), (1, 1));
+        assert_eq!(line_col(program, 9), (2, 4));
+        assert_eq!(line_col("λ x", 3), (1, 3));
+    }
 }
@@ -0,0 +1,295 @@
+// ディレクトリに置いた .lisp ファイルをプラグインとして読み込み、ホストのイベントを渡す仕組み。
+//
+// プラグインはそれぞれ大域の Env の子の Env で評価するので、プラグインの define は他のプラグインから見えない。
+// ホストが Interpreter に定義した関数や変数はすべてのプラグインから使える。
+// fuel や MR_LISP_PATH、読み取り表などの設定も、ホストの Interpreter のものがプラグインの評価に効く。
+// 次の名前の関数を定義しておくと、ホストから呼ばれる。
+//   (on-load)          読み込んだ直後
+//   (on-unload)        取り除く直前
+//   (on-<event> ...)   emit でイベントを送ったとき。(on-tick dt) など
+// 名前とバージョン、必要な機能は manifest のヘッダーで書く。名前を書かなければファイル名を使う。
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::eval::{Env, apply, eval_toplevel};
+use crate::interpreter::Interpreter;
+use crate::manifest::{self, Manifest};
+use crate::parser::{Object, parse_spanned};
+
+pub struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+    pub manifest: Manifest,
+    env: Rc<RefCell<Env>>,
+}
+
+impl Plugin {
+    pub fn env(&mut self) -> &mut Rc<RefCell<Env>> {
+        &mut self.env
+    }
+
+    // プラグイン自身が定義した関数を、interpreter の fuel などの設定で呼ぶ。定義されていなければ None。
+    fn call_hook(
+        &mut self,
+        interpreter: &Interpreter,
+        hook: &str,
+        args: &[Object],
+    ) -> Option<Result<Object, String>> {
+        let func = self.env.borrow().get_local(hook)?;
+        let result = interpreter.run_in(&mut self.env, |env| apply(&func, args, env));
+        Some(result.map_err(|e| format!("{}: {}: {}", self.name, hook, e)))
+    }
+}
+
+pub struct PluginHost {
+    interpreter: Interpreter,
+    plugins: Vec<Plugin>,
+    capabilities: Vec<String>, // requires と比べる機能。manifest::FEATURES にホストが足したもの
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        PluginHost {
+            interpreter: Interpreter::new(),
+            plugins: Vec::new(),
+            capabilities: manifest::FEATURES.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    // プラグインに共通の関数や変数は、この Interpreter で定義する。
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    // ホストが提供する機能の名前を足す。プラグインの requires に書ける。
+    pub fn add_capability(&mut self, name: &str) {
+        self.capabilities.push(name.to_string());
+    }
+
+    pub fn plugins(&self) -> &[Plugin] {
+        &self.plugins
+    }
+
+    pub fn plugin(&mut self, name: &str) -> Option<&mut Plugin> {
+        self.plugins.iter_mut().find(|p| p.name == name)
+    }
+
+    // dir の .lisp ファイルをファイル名の順に読み込む。読み込めなかったファイルはエラーとして返し、残りは続けて読む。
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Result<Vec<String>, String> {
+        let dir = dir.as_ref();
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| format!("{}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "lisp"))
+            .collect();
+        paths.sort();
+        let mut errors = Vec::new();
+        for path in paths {
+            if let Err(e) = self.load(&path) {
+                errors.push(e);
+            }
+        }
+        Ok(errors)
+    }
+
+    // ファイルを 1 つ読み込んで on-load を呼ぶ。同じ名前のプラグインがあれば、先に取り除いてから読み込む。
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<&mut Plugin, String> {
+        let path = path.as_ref();
+        let source =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let manifest = manifest::parse_str(&source);
+        let name = match &manifest.name {
+            Some(name) => name.clone(),
+            None => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let capabilities: Vec<&str> = self.capabilities.iter().map(String::as_str).collect();
+        let missing = manifest.missing(&capabilities);
+        if !missing.is_empty() {
+            return Err(format!("{}: requires {}", name, missing.join(", ")));
+        }
+        if self.plugins.iter().any(|p| p.name == name) {
+            self.unload(&name)?;
+        }
+
+        let mut env = Rc::new(RefCell::new(Env::extend(Rc::clone(self.interpreter.env()))));
+        self.interpreter
+            .run_in(&mut env, |env| {
+                let forms = parse_spanned(&source).map_err(|(e, _)| e.to_string())?;
+                for (form, _) in forms {
+                    eval_toplevel(&form, env)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: String| format!("{}: {}", name, e))?;
+        let mut plugin = Plugin {
+            name,
+            path: path.to_path_buf(),
+            manifest,
+            env,
+        };
+        if let Some(Err(e)) = plugin.call_hook(&self.interpreter, "on-load", &[]) {
+            return Err(e);
+        }
+        self.plugins.push(plugin);
+        Ok(self.plugins.last_mut().unwrap())
+    }
+
+    // on-unload を呼んでから取り除く。on-unload がエラーになっても取り除く。
+    pub fn unload(&mut self, name: &str) -> Result<(), String> {
+        let Some(i) = self.plugins.iter().position(|p| p.name == name) else {
+            return Err(format!("No such plugin: {}", name));
+        };
+        let mut plugin = self.plugins.remove(i);
+        match plugin.call_hook(&self.interpreter, "on-unload", &[]) {
+            Some(Err(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn unload_all(&mut self) -> Vec<String> {
+        let names: Vec<String> = self.plugins.iter().map(|p| p.name.clone()).collect();
+        names
+            .iter()
+            .filter_map(|name| self.unload(name).err())
+            .collect()
+    }
+
+    // on-<event> を定義しているプラグインに、読み込んだ順で args を渡して呼ぶ。
+    // 呼んだプラグインの名前と結果を返す。
+    pub fn emit(&mut self, event: &str, args: &[Object]) -> Vec<(String, Result<Object, String>)> {
+        let hook = format!("on-{}", event);
+        let interpreter = &self.interpreter;
+        self.plugins
+            .iter_mut()
+            .filter_map(|plugin| {
+                let result = plugin.call_hook(interpreter, &hook, args)?;
+                Some((plugin.name.clone(), result))
+            })
+            .collect()
+    }
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_plugin_host() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-plugins-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.lisp"),
+            ";;; name: counter\n;;; version: 1.0\n\
+             (define count 0)\n\
+             (define (on-load) (set! loaded (+ loaded 1)))\n\
+             (define (on-tick n) (set! count (+ count n)) count)\n\
+             (define (on-unload) (set! loaded (- loaded 1)))",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b.lisp"),
+            "(define count 100)\n(define (on-tick n) (scale n))",
+        )
+        .unwrap();
+        fs::write(dir.join("c.lisp"), ";;; requires: teleport\n(f)").unwrap();
+        fs::write(dir.join("d.lisp"), "(undefined-function)").unwrap();
+        fs::write(dir.join("notes.txt"), "(not lisp").unwrap();
+
+        let mut host = PluginHost::new();
+        host.interpreter()
+            .eval("(define loaded 0) (define (scale n) (* n 10))")
+            .unwrap();
+        let errors = host.load_dir(&dir).unwrap();
+        assert_eq!(
+            errors,
+            [
+                "c: requires teleport".to_string(),
+                "d: Undefined function: undefined-function".to_string()
+            ]
+        );
+        let names: Vec<&str> = host.plugins().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["counter", "b"]);
+        assert_eq!(host.interpreter().eval("loaded"), Ok(Object::Integer(1)));
+
+        host.emit("tick", &[Object::Integer(2)]);
+        assert_eq!(
+            host.emit("tick", &[Object::Integer(3)]),
+            [
+                ("counter".to_string(), Ok(Object::Integer(5))),
+                ("b".to_string(), Ok(Object::Integer(30)))
+            ]
+        );
+        assert!(host.emit("quit", &[]).is_empty());
+        assert!(host.interpreter().eval("count").is_err());
+
+        host.add_capability("teleport");
+        assert!(host.load(dir.join("c.lisp")).is_err());
+        host.load(dir.join("a.lisp")).unwrap();
+        assert_eq!(host.plugins().len(), 2);
+        assert_eq!(host.interpreter().eval("loaded"), Ok(Object::Integer(1)));
+        assert_eq!(
+            host.emit("tick", &[Object::Integer(1)])[1],
+            ("counter".to_string(), Ok(Object::Integer(1)))
+        );
+
+        assert!(host.unload_all().is_empty());
+        assert_eq!(host.interpreter().eval("loaded"), Ok(Object::Integer(0)));
+        assert!(host.unload("counter").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_plugins_use_fuel() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-fuel-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("spin.lisp"),
+            "(define (loop) (loop))
+(define (on-load) (loop))",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("tick.lisp"),
+            "(define (loop) (loop))
+(define (on-tick) (loop))",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("top.lisp"),
+            "(define (loop) (loop))
+(loop)",
+        )
+        .unwrap();
+
+        let mut host = PluginHost::new();
+        host.interpreter().set_fuel(Some(1000));
+        let errors = host.load_dir(&dir).unwrap();
+        assert_eq!(
+            errors,
+            [
+                "spin: on-load: Out of fuel".to_string(),
+                "top: Out of fuel".to_string()
+            ]
+        );
+        assert_eq!(
+            host.emit("tick", &[]),
+            [(
+                "tick".to_string(),
+                Err("tick: on-tick: Out of fuel".to_string())
+            )]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,10 @@
+// 埋め込む側がふつう使う型。use mr_lisp::prelude::*; でまとめて読み込める。
+//
+// ここに並べる型は互換性を保つ対象にする。今後も増やす enum や struct には #[non_exhaustive] を付けているので、
+// crate の外では match に _ の分岐を書き、Config などは default() から作ってフィールドを書き換えること。
+// 他のモジュールにしかない関数や型は、版を上げるときに変わることがある。
+
+pub use crate::config::Config;
+pub use crate::eval::Env;
+pub use crate::interpreter::{ErrorKind, EvalResult, Interpreter, LispError};
+pub use crate::parser::Object;
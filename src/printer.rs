@@ -0,0 +1,422 @@
+// Object を文字列にする。表示の仕方ごとに Mode を分け、Object のすべての型の書き方をここの 1 か所に書く。
+//
+//   Display  print や Object の Display。文字列や文字は中身をそのまま書く
+//   Write    読み戻せる表記。文字列は "..."、文字は #\a、(quote x) は 'x のように書く。write-to-string で使う
+//   Debug    エラーメッセージで式や値を見せるときに使う。Write と同じだが、Void や lambda も #<...> で短く書く
+//   Pretty   REPL で結果を見せるときに使う。Write と同じ表記で、1 行に収まらないリストは要素ごとに改行して字下げする
+//
+// 型を足したら write_object に分岐を足す。match に _ の分岐は書かないので、足し忘れるとコンパイルできない。
+
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::keyword::SpecialForm;
+use crate::pair::pair_items;
+use crate::parser::{Lambda, Object};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Mode {
+    Display,
+    Write,
+    Debug,
+    Pretty,
+}
+
+// Pretty でこの幅を超える行は改行する。
+const WIDTH: usize = 80;
+
+pub fn to_string(obj: &Object, mode: Mode) -> String {
+    if mode == Mode::Pretty {
+        return pretty(obj, WIDTH);
+    }
+    let mut out = String::new();
+    write_object(&mut out, obj, mode).unwrap();
+    out
+}
+
+// Pretty と同じ書き方で、width を超える行を改行する。
+pub fn pretty(obj: &Object, width: usize) -> String {
+    let mut out = String::new();
+    write_pretty(&mut out, obj, 0, width);
+    out
+}
+
+// (write-to-string '("a" #\\b)) => "(\"a\" #\\b)"
+pub(crate) fn write_to_string(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    Ok(Object::String(to_string(&args[0], Mode::Write).into()))
+}
+
+// エラーメッセージ用。(define) のような式の並びを、リストとして Debug で書く。
+pub(crate) fn debug_form(list: &[Object]) -> String {
+    let mut out = String::new();
+    write_items(&mut out, list.iter(), None, Mode::Debug).unwrap();
+    out
+}
+
+pub(crate) fn debug(obj: &Object) -> String {
+    to_string(obj, Mode::Debug)
+}
+
+// (define name value) の形。Interpreter::dump_canonical で使う。評価すると同じ値になるように、データは ' を付け、
+// lambda は (lambda (x) ...) の式に、組み込み関数は名前に戻す。それ以外の表記の無い値は Debug で書く。
+pub(crate) fn write_definition(name: &str, value: &Object) -> String {
+    let mut out = format!("(define {} ", name);
+    match value {
+        Object::Symbol(_)
+        | Object::ListData(_)
+        | Object::List(_)
+        | Object::Pair(_)
+        | Object::Vector(_) => {
+            out.push('\'');
+            write_object(&mut out, value, Mode::Write).unwrap();
+        }
+        Object::Lambda(lambda) => out.push_str(&write_lambda(lambda)),
+        Object::Builtin(builtin) => out.push_str(builtin.name),
+        Object::Integer(_)
+        | Object::BigInt(_)
+        | Object::Float(_)
+        | Object::Bool(_)
+        | Object::Char(_)
+        | Object::String(_)
+        | Object::Keyword(_)
+        | Object::BinaryOp(_)
+        | Object::KeywordArg(_) => write_object(&mut out, value, Mode::Write).unwrap(),
+        _ => write_object(&mut out, value, Mode::Debug).unwrap(),
+    }
+    out.push(')');
+    out
+}
+
+// 評価すると同じ手続きになる (lambda (x) ...) の式。閉じ込めた環境は書かない。
+pub(crate) fn write_lambda(lambda: &Lambda) -> String {
+    let mut out = format!("(lambda {}", formals(lambda));
+    for expr in lambda.body.iter() {
+        out.push(' ');
+        write_object(&mut out, expr, Mode::Write).unwrap();
+    }
+    out.push(')');
+    out
+}
+
+pub(crate) fn write_object(out: &mut dyn Write, obj: &Object, mode: Mode) -> fmt::Result {
+    // Pretty の 1 行に収まる部分は Write と同じ
+    let mode = if mode == Mode::Pretty {
+        Mode::Write
+    } else {
+        mode
+    };
+    match obj {
+        Object::Void if mode == Mode::Debug => out.write_str("#<void>"),
+        Object::Void => out.write_str("Void"),
+        Object::Keyword(kw) => write!(out, "{}", kw),
+        Object::BinaryOp(op) => write!(out, "{}", op),
+        Object::Integer(i) => write!(out, "{}", i),
+        Object::BigInt(n) => write!(out, "{}", n),
+        Object::Float(f) => out.write_str(&format_float(*f)),
+        Object::Bool(b) => write!(out, "{}", b),
+        Object::Char(c) if mode == Mode::Display => out.write_char(*c),
+        Object::Char(c) => write_char_literal(out, *c),
+        Object::String(s) if mode == Mode::Display => out.write_str(s),
+        Object::String(s) => out.write_str(&write_string(s)),
+        Object::Symbol(s) => out.write_str(s),
+        Object::Lambda(lambda) if mode == Mode::Debug => {
+            write!(out, "#<lambda {}>", formals(lambda))
+        }
+        Object::Lambda(lambda) => {
+            write!(out, "Lambda({})", param_list(lambda))?;
+            for expr in lambda.body.iter() {
+                out.write_char(' ')?;
+                write_object(out, expr, mode)?;
+            }
+            Ok(())
+        }
+        Object::Macro(lambda) => write!(out, "#<macro {}>", formals(lambda)),
+        Object::SyntaxRules(_) => out.write_str("#<syntax-rules>"),
+        Object::Promise(_) => out.write_str("#<promise>"),
+        Object::Record(record) => {
+            write!(out, "#<{}", record.record_type.name)?;
+            for (field, value) in record
+                .record_type
+                .fields
+                .iter()
+                .zip(record.values.borrow().iter())
+            {
+                write!(out, " {}=", field)?;
+                write_object(out, value, mode)?;
+            }
+            out.write_char('>')
+        }
+        Object::Pair(_) => {
+            let (items, tail) = pair_items(obj);
+            match quote_prefix(&items) {
+                Some((prefix, quoted)) if mode == Mode::Write && tail.is_none() => {
+                    out.write_str(prefix)?;
+                    write_object(out, quoted, mode)
+                }
+                _ => write_items(out, items.iter(), tail.as_ref(), mode),
+            }
+        }
+        Object::List(list) | Object::ListData(list)
+            if mode == Mode::Write
+                && let Some((prefix, quoted)) = quote_prefix(list) =>
+        {
+            out.write_str(prefix)?;
+            write_object(out, quoted, mode)
+        }
+        Object::List(list) | Object::ListData(list) => write_items(out, list.iter(), None, mode),
+        Object::Vector(vector) => {
+            out.write_char('#')?;
+            write_items(out, vector.borrow().iter(), None, mode)
+        }
+        Object::KeywordArg(s) => write!(out, "#:{}", s),
+        Object::Builtin(builtin) => write!(out, "#<builtin {}>", builtin.name),
+        Object::Foreign(foreign) => write!(out, "#<{}>", foreign.type_name()),
+    }
+}
+
+// x y や x y . rest
+fn param_list(lambda: &Lambda) -> String {
+    let mut list = lambda.params.join(" ");
+    if let Some(rest) = &lambda.rest {
+        if !list.is_empty() {
+            list.push(' ');
+        }
+        list.push_str(". ");
+        list.push_str(rest);
+    }
+    list
+}
+
+// lambda に書いた仮引数の形。(x y)、(x . rest)、または残りの引数だけを受け取る args。
+pub(crate) fn formals(lambda: &Lambda) -> String {
+    match &lambda.rest {
+        Some(rest) if lambda.params.is_empty() => rest.clone(),
+        _ => format!("({})", param_list(lambda)),
+    }
+}
+
+// (a b c) や (a b . tail)
+fn write_items<'a>(
+    out: &mut dyn Write,
+    items: impl Iterator<Item = &'a Object>,
+    tail: Option<&Object>,
+    mode: Mode,
+) -> fmt::Result {
+    out.write_char('(')?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.write_char(' ')?;
+        }
+        write_object(out, item, mode)?;
+    }
+    if let Some(tail) = tail {
+        out.write_str(" . ")?;
+        write_object(out, tail, mode)?;
+    }
+    out.write_char(')')
+}
+
+// (quote x) なら ' と x。quasiquote、unquote、unquote-splicing も読むときの略記に戻す。
+fn quote_prefix(list: &[Object]) -> Option<(&'static str, &Object)> {
+    let [Object::Keyword(keyword), quoted] = list else {
+        return None;
+    };
+    let prefix = match keyword {
+        SpecialForm::Quote => "'",
+        SpecialForm::Quasiquote => "`",
+        SpecialForm::Unquote => ",",
+        SpecialForm::UnquoteSplicing => ",@",
+        _ => return None,
+    };
+    Some((prefix, quoted))
+}
+
+// 幅に収まらないリストは、先頭の要素の後で改行し、残りの要素を 1 行に 1 つずつ ( の次の列にそろえて書く。
+// '(...) のような略記の後のリストは、略記の次の列から書く。
+fn write_pretty(out: &mut String, obj: &Object, indent: usize, width: usize) {
+    let mut flat = String::new();
+    write_object(&mut flat, obj, Mode::Write).unwrap();
+    // ドットの付いたリストは 1 行で書く
+    let items: Vec<Object> = match obj {
+        Object::List(list) | Object::ListData(list) => list.to_vec(),
+        Object::Pair(_) => match pair_items(obj) {
+            (items, None) => items,
+            (_, Some(_)) => Vec::new(),
+        },
+        Object::Vector(vector) => vector.borrow().clone(),
+        _ => Vec::new(),
+    };
+    if !matches!(obj, Object::Vector(_))
+        && let Some((prefix, quoted)) = quote_prefix(&items)
+        && indent + flat.chars().count() > width
+    {
+        out.push_str(prefix);
+        write_pretty(out, quoted, indent + prefix.len(), width);
+        return;
+    }
+    if indent + flat.chars().count() <= width || items.len() < 2 {
+        out.push_str(&flat);
+        return;
+    }
+    let open = if matches!(obj, Object::Vector(_)) {
+        "#("
+    } else {
+        "("
+    };
+    out.push_str(open);
+    let indent = indent + open.len();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+        write_pretty(out, item, indent, width);
+    }
+    out.push(')');
+}
+
+fn write_char_literal(out: &mut dyn Write, c: char) -> fmt::Result {
+    match c {
+        ' ' => out.write_str("#\\space"),
+        '\n' => out.write_str("#\\newline"),
+        '\t' => out.write_str("#\\tab"),
+        c => write!(out, "#\\{}", c),
+    }
+}
+
+// 字句解析器で読み戻すと s になる文字列リテラル。" と \ は \" と \\ に書く。
+pub(crate) fn write_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+// 読み戻すと同じ値になる最短の表記。ロケールには依存しない。
+// 整数値でも小数点を付けて Integer と区別し、無限大と NaN は Scheme の表記に合わせる。
+pub(crate) fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        "+nan.0".to_string()
+    } else if f.is_infinite() {
+        if f > 0.0 { "+inf.0" } else { "-inf.0" }.to_string()
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_modes() {
+        let mut interpreter = Interpreter::new();
+        let value = interpreter
+            .eval("(list \"a b\" #\\x #\\space 1.0 'sym (cons 1 2) #(\"v\") (lambda (x) (* x x)))")
+            .unwrap();
+        assert_eq!(
+            to_string(&value, Mode::Display),
+            "(a b x   1.0 sym (1 . 2) #(v) Lambda(x) (* x x))"
+        );
+        assert_eq!(
+            to_string(&value, Mode::Write),
+            "(\"a b\" #\\x #\\space 1.0 sym (1 . 2) #(\"v\") Lambda(x) (* x x))"
+        );
+        assert_eq!(
+            to_string(&value, Mode::Debug),
+            "(\"a b\" #\\x #\\space 1.0 sym (1 . 2) #(\"v\") #<lambda (x)>)"
+        );
+        assert_eq!(value.to_string(), to_string(&value, Mode::Display));
+        assert_eq!(
+            interpreter.eval("(write-to-string (list \"s\" #\\a))"),
+            Ok(Object::String("(\"s\" #\\a)".into()))
+        );
+        assert_eq!(debug(&Object::Void), "#<void>");
+        assert_eq!(
+            interpreter.eval("(define)"),
+            Err("Invalid define syntax: (define)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pretty() {
+        let mut interpreter = Interpreter::new();
+        let short = interpreter.eval("'(a (b c) \"d\")").unwrap();
+        assert_eq!(to_string(&short, Mode::Pretty), "(a (b c) \"d\")");
+
+        let long = interpreter
+            .eval("(list 'first-element-of-a-long-list (make-vector 12 'element) \"end\")")
+            .unwrap();
+        assert_eq!(
+            to_string(&long, Mode::Pretty),
+            "(first-element-of-a-long-list\n #(element\n   element\n   element\n   element\n   element\n   element\n   element\n   element\n   element\n   element\n   element\n   element)\n \"end\")"
+        );
+    }
+
+    #[test]
+    fn test_write_reads_back() {
+        let mut interpreter = Interpreter::new();
+        let value = interpreter
+            .eval("'(\"a (b)\" #\\( 'x `(y ,z ,@w ,(quote v) (quote)) #(1.5 \"\"\"q\"uote\"\"\") (1 . 2))")
+            .unwrap();
+        let written = value.to_write_string();
+        assert_eq!(
+            written,
+            "(\"a (b)\" #\\( 'x `(y ,z ,@w ,'v (quote)) #(1.5 \"q\\\"uote\") (1 . 2))"
+        );
+        assert_eq!(
+            interpreter.eval(&format!("'{}", written)),
+            Ok(value.clone())
+        );
+        let quoted = interpreter.eval(&format!("''{}", written)).unwrap();
+        assert_eq!(quoted.to_write_string(), format!("'{}", written));
+
+        let pretty = value.pretty(20);
+        assert_eq!(
+            pretty,
+            "(\"a (b)\"\n #\\(\n 'x\n `(y\n   ,z\n   ,@w\n   ,'v\n   (quote))\n #(1.5 \"q\\\"uote\")\n (1 . 2))"
+        );
+        assert_eq!(
+            crate::parser::parse(&pretty).unwrap().to_write_string(),
+            written
+        );
+        // 略記の後のリストは、略記の次の列から字下げする
+        assert_eq!(quoted.pretty(20).lines().nth(1), Some("  #\\("));
+    }
+
+    #[test]
+    fn test_write_string_reads_back() {
+        for s in [
+            "plain",
+            "with \"quotes\" inside",
+            "\nleading \"q\" newline",
+            "",
+            "\"",
+            "ends with \"",
+            "has \"\"\" inside",
+            "back\\slash \\\" and \\d",
+        ] {
+            let written = write_string(s);
+            assert_eq!(
+                crate::parser::parse(&written).unwrap(),
+                Object::String(s.into()),
+                "{}",
+                written
+            );
+        }
+        assert_eq!(write_string("\""), r#""\"""#);
+    }
+}
@@ -0,0 +1,140 @@
+// 埋め込む側が Rust で足す、# で始まる読み取りの構文 (リーダーマクロ)。
+//
+//   interpreter.register_reader_macro('d', |text| Ok(Object::String(parse_date(text)?.into())))?;
+//   interpreter.eval("#d\"2024-01-01\"")   ; 読んだときに parse_date を呼んだ値
+//
+// # と登録した文字の後の 1 つ分のソースを、読んだままの文字列でコールバックに渡し、返した値をその位置の式にする。
+// 1 つ分とは、(...)、[...]、{...} なら対応する閉じ括弧まで、"..." なら閉じる " まで、それ以外は区切りの手前まで。
+// #{1 2 3} なら "{1 2 3}" を受け取るので、中身は parser::parse_all で読める。
+// #t や #( のような組み込みの構文の文字は登録できない。読み取り表は Interpreter ごとに持ち、
+// その Interpreter で読むソース (load や eval で読むものも含む) にだけ効く。
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::parser::Object;
+
+pub type ReaderMacro = Rc<dyn Fn(&str) -> Result<Object, String>>;
+
+// # の後で組み込みの構文が使う文字
+const RESERVED: &str = "tf\\(:|;xXbBoO";
+
+#[derive(Clone, Default)]
+pub struct ReadTable {
+    macros: HashMap<char, ReaderMacro>,
+}
+
+impl fmt::Debug for ReadTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut dispatch: Vec<&char> = self.macros.keys().collect();
+        dispatch.sort();
+        f.debug_struct("ReadTable")
+            .field("dispatch", &dispatch)
+            .finish()
+    }
+}
+
+impl ReadTable {
+    pub fn new() -> Self {
+        ReadTable::default()
+    }
+
+    // #dispatch を読んだときに reader を呼ぶ。同じ文字を登録し直すと、前のものと置き換える。
+    pub fn register(
+        &mut self,
+        dispatch: char,
+        reader: impl Fn(&str) -> Result<Object, String> + 'static,
+    ) -> Result<(), String> {
+        if RESERVED.contains(dispatch) || dispatch.is_whitespace() || "()\"';".contains(dispatch) {
+            return Err(format!("#{} is reserved for built-in syntax", dispatch));
+        }
+        self.macros.insert(dispatch, Rc::new(reader));
+        Ok(())
+    }
+}
+
+thread_local! {
+    // 読んでいる Interpreter の読み取り表
+    static READ_TABLE: RefCell<ReadTable> = RefCell::new(ReadTable::new());
+}
+
+pub(crate) fn with_read_table<T>(table: &ReadTable, f: impl FnOnce() -> T) -> T {
+    let saved = READ_TABLE.replace(table.clone());
+    let result = f();
+    READ_TABLE.set(saved);
+    result
+}
+
+pub(crate) fn reader_macro(dispatch: char) -> Option<ReaderMacro> {
+    READ_TABLE.with_borrow(|table| table.macros.get(&dispatch).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::{Parsed, parse, parse_all};
+
+    #[test]
+    fn test_reader_macros() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .register_reader_macro('d', |text| {
+                let date = text.trim_matches('"');
+                match date.split('-').collect::<Vec<_>>()[..] {
+                    [y, m, d] if [y, m, d].iter().all(|p| p.parse::<u32>().is_ok()) => {
+                        Ok(Object::String(format!("{}/{}/{}", d, m, y).into()))
+                    }
+                    _ => Err(format!("invalid date {}", text)),
+                }
+            })
+            .unwrap();
+        interpreter
+            .register_reader_macro('{', |text| {
+                let items = parse_all(&text[1..text.len() - 1]).map_err(|e| e.to_string())?;
+                let mut code = vec![parse("list").unwrap()];
+                code.extend(items);
+                Ok(Object::List(Rc::new(code)))
+            })
+            .unwrap();
+
+        assert_eq!(
+            interpreter
+                .eval("(list #d\"2024-01-31\" #{1 (+ 1 1) \"}\"} #d2024-02-01)")
+                .unwrap()
+                .to_string(),
+            "(31/01/2024 (1 2 }) 01/02/2024)"
+        );
+        let path = std::env::temp_dir().join(format!("mr-lisp-reader-{}.lisp", std::process::id()));
+        std::fs::write(&path, "(define loaded #{3 #d1999-12-31})").unwrap();
+        interpreter
+            .eval(&format!("(load \"{}\")", path.display()))
+            .unwrap();
+        assert_eq!(
+            interpreter.eval("loaded").unwrap().to_string(),
+            "(3 31/12/1999)"
+        );
+        assert_eq!(
+            interpreter.eval("(f #d\"soon\")"),
+            Err("ParseError: #d: invalid date \"soon\"".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("#{1 2"),
+            Err("ParseError: #{: unterminated {".to_string())
+        );
+        let mut parser = interpreter.parser();
+        assert!(matches!(parser.feed("#{1 2\n"), Parsed::NeedMoreInput));
+        assert!(matches!(
+            parser.feed("3}"),
+            Parsed::Complete { forms, .. } if forms[0].to_string() == "(list 1 2 3)"
+        ));
+        assert_eq!(
+            interpreter.register_reader_macro('t', |_| Ok(Object::Void)),
+            Err("#t is reserved for built-in syntax".to_string())
+        );
+        // 登録した Interpreter の外では読めない
+        assert!(parse("#d\"2024-01-31\"").is_err());
+    }
+}
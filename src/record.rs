@@ -0,0 +1,272 @@
+// define-record-type による名前付きのフィールドを持つ型。
+//
+//   (define-record-type point
+//     (make-point x y)
+//     point?
+//     (x point-x)
+//     (y point-y set-point-y!))
+//
+// 型の名前、コンストラクター、述語、各フィールドのアクセサー (と書き換え用の関数) を定義する。
+// コンストラクターに無いフィールドは Void で初期化する。型の名前には型を表す値を束縛する。
+// 作った関数は Rust 側の値を持つ必要があるので、Lambda ではなくここで呼び出しを処理する Foreign になる。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::eval::Env;
+use crate::parser::{Foreign, Object, Record, RecordType};
+use crate::printer::debug_form;
+
+#[derive(Debug)]
+pub(crate) enum Operation {
+    Construct(Vec<usize>), // 引数を入れるフィールドの位置
+    Predicate,
+    Access(usize),
+    Modify(usize),
+}
+
+#[derive(Debug)]
+struct RecordProcedure {
+    name: String,
+    record_type: Rc<RecordType>,
+    operation: Operation,
+}
+
+impl Foreign for RecordProcedure {
+    fn type_name(&self) -> &str {
+        "procedure"
+    }
+}
+
+impl Foreign for RecordType {
+    fn type_name(&self) -> &str {
+        "record-type"
+    }
+}
+
+fn as_record_procedure(obj: &Object) -> Option<&RecordProcedure> {
+    match obj {
+        Object::Foreign(foreign) => {
+            (foreign.as_ref() as &dyn Any).downcast_ref::<RecordProcedure>()
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn is_record_procedure(obj: &Object) -> bool {
+    as_record_procedure(obj).is_some()
+}
+
+// define-record-type で作った関数なら、その型と何をする関数か。
+pub(crate) fn record_procedure(obj: &Object) -> Option<(&Rc<RecordType>, &Operation)> {
+    let procedure = as_record_procedure(obj)?;
+    Some((&procedure.record_type, &procedure.operation))
+}
+
+// record_type のすべてのフィールドを、定義した順に引数に取るコンストラクター。
+pub(crate) fn constructor(name: &str, record_type: &Rc<RecordType>) -> Object {
+    Object::Foreign(Rc::new(RecordProcedure {
+        name: name.to_string(),
+        record_type: Rc::clone(record_type),
+        operation: Operation::Construct((0..record_type.fields.len()).collect()),
+    }))
+}
+
+// define-record-type で作った関数を呼ぶ。func がそうでなければ None。
+pub(crate) fn invoke(func: &Object, args: &[Object]) -> Option<Result<Object, String>> {
+    let procedure = as_record_procedure(func)?;
+    Some(procedure.call(args))
+}
+
+impl RecordProcedure {
+    fn call(&self, args: &[Object]) -> Result<Object, String> {
+        let expected = match &self.operation {
+            Operation::Construct(indices) => indices.len(),
+            Operation::Predicate | Operation::Access(_) => 1,
+            Operation::Modify(_) => 2,
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "{}: expected {} arguments, got {}",
+                self.name,
+                expected,
+                args.len()
+            ));
+        }
+        match &self.operation {
+            Operation::Construct(indices) => {
+                let mut values = vec![Object::Void; self.record_type.fields.len()];
+                for (&index, arg) in indices.iter().zip(args) {
+                    values[index] = arg.clone();
+                }
+                Ok(Object::Record(Rc::new(Record {
+                    record_type: Rc::clone(&self.record_type),
+                    values: RefCell::new(values),
+                })))
+            }
+            Operation::Predicate => Ok(Object::truth(self.as_record(&args[0]).is_some())),
+            Operation::Access(index) => {
+                Ok(self.expect_record(&args[0])?.values.borrow()[*index].clone())
+            }
+            Operation::Modify(index) => {
+                self.expect_record(&args[0])?.values.borrow_mut()[*index] = args[1].clone();
+                Ok(Object::Void)
+            }
+        }
+    }
+
+    fn as_record<'a>(&self, obj: &'a Object) -> Option<&'a Record> {
+        match obj {
+            Object::Record(record) if Rc::ptr_eq(&record.record_type, &self.record_type) => {
+                Some(record)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_record<'a>(&self, obj: &'a Object) -> Result<&'a Record, String> {
+        self.as_record(obj).ok_or_else(|| {
+            format!(
+                "{}: expected a {}, got {}",
+                self.name, self.record_type.name, obj
+            )
+        })
+    }
+}
+
+fn symbol<'a>(obj: &'a Object, list: &[Object]) -> Result<&'a str, String> {
+    match obj {
+        Object::Symbol(s) => Ok(s),
+        _ => Err(format!(
+            "Invalid define-record-type syntax: {}",
+            debug_form(list)
+        )),
+    }
+}
+
+pub(crate) fn eval_define_record_type(
+    list: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let invalid = || format!("Invalid define-record-type syntax: {}", debug_form(list));
+    let [
+        _,
+        type_name,
+        Object::List(constructor),
+        predicate,
+        field_specs @ ..,
+    ] = list
+    else {
+        return Err(invalid());
+    };
+    let type_name = symbol(type_name, list)?;
+    let mut fields = Vec::new();
+    let mut procedures = Vec::new();
+    for (index, spec) in field_specs.iter().enumerate() {
+        let Object::List(spec) = spec else {
+            return Err(invalid());
+        };
+        let Some((field, names)) = spec.split_first() else {
+            return Err(invalid());
+        };
+        fields.push(symbol(field, list)?.to_string());
+        match names {
+            [accessor] => procedures.push((symbol(accessor, list)?, Operation::Access(index))),
+            [accessor, modifier] => {
+                procedures.push((symbol(accessor, list)?, Operation::Access(index)));
+                procedures.push((symbol(modifier, list)?, Operation::Modify(index)));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    let Some((constructor_name, params)) = constructor.split_first() else {
+        return Err(invalid());
+    };
+    let mut indices = Vec::new();
+    for param in params {
+        let param = symbol(param, list)?;
+        match fields.iter().position(|field| field == param) {
+            Some(index) => indices.push(index),
+            None => {
+                return Err(format!(
+                    "define-record-type: {} is not a field of {}",
+                    param, type_name
+                ));
+            }
+        }
+    }
+    procedures.push((
+        symbol(constructor_name, list)?,
+        Operation::Construct(indices),
+    ));
+    procedures.push((symbol(predicate, list)?, Operation::Predicate));
+
+    let record_type = Rc::new(RecordType {
+        name: type_name.to_string(),
+        fields,
+    });
+    let mut env = env.borrow_mut();
+    env.define(type_name, Object::Foreign(record_type.clone()))?;
+    for (name, operation) in procedures {
+        let procedure = RecordProcedure {
+            name: name.to_string(),
+            record_type: Rc::clone(&record_type),
+            operation,
+        };
+        env.define(name, Object::Foreign(Rc::new(procedure)))?;
+    }
+    Ok(Object::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_define_record_type() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define-record-type point
+                (make-point x y)
+                point?
+                (x point-x)
+                (y point-y set-point-y!)
+                (label point-label))
+            (define p (make-point 1 2))
+            (set-point-y! p 5)
+            (list (point-x p) (point-y p) (point? p) (point? 1) (point-label p))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "(1 5 true false Void)"
+        );
+        assert_eq!(
+            interpreter.eval("p").unwrap().to_string(),
+            "#<point x=1 y=5 label=Void>"
+        );
+        let Ok(Object::Record(record)) = interpreter.eval("p") else {
+            panic!("expected a record");
+        };
+        assert_eq!(record.get("y"), Some(Object::Integer(5)));
+        assert_eq!(
+            interpreter.eval("(call-with-values (lambda () p) point-x)"),
+            Ok(Object::Integer(1))
+        );
+
+        assert_eq!(
+            interpreter.eval("(make-point 1)"),
+            Err("make-point: expected 2 arguments, got 1".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(point-x 3)"),
+            Err("point-x: expected a point, got 3".to_string())
+        );
+        assert!(
+            interpreter
+                .eval("(define-record-type q (make-q z) q? (x q-x))")
+                .is_err()
+        );
+    }
+}
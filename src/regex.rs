@@ -0,0 +1,545 @@
+// string-split などで区切りに使う正規表現。(regex "pattern") で一度コンパイルした値を使い回す。
+//
+//   (string-split "a, b,c" (regex ",\s*"))   ; ("a" "b" "c")
+//
+// 使える構文は . [abc] [^a-z] \d \w \s (とその否定の \D \W \S) ^ $ ( ) (?: ) | * + ? {m} {m,} {m,n}。
+// \ の後の記号はその文字そのもの、\t と \n はタブと改行。繰り返しは最長一致のみで、キャプチャはしない。
+// パターンを命令の列にコンパイルし、Pike VM (NFA のすべての状態を優先順位付きで同時に進める方法) で照合する。
+// 照合の時間は入力の長さと命令の数の積に比例するので、(a*)*b のようなパターンでも遅くならない。
+// 一致する範囲はバックトラックで先に見つかるものと同じで、| は左を、繰り返しは長い方を優先する。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::builtins::expect_string;
+use crate::eval::Env;
+use crate::parser::{Foreign, Object};
+
+// a{1000} のような繰り返しは命令を並べて展開するので、大きくなりすぎるパターンはエラーにする
+const MAX_INSTRUCTIONS: usize = 100_000;
+
+#[derive(Debug)]
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Split(usize, usize), // 両方に進む。1 つ目を優先する
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any, // . 改行以外の 1 文字
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+    // \d \w \s。否定の \D などは Class ごと negated にする
+    named: Vec<fn(char) -> bool>,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let hit = self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+            || self.named.iter().any(|f| f(c));
+        hit != self.negated
+    }
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_space(c: char) -> bool {
+    c.is_whitespace()
+}
+
+impl Foreign for Regex {
+    fn type_name(&self) -> &str {
+        "regex"
+    }
+}
+
+pub(crate) fn as_regex(obj: &Object) -> Option<&Regex> {
+    match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<Regex>(),
+        _ => None,
+    }
+}
+
+pub(crate) fn regex(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let regex =
+        Regex::new(expect_string("regex", &args[0])?).map_err(|e| format!("regex: {}", e))?;
+    Ok(Object::Foreign(Rc::new(regex)))
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.alt()?;
+        match parser.peek() {
+            None => {}
+            Some(')') => return Err(format!("unmatched ) in {:?}", pattern)),
+            Some(c) => return Err(format!("unexpected {:?} in {:?}", c, pattern)),
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    // s の from 以降で最初に一致する範囲。同じ位置から始まる一致の中では、繰り返しを最長にしたものを返す。
+    // 一致が見つかるまでは、各位置で先頭の命令から始まるスレッドを一番低い優先順位で足す。
+    pub(crate) fn find_at(&self, s: &str, from: usize) -> Option<Range<usize>> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut found = None;
+        let mut pos = from;
+        loop {
+            if found.is_none() {
+                self.add_thread(&mut current, 0, pos, pos, s);
+            }
+            if current.list.is_empty() && found.is_some() {
+                break;
+            }
+            let c = s[pos..].chars().next();
+            for &(pc, start) in &current.list {
+                let step = match (&self.program[pc], c) {
+                    (Inst::Match, _) => {
+                        // これより優先順位の低いスレッドは捨てる
+                        found = Some(start..pos);
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => *expected == c,
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c),
+                    _ => false,
+                };
+                if let (true, Some(c)) = (step, c) {
+                    self.add_thread(&mut next, pc + 1, start, pos + c.len_utf8(), s);
+                }
+            }
+            let Some(c) = c else {
+                break;
+            };
+            pos += c.len_utf8();
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        found
+    }
+
+    // pc から Split と Jump、位置の条件をたどり、文字を読む命令か Match に着いたスレッドを優先順に足す。
+    // 同じ位置で一度たどった命令には、後から着いた優先順位の低いスレッドを足さない。
+    fn add_thread(&self, threads: &mut Threads, pc: usize, start: usize, pos: usize, s: &str) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if std::mem::replace(&mut threads.seen[pc], true) {
+                continue;
+            }
+            match &self.program[pc] {
+                Inst::Jump(to) => stack.push(*to),
+                Inst::Split(first, second) => {
+                    stack.push(*second);
+                    stack.push(*first);
+                }
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == s.len() => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push((pc, start)),
+            }
+        }
+    }
+}
+
+// 同じ位置にいるスレッド。命令の位置と、一致を始めた位置の組を優先順に並べる。
+struct Threads {
+    list: Vec<(usize, usize)>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            list: Vec::new(),
+            seen: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_INSTRUCTIONS {
+        return Err("pattern is too large".to_string());
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        // Split で各候補に分かれ、最後の候補以外は一致したら終わりに Jump する
+        Node::Alt(nodes) => {
+            let mut jumps = Vec::new();
+            for (i, node) in nodes.iter().enumerate() {
+                if i + 1 == nodes.len() {
+                    compile(node, program)?;
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(node, program)?;
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        }
+        // min 回は並べ、残りは繰り返すか抜けるかの Split にする
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            let mut splits = Vec::new();
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    splits.push(split);
+                }
+                Some(max) => {
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program)?;
+                    }
+                }
+            }
+            for split in splits {
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alt(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.next().unwrap();
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("only (?: ...) groups are supported".to_string());
+                }
+                let node = self.alt()?;
+                if !self.eat(')') {
+                    return Err("missing )".to_string());
+                }
+                node
+            }
+            '[' => Node::Class(self.class()?),
+            '\\' => match self.escape()? {
+                Escape::Char(c) => Node::Char(c),
+                Escape::Class(class) => Node::Class(class),
+            },
+            '*' | '+' | '?' | '{' => return Err(format!("{} has nothing to repeat", c)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn quantifier(&mut self, node: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                let min = self.number().ok_or("expected a number after {")?;
+                let max = if self.eat(',') {
+                    self.number()
+                } else {
+                    Some(min)
+                };
+                if !self.eat('}') {
+                    return Err("missing }".to_string());
+                }
+                if max.is_some_and(|max| max < min) {
+                    return Err(format!("invalid repetition {{{},{}}}", min, max.unwrap()));
+                }
+                return self.quantifier_end(Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                });
+            }
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        self.quantifier_end(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        })
+    }
+
+    // a** のような重ねた繰り返しは受け付けない
+    fn quantifier_end(&mut self, node: Node) -> Result<Node, String> {
+        match self.peek() {
+            Some(c @ ('*' | '+' | '?' | '{')) => Err(format!("{} has nothing to repeat", c)),
+            _ => Ok(node),
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    // [ の後から ] まで
+    fn class(&mut self) -> Result<Class, String> {
+        let mut class = Class {
+            negated: self.eat('^'),
+            ranges: Vec::new(),
+            named: Vec::new(),
+        };
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                None => return Err("missing ]".to_string()),
+                // 先頭の ] は文字として扱う
+                Some(']') if !first => return Ok(class),
+                Some('\\') => match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(named) => {
+                        class.ranges.extend(named.ranges);
+                        class.named.extend(named.named);
+                        first = false;
+                        continue;
+                    }
+                },
+                Some(c) => c,
+            };
+            first = false;
+            let hi = if self.peek() == Some('-')
+                && !matches!(self.chars.get(self.pos + 1), None | Some(']'))
+            {
+                self.pos += 1;
+                match self.next().unwrap() {
+                    '\\' => match self.escape()? {
+                        Escape::Char(hi) => hi,
+                        Escape::Class(_) => return Err("invalid range in [...]".to_string()),
+                    },
+                    hi => hi,
+                }
+            } else {
+                c
+            };
+            if hi < c {
+                return Err(format!("invalid range {}-{}", c, hi));
+            }
+            class.ranges.push((c, hi));
+        }
+    }
+
+    // \ の後
+    fn escape(&mut self) -> Result<Escape, String> {
+        let named = |negated, f: fn(char) -> bool| {
+            Escape::Class(Class {
+                negated,
+                ranges: Vec::new(),
+                named: vec![f],
+            })
+        };
+        Ok(match self.next().ok_or("trailing \\")? {
+            'd' => named(false, is_digit),
+            'D' => named(true, is_digit),
+            'w' => named(false, is_word),
+            'W' => named(true, is_word),
+            's' => named(false, is_space),
+            'S' => named(true, is_space),
+            't' => Escape::Char('\t'),
+            'n' => Escape::Char('\n'),
+            c if !c.is_alphanumeric() => Escape::Char(c),
+            c => return Err(format!("unknown escape \\{}", c)),
+        })
+    }
+}
+
+enum Escape {
+    Char(char),
+    Class(Class),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, s: &str) -> Option<String> {
+        let regex = Regex::new(pattern).unwrap();
+        regex.find_at(s, 0).map(|range| s[range].to_string())
+    }
+
+    #[test]
+    fn test_find() {
+        let cases = [
+            ("b+", "aabbbc", Some("bbb")),
+            ("a.c", "xabcx", Some("abc")),
+            ("^a", "ba", None),
+            ("a$", "aba", Some("a")),
+            ("\\d{2,3}", "a1b1234", Some("123")),
+            ("x{2}", "xxx", Some("xx")),
+            ("[a-c]+", "xxcabd", Some("cab")),
+            ("[^,\\s]+", ", ab,c", Some("ab")),
+            ("[]a]+", "x]a]", Some("]a]")),
+            ("[a-]+", "b-a-", Some("-a-")),
+            ("(ab|cd)+e", "xabcdabe", Some("abcdabe")),
+            ("(?:a|ab)c", "abc", Some("abc")),
+            ("colou?r", "color", Some("color")),
+            ("\\w+", "  日本_語! ", Some("日本_語")),
+            ("\\.", "a.b", Some(".")),
+            ("a*", "bbb", Some("")),
+            ("(a*)*b", "aaab", Some("aaab")),
+            ("(a|ab)(c|bcd)", "abcd", Some("abcd")),
+            ("(a|ab)c?", "abc", Some("a")),
+            ("x*$", "axx", Some("xx")),
+            ("(?:a?){3}b", "aab", Some("aab")),
+            ("a{2,3}", "aaaa", Some("aaa")),
+            ("\\S+$", "x y", Some("y")),
+        ];
+        for (pattern, s, expected) in cases {
+            assert_eq!(
+                find(pattern, s).as_deref(),
+                expected,
+                "{} in {:?}",
+                pattern,
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_repetition_is_linear() {
+        let s = "a".repeat(10_000);
+        assert_eq!(find("(a*)*c", &s), None);
+        assert_eq!(find("(a|aa)*(b|a*)*$", &s).map(|m| m.len()), Some(10_000));
+        assert_eq!(
+            Regex::new("(a{1000}){1000}").unwrap_err(),
+            "pattern is too large"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        for (pattern, error) in [
+            ("(a", "missing )"),
+            ("a)", "unmatched ) in \"a)\""),
+            ("[a", "missing ]"),
+            ("*a", "* has nothing to repeat"),
+            ("a**", "* has nothing to repeat"),
+            ("a{3,1}", "invalid repetition {3,1}"),
+            ("[z-a]", "invalid range z-a"),
+            ("\\q", "unknown escape \\q"),
+            ("(?=a)", "only (?: ...) groups are supported"),
+        ] {
+            assert_eq!(Regex::new(pattern).unwrap_err(), error, "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// mr-lisp --listen 127.0.0.1:7777 で動くリモート REPL。エディタなどの別プロセスから式を評価するのに使う。
+// 接続してきた相手は任意のコードを実行できるので、外部から届くアドレスでは listen しないこと。
+//
+// プロトコルは 1 行に 1 つの JSON (JSON Lines)。リクエストとレスポンスは次の形をしている。
+//
+//   {"id": 1, "op": "eval", "code": "(define x 1) (+ x 1)"}
+//   {"id": 1, "status": "ok", "value": "2", "type": "integer", "out": "", "duration": 0.000012}
+//   {"id": 2, "status": "error", "error": "Undefined symbol: y", "span": {"start": 0, "end": 5},
+//    "line": 1, "column": 1, "out": "", "duration": 0.000003}
+//
+// id はそのまま返す。code には複数の式を書けて、最後の式の値が value になる。
+// out は print の出力、duration は評価にかかった秒数で、span はエラーになったトップレベルの式の code 中のバイト範囲。
+// line と column は、その中でエラーになった式の先頭の位置 (1 から数える)。
+// 接続は 1 つずつ順番に処理し、定義は接続をまたいで同じ Env に残る。
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::interpreter::{EvalResult, Interpreter};
+use crate::json::{Json, object};
+use crate::parser::Object;
+
+pub fn listen(addr: &str) -> Result<(), String> {
+    let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+    if let Ok(addr) = listener.local_addr() {
+        eprintln!("mr-lisp: listening on {}", addr);
+    }
+    serve_listener(&listener, None)
+}
+
+fn serve_listener(listener: &TcpListener, max_connections: Option<usize>) -> Result<(), String> {
+    let mut interpreter = Interpreter::new();
+    for (served, stream) in listener.incoming().enumerate() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(&stream, &mut interpreter) {
+                    eprintln!("mr-lisp: {}", e);
+                }
+            }
+            Err(e) => eprintln!("mr-lisp: {}", e),
+        }
+        if max_connections.is_some_and(|max| served + 1 >= max) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(mut stream: &TcpStream, interpreter: &mut Interpreter) -> io::Result<()> {
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = respond(&line, interpreter);
+        writeln!(stream, "{}", response)?;
+    }
+    Ok(())
+}
+
+fn respond(line: &str, interpreter: &mut Interpreter) -> Json {
+    let request = match Json::parse(line) {
+        Ok(request) => request,
+        Err(e) => return error(Json::Null, e),
+    };
+    let id = request.get("id").cloned().unwrap_or(Json::Null);
+    match request.get("op").and_then(Json::as_str).unwrap_or("eval") {
+        "eval" => match request.get("code").and_then(Json::as_str) {
+            Some(code) => eval_result(id, interpreter.eval_rich(code)),
+            None => error(id, "missing code".to_string()),
+        },
+        op => error(id, format!("unknown op: {}", op)),
+    }
+}
+
+fn eval_result(id: Json, result: EvalResult) -> Json {
+    let out = ("out", result.output.into());
+    let duration = ("duration", Json::Number(result.duration.as_secs_f64()));
+    match result.value {
+        Ok(value) => {
+            let (value, type_name) = match value {
+                Object::Void => (Json::Null, Json::Null),
+                value => (value.to_string().into(), value.type_name().into()),
+            };
+            object([
+                ("id", id),
+                ("status", "ok".into()),
+                ("value", value),
+                ("type", type_name),
+                out,
+                duration,
+            ])
+        }
+        Err(e) => {
+            let span = object([
+                ("start", Json::Number(e.span.start as f64)),
+                ("end", Json::Number(e.span.end as f64)),
+            ]);
+            object([
+                ("id", id),
+                ("status", "error".into()),
+                ("error", e.message.into()),
+                ("span", span),
+                ("line", Json::Number(e.line as f64)),
+                ("column", Json::Number(e.column as f64)),
+                out,
+                duration,
+            ])
+        }
+    }
+}
+
+// リクエストそのものが不正な場合のエラー
+fn error(id: Json, message: String) -> Json {
+    object([
+        ("id", id),
+        ("status", "error".into()),
+        ("error", message.into()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::Shutdown;
+    use std::thread;
+
+    #[test]
+    fn test_remote_repl() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let requests = [
+                r#"{"id": 1, "op": "eval", "code": "(define (sq x) (* x x))\n(print \"hi\")"}"#,
+                r#"{"id": "two", "code": "(sq 12)"}"#,
+                r#"{"id": 3, "code": "(sq y)"}"#,
+                r#"{"id": 4, "op": "complete"}"#,
+                "not json",
+            ];
+            for request in requests {
+                writeln!(stream, "{}", request).unwrap();
+            }
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut responses = String::new();
+            stream.read_to_string(&mut responses).unwrap();
+            responses
+        });
+        serve_listener(&listener, Some(1)).unwrap();
+
+        let responses = client.join().unwrap();
+        let responses: Vec<Json> = responses
+            .lines()
+            .map(|line| Json::parse(line).unwrap())
+            .collect();
+        let field = |i: usize, name: &str| responses[i].get(name).cloned().unwrap_or(Json::Null);
+        assert_eq!(field(0, "id"), Json::Number(1.0));
+        assert_eq!(field(0, "value"), Json::Null);
+        assert_eq!(field(0, "out"), "hi\n".into());
+        assert!(field(0, "duration").as_f64().is_some());
+        assert_eq!(field(1, "id"), "two".into());
+        assert_eq!(field(1, "value"), "144".into());
+        assert_eq!(field(1, "type"), "integer".into());
+        assert_eq!(field(2, "status"), "error".into());
+        assert_eq!(field(2, "error"), "Undefined symbol: y".into());
+        assert_eq!(field(2, "span").to_string(), r#"{"start":0,"end":6}"#);
+        assert_eq!(field(2, "column"), Json::Number(1.0));
+        assert_eq!(
+            responses[3].to_string(),
+            r#"{"id":4,"status":"error","error":"unknown op: complete"}"#
+        );
+        assert_eq!(field(4, "status"), "error".into());
+    }
+}
@@ -0,0 +1,77 @@
+// REPL で値を表示する方法を差し替えるためのレジストリ。
+// キャンバスや表のような値は、既定の Display の代わりにアスキーアートなどで表示できる。
+//
+//   let mut renderers = Renderers::new();
+//   renderers.register(|obj| match obj {
+//       Object::Integer(n) => Some(format!("{:#x}", n)),
+//       _ => None,
+//   });
+//
+// render は登録の新しいものから順に試し、どれも None を返したら None になる。
+// そのときは REPL の既定の表示を使う。
+
+use crate::parser::Object;
+
+pub type Renderer = fn(&Object) -> Option<String>;
+
+pub struct Renderers {
+    renderers: Vec<Renderer>,
+}
+
+impl Renderers {
+    // feature で有効になっている組み込みのレンダラーを登録した状態で作る。
+    pub fn new() -> Self {
+        Renderers {
+            renderers: vec![
+                #[cfg(feature = "graphics")]
+                crate::graphics::render_canvas,
+            ],
+        }
+    }
+
+    pub fn register(&mut self, renderer: Renderer) {
+        self.renderers.push(renderer);
+    }
+
+    pub fn render(&self, obj: &Object) -> Option<String> {
+        self.renderers
+            .iter()
+            .rev()
+            .find_map(|renderer| renderer(obj))
+    }
+}
+
+impl Default for Renderers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_order() {
+        let mut renderers = Renderers::new();
+        assert_eq!(renderers.render(&Object::Integer(255)), None);
+
+        renderers.register(|obj| match obj {
+            Object::Integer(n) => Some(format!("{:#x}", n)),
+            _ => None,
+        });
+        renderers.register(|obj| match obj {
+            Object::Integer(0) => Some("zero".to_string()),
+            _ => None,
+        });
+        assert_eq!(
+            renderers.render(&Object::Integer(255)),
+            Some("0xff".to_string())
+        );
+        assert_eq!(
+            renderers.render(&Object::Integer(0)),
+            Some("zero".to_string())
+        );
+        assert_eq!(renderers.render(&Object::truth(true)), None);
+    }
+}
@@ -0,0 +1,240 @@
+// Lisp のプログラムが、信用できないコードの文字列を安全に評価するための eval-in-sandbox。
+//
+//   (eval-in-sandbox "(* price 1.1)")                                ; 評価した値
+//   (eval-in-sandbox user-formula #:fuel 1000 #:allow '(math string))
+//   (error-object? (eval-in-sandbox "(read-all-stdin)"))             ; true
+//
+// コードは呼び出し元の束縛が見えない新しい大域の Env で評価し、失敗すればエラーを投げずにエラーオブジェクトを返す。
+// Env に置く組み込み関数は、どのサンドボックスにもある BASE と、#:allow で選んだまとまりだけ。
+// ファイルや標準入力、プロセス、ほかの Env に触れる組み込み関数はどのまとまりにも入れず、import と module も使えない。
+// 評価できる式の数は #:fuel (省略すると DEFAULT_FUEL) まで。外側に fuel があれば、その残りも超えない。
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::builtins::{BUILTINS, Builtin, expect_string, split_keyword_args};
+use crate::eval::{Env, eval, remaining_fuel, with_sub_fuel};
+use crate::exception::error_object;
+use crate::pair::list_items;
+use crate::parser::Object;
+
+const DEFAULT_FUEL: u64 = 10_000;
+
+// どのサンドボックスにもある組み込み関数
+const BASE: &[&str] = &[
+    "not",
+    "eval",
+    "values",
+    "call-with-values",
+    "force",
+    "promise?",
+    "raise",
+    "error",
+    "error-message",
+    "error-object?",
+];
+
+// #:allow で選べるまとまり
+const GROUPS: &[(&str, &[&str])] = &[
+    (
+        "math",
+        &["number->string", "format-number", "string->number"],
+    ),
+    (
+        "string",
+        &[
+            "string-length",
+            "string-ref",
+            "string->list",
+            "list->string",
+            "substring",
+            "string-byte-length",
+            "substring/bytes",
+            "string-pad-left",
+            "string-foldcase",
+            "string-split",
+            "string-join",
+            "regex",
+            "format",
+            "template",
+            "write-to-string",
+            "html->string",
+        ],
+    ),
+    (
+        "char",
+        &[
+            "char-alphabetic?",
+            "char-numeric?",
+            "char-whitespace?",
+            "char->integer",
+            "integer->char",
+            "char-upcase",
+            "char=?",
+        ],
+    ),
+    ("list", &["cons", "car", "cdr", "pair?", "null?", "length"]),
+    (
+        "vector",
+        &[
+            "make-vector",
+            "vector-ref",
+            "vector-set!",
+            "vector-length",
+            "vector->list",
+        ],
+    ),
+];
+
+thread_local! {
+    // eval-in-sandbox のコードを評価している間は true
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+// import や module のように、サンドボックスの中では使えない特殊形式かを確かめるのに使う。
+pub(crate) fn active() -> bool {
+    ACTIVE.get()
+}
+
+fn allowed_builtins(groups: &[&str]) -> impl Iterator<Item = &'static Builtin> {
+    let names: Vec<&str> = GROUPS
+        .iter()
+        .filter(|(group, _)| groups.contains(group))
+        .flat_map(|(_, names)| names.iter().copied())
+        .chain(BASE.iter().copied())
+        .collect();
+    BUILTINS
+        .iter()
+        .filter(move |builtin| names.contains(&builtin.name))
+}
+
+// (eval-in-sandbox code #:fuel n #:allow '(group...))
+pub(crate) fn eval_in_sandbox(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let (positional, keywords) = split_keyword_args("eval-in-sandbox", args)?;
+    let [code] = positional[..] else {
+        return Err(format!(
+            "eval-in-sandbox: expected 1 argument, got {}",
+            positional.len()
+        ));
+    };
+    let code = expect_string("eval-in-sandbox", code)?;
+    let mut fuel = DEFAULT_FUEL;
+    let mut groups = Vec::new();
+    for (name, value) in keywords {
+        match (name, value) {
+            ("fuel", Object::Integer(n)) if *n >= 0 => fuel = *n as u64,
+            ("fuel", value) => {
+                return Err(format!(
+                    "eval-in-sandbox: #:fuel expected a non-negative integer, got {}",
+                    value
+                ));
+            }
+            ("allow", value) => {
+                let Some(items) = list_items(value) else {
+                    return Err(format!(
+                        "eval-in-sandbox: #:allow expected a list of groups, got {}",
+                        value
+                    ));
+                };
+                for group in items {
+                    // list のように特殊形式と同じ名前のまとまりは、シンボルではなく Keyword として読まれる
+                    let name = match &group {
+                        Object::Symbol(s) => s.as_ref(),
+                        Object::Keyword(form) => form.name(),
+                        _ => "",
+                    };
+                    match GROUPS.iter().find(|(group, _)| *group == name) {
+                        Some((group, _)) => groups.push(*group),
+                        None => return Err(format!("eval-in-sandbox: unknown group {}", group)),
+                    }
+                }
+            }
+            (name, _) => {
+                return Err(format!(
+                    "eval-in-sandbox: unknown keyword argument #:{}",
+                    name
+                ));
+            }
+        }
+    }
+    let mut sandbox = Rc::new(RefCell::new(Env::with_builtins(allowed_builtins(&groups))));
+    let outer = ACTIVE.replace(true);
+    let result = with_sub_fuel(fuel, || eval(code, &mut sandbox));
+    ACTIVE.set(outer);
+    match result {
+        // 外側の fuel を使い切ったなら、呼び出し元の評価も続けられない
+        Err(e) if remaining_fuel() == Some(0) => Err(e),
+        result => Ok(result.unwrap_or_else(error_object)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_eval_in_sandbox() {
+        let mut interpreter = Interpreter::new();
+        let mut eval = |program: &str| {
+            interpreter
+                .eval(program)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|e| format!("Err: {}", e))
+        };
+        eval("(define secret 42)");
+        assert_eq!(eval("(eval-in-sandbox \"(define x 2) (* x 21)\")"), "42");
+        assert_eq!(
+            eval("(error-message (eval-in-sandbox \"secret\"))"),
+            "Undefined symbol: secret"
+        );
+        assert_eq!(
+            eval("(error-message (eval-in-sandbox \"(read-all-stdin)\"))"),
+            "Undefined function: read-all-stdin"
+        );
+        assert_eq!(
+            eval("(error-object? (eval-in-sandbox \"(string-length 1)\"))"),
+            "true"
+        );
+        assert_eq!(
+            eval(
+                "(eval-in-sandbox \"(string-length (number->string 123))\" #:allow '(math string))"
+            ),
+            "3"
+        );
+        assert_eq!(
+            eval("(error-message (eval-in-sandbox \"(define (f) (f)) (f)\" #:fuel 100))"),
+            "Out of fuel"
+        );
+        assert_eq!(
+            eval("(error-message (eval-in-sandbox \"(import m)\"))"),
+            "import: not allowed in a sandbox"
+        );
+        assert_eq!(
+            eval("(error-message (eval-in-sandbox \"(+ 1\"))"),
+            "ParseError: Expected ')' at the end of list"
+        );
+        assert_eq!(
+            eval("(eval-in-sandbox \"1\" #:allow '(files))"),
+            "Err: eval-in-sandbox: unknown group files"
+        );
+        assert_eq!(
+            eval(
+                "(eval-in-sandbox \"(length (cons 1 (vector->list (make-vector 2))))\" #:allow '(list vector))"
+            ),
+            "3"
+        );
+        assert_eq!(
+            eval("(eval-in-sandbox \"1\" #:allow 'math)"),
+            "Err: eval-in-sandbox: #:allow expected a list of groups, got math"
+        );
+        // 外側の fuel の残りは超えられない
+        interpreter.set_fuel(Some(50));
+        assert_eq!(
+            interpreter.eval("(eval-in-sandbox \"(define (f) (f)) (f)\" #:fuel 1000)"),
+            Err("Out of fuel".to_string())
+        );
+    }
+}
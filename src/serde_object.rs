@@ -0,0 +1,741 @@
+// Lisp のデータと serde に対応した Rust の型を相互に変換する。設定を S 式で書く場合などに使う。
+//
+//   '((name "app") (port 8080) (tags ("a" "b")))
+//
+// 構造体やマップは (キー 値) の連想リストか、(#:key 値 ...) の形で書く。キーはシンボルか文字列。
+// Lisp 風の a-b という名前のキーは #[serde(rename_all = "kebab-case")] で受け取る。
+// enum はバリアント名のシンボルか、(バリアント 値) の形で書く。
+// 変換できない場合のエラーには、問題のある値までのパス (servers[0].port など) が付く。
+
+use std::fmt;
+use std::rc::Rc;
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, Unexpected,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+use serde::{Deserialize, forward_to_deserialize_any};
+
+use crate::parser::Object;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    path: Vec<String>, // 外側から順に、フィールド名か [添字]
+    message: String,
+}
+
+impl Error {
+    fn at(mut self, segment: String) -> Self {
+        self.path.insert(0, segment);
+        self
+    }
+
+    // servers[0].port のような、エラーになった値の位置。一番外側の値なら空文字列
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            if !path.is_empty() && !segment.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error {
+            path: Vec::new(),
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        de::Error::custom(msg)
+    }
+}
+
+pub fn from_object<'de, T: Deserialize<'de>>(obj: &'de Object) -> Result<T, Error> {
+    T::deserialize(ObjectDeserializer(obj))
+}
+
+pub fn to_object<T: Serialize + ?Sized>(value: &T) -> Result<Object, Error> {
+    value.serialize(ObjectSerializer)
+}
+
+// 真リストの要素。(list 1 2) のようなペアの並びは cdr をたどって、コピーせずに参照を集める。
+fn items(obj: &Object) -> Option<Vec<&Object>> {
+    let mut items = Vec::new();
+    let mut rest = obj;
+    loop {
+        match rest {
+            Object::Pair(pair) => {
+                items.push(&pair.car);
+                rest = &pair.cdr;
+            }
+            Object::ListData(list) | Object::List(list) => {
+                items.extend(list.iter());
+                return Some(items);
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn unexpected(obj: &Object) -> Unexpected<'_> {
+    match obj {
+        Object::Void => Unexpected::Unit,
+        Object::Bool(b) => Unexpected::Bool(*b),
+        Object::Integer(n) => Unexpected::Signed(*n),
+        Object::Float(f) => Unexpected::Float(*f),
+        Object::String(s) => Unexpected::Str(s),
+        Object::ListData(_) | Object::List(_) | Object::Pair(_) => Unexpected::Seq,
+        _ => Unexpected::Other(obj.type_name()),
+    }
+}
+
+struct ObjectDeserializer<'de>(&'de Object);
+
+impl<'de> de::Deserializer<'de> for ObjectDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Object::Void => visitor.visit_unit(),
+            Object::Bool(b) => visitor.visit_bool(*b),
+            Object::Integer(n) => visitor.visit_i64(*n),
+            Object::Float(f) => visitor.visit_f64(*f),
+            Object::String(s) => visitor.visit_borrowed_str(s),
+            Object::Symbol(s) | Object::KeywordArg(s) => visitor.visit_borrowed_str(s),
+            obj => match items(obj) {
+                Some(items) => visitor.visit_seq(Seq { items, index: 0 }),
+                None => Err(de::Error::invalid_type(unexpected(obj), &visitor)),
+            },
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Object::Void => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match items(self.0) {
+            Some(list) => visitor.visit_map(Map {
+                entries: entries(&list)?,
+                index: 0,
+            }),
+            None => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Object::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            Object::Symbol(s) => visitor.visit_enum(s.as_ref().into_deserializer()),
+            obj => match items(obj).as_deref() {
+                Some(&[variant, value]) => visitor.visit_enum(Enum { variant, value }),
+                _ => Err(de::Error::invalid_type(unexpected(obj), &visitor)),
+            },
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct identifier ignored_any
+    }
+}
+
+struct Seq<'de> {
+    items: Vec<&'de Object>,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for Seq<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        let Some(&item) = self.items.get(self.index) else {
+            return Ok(None);
+        };
+        let index = self.index;
+        self.index += 1;
+        seed.deserialize(ObjectDeserializer(item))
+            .map(Some)
+            .map_err(|e| e.at(format!("[{}]", index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len() - self.index)
+    }
+}
+
+// ((key value) ...) または (#:key value ...) をキーと値の組にする。
+fn entries<'a>(list: &[&'a Object]) -> Result<Vec<(&'a Object, &'a Object)>, Error> {
+    if let Some(Object::KeywordArg(_)) = list.first() {
+        return list
+            .chunks(2)
+            .map(|pair| match *pair {
+                [key @ Object::KeywordArg(_), value] => Ok((key, value)),
+                _ => Err(de::Error::custom(format!(
+                    "expected #:key value pairs, got {}",
+                    Object::ListData(Rc::new(pair.iter().map(|&obj| obj.clone()).collect()))
+                ))),
+            })
+            .collect();
+    }
+    list.iter()
+        .map(|entry| match items(entry).as_deref() {
+            Some(&[key, value]) => Ok((key, value)),
+            _ => Err(de::Error::custom(format!(
+                "expected an association list ((key value) ...), got {} in it",
+                entry
+            ))),
+        })
+        .collect()
+}
+
+struct Map<'de> {
+    entries: Vec<(&'de Object, &'de Object)>,
+    index: usize,
+}
+
+impl<'de> MapAccess<'de> for Map<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.get(self.index) {
+            Some((key, _)) => seed.deserialize(ObjectDeserializer(key)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (key, value) = self.entries[self.index];
+        self.index += 1;
+        let name = match key {
+            Object::String(s) => s.to_string(),
+            Object::Symbol(s) | Object::KeywordArg(s) => s.to_string(),
+            _ => key.to_string(),
+        };
+        seed.deserialize(ObjectDeserializer(value))
+            .map_err(|e| e.at(name))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len() - self.index)
+    }
+}
+
+struct Enum<'de> {
+    variant: &'de Object,
+    value: &'de Object,
+}
+
+impl<'de> EnumAccess<'de> for Enum<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(ObjectDeserializer(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Enum<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Err(de::Error::invalid_type(
+            unexpected(self.value),
+            &"unit variant",
+        ))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(ObjectDeserializer(self.value))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(ObjectDeserializer(self.value), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(ObjectDeserializer(self.value), visitor)
+    }
+}
+
+fn list(items: Vec<Object>) -> Object {
+    Object::ListData(Rc::new(items))
+}
+
+struct ObjectSerializer;
+
+impl ser::Serializer for ObjectSerializer {
+    type Ok = Object;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Object, Error> {
+        Ok(Object::truth(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Object, Error> {
+        Ok(Object::Integer(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Object, Error> {
+        Ok(Object::Integer(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Object, Error> {
+        Ok(Object::Integer(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Object, Error> {
+        Ok(Object::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Object, Error> {
+        Ok(Object::Integer(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Object, Error> {
+        Ok(Object::Integer(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Object, Error> {
+        Ok(Object::Integer(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Object, Error> {
+        i64::try_from(v)
+            .map(Object::Integer)
+            .map_err(|_| ser::Error::custom(format!("{} is too large for an integer", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Object, Error> {
+        Ok(Object::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Object, Error> {
+        Ok(Object::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Object, Error> {
+        Ok(Object::String(v.to_string().into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Object, Error> {
+        Ok(Object::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Object, Error> {
+        Ok(list(
+            v.iter().map(|b| Object::Integer((*b).into())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Object, Error> {
+        Ok(Object::Void)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Object, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Object, Error> {
+        Ok(Object::Void)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Object, Error> {
+        Ok(Object::Void)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Object, Error> {
+        Ok(Object::Symbol(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Object, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Object, Error> {
+        let value = to_object(value).map_err(|e| e.at(variant.to_string()))?;
+        Ok(list(vec![Object::Symbol(variant.into()), value]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+// (バリアント 値) の形にする
+fn with_variant(variant: Option<&'static str>, value: Object) -> Object {
+    match variant {
+        Some(variant) => list(vec![Object::Symbol(variant.into()), value]),
+        None => value,
+    }
+}
+
+struct SeqSerializer {
+    items: Vec<Object>,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let item = to_object(value).map_err(|e| e.at(format!("[{}]", self.items.len())))?;
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Object, Error> {
+        Ok(with_variant(self.variant, list(self.items)))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<Object>,
+    key: Option<Object>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: Object, value: &T) -> Result<(), Error> {
+        let value = to_object(value).map_err(|e| e.at(key.to_string()))?;
+        self.entries.push(list(vec![key, value]));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Object, Error> {
+        Ok(with_variant(self.variant, list(self.entries)))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(to_object(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or_else(|| {
+            <Error as ser::Error>::custom("serialize_value called before serialize_key")
+        })?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(Object::Symbol(key.into()), value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Object;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(Object::Symbol(key.into()), value)
+    }
+
+    fn end(self) -> Result<Object, Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use serde::Serialize;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Config {
+        name: String,
+        max_connections: u16,
+        ratio: f64,
+        tags: Vec<String>,
+        debug: Option<bool>,
+        mode: Mode,
+        servers: Vec<Server>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    enum Mode {
+        Fast,
+        Retry(u32),
+    }
+
+    #[test]
+    fn test_from_object_and_to_object() {
+        let mut interpreter = Interpreter::new();
+        let data = interpreter
+            .eval(
+                "`((name \"app\")
+                   (max-connections 8)
+                   (ratio 1)
+                   (tags (\"a\" b))
+                   (debug ,(< 1 2))
+                   (mode (retry 3))
+                   (servers (((host \"x\") (port 80)) (#:host \"y\" #:port 81))))",
+            )
+            .unwrap();
+        let config: Config = from_object(&data).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                name: "app".to_string(),
+                max_connections: 8,
+                ratio: 1.0,
+                tags: vec!["a".to_string(), "b".to_string()],
+                debug: Some(true),
+                mode: Mode::Retry(3),
+                servers: vec![
+                    Server {
+                        host: "x".to_string(),
+                        port: 80
+                    },
+                    Server {
+                        host: "y".to_string(),
+                        port: 81
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            from_object::<Config>(&to_object(&config).unwrap()),
+            Ok(config)
+        );
+        assert_eq!(to_object(&Mode::Fast).unwrap().to_string(), "fast");
+
+        let data = interpreter
+            .eval(
+                "'((name \"app\") (max-connections 8) (ratio 1) (tags ()) (mode fast)
+                   (servers (((host \"x\") (port 80)) ((host \"y\") (port \"81\")))))",
+            )
+            .unwrap();
+        let error = from_object::<Config>(&data).unwrap_err();
+        assert_eq!(error.path(), "servers[1].port");
+        assert_eq!(
+            error.to_string(),
+            "servers[1].port: invalid type: string \"81\", expected u16"
+        );
+        let error = from_object::<Config>(&interpreter.eval("'((name 1 2))").unwrap()).unwrap_err();
+        assert_eq!(error.path(), "");
+        assert!(from_object::<Server>(&interpreter.eval("'((host \"x\"))").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// Unix のシグナルを Lisp の関数で受け取る on-signal。
+//
+//   (on-signal 'sigterm (lambda () (save-state) (print "bye")))
+//
+// シグナルハンドラーの中では印を付けるだけで、関数は評価を続けている途中の、次に式を評価するところで呼ぶ。
+// REPL の Ctrl-C も同じ仕組みで、評価中の式を eval::interrupt で中断する。
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::c_int;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::eval::{Env, apply, interrupt};
+use crate::parser::Object;
+
+const SIGNALS: &[(&str, c_int)] = &[
+    ("sighup", 1),
+    ("sigint", 2),
+    ("sigquit", 3),
+    ("sigterm", 15),
+];
+
+unsafe extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+// 届いたシグナル。SIGNALS と同じ順番
+static PENDING: [AtomicBool; SIGNALS.len()] = [const { AtomicBool::new(false) }; SIGNALS.len()];
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static HANDLERS: RefCell<HashMap<c_int, Object>> = RefCell::new(HashMap::new());
+}
+
+extern "C" fn record_signal(signum: c_int) {
+    if let Some(i) = SIGNALS.iter().position(|(_, n)| *n == signum) {
+        PENDING[i].store(true, Ordering::SeqCst);
+        SIGNALED.store(true, Ordering::SeqCst);
+    }
+}
+
+extern "C" fn interrupt_on_signal(_signum: c_int) {
+    interrupt();
+}
+
+// Ctrl-C (SIGINT) でプロセスを終わらせずに、評価中の式を中断するようにする。REPL で使う。
+pub fn install_interrupt_handler() {
+    unsafe {
+        signal(2, interrupt_on_signal);
+    }
+}
+
+// 届いたシグナルのうち、このスレッドで on-signal した関数があるものについて関数を呼ぶ。
+// 関数の無いシグナルは、関数を登録した別のスレッドのために残しておく。
+pub(crate) fn dispatch(env: &mut Rc<RefCell<Env>>) -> Result<(), String> {
+    if !SIGNALED.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    for (i, (_, signum)) in SIGNALS.iter().enumerate() {
+        if !PENDING[i].load(Ordering::SeqCst) {
+            continue;
+        }
+        match HANDLERS.with_borrow(|handlers| handlers.get(signum).cloned()) {
+            Some(handler) => {
+                PENDING[i].store(false, Ordering::SeqCst);
+                apply(&handler, &[], env)?;
+            }
+            None => SIGNALED.store(true, Ordering::SeqCst),
+        }
+    }
+    Ok(())
+}
+
+// (on-signal 'sigint handler) は、シグナルが届いたときに handler を引数なしで呼ぶようにする。
+pub(crate) fn on_signal(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (name, handler) = (&args[0], &args[1]);
+    let name_str = match name {
+        Object::Symbol(s) => Some(s.as_ref()),
+        Object::String(s) => Some(s.as_str()),
+        _ => None,
+    };
+    let signum = name_str.and_then(|s| {
+        SIGNALS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, n)| *n)
+    });
+    let Some(signum) = signum else {
+        let names: Vec<&str> = SIGNALS.iter().map(|(name, _)| *name).collect();
+        return Err(format!(
+            "on-signal: unknown signal {} (expected one of {})",
+            name,
+            names.join(", ")
+        ));
+    };
+    HANDLERS.with_borrow_mut(|handlers| handlers.insert(signum, handler.clone()));
+    unsafe {
+        signal(signum, record_signal);
+    }
+    Ok(Object::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+    use std::os::raw::c_int;
+
+    unsafe extern "C" {
+        fn raise(sig: c_int) -> c_int;
+    }
+
+    #[test]
+    fn test_on_signal() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("(define hups 0) (on-signal 'sighup (lambda () (set! hups (+ hups 1))))")
+            .unwrap();
+        unsafe {
+            raise(1);
+        }
+        let program = "
+            (define i 0)
+            (while (< hups 1) (set! i (+ i 1)))
+            hups
+        ";
+        assert_eq!(interpreter.eval(program), Ok(Object::Integer(1)));
+        assert!(
+            interpreter
+                .eval("(on-signal 'sigfoo (lambda () 1))")
+                .is_err()
+        );
+        assert!(interpreter.eval("(on-signal 'sigterm 1)").is_err());
+    }
+}
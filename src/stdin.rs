@@ -0,0 +1,128 @@
+// 標準入力を読む組み込み関数。パイプラインの中で awk や sed の代わりに使う。
+//
+//   (for-each-line (lambda (line) (print (string-length line) line)))
+//   (define text (read-all-stdin))
+//
+// 行は末尾の改行 (\n と \r\n) を取り除いて渡す。for-each-line の後に read-all-stdin を呼ぶと、残りを読む。
+// mr-lisp --filter は、スクリプトの値の関数を各行に適用して結果を書く (Interpreter::filter)。
+
+use std::cell::RefCell;
+use std::io::{self, BufRead, Cursor, Read};
+use std::rc::Rc;
+
+use crate::eval::{Env, apply, write_output};
+use crate::parser::Object;
+
+thread_local! {
+    static INPUT: RefCell<Option<Cursor<String>>> = const { RefCell::new(None) };
+}
+
+// f を実行する間、標準入力の代わりに input を読ませる。テストや埋め込みで使う。
+pub fn with_input<T>(input: &str, f: impl FnOnce() -> T) -> T {
+    let outer = INPUT.replace(Some(Cursor::new(input.to_string())));
+    let result = f();
+    INPUT.set(outer);
+    result
+}
+
+// 入力の終わりなら None。
+fn read_line() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    let read = INPUT.with_borrow_mut(|input| match input {
+        Some(input) => input.read_line(&mut line),
+        None => io::stdin().lock().read_line(&mut line),
+    })?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+fn each_line(name: &str, mut f: impl FnMut(String) -> Result<(), String>) -> Result<(), String> {
+    while let Some(line) = read_line().map_err(|e| format!("{}: {}", name, e))? {
+        f(line)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn for_each_line(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let func = &args[0];
+    each_line("for-each-line", |line| {
+        apply(func, &[Object::String(line.into())], env).map(|_| ())
+    })?;
+    Ok(Object::Void)
+}
+
+pub(crate) fn read_all_stdin(
+    _args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let mut text = String::new();
+    INPUT
+        .with_borrow_mut(|input| match input {
+            Some(input) => input.read_to_string(&mut text),
+            None => io::stdin().lock().read_to_string(&mut text),
+        })
+        .map_err(|e| format!("read-all-stdin: {}", e))?;
+    Ok(Object::String(text.into()))
+}
+
+// --filter の本体。Void と false を返した行は何も書かないので、grep のように行を選ぶのにも使える。
+pub(crate) fn filter_lines(func: &Object, env: &mut Rc<RefCell<Env>>) -> Result<(), String> {
+    each_line("filter", |line| {
+        match apply(func, &[Object::String(line.into())], env)? {
+            Object::Void | Object::Bool(false) => {}
+            value => write_output(&format!("{}\n", value)),
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::capture_output;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_stdin() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define count 0)
+            (for-each-line (lambda (line) (set! count (+ count 1)) (print count line)))
+            count
+        ";
+        let (result, output) =
+            capture_output(|| with_input("a b\r\nc\n\nlast", || interpreter.eval(program)));
+        assert_eq!(result, Ok(Object::Integer(4)));
+        assert_eq!(output, "1 a b\n2 c\n3 \n4 last\n");
+
+        assert_eq!(
+            with_input("x\ny\n", || interpreter.eval("(read-all-stdin)")),
+            Ok(Object::String("x\ny\n".into()))
+        );
+        assert_eq!(
+            with_input("x\n", || interpreter.eval("(for-each-line car)")),
+            Err("car: expected a pair, got x".to_string())
+        );
+
+        let (result, output) = capture_output(|| {
+            with_input("apple\nbanana\ncherry\n", || {
+                interpreter
+                    .filter("(lambda (line) (if (> (string-length line) 5) (list line) (< 1 0)))")
+            })
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "(banana)\n(cherry)\n");
+        assert_eq!(
+            with_input("x\n", || interpreter.filter("1")),
+            Err("1 is not a function".to_string())
+        );
+    }
+}
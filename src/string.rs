@@ -0,0 +1,148 @@
+// Lisp の文字列の中身。バッファを Rc で共有し、その中のバイト範囲を指す。
+//
+// substring などで切り出しても中身はコピーせず、同じバッファの別の範囲を指す Str を作るだけなので、
+// 大きな入力を細かく切り分けても入力の大きさの分だけコピーが増えることはない。
+// その代わり、切り出した一部が生きている間はバッファ全体が解放されない。
+// Object を 24 bytes に収めるため、範囲は u32 で持つ。4 GiB を超える文字列は扱えない。
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub struct Str {
+    buf: Rc<String>,
+    start: u32,
+    end: u32,
+}
+
+impl Str {
+    pub fn as_str(&self) -> &str {
+        &self.buf[self.start as usize..self.end as usize]
+    }
+
+    // range はこの文字列の中のバイト範囲。文字の境界でなければ None。中身はコピーしない。
+    pub fn slice(&self, range: Range<usize>) -> Option<Str> {
+        self.as_str().get(range.clone())?;
+        Some(Str {
+            buf: Rc::clone(&self.buf),
+            start: self.start + range.start as u32,
+            end: self.start + range.end as u32,
+        })
+    }
+
+    // 部分文字列 sub が、この文字列の中を指している場合にその範囲の Str を返す。
+    // str::split などが返した &str から、コピーせずに Str を作るのに使う。
+    pub fn slice_ref(&self, sub: &str) -> Str {
+        let offset = (sub.as_ptr() as usize).wrapping_sub(self.as_str().as_ptr() as usize);
+        match self.slice(offset..offset + sub.len()) {
+            Some(s) if std::ptr::eq(s.as_str(), sub) => s,
+            _ => Str::from(sub),
+        }
+    }
+}
+
+impl From<String> for Str {
+    fn from(s: String) -> Str {
+        let end = u32::try_from(s.len()).expect("strings longer than 4 GiB are not supported");
+        Str {
+            buf: Rc::new(s),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<&str> for Str {
+    fn from(s: &str) -> Str {
+        Str::from(s.to_string())
+    }
+}
+
+impl Deref for Str {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Str {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Str {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Str) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Str {}
+
+impl PartialEq<str> for Str {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialOrd for Str {
+    fn partial_cmp(&self, other: &Str) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Str {
+    fn cmp(&self, other: &Str) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for Str {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice_shares_buffer() {
+        let s = Str::from("héllo, world");
+        let hello = s.slice(0..6).unwrap();
+        assert_eq!(&*hello, "héllo");
+        assert!(Rc::ptr_eq(&hello.buf, &s.buf));
+        assert_eq!(hello.slice(1..3).unwrap(), Str::from("é"));
+        assert!(s.slice(0..2).is_none());
+        assert!(s.slice(0..100).is_none());
+
+        let world = s.split(", ").nth(1).unwrap();
+        let world = s.slice_ref(world);
+        assert_eq!(world, Str::from("world"));
+        assert!(Rc::ptr_eq(&world.buf, &s.buf));
+        assert!(!Rc::ptr_eq(&s.slice_ref("world").buf, &s.buf));
+    }
+}
@@ -0,0 +1,315 @@
+// define-syntax と syntax-rules によるパターンマッチのマクロ。
+//
+// (define-syntax name
+//   (syntax-rules (literal ...)
+//     ((_ pattern ...) template)
+//     ...))
+//
+// 衛生的にするため、テンプレートが持ち込んだシンボルは展開のたびに name#1 のような別名に置き換える。
+// 別名で束縛が見つからない場合は元の名前で探す (eval::lookup) ので、大域の関数などはそのまま参照できる。
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::keyword::SpecialForm;
+use crate::parser::Object;
+
+const ELLIPSIS: &str = "...";
+
+pub struct SyntaxRules {
+    literals: Vec<Object>,
+    rules: Vec<(Object, Object)>, // (パターン, テンプレート)
+}
+
+impl fmt::Debug for SyntaxRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyntaxRules")
+            .field("literals", &self.literals)
+            .field("rules", &self.rules)
+            .finish()
+    }
+}
+
+impl PartialEq for SyntaxRules {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// パターン変数に対応する式。... の付いたパターンの中の変数は、繰り返しの回数だけの並びになる。
+#[derive(Clone)]
+enum Binding {
+    One(Object),
+    Many(Vec<Binding>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+thread_local! {
+    static EXPANSIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// 別名 name#1 の元の名前。別名でなければ None。
+pub(crate) fn original_name(name: &str) -> Option<&str> {
+    let (base, id) = name.rsplit_once('#')?;
+    (!base.is_empty() && !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())).then_some(base)
+}
+
+fn is_ellipsis(obj: &Object) -> bool {
+    matches!(obj, Object::Symbol(s) if s.as_ref() == ELLIPSIS)
+}
+
+impl SyntaxRules {
+    // (syntax-rules (literal ...) (pattern template) ...) を読む。
+    pub(crate) fn parse(spec: &Object) -> Result<SyntaxRules, String> {
+        let invalid = || format!("Invalid syntax-rules: {}", spec);
+        let Object::List(spec) = spec else {
+            return Err(invalid());
+        };
+        match spec.first() {
+            Some(Object::Symbol(s)) if s.as_ref() == "syntax-rules" => {}
+            _ => return Err(invalid()),
+        }
+        let literals = match spec.get(1) {
+            Some(Object::List(literals)) => literals.to_vec(),
+            _ => return Err(invalid()),
+        };
+        let mut rules = Vec::new();
+        for rule in &spec[2..] {
+            match rule {
+                Object::List(rule) if rule.len() == 2 && matches!(rule[0], Object::List(_)) => {
+                    rules.push((rule[0].clone(), rule[1].clone()))
+                }
+                _ => return Err(format!("Invalid syntax-rules rule: {}", rule)),
+            }
+        }
+        Ok(SyntaxRules { literals, rules })
+    }
+
+    // 最初にマッチした規則のテンプレートで form を置き換える。パターンの先頭 (マクロの名前) は見ない。
+    pub(crate) fn expand(&self, form: &[Object]) -> Result<Object, String> {
+        for (pattern, template) in &self.rules {
+            let Object::List(pattern) = pattern else {
+                continue;
+            };
+            let mut bindings = Bindings::new();
+            if self.match_list(&pattern[1..], &form[1..], &mut bindings) {
+                let id = EXPANSIONS.with(|n| {
+                    n.set(n.get() + 1);
+                    n.get()
+                });
+                return instantiate(template, &bindings, Some(id));
+            }
+        }
+        Err(format!(
+            "no syntax-rules pattern matches {}",
+            Object::List(Rc::new(form.to_vec()))
+        ))
+    }
+
+    fn match_pattern(&self, pattern: &Object, form: &Object, bindings: &mut Bindings) -> bool {
+        match pattern {
+            Object::Symbol(s) if s.as_ref() == "_" => true,
+            Object::Symbol(_) if self.literals.contains(pattern) => pattern == form,
+            Object::Symbol(s) => {
+                bindings.insert(s.to_string(), Binding::One(form.clone()));
+                true
+            }
+            Object::List(patterns) => match form {
+                Object::List(forms) => self.match_list(patterns, forms, bindings),
+                _ => false,
+            },
+            _ => pattern == form,
+        }
+    }
+
+    // (a b ... c) のように ... は 1 つだけ置ける。... の直前のパターンに 0 個以上の式がマッチする。
+    fn match_list(&self, patterns: &[Object], forms: &[Object], bindings: &mut Bindings) -> bool {
+        let Some(i) = patterns.iter().position(is_ellipsis).filter(|i| *i > 0) else {
+            return patterns.len() == forms.len()
+                && patterns
+                    .iter()
+                    .zip(forms)
+                    .all(|(p, f)| self.match_pattern(p, f, bindings));
+        };
+        let (before, repeated, after) = (&patterns[..i - 1], &patterns[i - 1], &patterns[i + 1..]);
+        if forms.len() < before.len() + after.len() {
+            return false;
+        }
+        let rest = forms.len() - after.len();
+        if !self.match_list(before, &forms[..before.len()], bindings)
+            || !self.match_list(after, &forms[rest..], bindings)
+        {
+            return false;
+        }
+
+        let mut matches = Vec::new();
+        for form in &forms[before.len()..rest] {
+            let mut m = Bindings::new();
+            if !self.match_pattern(repeated, form, &mut m) {
+                return false;
+            }
+            matches.push(m);
+        }
+        for var in self.pattern_vars(repeated) {
+            let seq = matches
+                .iter_mut()
+                .map(|m| m.remove(&var).unwrap())
+                .collect();
+            bindings.insert(var, Binding::Many(seq));
+        }
+        true
+    }
+
+    fn pattern_vars(&self, pattern: &Object) -> Vec<String> {
+        match pattern {
+            Object::Symbol(s)
+                if s.as_ref() != "_"
+                    && !is_ellipsis(pattern)
+                    && !self.literals.contains(pattern) =>
+            {
+                vec![s.to_string()]
+            }
+            Object::List(patterns) => patterns.iter().flat_map(|p| self.pattern_vars(p)).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+// テンプレートのパターン変数を置き換える。id が Some のときは、持ち込んだシンボルを別名にする。
+// quote の中のシンボルはデータなので別名にしない。
+fn instantiate(
+    template: &Object,
+    bindings: &Bindings,
+    id: Option<usize>,
+) -> Result<Object, String> {
+    match template {
+        Object::Symbol(s) => match bindings.get(s.as_ref()) {
+            Some(Binding::One(obj)) => Ok(obj.clone()),
+            Some(Binding::Many(_)) => Err(format!("syntax-rules: {} must be followed by ...", s)),
+            None => match id {
+                Some(id) => Ok(Object::Symbol(format!("{}#{}", s, id).into())),
+                None => Ok(template.clone()),
+            },
+        },
+        Object::List(items) => {
+            let id = match items.first() {
+                Some(Object::Keyword(SpecialForm::Quote)) => None,
+                _ => id,
+            };
+            let mut result = Vec::new();
+            let mut i = 0;
+            while i < items.len() {
+                if items.get(i + 1).is_some_and(is_ellipsis) {
+                    result.extend(instantiate_many(&items[i], bindings, id)?);
+                    i += 2;
+                } else {
+                    result.push(instantiate(&items[i], bindings, id)?);
+                    i += 1;
+                }
+            }
+            Ok(Object::List(Rc::new(result)))
+        }
+        _ => Ok(template.clone()),
+    }
+}
+
+// template ... を、template の中の繰り返しのパターン変数ごとに展開する。
+fn instantiate_many(
+    template: &Object,
+    bindings: &Bindings,
+    id: Option<usize>,
+) -> Result<Vec<Object>, String> {
+    let mut vars = Vec::new();
+    template_symbols(template, &mut vars);
+    let seqs: Vec<(&String, &Vec<Binding>)> = bindings
+        .iter()
+        .filter(|(name, _)| vars.contains(name))
+        .filter_map(|(name, binding)| match binding {
+            Binding::Many(seq) => Some((name, seq)),
+            Binding::One(_) => None,
+        })
+        .collect();
+    let Some(len) = seqs.first().map(|(_, seq)| seq.len()) else {
+        return Err(format!(
+            "syntax-rules: no pattern variable before ... in {}",
+            template
+        ));
+    };
+    if seqs.iter().any(|(_, seq)| seq.len() != len) {
+        return Err(format!(
+            "syntax-rules: mismatched ... lengths in {}",
+            template
+        ));
+    }
+
+    let mut result = Vec::with_capacity(len);
+    for i in 0..len {
+        let mut inner = bindings.clone();
+        for (name, seq) in &seqs {
+            inner.insert(name.to_string(), seq[i].clone());
+        }
+        result.push(instantiate(template, &inner, id)?);
+    }
+    Ok(result)
+}
+
+fn template_symbols(template: &Object, symbols: &mut Vec<String>) {
+    match template {
+        Object::Symbol(s) => symbols.push(s.to_string()),
+        Object::List(items) => items
+            .iter()
+            .for_each(|item| template_symbols(item, symbols)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn rules(spec: &str) -> SyntaxRules {
+        SyntaxRules::parse(&parse(spec).unwrap()).unwrap()
+    }
+
+    fn expand(rules: &SyntaxRules, form: &str) -> Result<String, String> {
+        match parse(form).unwrap() {
+            Object::List(form) => rules.expand(&form).map(|obj| obj.to_string()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let my_let = rules(
+            "(syntax-rules () ((_ ((name val) ...) body1 body2 ...) ((lambda (name ...) body1 body2 ...) val ...)))",
+        );
+        assert_eq!(
+            expand(&my_let, "(my-let ((a 1) (b (+ 1 1))) (print a) (+ a b))").unwrap(),
+            "((lambda (a b) (print a) (+ a b)) 1 (+ 1 1))"
+        );
+        assert!(expand(&my_let, "(my-let ((a 1)))").is_err());
+
+        let my_if = rules(
+            "(syntax-rules (then else) ((_ c then t else e) (if c t e)) ((_ c then t) (let ((tmp c)) (if tmp t 'tmp))))",
+        );
+        assert_eq!(
+            expand(&my_if, "(my-if x then 1 else 2)").unwrap(),
+            "(if x 1 2)"
+        );
+        let expanded = expand(&my_if, "(my-if x then tmp)").unwrap();
+        let id = EXPANSIONS.with(Cell::get);
+        assert_eq!(
+            expanded,
+            format!("(let ((tmp#{id} x)) (if tmp#{id} tmp (quote tmp)))")
+        );
+        assert!(expand(&my_if, "(my-if x else 1)").is_err());
+
+        assert_eq!(original_name("tmp#12"), Some("tmp"));
+        assert_eq!(original_name("a#b"), None);
+        assert_eq!(original_name("tmp"), None);
+    }
+}
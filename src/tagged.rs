@@ -0,0 +1,192 @@
+// NaN-boxing による 64bit の値表現の実験。
+// f64 はそのままのビット列で持ち、quiet NaN の空きビットにタグと 48bit のペイロードを詰める。
+// 48bit に収まらない整数や文字列・リストなどはヒープ上の Rc<Object> を指すポインタとして持つ。
+
+use std::{fmt, rc::Rc};
+
+use crate::parser::Object;
+
+#[cfg(not(target_pointer_width = "64"))]
+compile_error!("the `tagged-value` feature requires a 64-bit target");
+
+const BOX_PREFIX: u64 = 0xfff8_0000_0000_0000; // 符号ビット + quiet NaN
+const TAG_SHIFT: u32 = 48;
+const TAG_MASK: u64 = 0x0007_0000_0000_0000;
+const PAYLOAD_MASK: u64 = 0x0000_ffff_ffff_ffff;
+const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+const TAG_VOID: u64 = 1;
+const TAG_BOOL: u64 = 2;
+const TAG_INT: u64 = 3;
+const TAG_HEAP: u64 = 4;
+
+const INT_MIN: i64 = -(1 << 47);
+const INT_MAX: i64 = (1 << 47) - 1;
+
+pub struct Value(u64);
+
+impl Value {
+    fn boxed(tag: u64, payload: u64) -> Self {
+        Value(BOX_PREFIX | (tag << TAG_SHIFT) | (payload & PAYLOAD_MASK))
+    }
+
+    fn tag(&self) -> Option<u64> {
+        if self.0 & BOX_PREFIX == BOX_PREFIX && self.0 & TAG_MASK != 0 {
+            Some((self.0 & TAG_MASK) >> TAG_SHIFT)
+        } else {
+            None
+        }
+    }
+
+    fn payload(&self) -> u64 {
+        self.0 & PAYLOAD_MASK
+    }
+
+    fn heap_ptr(&self) -> *const Object {
+        self.payload() as usize as *const Object
+    }
+
+    /// ヒープを使わずに 64bit の中だけで表現できているか。
+    pub fn is_immediate(&self) -> bool {
+        self.tag() != Some(TAG_HEAP)
+    }
+}
+
+impl From<Object> for Value {
+    fn from(obj: Object) -> Self {
+        match obj {
+            Object::Void => Value::boxed(TAG_VOID, 0),
+            Object::Bool(b) => Value::boxed(TAG_BOOL, b as u64),
+            Object::Integer(n) if (INT_MIN..=INT_MAX).contains(&n) => {
+                Value::boxed(TAG_INT, n as u64)
+            }
+            Object::Float(f) if f.is_nan() => Value(CANONICAL_NAN),
+            Object::Float(f) => Value(f.to_bits()),
+            obj => {
+                let ptr = Rc::into_raw(Rc::new(obj)) as usize as u64;
+                debug_assert_eq!(ptr & !PAYLOAD_MASK, 0, "pointer does not fit in 48 bits");
+                Value::boxed(TAG_HEAP, ptr)
+            }
+        }
+    }
+}
+
+impl From<Value> for Object {
+    fn from(value: Value) -> Self {
+        Object::from(&value)
+    }
+}
+
+impl From<&Value> for Object {
+    fn from(value: &Value) -> Self {
+        match value.tag() {
+            None => Object::Float(f64::from_bits(value.0)),
+            Some(TAG_VOID) => Object::Void,
+            Some(TAG_BOOL) => Object::truth(value.payload() != 0),
+            // 48bit の符号拡張
+            Some(TAG_INT) => Object::Integer(((value.payload() << 16) as i64) >> 16),
+            Some(TAG_HEAP) => unsafe { (*value.heap_ptr()).clone() },
+            Some(tag) => unreachable!("unknown value tag: {}", tag),
+        }
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        if !self.is_immediate() {
+            unsafe { Rc::increment_strong_count(self.heap_ptr()) };
+        }
+        Value(self.0)
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        if !self.is_immediate() {
+            unsafe { Rc::decrement_strong_count(self.heap_ptr()) };
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        Object::from(self) == Object::from(other)
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Value({:#018x}, {:?})", self.0, Object::from(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(obj: Object) -> Object {
+        Object::from(Value::from(obj))
+    }
+
+    #[test]
+    fn test_value_size() {
+        assert_eq!(std::mem::size_of::<Value>(), 8);
+    }
+
+    #[test]
+    fn test_immediates() {
+        for obj in [
+            Object::Void,
+            Object::truth(true),
+            Object::truth(false),
+            Object::Integer(0),
+            Object::Integer(-1),
+            Object::Integer(INT_MIN),
+            Object::Integer(INT_MAX),
+            Object::Float(1.5),
+            Object::Float(-0.0),
+            Object::Float(f64::INFINITY),
+            Object::Float(f64::NEG_INFINITY),
+        ] {
+            assert!(Value::from(obj.clone()).is_immediate());
+            assert_eq!(roundtrip(obj.clone()), obj);
+        }
+        assert!(roundtrip(Object::Float(-0.0)).to_string().starts_with('-'));
+    }
+
+    #[test]
+    fn test_nan_is_canonicalized() {
+        let nan = f64::from_bits(0xfff8_0000_0000_0001);
+        let value = Value::from(Object::Float(nan));
+        assert!(value.is_immediate());
+        match Object::from(value) {
+            Object::Float(f) => assert!(f.is_nan()),
+            obj => panic!("expected float, got {:?}", obj),
+        }
+    }
+
+    #[test]
+    fn test_heap_values() {
+        for obj in [
+            Object::Integer(INT_MAX + 1),
+            Object::Integer(i64::MIN),
+            Object::String("hello".into()),
+            Object::Symbol("x".into()),
+            Object::ListData(Rc::new(vec![Object::Integer(1), Object::Integer(2)])),
+        ] {
+            assert!(!Value::from(obj.clone()).is_immediate());
+            assert_eq!(roundtrip(obj.clone()), obj);
+        }
+    }
+
+    #[test]
+    fn test_clone_and_drop_share_heap_cell() {
+        let list = Rc::new(vec![Object::Integer(1)]);
+        let value = Value::from(Object::ListData(Rc::clone(&list)));
+        let copy = value.clone();
+        drop(value);
+        assert_eq!(Object::from(&copy), Object::ListData(Rc::clone(&list)));
+        drop(copy);
+        assert_eq!(Rc::strong_count(&list), 1);
+    }
+}
@@ -0,0 +1,332 @@
+// mr-lisp test の本体。テストのファイルを探して評価し、(test name thunk) で登録したテストを順に実行する。
+//
+//   ; tests/math-test.lisp
+//   (test "addition" (lambda () (assert-equal 3 (+ 1 2))))
+//   (test "comparison" (lambda () (assert (<= 1 2) "1 <= 2")))
+//
+// mr-lisp test [--filter name] [path...] は、path (省略すると今のディレクトリ) の下の *-test.lisp と、
+// tests ディレクトリの下の *.lisp を探す。ファイルごとに新しい Interpreter で評価するので、定義は混ざらない。
+// テストの中の print の出力は、失敗したときだけ見せる。1 つでも失敗すれば終了コードは 1。
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::builtins::{ArgType, expect_string};
+use crate::config::Config;
+use crate::eval::{Env, apply, capture_output};
+use crate::interpreter::Interpreter;
+use crate::parser::Object;
+use crate::printer::debug;
+
+thread_local! {
+    // run_file で評価しているファイルの (test ...)。None ならその場で実行する
+    static TESTS: RefCell<Option<Vec<(String, Object)>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: Result<(), String>,
+    pub output: String, // テストの中の print の出力
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub tests: Vec<TestResult>,
+    pub error: Option<String>, // ファイルの評価に失敗した。それまでに登録したテストは実行する
+}
+
+impl FileResult {
+    pub fn failed(&self) -> usize {
+        self.tests.iter().filter(|t| t.outcome.is_err()).count() + self.error.iter().count()
+    }
+}
+
+// (test "name" (lambda () ...))
+pub(crate) fn test(args: &[Object], env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let name = expect_string("test", &args[0])?;
+    let thunk = &args[1];
+    let registered = TESTS.with_borrow_mut(|tests| {
+        tests
+            .as_mut()
+            .map(|tests| tests.push((name.to_string(), thunk.clone())))
+            .is_some()
+    });
+    if !registered {
+        apply(thunk, &[], env).map_err(|e| format!("test: {}: {}", name, e))?;
+    }
+    Ok(Object::Void)
+}
+
+// (assert (< 1 2)) や (assert ok "message")。条件は真偽値でなければならない。
+pub(crate) fn assert(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let message = match args.get(1) {
+        Some(message) => expect_string("assert", message)?,
+        None => "assertion failed",
+    };
+    match args[0] {
+        Object::Bool(false) => Err(format!("assert: {}", message)),
+        _ => Ok(Object::Void),
+    }
+}
+
+// (assert-equal expected actual)
+pub(crate) fn assert_equal(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (expected, actual) = (&args[0], &args[1]);
+    if expected != actual {
+        return Err(format!(
+            "assert-equal: expected {}, got {}",
+            debug(expected),
+            debug(actual)
+        ));
+    }
+    Ok(Object::Void)
+}
+
+// (assert-type x 'integer) は x が lambda の仮引数の注釈と同じ名前の型の値かを確かめる。
+pub(crate) fn assert_type(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let Object::Symbol(name) = &args[1] else {
+        unreachable!();
+    };
+    let ty =
+        ArgType::from_name(name).ok_or_else(|| format!("assert-type: unknown type {}", name))?;
+    if !ty.accepts(&args[0]) {
+        return Err(format!(
+            "assert-type: expected {}, got {}",
+            ty.with_article(),
+            debug(&args[0])
+        ));
+    }
+    Ok(args[0].clone())
+}
+
+// paths の下のテストのファイルを名前順に返す。paths が空なら今のディレクトリから探す。
+// ファイルを直接渡した場合は、名前によらずテストのファイルとして扱う。
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let default = [PathBuf::from(".")];
+    let roots = if paths.is_empty() { &default } else { paths };
+    let mut files = Vec::new();
+    for root in roots {
+        if root.is_file() {
+            files.push(root.clone());
+        } else if root.is_dir() {
+            let in_tests = root.file_name().is_some_and(|name| name == "tests");
+            walk(root, in_tests, &mut files)
+                .map_err(|e| format!("test: {}: {}", root.display(), e))?;
+        } else {
+            return Err(format!(
+                "test: {}: no such file or directory",
+                root.display()
+            ));
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+// . で始まるディレクトリと target は見ない。
+fn walk(dir: &Path, in_tests: bool, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                walk(&path, in_tests || name == "tests", files)?;
+            }
+        } else if name.ends_with("-test.lisp") || (in_tests && name.ends_with(".lisp")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// path を評価して、名前に filter を含むテストを実行する。path のディレクトリは import や load の探索パスに足す。
+pub fn run_file(path: &Path, config: &Config, filter: Option<&str>) -> FileResult {
+    let mut result = FileResult {
+        path: path.to_path_buf(),
+        tests: Vec::new(),
+        error: None,
+    };
+    let mut config = config.clone();
+    if let Some(dir) = path.parent() {
+        config.path.insert(0, dir.to_path_buf());
+    }
+    let mut interpreter = match Interpreter::with_config(config) {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            result.error = Some(e);
+            return result;
+        }
+    };
+    let (loaded, tests) = collect_tests(|| match std::fs::read_to_string(path) {
+        Ok(source) => interpreter
+            .eval_rich(&source)
+            .value
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    });
+    result.error = loaded.err();
+    for (name, thunk) in tests {
+        if filter.is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+        let start = Instant::now();
+        let (outcome, output) = capture_output(|| interpreter.apply(&thunk, &[]).map(|_| ()));
+        result.tests.push(TestResult {
+            name,
+            outcome,
+            output,
+            duration: start.elapsed(),
+        });
+    }
+    result
+}
+
+fn collect_tests<T>(f: impl FnOnce() -> T) -> (T, Vec<(String, Object)>) {
+    let outer = TESTS.replace(Some(Vec::new()));
+    let result = f();
+    let tests = TESTS.replace(outer).unwrap_or_default();
+    (result, tests)
+}
+
+// ファイル 1 つ分の結果。失敗したテストにはエラーと print の出力を付ける。
+pub fn format_file(file: &FileResult, color: bool) -> String {
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    };
+    let mut out = format!("{}\n", file.path.display());
+    for test in &file.tests {
+        let status = match test.outcome {
+            Ok(()) => paint("32", "ok   "),
+            Err(_) => paint("31", "FAIL "),
+        };
+        writeln!(out, "  {} {} ({:.2?})", status, test.name, test.duration).unwrap();
+        if let Err(e) = &test.outcome {
+            writeln!(out, "        {}", e).unwrap();
+            for line in test.output.lines() {
+                writeln!(out, "        | {}", line).unwrap();
+            }
+        }
+    }
+    if let Some(e) = &file.error {
+        writeln!(out, "  {} {}", paint("31", "ERROR"), e).unwrap();
+    }
+    out
+}
+
+// test result: ok. 3 passed; 0 failed; 2 files; finished in 12.34ms
+pub fn format_summary(files: &[FileResult], elapsed: Duration, color: bool) -> String {
+    let failed: usize = files.iter().map(FileResult::failed).sum();
+    let passed: usize = files
+        .iter()
+        .map(|file| file.tests.iter().filter(|t| t.outcome.is_ok()).count())
+        .sum();
+    let status = match (failed, color) {
+        (0, true) => "\x1b[32mok\x1b[0m",
+        (0, false) => "ok",
+        (_, true) => "\x1b[31mFAILED\x1b[0m",
+        (_, false) => "FAILED",
+    };
+    format!(
+        "test result: {}. {} passed; {} failed; {} files; finished in {:.2?}",
+        status,
+        passed,
+        failed,
+        files.len(),
+        elapsed
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runner() {
+        let dir = std::env::temp_dir().join(format!("mr-lisp-test-runner-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("tests")).unwrap();
+        std::fs::create_dir_all(dir.join(".hidden")).unwrap();
+        std::fs::write(dir.join("tests").join("helper.lisp"), "(define helped 1)").unwrap();
+        std::fs::write(dir.join(".hidden").join("skip-test.lisp"), "").unwrap();
+        std::fs::write(dir.join("notes.lisp"), "").unwrap();
+        let math = dir.join("math-test.lisp");
+        std::fs::write(
+            &math,
+            "(load \"tests/helper.lisp\")
+             (test \"addition\" (lambda () (assert-equal 3 (+ 1 2))))
+             (test \"wrong\" (lambda () (print \"debug\") (assert-equal '(1) (list helped 2))))
+             (test \"comparison\" (lambda () (assert (<= 2 1) \"2 <= 1\")))
+             (car 1)
+             (test \"never registered\" (lambda () 0))",
+        )
+        .unwrap();
+
+        assert_eq!(
+            discover(std::slice::from_ref(&dir)).unwrap(),
+            [math.clone(), dir.join("tests").join("helper.lisp")]
+        );
+        assert!(discover(&[dir.join("missing")]).is_err());
+
+        let file = run_file(&math, &Config::default(), None);
+        let outcomes: Vec<(&str, bool)> = file
+            .tests
+            .iter()
+            .map(|t| (t.name.as_str(), t.outcome.is_ok()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [("addition", true), ("wrong", false), ("comparison", false)]
+        );
+        assert_eq!(
+            file.tests[1].outcome,
+            Err("assert-equal: expected (1), got (1 2)".to_string())
+        );
+        assert_eq!(file.tests[1].output, "debug\n");
+        assert_eq!(
+            file.error.as_deref(),
+            Some("line 5, col 14: car: expected a pair, got 1")
+        );
+        assert_eq!(file.failed(), 3);
+        let report = format_file(&file, false);
+        assert!(report.contains("  FAIL  comparison ("), "{}", report);
+        assert!(report.contains("        assert: 2 <= 1\n"), "{}", report);
+        assert!(report.contains("        | debug\n"), "{}", report);
+
+        let filtered = run_file(&math, &Config::default(), Some("add"));
+        assert_eq!(filtered.tests.len(), 1);
+        let summary = format_summary(&[file, filtered], Duration::ZERO, false);
+        assert_eq!(
+            summary,
+            "test result: FAILED. 2 passed; 4 failed; 2 files; finished in 0.00ns"
+        );
+    }
+
+    #[test]
+    fn test_outside_runner() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.eval("(test \"now\" (lambda () (assert-equal 1 1)))"),
+            Ok(Object::Void)
+        );
+        assert_eq!(
+            interpreter.eval("(test \"now\" (lambda () (assert (= 1 1))))"),
+            Err("test: now: Unsupported binary operator: =".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(assert 1)"),
+            Err("assert: expected a boolean, got 1".to_string())
+        );
+    }
+}
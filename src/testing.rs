@@ -0,0 +1,164 @@
+// 評価結果をリポジトリに置いたスナップショットと比べるテスト用の仕組み。
+// スナップショットはテストのモジュールごとに snapshots/<モジュール>.snap の 1 ファイルで、
+// プログラムと、print の出力に続けて評価結果を書いた項目を並べる。
+//
+//     >>> (+ 1 2)
+//     3
+//
+// MR_LISP_UPDATE_SNAPSHOTS=1 を付けてテストを実行すると、食い違う項目や無い項目を書き換える。
+
+use crate::interpreter::Interpreter;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub use crate::assert_eval_snapshot;
+
+// 同じファイルを複数のテストが同時に書き換えないようにする
+static LOCK: Mutex<()> = Mutex::new(());
+
+// assert_eval_snapshot!(program) は、新しい Interpreter で program を評価して、
+// 呼び出したモジュールのスナップショットにある同じプログラムの項目と比べる。
+#[macro_export]
+macro_rules! assert_eval_snapshot {
+    ($program:expr) => {
+        $crate::testing::assert_snapshot(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/snapshots"),
+            module_path!(),
+            $program,
+        )
+    };
+}
+
+// スナップショットに書く内容。エラーになった場合は "error: " に続けてメッセージを書く。
+pub fn eval_snapshot(program: &str) -> String {
+    let result = Interpreter::new().eval_rich(program);
+    let value = match result.value {
+        Ok(value) => value.to_string(),
+        Err(e) => format!("error: {}", e.message),
+    };
+    format!("{}{}", result.output, value).trim_end().to_string()
+}
+
+#[track_caller]
+pub fn assert_snapshot(dir: &str, module: &str, program: &str) {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = Path::new(dir).join(format!("{}.snap", module.replace("::", "__")));
+    let program = normalize(program);
+    let actual = eval_snapshot(&program);
+    let mut entries = fs::read_to_string(&path)
+        .map(|text| parse_snapshots(&text))
+        .unwrap_or_default();
+    let expected = entries.iter().position(|(p, _)| *p == program);
+    if let Some(i) = expected
+        && entries[i].1 == actual
+    {
+        return;
+    }
+
+    if std::env::var_os("MR_LISP_UPDATE_SNAPSHOTS").is_some_and(|v| v != "0") {
+        match expected {
+            Some(i) => entries[i].1 = actual,
+            None => entries.push((program, actual)),
+        }
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&path, render_snapshots(&entries)).unwrap();
+        return;
+    }
+
+    let expected = match expected {
+        Some(i) => entries[i].1.as_str(),
+        None => "(no snapshot)",
+    };
+    panic!(
+        "snapshot mismatch in {}\nprogram:\n{}\nexpected:\n{}\nactual:\n{}\n\
+         rerun with MR_LISP_UPDATE_SNAPSHOTS=1 to update the snapshot",
+        path.display(),
+        program,
+        expected,
+        actual
+    );
+}
+
+// 字下げの違いで別の項目にならないよう、共通の字下げと前後の空白、行末の空白を取り除く
+fn normalize(program: &str) -> String {
+    let indent = program
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = program
+        .lines()
+        .map(|line| line.get(indent..).unwrap_or("").trim_end())
+        .collect();
+    lines.join("\n").trim().to_string()
+}
+
+fn parse_snapshots(text: &str) -> Vec<(String, String)> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+    let mut in_program = false;
+    for line in text.lines() {
+        if let Some(code) = line.strip_prefix(">>>") {
+            let code = code.strip_prefix(' ').unwrap_or(code);
+            if in_program {
+                let (program, _) = entries.last_mut().unwrap();
+                program.push('\n');
+                program.push_str(code);
+            } else {
+                entries.push((code.to_string(), String::new()));
+                in_program = true;
+            }
+        } else if let Some((_, body)) = entries.last_mut() {
+            in_program = false;
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    for (_, body) in &mut entries {
+        *body = body.trim_end().to_string();
+    }
+    entries
+}
+
+fn render_snapshots(entries: &[(String, String)]) -> String {
+    let mut text = String::new();
+    for (program, body) in entries {
+        for line in program.lines() {
+            text.push_str(format!(">>> {}", line).trim_end());
+            text.push('\n');
+        }
+        text.push_str(body);
+        text.push_str("\n\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_file() {
+        assert_eq!(eval_snapshot("(print \"a\") (+ 1 2)"), "a\n3");
+        assert_eq!(eval_snapshot("(f 1)"), "error: Undefined function: f");
+
+        let entries = vec![
+            ("(+ 1 2)".to_string(), "3".to_string()),
+            (
+                "(begin\n\n  (print \"a\"))".to_string(),
+                "a\n\nVoid".to_string(),
+            ),
+        ];
+        let text = render_snapshots(&entries);
+        assert_eq!(
+            text,
+            ">>> (+ 1 2)\n3\n\n>>> (begin\n>>>\n>>>   (print \"a\"))\na\n\nVoid\n\n"
+        );
+        assert_eq!(parse_snapshots(&text), entries);
+        assert_eq!(
+            normalize("\n    (f  \n      x)\n    (g)\n  "),
+            "(f\n  x)\n(g)"
+        );
+    }
+}
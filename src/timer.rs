@@ -0,0 +1,167 @@
+// after や every で登録した関数を、run-event-loop の中で時刻になったら呼ぶ。
+//
+//   (define id (every 1000 (lambda () (print "tick"))))
+//   (after 5000 (lambda () (cancel-timer id)))
+//   (run-event-loop #:timeout 10000)
+//
+// 時間はミリ秒。after と every はタイマーの番号を返し、cancel-timer に渡すと取り消せる。
+// run-event-loop はタイマーが無くなるか #:timeout の時間が過ぎると戻る。
+// 呼んだ関数がエラーになった場合は、run-event-loop もそのエラーで終わる。
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::builtins::{expect_usize, split_keyword_args};
+use crate::eval::{Env, apply};
+use crate::parser::Object;
+
+struct Timer {
+    id: i64,
+    due: Instant,
+    interval: Option<Duration>, // every で登録したものだけ Some
+    thunk: Object,
+}
+
+thread_local! {
+    static TIMERS: RefCell<Vec<Timer>> = const { RefCell::new(Vec::new()) };
+    static NEXT_ID: Cell<i64> = const { Cell::new(1) };
+}
+
+fn schedule(name: &str, args: &[Object], repeat: bool) -> Result<Object, String> {
+    let ms = Duration::from_millis(expect_usize(name, &args[0])? as u64);
+    if repeat && ms.is_zero() {
+        return Err(format!("{}: interval must be positive", name));
+    }
+    let id = NEXT_ID.replace(NEXT_ID.get() + 1);
+    TIMERS.with_borrow_mut(|timers| {
+        timers.push(Timer {
+            id,
+            due: Instant::now() + ms,
+            interval: repeat.then_some(ms),
+            thunk: args[1].clone(),
+        })
+    });
+    Ok(Object::Integer(id))
+}
+
+// (after ms thunk) は ms 後に thunk を 1 度だけ呼ぶ。
+pub(crate) fn after(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    schedule("after", args, false)
+}
+
+// (every ms thunk) は ms ごとに thunk を呼ぶ。
+pub(crate) fn every(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    schedule("every", args, true)
+}
+
+// (cancel-timer id) はタイマーを取り消す。まだ残っていたタイマーを取り消した場合だけ true。
+pub(crate) fn cancel_timer(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    // i64 に収まらない id のタイマーは無い
+    let Object::Integer(id) = &args[0] else {
+        return Ok(Object::truth(false));
+    };
+    TIMERS.with_borrow_mut(|timers| {
+        let len = timers.len();
+        timers.retain(|timer| timer.id != *id);
+        Ok(Object::truth(timers.len() < len))
+    })
+}
+
+// 一番早く時刻になるタイマーを取り出す。同じ時刻なら先に登録したもの。every のタイマーは次の時刻で登録し直す。
+fn next_timer(deadline: Option<Instant>) -> Option<(Instant, Object)> {
+    TIMERS.with_borrow_mut(|timers| {
+        let i = (0..timers.len()).min_by_key(|&i| (timers[i].due, timers[i].id))?;
+        let due = timers[i].due;
+        if deadline.is_some_and(|deadline| due > deadline) {
+            return None;
+        }
+        let thunk = timers[i].thunk.clone();
+        match timers[i].interval {
+            Some(interval) => timers[i].due += interval,
+            None => {
+                timers.remove(i);
+            }
+        }
+        Some((due, thunk))
+    })
+}
+
+// (run-event-loop #:timeout ms)
+pub(crate) fn run_event_loop(
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let name = "run-event-loop";
+    let (positional, keywords) = split_keyword_args(name, args)?;
+    if let Some(arg) = positional.first() {
+        return Err(format!("{}: unexpected argument {}", name, arg));
+    }
+    let mut deadline = None;
+    for (kw, value) in keywords {
+        match kw {
+            "timeout" => {
+                let ms = expect_usize(name, value)? as u64;
+                deadline = Some(Instant::now() + Duration::from_millis(ms));
+            }
+            _ => return Err(format!("{}: unknown keyword #:{}", name, kw)),
+        }
+    }
+
+    while let Some((due, thunk)) = next_timer(deadline) {
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        apply(&thunk, &[], env).map_err(|e| format!("{}: {}", name, e))?;
+    }
+    if let Some(deadline) = deadline {
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    }
+    Ok(Object::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_timers() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define n 0)
+            (define order 0)
+            (define ticker (every 5 (lambda () (set! n (+ n 1)))))
+            (after 32 (lambda () (set! order (* order 10)) (cancel-timer ticker)))
+            (after 1 (lambda () (set! order (+ order 1))))
+            (after 1 (lambda () (set! order (+ order 2))))
+            (run-event-loop)
+            (list n order)
+        ";
+        assert_eq!(interpreter.eval(program).unwrap().to_string(), "(6 30)");
+        assert_eq!(
+            interpreter.eval("(cancel-timer ticker)"),
+            Ok(Object::truth(false))
+        );
+
+        let start = Instant::now();
+        let program = "
+            (set! n 0)
+            (define ticker (every 10 (lambda () (set! n (+ n 1)))))
+            (run-event-loop #:timeout 25)
+            n
+        ";
+        assert_eq!(interpreter.eval(program), Ok(Object::Integer(2)));
+        assert!(start.elapsed() >= Duration::from_millis(25));
+        assert_eq!(
+            interpreter.eval("(cancel-timer ticker)"),
+            Ok(Object::truth(true))
+        );
+
+        assert_eq!(
+            interpreter.eval("(after 0 (lambda () (undefined))) (run-event-loop)"),
+            Err("run-event-loop: Undefined function: undefined".to_string())
+        );
+        assert!(interpreter.eval("(every 0 (lambda () 1))").is_err());
+        assert!(interpreter.eval("(after 1 2)").is_err());
+    }
+}
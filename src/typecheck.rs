@@ -0,0 +1,654 @@
+// mr-lisp typecheck の本体。ソースを評価せずに読んで、実行すれば失敗するとわかる呼び出しを探す。
+//
+//   (define (repeat s (n : integer)) ...)
+//   (repeat "ab" "3")          ; repeat expected n to be an integer, got a string
+//   (string-length 42)          ; string-length: expected a string, got an integer
+//
+// 確かめるのは、builtin! で引数の並びを宣言した組み込み関数と、define や let で束縛した lambda の呼び出しの
+// 引数の数と型、二項演算子の引数が数かどうか、関数でない値を呼んでいないか。
+// 値の型はリテラル、注釈した仮引数、演算子の結果と、それらを束縛した変数からだけ推論する。わからない値は any として
+// 何も報告しないので、見逃しはあっても、正しいプログラムを誤りとすることはない。
+// set! する変数や 2 回 define する名前は、型がわからないものとして扱う。マクロの呼び出しの中は見ない。
+
+use std::collections::{HashMap, HashSet};
+
+use crate::builtins::{ArgType, BUILTINS, Param, check_arity, param_types};
+use crate::interpreter::{ErrorKind, LispError};
+use crate::keyword::{Op, SpecialForm};
+use crate::parser::{Object, SourceMap, Span, parse_mapped};
+use crate::printer::debug;
+
+// 名前に束縛したもののうち、わかっていること
+#[derive(Debug, Clone)]
+enum Known {
+    Value(ArgType),
+    // 仮引数の名前と型。注釈の無い仮引数は Any
+    Function {
+        name: String,
+        params: Vec<(String, ArgType)>,
+    },
+    Macro,
+}
+
+struct Checker<'a> {
+    program: &'a str,
+    source_map: &'a SourceMap,
+    form: Span, // 確かめているトップレベルの式
+    scopes: Vec<HashMap<String, Known>>,
+    mutated: HashSet<String>, // set! したり、同じスコープで 2 回 define したりする名前
+    errors: Vec<LispError>,
+}
+
+// program を読んで、見つけた誤りをソースの順に返す。読めなければ、その ParseError だけを返す。
+pub fn typecheck(program: &str) -> Vec<LispError> {
+    let (forms, source_map) = match parse_mapped(program) {
+        Ok(parsed) => parsed,
+        Err((e, span)) => {
+            return vec![LispError::new(
+                ErrorKind::Parse,
+                e.to_string(),
+                span.clone(),
+                program,
+                span,
+            )];
+        }
+    };
+    let mut mutated = HashSet::new();
+    for (form, _) in &forms {
+        collect_set_targets(form, &mut mutated);
+    }
+    let mut checker = Checker {
+        program,
+        source_map: &source_map,
+        form: 0..0,
+        scopes: Vec::new(),
+        mutated,
+        errors: Vec::new(),
+    };
+    let body: Vec<Object> = forms.iter().map(|(form, _)| form.clone()).collect();
+    checker.enter(&[], &body);
+    for (form, span) in &forms {
+        checker.form = span.clone();
+        checker.expr(form);
+    }
+    checker.errors
+}
+
+fn collect_set_targets(obj: &Object, mutated: &mut HashSet<String>) {
+    if let Object::List(list) = obj {
+        if let [Object::Keyword(SpecialForm::Set), Object::Symbol(name), ..] = &list[..] {
+            mutated.insert(name.to_string());
+        }
+        for item in list.iter() {
+            collect_set_targets(item, mutated);
+        }
+    }
+}
+
+// 型を確かめなくてもわかる、リテラルの型
+fn literal_type(obj: &Object) -> ArgType {
+    match obj {
+        Object::Integer(_) | Object::BigInt(_) => ArgType::Integer,
+        Object::Float(_) => ArgType::Number,
+        Object::String(_) => ArgType::String,
+        Object::Char(_) => ArgType::Char,
+        Object::Bool(_) => ArgType::Boolean,
+        _ => ArgType::Any,
+    }
+}
+
+// 型が expected の引数に actual の型の値を渡しても、失敗するとは限らないか
+fn compatible(expected: ArgType, actual: ArgType) -> bool {
+    use ArgType::{Any, Integer, Number};
+    expected == actual
+        || matches!(
+            (expected, actual),
+            (Any, _) | (_, Any) | (Number, Integer) | (Integer, Number)
+        )
+}
+
+impl Checker<'_> {
+    fn error(&mut self, list: &Vec<Object>, message: String) {
+        let at = self
+            .source_map
+            .span(list)
+            .unwrap_or_else(|| self.form.clone());
+        self.errors.push(LispError::new(
+            ErrorKind::Type,
+            message,
+            self.form.clone(),
+            self.program,
+            at,
+        ));
+    }
+
+    fn lookup(&self, name: &str) -> Option<Known> {
+        if let Some(known) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Some(known.clone());
+        }
+        BUILTINS
+            .iter()
+            .any(|builtin| builtin.name == name)
+            .then_some(Known::Value(ArgType::Procedure))
+    }
+
+    fn bind(&mut self, name: &str, known: Known) {
+        let known = match known {
+            _ if self.mutated.contains(name) => Known::Value(ArgType::Any),
+            known => known,
+        };
+        self.scopes
+            .last_mut()
+            .expect("scope")
+            .insert(name.to_string(), known);
+    }
+
+    // 新しいスコープに params を束縛し、body の中の define を先に宣言する。
+    // 後で定義する関数を前の関数の本体から呼べるように、本体を確かめる前にすべて宣言しておく。
+    fn enter(&mut self, params: &[(String, Known)], body: &[Object]) {
+        self.scopes.push(HashMap::new());
+        for (name, known) in params {
+            self.bind(name, known.clone());
+        }
+        let mut defined = HashSet::new();
+        self.declare(body, &mut defined);
+    }
+
+    fn declare(&mut self, body: &[Object], defined: &mut HashSet<String>) {
+        for form in body {
+            let Object::List(list) = form else {
+                continue;
+            };
+            let (name, known) = match &list[..] {
+                [Object::Keyword(SpecialForm::Begin), rest @ ..] => {
+                    self.declare(rest, defined);
+                    continue;
+                }
+                [
+                    Object::Keyword(SpecialForm::Define | SpecialForm::DefinePrivate),
+                    Object::List(signature),
+                    ..,
+                ] => match signature.split_first() {
+                    Some((Object::Symbol(name), params)) => {
+                        (name.to_string(), self.function(name, params))
+                    }
+                    _ => continue,
+                },
+                [
+                    Object::Keyword(SpecialForm::Define | SpecialForm::DefinePrivate),
+                    Object::Symbol(name),
+                    value,
+                ] => (name.to_string(), self.binding_of(name, value)),
+                [
+                    Object::Keyword(SpecialForm::DefineMacro | SpecialForm::DefineSyntax),
+                    name,
+                    ..,
+                ] => {
+                    // (define-macro (name args...) ...) と (define-syntax name ...)
+                    let name = match name {
+                        Object::List(signature) => signature.first(),
+                        name => Some(name),
+                    };
+                    let Some(Object::Symbol(name)) = name else {
+                        continue;
+                    };
+                    (name.to_string(), Known::Macro)
+                }
+                _ => continue,
+            };
+            // 2 回定義する名前は、どちらの値かわからない
+            if !defined.insert(name.clone()) {
+                self.mutated.insert(name.clone());
+            }
+            self.bind(&name, known);
+        }
+    }
+
+    // 束縛する前にわかること。lambda なら引数の型、リテラルならその型
+    fn binding_of(&self, name: &str, value: &Object) -> Known {
+        match value {
+            Object::List(list) => match &list[..] {
+                [
+                    Object::Keyword(SpecialForm::Lambda),
+                    Object::List(params),
+                    _,
+                    ..,
+                ] => self.function(name, params),
+                _ => Known::Value(ArgType::Any),
+            },
+            value => Known::Value(literal_type(value)),
+        }
+    }
+
+    fn function(&self, name: &str, params: &[Object]) -> Known {
+        match parse_params(params) {
+            Ok(params) => Known::Function {
+                name: name.to_string(),
+                params,
+            },
+            Err(_) => Known::Value(ArgType::Procedure),
+        }
+    }
+
+    // obj を確かめて、その値の型を返す。
+    fn expr(&mut self, obj: &Object) -> ArgType {
+        match obj {
+            Object::Symbol(name) => match self.lookup(name) {
+                Some(Known::Value(ty)) => ty,
+                Some(Known::Function { .. }) => ArgType::Procedure,
+                _ => ArgType::Any,
+            },
+            Object::List(list) => self.list(list),
+            obj => literal_type(obj),
+        }
+    }
+
+    fn body(&mut self, body: &[Object]) -> ArgType {
+        body.iter()
+            .map(|obj| self.expr(obj))
+            .last()
+            .unwrap_or(ArgType::Any)
+    }
+
+    fn list(&mut self, list: &Vec<Object>) -> ArgType {
+        let Some((head, args)) = list.split_first() else {
+            return ArgType::Any;
+        };
+        match head {
+            Object::Keyword(form) => self.special_form(*form, list),
+            Object::BinaryOp(op) => self.binary_op(*op, list),
+            Object::Symbol(name) => match self.lookup(name) {
+                Some(Known::Macro) => ArgType::Any,
+                Some(Known::Function { name, params }) => {
+                    let arg_types: Vec<ArgType> = args.iter().map(|arg| self.expr(arg)).collect();
+                    // #:name で渡すと並びが変わるので、数も型も確かめない
+                    if args.iter().any(|arg| matches!(arg, Object::KeywordArg(_))) {
+                        return ArgType::Any;
+                    }
+                    if params.len() != args.len() {
+                        let plural = if params.len() == 1 { "" } else { "s" };
+                        self.error(
+                            list,
+                            format!(
+                                "{}: expected {} argument{}, got {}",
+                                name,
+                                params.len(),
+                                plural,
+                                args.len()
+                            ),
+                        );
+                        return ArgType::Any;
+                    }
+                    for ((param, expected), actual) in params.into_iter().zip(arg_types) {
+                        if !compatible(expected, actual) {
+                            self.error(
+                                list,
+                                format!(
+                                    "{} expected {} to be {}, got {}",
+                                    name,
+                                    param,
+                                    expected.with_article(),
+                                    actual.with_article()
+                                ),
+                            );
+                        }
+                    }
+                    ArgType::Any
+                }
+                Some(Known::Value(ArgType::Procedure)) => {
+                    let arg_types: Vec<ArgType> = args.iter().map(|arg| self.expr(arg)).collect();
+                    if let Some(params) = self.builtin_params(name) {
+                        self.builtin_call(name, params, &arg_types, list);
+                    }
+                    ArgType::Any
+                }
+                Some(Known::Value(ty)) if ty != ArgType::Any => {
+                    self.error(list, format!("{} is not a function", name));
+                    ArgType::Any
+                }
+                _ => {
+                    self.body(args);
+                    ArgType::Any
+                }
+            },
+            _ => {
+                self.body(list);
+                ArgType::Any
+            }
+        }
+    }
+
+    // name がスコープで束縛されていない組み込み関数なら、その引数の並び
+    fn builtin_params(&self, name: &str) -> Option<&'static [Param]> {
+        if self.scopes.iter().any(|scope| scope.contains_key(name)) {
+            return None;
+        }
+        BUILTINS.iter().find(|builtin| builtin.name == name)?.params
+    }
+
+    fn builtin_call(
+        &mut self,
+        name: &str,
+        params: &[Param],
+        arg_types: &[ArgType],
+        list: &Vec<Object>,
+    ) {
+        if let Err(message) = check_arity(name, params, arg_types.len()) {
+            self.error(list, message);
+            return;
+        }
+        for (expected, &actual) in param_types(params).zip(arg_types) {
+            if !compatible(expected, actual) {
+                let message = format!(
+                    "{}: expected {}, got {}",
+                    name,
+                    expected.with_article(),
+                    actual.with_article()
+                );
+                self.error(list, message);
+            }
+        }
+    }
+
+    fn binary_op(&mut self, op: Op, list: &Vec<Object>) -> ArgType {
+        let types: Vec<ArgType> = list[1..].iter().map(|arg| self.expr(arg)).collect();
+        let arithmetic = matches!(op, Op::Add | Op::Sub | Op::Mul | Op::Div);
+        let comparison = matches!(op, Op::Lt | Op::Gt | Op::Le | Op::Ge | Op::EqEq | Op::Ne);
+        if !arithmetic && !comparison {
+            return ArgType::Any;
+        }
+        if types.len() != 2 {
+            self.error(
+                list,
+                format!("{}: expected 2 arguments, got {}", op, types.len()),
+            );
+            return ArgType::Any;
+        }
+        for &ty in &types {
+            if !compatible(ArgType::Number, ty) {
+                self.error(
+                    list,
+                    format!("{}: expected a number, got {}", op, ty.with_article()),
+                );
+                return ArgType::Any;
+            }
+        }
+        match (comparison, &types[..]) {
+            (true, _) => ArgType::Boolean,
+            (false, [ArgType::Integer, ArgType::Integer]) => ArgType::Integer,
+            (false, [ArgType::Any, _] | [_, ArgType::Any]) => ArgType::Any,
+            (false, _) => ArgType::Number,
+        }
+    }
+
+    fn special_form(&mut self, form: SpecialForm, list: &Vec<Object>) -> ArgType {
+        match (form, &list[1..]) {
+            (SpecialForm::Quote, [Object::Symbol(_)]) => ArgType::Symbol,
+            (SpecialForm::Quote, [datum]) => literal_type(datum),
+            (SpecialForm::Lambda, [Object::List(params), body @ ..]) => {
+                self.lambda(params, body, list);
+                ArgType::Procedure
+            }
+            (
+                SpecialForm::Define | SpecialForm::DefinePrivate,
+                [Object::List(signature), body @ ..],
+            ) => {
+                if let Some((_, params)) = signature.split_first() {
+                    self.lambda(params, body, list);
+                }
+                ArgType::Any
+            }
+            (SpecialForm::Define | SpecialForm::DefinePrivate, [Object::Symbol(name), value]) => {
+                let ty = self.expr(value);
+                // 式から推論した型は、定義した後の式でだけ使う
+                if let Some(Known::Value(_)) = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(name.as_ref()))
+                {
+                    self.bind(name, Known::Value(ty));
+                }
+                ArgType::Any
+            }
+            (SpecialForm::Let, [Object::Symbol(name), Object::List(bindings), body @ ..]) => {
+                // 名前付き let の name は、束縛の数の引数を取る関数。呼び直すときは別の型の値も渡せる
+                let bindings = let_bindings(bindings);
+                for (_, init) in &bindings {
+                    self.expr(init);
+                }
+                let params: Vec<(String, ArgType)> = bindings
+                    .iter()
+                    .map(|(var, _)| (var.to_string(), ArgType::Any))
+                    .collect();
+                let function = Known::Function {
+                    name: name.to_string(),
+                    params: params.clone(),
+                };
+                self.scopes.push(HashMap::new());
+                self.bind(name, function);
+                let params: Vec<(String, Known)> = params
+                    .into_iter()
+                    .map(|(var, ty)| (var, Known::Value(ty)))
+                    .collect();
+                let ty = self.scope(&params, body);
+                self.scopes.pop();
+                ty
+            }
+            (SpecialForm::Let, [Object::List(bindings), body @ ..]) => {
+                let params: Vec<(String, Known)> = let_bindings(bindings)
+                    .into_iter()
+                    .map(|(var, init)| {
+                        let known = self.init(var, init);
+                        (var.to_string(), known)
+                    })
+                    .collect();
+                self.scope(&params, body)
+            }
+            (SpecialForm::LetStar, [Object::List(bindings), body @ ..]) => {
+                let depth = self.scopes.len();
+                for (var, init) in let_bindings(bindings) {
+                    let known = self.init(var, init);
+                    self.scopes.push(HashMap::new());
+                    self.bind(var, known);
+                }
+                let ty = self.scope(&[], body);
+                self.scopes.truncate(depth);
+                ty
+            }
+            (
+                SpecialForm::Letrec | SpecialForm::LetrecStar,
+                [Object::List(bindings), body @ ..],
+            ) => {
+                let bindings = let_bindings(bindings);
+                let params: Vec<(String, Known)> = bindings
+                    .iter()
+                    .map(|(var, init)| (var.to_string(), self.binding_of(var, init)))
+                    .collect();
+                self.enter(&params, &[]);
+                for (_, init) in &bindings {
+                    self.expr(init);
+                }
+                let ty = self.scope(&[], body);
+                self.scopes.pop();
+                ty
+            }
+            (SpecialForm::If, [test, then, rest @ ..]) => {
+                self.expr(test);
+                let then = self.expr(then);
+                match rest {
+                    [otherwise] if self.expr(otherwise) == then => then,
+                    _ => ArgType::Any,
+                }
+            }
+            (SpecialForm::Cond, clauses) => {
+                for clause in clauses {
+                    match clause {
+                        Object::List(clause) => {
+                            for obj in clause.iter() {
+                                if !matches!(obj, Object::Keyword(_)) {
+                                    self.expr(obj);
+                                }
+                            }
+                        }
+                        obj => {
+                            self.expr(obj);
+                        }
+                    }
+                }
+                ArgType::Any
+            }
+            (SpecialForm::Set, [Object::Symbol(_), value]) => {
+                self.expr(value);
+                ArgType::Any
+            }
+            (SpecialForm::Begin, body) => self.body(body),
+            (SpecialForm::And | SpecialForm::Or, args) => {
+                self.body(args);
+                ArgType::Any
+            }
+            (
+                SpecialForm::When
+                | SpecialForm::Unless
+                | SpecialForm::While
+                | SpecialForm::List
+                | SpecialForm::Print
+                | SpecialForm::Delay,
+                args,
+            ) => {
+                self.body(args);
+                ArgType::Any
+            }
+            // 残りの特殊形式は中を見ない
+            _ => ArgType::Any,
+        }
+    }
+
+    // let の束縛の式を確かめて、変数についてわかること
+    fn init(&mut self, var: &str, init: &Object) -> Known {
+        let ty = self.expr(init);
+        match self.binding_of(var, init) {
+            Known::Value(_) => Known::Value(ty),
+            known => known,
+        }
+    }
+
+    fn scope(&mut self, params: &[(String, Known)], body: &[Object]) -> ArgType {
+        self.enter(params, body);
+        let ty = self.body(body);
+        self.scopes.pop();
+        ty
+    }
+
+    fn lambda(&mut self, params: &[Object], body: &[Object], list: &Vec<Object>) {
+        match parse_params(params) {
+            Ok(params) => {
+                let params: Vec<(String, Known)> = params
+                    .into_iter()
+                    .map(|(name, ty)| (name, Known::Value(ty)))
+                    .collect();
+                self.scope(&params, body);
+            }
+            Err(message) => self.error(list, message),
+        }
+    }
+}
+
+// lambda の仮引数。注釈の無い仮引数の型は Any
+fn parse_params(params: &[Object]) -> Result<Vec<(String, ArgType)>, String> {
+    params
+        .iter()
+        .map(|param| match param {
+            Object::Symbol(s) => Ok((s.to_string(), ArgType::Any)),
+            Object::List(annotated) => match &annotated[..] {
+                [Object::Symbol(s), Object::Symbol(colon), Object::Symbol(ty)]
+                    if colon.as_ref() == ":" =>
+                {
+                    let ty = ArgType::from_name(ty)
+                        .ok_or_else(|| format!("Unknown parameter type: {}", ty))?;
+                    Ok((s.to_string(), ty))
+                }
+                _ => Err(format!("Invalid lamdba parameter: {}", debug(param))),
+            },
+            _ => Err(format!("Invalid lamdba parameter: {}", debug(param))),
+        })
+        .collect()
+}
+
+fn let_bindings(bindings: &[Object]) -> Vec<(&str, &Object)> {
+    bindings
+        .iter()
+        .filter_map(|binding| match binding {
+            Object::List(binding) => match &binding[..] {
+                [Object::Symbol(var), init] => Some((var.as_ref(), init)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(program: &str) -> Vec<String> {
+        typecheck(program).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_typecheck() {
+        let program = r#"
+(define (repeat s (n : integer)) (if (<= n 0) "" s))
+(define greeting "hello")
+(define (shout s) (string-ref s 0))
+(repeat "ab" "3")
+(string-length (+ 1 2))
+(shout 1 2)
+(let ((n (string-length greeting)))
+  (+ greeting n)
+  (greeting))
+(char->integer #\a #\b)
+"#;
+        assert_eq!(
+            messages(program),
+            [
+                "line 5, col 1: repeat expected n to be an integer, got a string",
+                "line 6, col 1: string-length: expected a string, got an integer",
+                "line 7, col 1: shout: expected 1 argument, got 2",
+                "line 9, col 3: +: expected a number, got a string",
+                "line 10, col 3: greeting is not a function",
+                "line 11, col 1: char->integer: expected 1 argument, got 2",
+            ]
+        );
+        assert_eq!(typecheck(program)[0].kind, ErrorKind::Type);
+    }
+
+    #[test]
+    fn test_typecheck_is_conservative() {
+        // 値がわからない式や、書き換える変数、名前を付け直した組み込み関数は報告しない
+        let program = r#"
+(define x 1)
+(set! x "one")
+(string-length x)
+(define (f s) (string-length s))
+(f 1)
+(define (string-length n) n)
+(string-length 1)
+(define-macro (swap a b) (list 'begin b a))
+(swap (+ "a" 1) 2)
+(let loop ((i 0)) (if (< i 3) (loop (+ i 1)) i))
+(define (g k) k)
+(g #:k 1)
+(even? 'a)
+"#;
+        assert_eq!(messages(program), Vec::<String>::new());
+        assert_eq!(
+            messages("(define (f (x : float)) x)"),
+            ["line 1, col 1: Unknown parameter type: float"]
+        );
+        assert_eq!(
+            messages("(f 1)\n(f"),
+            ["line 2, col 1: ParseError: Expected ')' at the end of list"]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+// values による多値。リストを作らずに複数の結果を返す。
+//
+//   (define (divmod a b) (values (/ a b) (- a (* (/ a b) b))))
+//   (call-with-values (lambda () (divmod 7 2)) (lambda (q r) (list q r)))
+//   (let-values (((q r) (divmod 7 2))) (+ q r))
+//
+// 値が 1 つの values はその値そのものになる。それ以外は多値を表す values オブジェクトになり、
+// call-with-values や let-values で受け取ると引数や変数に展開される。
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::continuation::escaping;
+use crate::eval::{Env, apply};
+use crate::parser::{Foreign, Object};
+
+#[derive(Debug)]
+struct Values(Vec<Object>);
+
+impl Foreign for Values {
+    fn type_name(&self) -> &str {
+        "values"
+    }
+}
+
+// 式の結果を値の並びにする。values オブジェクトでなければ値 1 つの並びになる。
+pub(crate) fn spread(obj: Object) -> Vec<Object> {
+    if let Object::Foreign(foreign) = &obj
+        && let Some(values) = (foreign.as_ref() as &dyn Any).downcast_ref::<Values>()
+    {
+        return values.0.clone();
+    }
+    vec![obj]
+}
+
+// (values a b ...) は引数をすべて多値として返す。
+pub(crate) fn values(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    match args {
+        [value] => Ok(value.clone()),
+        _ => Ok(Object::Foreign(Rc::new(Values(args.to_vec())))),
+    }
+}
+
+// (call-with-values producer consumer) は producer を引数なしで呼び、返った値を引数にして consumer を呼ぶ。
+pub(crate) fn call_with_values(
+    args: &[Object],
+    env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    apply(&args[0], &[], env)
+        .and_then(|produced| apply(&args[1], &spread(produced), env))
+        .map_err(|e| {
+            if escaping() {
+                e
+            } else {
+                format!("call-with-values: {}", e)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_values() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define (divmod a b) (values (/ a b) (- a (* (/ a b) b))))
+            (list
+                (call-with-values (lambda () (divmod 7 2)) (lambda (q r) (list q r)))
+                (call-with-values (lambda () (values)) (lambda () 'none))
+                (call-with-values (lambda () 5) (lambda (x) (* x 2)))
+                (let-values (((q r) (divmod 17 5)) ((x) (values 1))) (list q r x))
+                (values 3))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "((3 1) none 10 (3 2 1) 3)"
+        );
+        assert_eq!(
+            interpreter.eval("(call-with-values (lambda () (divmod 7 2)) (lambda (q) q))"),
+            Err("call-with-values: Expected 1 arguments, got 2: Lambda(q) q".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(let-values (((q r) (values 1))) q)"),
+            Err("let-values: expected 2 values, got 1".to_string())
+        );
+    }
+}
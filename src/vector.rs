@@ -0,0 +1,127 @@
+// 添字で O(1) で読み書きできるベクタ。#(1 2 3) で書けて、評価すると新しいベクタになる。
+//
+//   (define v (make-vector 3 0))   ; #(0 0 0)
+//   (vector-set! v 0 'a)           ; #(a 0 0)
+//   (vector-ref #(1 2 3) 1)        ; 2
+//
+// vector-set! は同じベクタを指しているすべての値から見える。#(...) はリテラルでも評価するたびにコピーを作るので、
+// 書き換えても次に評価したときの値は変わらない。
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::builtins::expect_usize;
+use crate::eval::Env;
+use crate::parser::Object;
+
+fn expect_vector<'a>(name: &str, obj: &'a Object) -> Result<&'a RefCell<Vec<Object>>, String> {
+    match obj {
+        Object::Vector(vector) => Ok(vector),
+        _ => Err(format!("{}: expected a vector, got {}", name, obj)),
+    }
+}
+
+fn expect_index(name: &str, vector: &[Object], obj: &Object) -> Result<usize, String> {
+    let index = expect_usize(name, obj)?;
+    if index < vector.len() {
+        Ok(index)
+    } else {
+        Err(format!(
+            "{}: index {} out of range for length {}",
+            name,
+            index,
+            vector.len()
+        ))
+    }
+}
+
+pub(crate) fn make_vector(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let len = expect_usize("make-vector", &args[0])?;
+    let fill = args.get(1).cloned().unwrap_or(Object::Integer(0));
+    Ok(Object::Vector(Rc::new(RefCell::new(vec![fill; len]))))
+}
+
+pub(crate) fn vector_ref(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let vector = expect_vector("vector-ref", &args[0])?.borrow();
+    let index = expect_index("vector-ref", &vector, &args[1])?;
+    Ok(vector[index].clone())
+}
+
+pub(crate) fn vector_set(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let mut vector = expect_vector("vector-set!", &args[0])?.borrow_mut();
+    let index = expect_index("vector-set!", &vector, &args[1])?;
+    vector[index] = args[2].clone();
+    Ok(Object::Void)
+}
+
+pub(crate) fn vector_length(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let len = expect_vector("vector-length", &args[0])?.borrow().len();
+    Ok(Object::Integer(len as i64))
+}
+
+pub(crate) fn vector_to_list(
+    args: &[Object],
+    _env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+    let items = expect_vector("vector->list", &args[0])?.borrow().clone();
+    if items.is_empty() {
+        return Ok(Object::nil());
+    }
+    Ok(Object::ListData(Rc::new(items)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_vectors() {
+        let mut interpreter = Interpreter::new();
+        let program = "
+            (define v (make-vector 3 0))
+            (vector-set! v 0 'a)
+            (vector-set! v 2 (+ 1 2))
+            (define (literal) #(1 (2 x) \"s\"))
+            (vector-set! (literal) 0 99)
+            (list v (vector-ref v 2) (vector-length v) (vector->list v) (literal) (vector->list #()))
+        ";
+        assert_eq!(
+            interpreter.eval(program).unwrap().to_string(),
+            "(#(a 0 3) 3 3 (a 0 3) #(1 (2 x) s) ())"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(vector-ref '#(a #(b)) 1)")
+                .unwrap()
+                .to_string(),
+            "#(b)"
+        );
+        assert_eq!(
+            interpreter
+                .eval("(define w v) (vector-set! w 1 'b) v")
+                .unwrap()
+                .to_string(),
+            "#(a b 3)"
+        );
+        assert_eq!(
+            interpreter.eval("(vector-length (make-vector 2))"),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(
+            interpreter.eval("(vector-ref v 3)"),
+            Err("vector-ref: index 3 out of range for length 3".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(vector-set! '(1) 0 1)"),
+            Err("vector-set!: expected a vector, got (1)".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("(make-vector (- 0 1))"),
+            Err("make-vector: expected a non-negative integer, got -1".to_string())
+        );
+    }
+}
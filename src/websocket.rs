@@ -0,0 +1,523 @@
+// WebSocket (RFC 6455) のクライアント。TLS は扱えないので ws:// のみ対応する。
+//
+//   (define conn (ws-connect "ws://localhost:9000/chat" #:timeout 5000))
+//   (ws-send! conn "hello")
+//   (ws-recv! conn #:timeout 1000)   ; 受信したテキスト。相手が接続を閉じていれば Void
+//   (ws-close! conn)
+//
+// #:timeout はミリ秒で、ws-connect では接続とハンドシェイク、ws-recv! では次のメッセージを待つ時間の上限になる。
+// #:timeout 0 は省略したときと同じく、いつまでも待つ。
+// 1 つのメッセージは MAX_MESSAGE バイトまで。相手がそれより長いと言ってきたら、受け取らずにエラーにする。
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use crate::builtins::{expect_string, expect_usize, split_keyword_args};
+use crate::eval::Env;
+use crate::parser::{Foreign, Object};
+
+const MAX_MESSAGE: usize = 16 << 20;
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+#[derive(Debug)]
+struct WebSocket {
+    stream: RefCell<TcpStream>,
+    closed: Cell<bool>,
+}
+
+impl Foreign for WebSocket {
+    fn type_name(&self) -> &str {
+        "websocket"
+    }
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    for chunk in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+    bytes
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (hi, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hi = hi.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, hi) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&hi.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+// クライアントから送るフレームは必ずマスクする。
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(0x80 | n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    let mask: [u8; 4] = random_bytes();
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame)
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<Frame> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head)?;
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as usize
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len) as usize
+        }
+        n => n as usize,
+    };
+    if len > MAX_MESSAGE {
+        return Err(too_large());
+    }
+    let mut mask = [0; 4];
+    if head[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0f,
+        payload,
+    })
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("message is larger than {} bytes", MAX_MESSAGE),
+    )
+}
+
+fn io_error(name: &str, e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => format!("{}: timed out", name),
+        _ => format!("{}: {}", name, e),
+    }
+}
+
+fn timeout_arg(name: &str, args: &[Object]) -> Result<(Vec<Object>, Option<Duration>), String> {
+    let (positional, keywords) = split_keyword_args(name, args)?;
+    let mut timeout = None;
+    for (kw, value) in keywords {
+        match kw {
+            "timeout" => {
+                timeout = match expect_usize(name, value)? {
+                    0 => None,
+                    ms => Some(Duration::from_millis(ms as u64)),
+                };
+            }
+            _ => return Err(format!("{}: unknown keyword #:{}", name, kw)),
+        }
+    }
+    Ok((positional.into_iter().cloned().collect(), timeout))
+}
+
+fn expect_websocket<'a>(name: &str, obj: &'a Object) -> Result<&'a WebSocket, String> {
+    let socket = match obj {
+        Object::Foreign(foreign) => (foreign.as_ref() as &dyn Any).downcast_ref::<WebSocket>(),
+        _ => None,
+    };
+    match socket {
+        Some(socket) if socket.closed.get() => Err(format!("{}: connection is closed", name)),
+        Some(socket) => Ok(socket),
+        None => Err(format!("{}: expected a websocket, got {}", name, obj)),
+    }
+}
+
+fn connect(url: &str, timeout: Option<Duration>) -> Result<WebSocket, String> {
+    let rest = match url.strip_prefix("ws://") {
+        Some(rest) => rest,
+        None if url.starts_with("wss://") => {
+            return Err("ws-connect: wss:// is not supported".to_string());
+        }
+        None => return Err(format!("ws-connect: invalid url {:?}", url)),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let err = |e| io_error("ws-connect", e);
+    let addr = address
+        .to_socket_addrs()
+        .map_err(err)?
+        .next()
+        .ok_or_else(|| format!("ws-connect: cannot resolve {}", address))?;
+    let mut stream = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+    .map_err(err)?;
+    stream.set_read_timeout(timeout).map_err(err)?;
+
+    let key = base64(&random_bytes::<16>());
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path, authority, key
+    );
+    stream.write_all(request.as_bytes()).map_err(err)?;
+
+    // フレームを読み過ぎないように、ヘッダの終わりまで 1 バイトずつ読む
+    let mut response = Vec::new();
+    let mut byte = [0; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).map_err(err)?;
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let mut lines = response.lines();
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("101") {
+        return Err(format!("ws-connect: handshake failed: {}", status));
+    }
+    let accept = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("sec-websocket-accept")
+            .then(|| value.trim().to_string())
+    });
+    if accept.as_deref() != Some(accept_key(&key).as_str()) {
+        return Err("ws-connect: invalid Sec-WebSocket-Accept".to_string());
+    }
+
+    stream.set_read_timeout(None).map_err(err)?;
+    Ok(WebSocket {
+        stream: RefCell::new(stream),
+        closed: Cell::new(false),
+    })
+}
+
+pub(crate) fn ws_connect(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, timeout) = timeout_arg("ws-connect", args)?;
+    let [url] = &positional[..] else {
+        return Err(format!(
+            "ws-connect: expected 1 argument, got {}",
+            positional.len()
+        ));
+    };
+    let socket = connect(expect_string("ws-connect", url)?, timeout)?;
+    Ok(Object::Foreign(Rc::new(socket)))
+}
+
+pub(crate) fn ws_send(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let socket = expect_websocket("ws-send!", &args[0])?;
+    let message = expect_string("ws-send!", &args[1])?;
+    write_frame(
+        &mut *socket.stream.borrow_mut(),
+        OP_TEXT,
+        message.as_bytes(),
+    )
+    .map_err(|e| io_error("ws-send!", e))?;
+    Ok(Object::Void)
+}
+
+pub(crate) fn ws_recv(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let (positional, timeout) = timeout_arg("ws-recv!", args)?;
+    let [conn] = &positional[..] else {
+        return Err(format!(
+            "ws-recv!: expected 1 argument, got {}",
+            positional.len()
+        ));
+    };
+    let socket = expect_websocket("ws-recv!", conn)?;
+    let mut stream = socket.stream.borrow_mut();
+    let err = |e| io_error("ws-recv!", e);
+    stream.set_read_timeout(timeout).map_err(err)?;
+
+    let mut message = Vec::new();
+    loop {
+        let frame = read_frame(&mut *stream).map_err(err)?;
+        match frame.opcode {
+            OP_CONTINUATION | OP_TEXT | OP_BINARY => {
+                if message.len() + frame.payload.len() > MAX_MESSAGE {
+                    return Err(err(too_large()));
+                }
+                message.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    let message = String::from_utf8_lossy(&message).into_owned();
+                    return Ok(Object::String(message.into()));
+                }
+            }
+            OP_CLOSE => {
+                // 相手からの close にはそのまま close を返す
+                let _ = write_frame(&mut *stream, OP_CLOSE, &frame.payload);
+                socket.closed.set(true);
+                return Ok(Object::Void);
+            }
+            OP_PING => write_frame(&mut *stream, OP_PONG, &frame.payload).map_err(err)?,
+            OP_PONG => {}
+            op => return Err(format!("ws-recv!: unknown opcode {:#x}", op)),
+        }
+    }
+}
+
+pub(crate) fn ws_close(args: &[Object], _env: &mut Rc<RefCell<Env>>) -> Result<Object, String> {
+    let socket = expect_websocket("ws-close!", &args[0])?;
+    // 1000: normal closure
+    write_frame(
+        &mut *socket.stream.borrow_mut(),
+        OP_CLOSE,
+        &1000u16.to_be_bytes(),
+    )
+    .map_err(|e| io_error("ws-close!", e))?;
+    socket.closed.set(true);
+    Ok(Object::Void)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_accept_key() {
+        // RFC 6455 1.3 の例
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    // 受け取ったテキストに "echo: " を付けて返し、その後 close を送るサーバー
+    fn echo_server(listener: TcpListener) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0; 1];
+            while !request.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                request.push(byte[0]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            let key = request
+                .lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                 Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            )
+            .unwrap();
+
+            let frame = read_frame(&mut stream).unwrap();
+            assert_eq!(frame.opcode, OP_TEXT);
+            let reply = format!("echo: {}", String::from_utf8(frame.payload).unwrap());
+            stream.write_all(&[0x80 | OP_PING, 0]).unwrap();
+            stream
+                .write_all(&[0x80 | OP_TEXT, reply.len() as u8])
+                .unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            assert_eq!(read_frame(&mut stream).unwrap().opcode, OP_PONG);
+            stream.write_all(&[0x80 | OP_CLOSE, 0]).unwrap();
+        })
+    }
+
+    #[test]
+    fn test_echo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/chat", listener.local_addr().unwrap());
+        let server = echo_server(listener);
+
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = format!(
+            "(begin
+                (define conn (ws-connect \"{}\" #:timeout 5000))
+                (ws-send! conn \"hello\")
+                (define reply (ws-recv! conn #:timeout 5000))
+                (define closed (ws-recv! conn #:timeout 5000))
+                reply)",
+            url
+        );
+        assert_eq!(
+            eval(&program, &mut env).unwrap(),
+            Object::String("echo: hello".into())
+        );
+        assert_eq!(eval("(begin closed)", &mut env).unwrap(), Object::Void);
+        assert!(eval("(ws-send! conn \"again\")", &mut env).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_recv_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+            let key = request
+                .lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            )
+            .unwrap();
+            // 何も送らずにクライアントのタイムアウトを待つ
+            let _ = stream.read(&mut buf);
+        });
+
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        let program = format!(
+            "(begin (define conn (ws-connect \"{}\")) (ws-recv! conn #:timeout 50))",
+            url
+        );
+        let err = eval(&program, &mut env).unwrap_err();
+        assert_eq!(err, "ws-recv!: timed out");
+        eval("(ws-close! conn)", &mut env).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_oversized_frame_is_rejected() {
+        let mut head = vec![0x81, 127];
+        head.extend_from_slice(&u64::MAX.to_be_bytes());
+        let Err(err) = read_frame(&mut &head[..]) else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            io_error("ws-recv!", err),
+            format!("ws-recv!: message is larger than {} bytes", MAX_MESSAGE)
+        );
+        assert_eq!(
+            timeout_arg(
+                "ws-recv!",
+                &[Object::KeywordArg("timeout".into()), Object::Integer(0)]
+            ),
+            Ok((Vec::new(), None))
+        );
+    }
+
+    #[test]
+    fn test_wss_is_rejected() {
+        let mut env = Rc::new(RefCell::new(Env::new()));
+        assert!(eval("(ws-connect \"wss://example.com/\")", &mut env).is_err());
+        assert!(eval("(ws-recv! 1)", &mut env).is_err());
+    }
+}